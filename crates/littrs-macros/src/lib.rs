@@ -16,7 +16,7 @@
 //! ///     unit: Temperature unit (celsius or fahrenheit)
 //! #[tool]
 //! fn fetch_weather(city: String, unit: Option<String>) -> PyValue {
-//!     PyValue::Dict(vec![
+//!     PyValue::dict(vec![
 //!         (PyValue::Str("city".to_string()), PyValue::Str(city)),
 //!         (PyValue::Str("temp".to_string()), PyValue::Int(22)),
 //!     ])
@@ -376,7 +376,7 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
                     Ok(v) => v,
                     Err(e) => {
                         // Return error as a dict with error info
-                        littrs::PyValue::Dict(vec![
+                        littrs::PyValue::dict(vec![
                             (littrs::PyValue::Str("error".to_string()), littrs::PyValue::Str(e.to_string())),
                         ])
                     }
@@ -3,47 +3,99 @@
 //! This module provides Python access to the littrs sandbox, allowing
 //! secure execution of untrusted Python code with tool registration.
 
+use std::collections::HashMap;
+
 use ::littrs::{
-    Limits, PyValue, Sandbox as RustSandbox, WasmError, WasmSandbox as RustWasmSandbox,
+    BigInt, Limits, PyValue, Sandbox as RustSandbox, WasmError, WasmSandbox as RustWasmSandbox,
     WasmSandboxConfig as RustWasmSandboxConfig,
 };
 use pyo3::IntoPyObjectExt;
 use pyo3::exceptions::{PyRuntimeError, PyTypeError};
 use pyo3::prelude::*;
-use pyo3::types::{PyBool, PyDict, PyFloat, PyFrozenSet, PyInt, PyList, PySet, PyString, PyTuple};
+use pyo3::types::{
+    PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyFrozenSet, PyInt, PyList, PySet, PyString,
+    PyTuple,
+};
 
 // ============================================================================
 // PyValue conversion
 // ============================================================================
 
 /// Convert a littrs::PyValue to a Python object.
+///
+/// A list or dict reachable more than once becomes a single Python object,
+/// so a list that contains itself converts to one that does too.
 fn pyvalue_to_py(py: Python<'_>, value: &PyValue) -> PyObject {
+    convert_value(py, value, &mut HashMap::new())
+}
+
+/// [`pyvalue_to_py`], where `seen` maps the identity of each list and dict
+/// already converted to its Python object.
+fn convert_value(py: Python<'_>, value: &PyValue, seen: &mut HashMap<usize, PyObject>) -> PyObject {
     match value {
         PyValue::None => py.None(),
         PyValue::Bool(b) => b.into_py_any(py).unwrap(),
         PyValue::Int(i) => i.into_py_any(py).unwrap(),
+        PyValue::BigInt(big) => big.as_ref().into_py_any(py).unwrap(),
         PyValue::Float(f) => f.into_py_any(py).unwrap(),
         PyValue::Str(s) => s.into_py_any(py).unwrap(),
+        PyValue::Bytes(data) => PyBytes::new(py, data).into_any().unbind(),
+        PyValue::ByteArray(data) => PyByteArray::new(py, &data.read()).into_any().unbind(),
         PyValue::List(items) => {
-            let list: Vec<PyObject> = items.iter().map(|v| pyvalue_to_py(py, v)).collect();
-            list.into_py_any(py).unwrap()
+            if let Some(list) = seen.get(&items.id()) {
+                return list.clone_ref(py);
+            }
+            let list = PyList::empty(py);
+            seen.insert(items.id(), list.clone().into_any().unbind());
+            for v in items.read().iter() {
+                list.append(convert_value(py, v, seen)).unwrap();
+            }
+            list.into_any().unbind()
         }
         PyValue::Tuple(items) => {
-            let elements: Vec<PyObject> = items.iter().map(|v| pyvalue_to_py(py, v)).collect();
+            let elements: Vec<PyObject> =
+                items.iter().map(|v| convert_value(py, v, seen)).collect();
             PyTuple::new(py, &elements).unwrap().into_any().unbind()
         }
         PyValue::Dict(pairs) => {
+            if let Some(dict) = seen.get(&pairs.id()) {
+                return dict.clone_ref(py);
+            }
             let dict = PyDict::new(py);
-            for (k, v) in pairs {
-                dict.set_item(pyvalue_to_py(py, k), pyvalue_to_py(py, v))
+            seen.insert(pairs.id(), dict.clone().into_any().unbind());
+            for (k, v) in pairs.read().iter() {
+                dict.set_item(convert_value(py, k, seen), convert_value(py, v, seen))
                     .unwrap();
             }
             dict.into_any().unbind()
         }
         PyValue::Set(items) => {
-            let elements: Vec<PyObject> = items.iter().map(|v| pyvalue_to_py(py, v)).collect();
+            let elements: Vec<PyObject> = items
+                .read()
+                .iter()
+                .map(|v| convert_value(py, v, seen))
+                .collect();
             PySet::new(py, &elements).unwrap().into_any().unbind()
         }
+        PyValue::FrozenSet(items) => {
+            let elements: Vec<PyObject> =
+                items.iter().map(|v| convert_value(py, v, seen)).collect();
+            PyFrozenSet::new(py, &elements).unwrap().into_any().unbind()
+        }
+        PyValue::DictView(view) => {
+            let items: Vec<PyObject> = view
+                .items()
+                .iter()
+                .map(|v| convert_value(py, v, seen))
+                .collect();
+            items.into_py_any(py).unwrap()
+        }
+        // A lazy Python range, so `range(10**18)` never materializes its items
+        PyValue::Range { start, stop, step } => py
+            .import("builtins")
+            .and_then(|builtins| builtins.getattr("range")?.call1((*start, *stop, *step)))
+            .unwrap()
+            .unbind(),
         PyValue::Function(f) => {
             if f.name == "<lambda>" {
                 "<function <lambda>>".into_py_any(py).unwrap()
@@ -56,39 +108,90 @@ fn pyvalue_to_py(py: Python<'_>, value: &PyValue) -> PyObject {
             .into_py_any(py)
             .unwrap(),
         PyValue::File(handle) => format!("<file handle={}>", handle).into_py_any(py).unwrap(),
+        PyValue::Exception(exc) => exc.repr().into_py_any(py).unwrap(),
+        PyValue::Class(_)
+        | PyValue::Type(_)
+        | PyValue::Instance(_)
+        | PyValue::Property(_)
+        | PyValue::StaticMethod(_)
+        | PyValue::ClassMethod(_)
+        | PyValue::BoundMethod { .. }
+        | PyValue::MethodDescriptor { .. }
+        | PyValue::Generator(_)
+        | PyValue::Iterator(_)
+        | PyValue::Decorator(_) => value.to_string().into_py_any(py).unwrap(),
     }
 }
 
 /// Convert a Python object to a littrs::PyValue.
+///
+/// As in [`pyvalue_to_py`], a list or dict reachable more than once
+/// becomes a single shared sandbox value.
 fn py_to_pyvalue(obj: &Bound<'_, PyAny>) -> PyResult<PyValue> {
+    convert_object(obj, &mut HashMap::new())
+}
+
+/// [`py_to_pyvalue`], where `seen` maps the address of each list and dict
+/// already converted to its sandbox value.
+fn convert_object(obj: &Bound<'_, PyAny>, seen: &mut HashMap<usize, PyValue>) -> PyResult<PyValue> {
+    if let Some(value) = seen.get(&(obj.as_ptr() as usize)) {
+        return Ok(value.clone());
+    }
     if obj.is_none() {
         Ok(PyValue::None)
     } else if let Ok(b) = obj.downcast::<PyBool>() {
         Ok(PyValue::Bool(b.is_true()))
     } else if let Ok(i) = obj.downcast::<PyInt>() {
-        Ok(PyValue::Int(i.extract()?))
+        match i.extract::<i64>() {
+            Ok(n) => Ok(PyValue::Int(n)),
+            Err(_) => Ok(PyValue::from(i.extract::<BigInt>()?)),
+        }
     } else if let Ok(f) = obj.downcast::<PyFloat>() {
         Ok(PyValue::Float(f.extract()?))
     } else if let Ok(s) = obj.downcast::<PyString>() {
         Ok(PyValue::Str(s.to_string()))
+    } else if let Ok(data) = obj.downcast::<PyBytes>() {
+        Ok(PyValue::Bytes(data.as_bytes().to_vec()))
+    } else if let Ok(data) = obj.downcast::<PyByteArray>() {
+        Ok(PyValue::bytearray(data.to_vec()))
     } else if let Ok(list) = obj.downcast::<PyList>() {
-        let items: PyResult<Vec<_>> = list.iter().map(|item| py_to_pyvalue(&item)).collect();
-        Ok(PyValue::List(items?))
+        let value = PyValue::list(Vec::new());
+        seen.insert(obj.as_ptr() as usize, value.clone());
+        let items: PyResult<Vec<_>> = list
+            .iter()
+            .map(|item| convert_object(&item, seen))
+            .collect();
+        let items = items?;
+        if let PyValue::List(shared) = &value {
+            shared.write(|v| *v = items);
+        }
+        Ok(value)
     } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
-        let items: PyResult<Vec<_>> = tuple.iter().map(|item| py_to_pyvalue(&item)).collect();
+        let items: PyResult<Vec<_>> = tuple
+            .iter()
+            .map(|item| convert_object(&item, seen))
+            .collect();
         Ok(PyValue::Tuple(items?))
     } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        let value = PyValue::dict([]);
+        seen.insert(obj.as_ptr() as usize, value.clone());
         let mut pairs = Vec::new();
         for (k, v) in dict.iter() {
-            pairs.push((py_to_pyvalue(&k)?, py_to_pyvalue(&v)?));
+            pairs.push((convert_object(&k, seen)?, convert_object(&v, seen)?));
+        }
+        if let PyValue::Dict(shared) = &value {
+            shared.write(|d| d.extend(pairs));
         }
-        Ok(PyValue::Dict(pairs))
+        Ok(value)
     } else if let Ok(set) = obj.downcast::<PySet>() {
-        let items: PyResult<Vec<_>> = set.iter().map(|item| py_to_pyvalue(&item)).collect();
-        Ok(PyValue::Set(items?))
+        let items: PyResult<Vec<_>> = set.iter().map(|item| convert_object(&item, seen)).collect();
+        Ok(PyValue::set(items?))
     } else if let Ok(fset) = obj.downcast::<PyFrozenSet>() {
-        let items: PyResult<Vec<_>> = fset.iter().map(|item| py_to_pyvalue(&item)).collect();
-        Ok(PyValue::Set(items?))
+        let items: PyResult<Vec<_>> = fset
+            .iter()
+            .map(|item| convert_object(&item, seen))
+            .collect();
+        Ok(PyValue::frozenset(items?))
     } else {
         Err(PyTypeError::new_err(format!(
             "Cannot convert {} to sandbox value",
@@ -224,7 +327,7 @@ impl Sandbox {
                     }
                     Err(e) => {
                         // Return error as a dict
-                        PyValue::Dict(vec![(
+                        PyValue::dict(vec![(
                             PyValue::Str("error".to_string()),
                             PyValue::Str(format!("{}", e)),
                         )])
@@ -279,7 +382,7 @@ impl Sandbox {
                             args.iter().map(|v| pyvalue_to_py(py, v)).collect();
                         match func.call1(py, (py_args,)) {
                             Ok(result) => py_to_pyvalue(result.bind(py)).unwrap_or(PyValue::None),
-                            Err(e) => PyValue::Dict(vec![(
+                            Err(e) => PyValue::dict(vec![(
                                 PyValue::Str("error".to_string()),
                                 PyValue::Str(format!("{}", e)),
                             )]),
@@ -482,7 +585,7 @@ impl WasmSandbox {
                         args.iter().map(|v| pyvalue_to_py(py, v)).collect();
                     match func.call1(py, (py_args,)) {
                         Ok(result) => py_to_pyvalue(result.bind(py)).unwrap_or(PyValue::None),
-                        Err(e) => PyValue::Dict(vec![(
+                        Err(e) => PyValue::dict(vec![(
                             PyValue::Str("error".to_string()),
                             PyValue::Str(format!("{}", e)),
                         )]),
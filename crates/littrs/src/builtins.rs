@@ -1,22 +1,48 @@
 //! Built-in Python functions for the sandbox.
 //!
 //! This module implements the built-in functions available in the sandbox:
-//! - Type conversions: str, int, float, bool, list
+//! - Type conversions: str, int, float, bool, list, bytes, bytearray, set,
+//!   frozenset, dict
 //! - Sequences: len, range, sum, min, max
 //! - I/O: print
 //! - Math: abs
+//!
+//! The names of built-in types (`int`, `str`, `dict`, ...) evaluate to type
+//! objects ([`PyValue::Type`]); calling one dispatches to its constructor here.
 
+use crate::bytecode::BinOp;
+use crate::bytes;
+use crate::class::ClassValue;
+use crate::dict::{Dict, Set, check_hashable};
 use crate::error::{Error, Result};
-use crate::methods::{arg_float, arg_int, check_args, check_args_range};
-use crate::operators::compare_values;
-use crate::value::PyValue;
-
-/// Extract items from any iterable PyValue (list, tuple, set, dict keys, str chars).
+use crate::exception::{ExceptionValue, is_builtin_exception};
+use crate::format;
+use crate::int;
+use crate::methods::{arg_float, arg_int, arg_str, check_args, check_args_range};
+use crate::operators::{apply_binop, compare_values};
+use crate::value::{PyValue, range_len};
+
+/// Extract items from any iterable PyValue (list, tuple, set, dict keys, dict
+/// view, str chars, range). Generators and iterator objects are consumed by the VM
+/// before builtins run.
 fn to_iterable_items(val: &PyValue) -> Result<Vec<PyValue>> {
-    match val {
-        PyValue::List(items) | PyValue::Tuple(items) | PyValue::Set(items) => Ok(items.clone()),
-        PyValue::Dict(pairs) => Ok(pairs.iter().map(|(k, _)| k.clone()).collect()),
+    match val.tuple_view().as_ref() {
+        PyValue::List(items) => Ok(items.read().to_vec()),
+        PyValue::Tuple(items) => Ok(items.clone()),
+        PyValue::Set(set) => Ok(set.read().iter().cloned().collect()),
+        PyValue::FrozenSet(set) => Ok(set.iter().cloned().collect()),
+        PyValue::Dict(dict) => Ok(dict.read().keys().cloned().collect()),
+        PyValue::DictView(view) => Ok(view.items()),
         PyValue::Str(s) => Ok(s.chars().map(|c| PyValue::Str(c.to_string())).collect()),
+        PyValue::Bytes(_) | PyValue::ByteArray(_) => Ok(val
+            .as_bytes()
+            .unwrap()
+            .iter()
+            .map(|&byte| PyValue::Int(byte as i64))
+            .collect()),
+        PyValue::Range { start, stop, step } => Ok((0..range_len(*start, *stop, *step))
+            .map(|i| PyValue::Int(start + i * step))
+            .collect()),
         other => Err(Error::Type {
             expected: "iterable".to_string(),
             got: other.type_name().to_string(),
@@ -24,6 +50,31 @@ fn to_iterable_items(val: &PyValue) -> Result<Vec<PyValue>> {
     }
 }
 
+/// Built-in types whose names evaluate to type objects.
+const BUILTIN_TYPES: &[&str] = &[
+    "bool",
+    "int",
+    "float",
+    "str",
+    "bytes",
+    "bytearray",
+    "list",
+    "tuple",
+    "dict",
+    "set",
+    "frozenset",
+    "range",
+    "type",
+];
+
+/// The type object for the built-in type `name`, if it is one.
+pub fn builtin_type_object(name: &str) -> Option<PyValue> {
+    BUILTIN_TYPES
+        .iter()
+        .find(|t| **t == name)
+        .map(|t| PyValue::Type(t.to_string()))
+}
+
 /// Result of attempting to handle a builtin function call.
 pub enum BuiltinResult {
     /// The function was handled and returned this value.
@@ -39,86 +90,152 @@ pub fn try_builtin(
     print_buffer: &mut Vec<String>,
 ) -> BuiltinResult {
     match func_name {
-        "len" => BuiltinResult::Handled(builtin_len(args)),
-        "str" => BuiltinResult::Handled(builtin_str(args)),
-        "int" => BuiltinResult::Handled(builtin_int(args)),
-        "float" => BuiltinResult::Handled(builtin_float(args)),
-        "bool" => BuiltinResult::Handled(builtin_bool(args)),
-        "list" => BuiltinResult::Handled(builtin_list(args)),
-        "range" => BuiltinResult::Handled(builtin_range(args)),
-        "enumerate" => BuiltinResult::Handled(builtin_enumerate(args)),
-        "zip" => BuiltinResult::Handled(builtin_zip(args)),
-        "reversed" => BuiltinResult::Handled(builtin_reversed(args)),
-        "any" => BuiltinResult::Handled(builtin_any(args)),
-        "all" => BuiltinResult::Handled(builtin_all(args)),
         "print" => BuiltinResult::Handled(builtin_print(args, print_buffer)),
-        "abs" => BuiltinResult::Handled(builtin_abs(args)),
-        "min" => BuiltinResult::Handled(builtin_min(args)),
-        "max" => BuiltinResult::Handled(builtin_max(args)),
-        "sum" => BuiltinResult::Handled(builtin_sum(args)),
-        "isinstance" => BuiltinResult::Handled(builtin_isinstance(args)),
-        "type" => BuiltinResult::Handled(builtin_type(args)),
-        "tuple" => BuiltinResult::Handled(builtin_tuple(args)),
-        "set" => BuiltinResult::Handled(builtin_set(args)),
-        "repr" => BuiltinResult::Handled(builtin_repr(args)),
-        "bin" => BuiltinResult::Handled(builtin_bin(args)),
-        "hex" => BuiltinResult::Handled(builtin_hex(args)),
-        "oct" => BuiltinResult::Handled(builtin_oct(args)),
-        "divmod" => BuiltinResult::Handled(builtin_divmod(args)),
-        "pow" => BuiltinResult::Handled(builtin_pow(args)),
-        "hash" => BuiltinResult::Handled(builtin_hash(args)),
-        _ => BuiltinResult::NotBuiltin,
+        name if is_builtin_exception(name) => BuiltinResult::Handled(Ok(PyValue::Exception(
+            Box::new(ExceptionValue::new(name, args)),
+        ))),
+        name => match builtin_function(name) {
+            Some(function) => BuiltinResult::Handled(function(args)),
+            None => BuiltinResult::NotBuiltin,
+        },
     }
 }
 
+/// Whether `name` is handled by [`try_builtin`].
+pub fn is_builtin(name: &str) -> bool {
+    name == "print" || is_builtin_exception(name) || builtin_function(name).is_some()
+}
+
+/// Look up a builtin function other than `print` by name.
+fn builtin_function(name: &str) -> Option<fn(Vec<PyValue>) -> Result<PyValue>> {
+    let function: fn(Vec<PyValue>) -> Result<PyValue> = match name {
+        "len" => builtin_len,
+        "str" => builtin_str,
+        "int" => builtin_int,
+        "float" => builtin_float,
+        "bool" => builtin_bool,
+        "bytes" => builtin_bytes,
+        "bytearray" => builtin_bytearray,
+        "list" => builtin_list,
+        "range" => builtin_range,
+        "reversed" => builtin_reversed,
+        "any" => builtin_any,
+        "all" => builtin_all,
+        "abs" => builtin_abs,
+        "min" => builtin_min,
+        "max" => builtin_max,
+        "sum" => builtin_sum,
+        "isinstance" => builtin_isinstance,
+        "type" => builtin_type,
+        "tuple" => builtin_tuple,
+        "set" => builtin_set,
+        "frozenset" => builtin_frozenset,
+        "repr" => builtin_repr,
+        "format" => builtin_format,
+        "bin" => builtin_bin,
+        "hex" => builtin_hex,
+        "oct" => builtin_oct,
+        "chr" => builtin_chr,
+        "ord" => builtin_ord,
+        "divmod" => builtin_divmod,
+        "pow" => builtin_pow,
+        "hash" => builtin_hash,
+        "property" => builtin_property,
+        "staticmethod" => builtin_staticmethod,
+        "classmethod" => builtin_classmethod,
+        _ => return None,
+    };
+    Some(function)
+}
+
 fn builtin_len(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("len", &args, 1)?;
-    let arg = &args[0];
-    let len = match arg {
-        PyValue::Str(s) => s.len(),
-        PyValue::List(l) => l.len(),
+    let arg = args[0].tuple_view();
+    let len = match arg.as_ref() {
+        PyValue::Str(s) => s.chars().count(),
+        PyValue::Bytes(b) => b.len(),
+        PyValue::ByteArray(b) => b.read().len(),
+        PyValue::List(l) => l.read().len(),
         PyValue::Tuple(t) => t.len(),
-        PyValue::Dict(d) => d.len(),
-        PyValue::Set(s) => s.len(),
+        PyValue::Dict(d) => d.read().len(),
+        PyValue::DictView(view) => view.len(),
+        PyValue::Set(s) => s.read().len(),
+        PyValue::FrozenSet(s) => s.len(),
+        PyValue::Range { start, stop, step } => range_len(*start, *stop, *step) as usize,
         _ => {
-            return Err(Error::Type {
-                expected: "sized".to_string(),
-                got: arg.type_name().to_string(),
-            });
+            return Err(Error::exception(
+                "TypeError",
+                format!("object of type '{}' has no len()", arg.type_name()),
+            ));
         }
     };
     Ok(PyValue::Int(len as i64))
 }
 
 fn builtin_str(args: Vec<PyValue>) -> Result<PyValue> {
-    check_args("str", &args, 1)?;
-    Ok(PyValue::Str(format!("{}", args[0])))
+    check_args_range("str", &args, 1, 3)?;
+    if args.len() == 1 {
+        return Ok(PyValue::Str(args[0].to_print_string()));
+    }
+    // `str(data, encoding)` decodes
+    let data = args[0].as_bytes().ok_or_else(|| {
+        Error::exception(
+            "TypeError",
+            format!(
+                "decoding to str: need a bytes-like object, {} found",
+                args[0].type_name()
+            ),
+        )
+    })?;
+    let errors = args.get(2).map(arg_str).transpose()?.unwrap_or("strict");
+    Ok(PyValue::Str(bytes::decode(
+        &data,
+        arg_str(&args[1])?,
+        errors,
+    )?))
 }
 
 fn builtin_int(args: Vec<PyValue>) -> Result<PyValue> {
-    check_args("int", &args, 1)?;
-    let arg = &args[0];
-    let val = match arg {
-        PyValue::Int(i) => *i,
-        PyValue::Float(f) => *f as i64,
-        PyValue::Bool(b) => {
-            if *b {
-                1
-            } else {
-                0
-            }
-        }
-        PyValue::Str(s) => s
-            .parse()
-            .map_err(|_| Error::Runtime(format!("invalid literal for int(): '{}'", s)))?,
-        _ => {
-            return Err(Error::Type {
-                expected: "number or string".to_string(),
-                got: arg.type_name().to_string(),
-            });
-        }
+    check_args_range("int", &args, 0, 2)?;
+    let Some(arg) = args.first() else {
+        return Ok(PyValue::Int(0));
     };
-    Ok(PyValue::Int(val))
+    if let Some(base) = args.get(1) {
+        let base = arg_int(base)?;
+        if base != 0 && !(2..=36).contains(&base) {
+            return Err(Error::exception(
+                "ValueError",
+                "int() base must be >= 2 and <= 36, or 0",
+            ));
+        }
+        let PyValue::Str(s) = arg else {
+            return Err(Error::exception(
+                "TypeError",
+                "int() can't convert non-string with explicit base",
+            ));
+        };
+        return parse_int(s, base as u32);
+    }
+    match arg {
+        PyValue::Int(_) | PyValue::BigInt(_) => Ok(arg.clone()),
+        PyValue::Bool(b) => Ok(PyValue::Int(*b as i64)),
+        PyValue::Float(f) => int::from_f64(*f),
+        PyValue::Str(s) => parse_int(s, 10),
+        _ => Err(Error::Type {
+            expected: "number or string".to_string(),
+            got: arg.type_name().to_string(),
+        }),
+    }
+}
+
+/// Parse the string argument of `int()`.
+fn parse_int(s: &str, base: u32) -> Result<PyValue> {
+    int::parse(s, base).ok_or_else(|| {
+        Error::exception(
+            "ValueError",
+            format!("invalid literal for int() with base {}: '{}'", base, s),
+        )
+    })
 }
 
 fn builtin_float(args: Vec<PyValue>) -> Result<PyValue> {
@@ -127,6 +244,7 @@ fn builtin_float(args: Vec<PyValue>) -> Result<PyValue> {
     let val = match arg {
         PyValue::Float(f) => *f,
         PyValue::Int(i) => *i as f64,
+        PyValue::BigInt(big) => int::to_f64(big)?,
         PyValue::Bool(b) => {
             if *b {
                 1.0
@@ -134,9 +252,12 @@ fn builtin_float(args: Vec<PyValue>) -> Result<PyValue> {
                 0.0
             }
         }
-        PyValue::Str(s) => s
-            .parse()
-            .map_err(|_| Error::Runtime(format!("invalid literal for float(): '{}'", s)))?,
+        PyValue::Str(s) => s.parse().map_err(|_| {
+            Error::exception(
+                "ValueError",
+                format!("could not convert string to float: '{}'", s),
+            )
+        })?,
         _ => {
             return Err(Error::Type {
                 expected: "number or string".to_string(),
@@ -152,13 +273,39 @@ fn builtin_bool(args: Vec<PyValue>) -> Result<PyValue> {
     Ok(PyValue::Bool(args[0].is_truthy()))
 }
 
+fn builtin_bytes(args: Vec<PyValue>) -> Result<PyValue> {
+    byte_string_contents("bytes", &args).map(PyValue::Bytes)
+}
+
+fn builtin_bytearray(args: Vec<PyValue>) -> Result<PyValue> {
+    byte_string_contents("bytearray", &args).map(PyValue::bytearray)
+}
+
+/// The contents of `bytes(...)` or `bytearray(...)`: empty, a str with an
+/// encoding, or any source accepted by [`bytes::from_source`].
+fn byte_string_contents(func: &str, args: &[PyValue]) -> Result<Vec<u8>> {
+    check_args_range(func, args, 0, 3)?;
+    match args {
+        [] => Ok(Vec::new()),
+        [source] => bytes::from_source(func, source, to_iterable_items),
+        [PyValue::Str(s), encoding, rest @ ..] => {
+            let errors = rest.first().map(arg_str).transpose()?.unwrap_or("strict");
+            bytes::encode(s, arg_str(encoding)?, errors)
+        }
+        _ => Err(Error::exception(
+            "TypeError",
+            "encoding without a string argument",
+        )),
+    }
+}
+
 fn builtin_list(args: Vec<PyValue>) -> Result<PyValue> {
     if args.is_empty() {
-        return Ok(PyValue::List(vec![]));
+        return Ok(PyValue::list(vec![]));
     }
     check_args("list", &args, 1)?;
     let items = to_iterable_items(&args[0])?;
-    Ok(PyValue::List(items))
+    Ok(PyValue::list(items))
 }
 
 fn builtin_range(args: Vec<PyValue>) -> Result<PyValue> {
@@ -166,27 +313,22 @@ fn builtin_range(args: Vec<PyValue>) -> Result<PyValue> {
         1 => (0, arg_int(&args[0])?, 1),
         2 => (arg_int(&args[0])?, arg_int(&args[1])?, 1),
         3 => (arg_int(&args[0])?, arg_int(&args[1])?, arg_int(&args[2])?),
-        _ => return Err(Error::Runtime("range() takes 1 to 3 arguments".to_string())),
+        _ => {
+            return Err(Error::exception(
+                "TypeError",
+                format!("range expected at most 3 arguments, got {}", args.len()),
+            ));
+        }
     };
 
     if step == 0 {
-        return Err(Error::Runtime("range() step cannot be zero".to_string()));
+        return Err(Error::exception(
+            "ValueError",
+            "range() arg 3 must not be zero",
+        ));
     }
 
-    let mut items = Vec::new();
-    let mut i = start;
-    if step > 0 {
-        while i < stop {
-            items.push(PyValue::Int(i));
-            i += step;
-        }
-    } else {
-        while i > stop {
-            items.push(PyValue::Int(i));
-            i += step;
-        }
-    }
-    Ok(PyValue::List(items))
+    Ok(PyValue::Range { start, stop, step })
 }
 
 fn builtin_print(args: Vec<PyValue>, print_buffer: &mut Vec<String>) -> Result<PyValue> {
@@ -198,19 +340,19 @@ fn builtin_print(args: Vec<PyValue>, print_buffer: &mut Vec<String>) -> Result<P
 fn builtin_abs(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("abs", &args, 1)?;
     match &args[0] {
-        PyValue::Int(i) => Ok(PyValue::Int(i.abs())),
         PyValue::Float(f) => Ok(PyValue::Float(f.abs())),
-        _ => Err(Error::Type {
+        other => int::abs(other).ok_or_else(|| Error::Type {
             expected: "number".to_string(),
-            got: args[0].type_name().to_string(),
+            got: other.type_name().to_string(),
         }),
     }
 }
 
 fn builtin_min(args: Vec<PyValue>) -> Result<PyValue> {
     if args.is_empty() {
-        return Err(Error::Runtime(
-            "min() requires at least 1 argument".to_string(),
+        return Err(Error::exception(
+            "TypeError",
+            "min expected at least 1 argument, got 0",
         ));
     }
 
@@ -218,7 +360,10 @@ fn builtin_min(args: Vec<PyValue>) -> Result<PyValue> {
         && let Ok(items) = to_iterable_items(&args[0])
     {
         if items.is_empty() {
-            return Err(Error::Runtime("min() arg is an empty sequence".to_string()));
+            return Err(Error::exception(
+                "ValueError",
+                "min() arg is an empty sequence",
+            ));
         }
         return find_min(&items);
     }
@@ -227,8 +372,9 @@ fn builtin_min(args: Vec<PyValue>) -> Result<PyValue> {
 
 fn builtin_max(args: Vec<PyValue>) -> Result<PyValue> {
     if args.is_empty() {
-        return Err(Error::Runtime(
-            "max() requires at least 1 argument".to_string(),
+        return Err(Error::exception(
+            "TypeError",
+            "max expected at least 1 argument, got 0",
         ));
     }
 
@@ -236,7 +382,10 @@ fn builtin_max(args: Vec<PyValue>) -> Result<PyValue> {
         && let Ok(items) = to_iterable_items(&args[0])
     {
         if items.is_empty() {
-            return Err(Error::Runtime("max() arg is an empty sequence".to_string()));
+            return Err(Error::exception(
+                "ValueError",
+                "max() arg is an empty sequence",
+            ));
         }
         return find_max(&items);
     }
@@ -245,46 +394,27 @@ fn builtin_max(args: Vec<PyValue>) -> Result<PyValue> {
 
 fn builtin_sum(args: Vec<PyValue>) -> Result<PyValue> {
     if args.is_empty() {
-        return Err(Error::Runtime(
-            "sum() requires at least 1 argument".to_string(),
+        return Err(Error::exception(
+            "TypeError",
+            "sum() takes at least 1 positional argument (0 given)",
         ));
     }
     let items = to_iterable_items(&args[0])?;
 
-    let mut total = 0i64;
-    let mut is_float = false;
-    let mut total_float = 0.0f64;
-
+    let mut total = PyValue::Int(0);
     for item in &items {
-        match item {
-            PyValue::Int(i) => {
-                if is_float {
-                    total_float += *i as f64;
-                } else {
-                    total += *i;
-                }
-            }
-            PyValue::Float(f) => {
-                if !is_float {
-                    is_float = true;
-                    total_float = total as f64;
-                }
-                total_float += *f;
-            }
-            _ => {
-                return Err(Error::Type {
-                    expected: "number".to_string(),
-                    got: item.type_name().to_string(),
-                });
-            }
+        if !matches!(
+            item,
+            PyValue::Int(_) | PyValue::BigInt(_) | PyValue::Bool(_) | PyValue::Float(_)
+        ) {
+            return Err(Error::Type {
+                expected: "number".to_string(),
+                got: item.type_name().to_string(),
+            });
         }
+        total = apply_binop(&BinOp::Add, &total, item)?;
     }
-
-    if is_float {
-        Ok(PyValue::Float(total_float))
-    } else {
-        Ok(PyValue::Int(total))
-    }
+    Ok(total)
 }
 
 fn find_min(items: &[PyValue]) -> Result<PyValue> {
@@ -307,50 +437,12 @@ fn find_max(items: &[PyValue]) -> Result<PyValue> {
     Ok(max)
 }
 
-fn builtin_enumerate(args: Vec<PyValue>) -> Result<PyValue> {
-    check_args_range("enumerate", &args, 1, 2)?;
-    let items = to_iterable_items(&args[0])?;
-    let start = if args.len() > 1 {
-        arg_int(&args[1])?
-    } else {
-        0
-    };
-
-    let result: Vec<PyValue> = items
-        .into_iter()
-        .enumerate()
-        .map(|(i, v)| PyValue::Tuple(vec![PyValue::Int(start + i as i64), v]))
-        .collect();
-
-    Ok(PyValue::List(result))
-}
-
-fn builtin_zip(args: Vec<PyValue>) -> Result<PyValue> {
-    if args.is_empty() {
-        return Ok(PyValue::List(vec![]));
-    }
-
-    // Convert all arguments to lists
-    let lists: Result<Vec<Vec<PyValue>>> = args.iter().map(to_iterable_items).collect();
-    let lists = lists?;
-
-    // Find the shortest length
-    let min_len = lists.iter().map(|l| l.len()).min().unwrap_or(0);
-
-    // Zip them together
-    let result: Vec<PyValue> = (0..min_len)
-        .map(|i| PyValue::Tuple(lists.iter().map(|l| l[i].clone()).collect()))
-        .collect();
-
-    Ok(PyValue::List(result))
-}
-
 fn builtin_reversed(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("reversed", &args, 1)?;
 
     let mut items = to_iterable_items(&args[0])?;
     items.reverse();
-    Ok(PyValue::List(items))
+    Ok(PyValue::list(items))
 }
 
 fn builtin_any(args: Vec<PyValue>) -> Result<PyValue> {
@@ -369,31 +461,116 @@ fn builtin_all(args: Vec<PyValue>) -> Result<PyValue> {
 
 fn builtin_isinstance(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("isinstance", &args, 2)?;
-    let type_name = args[1].as_str().ok_or_else(|| Error::Type {
-        expected: "str (type name)".to_string(),
-        got: args[1].type_name().to_string(),
-    })?;
+    Ok(PyValue::Bool(is_instance(&args[0], &args[1])?))
+}
+
+/// Whether `value` is an instance of `class_info`: a type object, a class,
+/// a type name, or a tuple of these.
+///
+/// Type names as strings are not valid in CPython. They are accepted as a
+/// compatibility shim for code written before type objects existed.
+fn is_instance(value: &PyValue, class_info: &PyValue) -> Result<bool> {
+    match class_info {
+        PyValue::Type(type_name) => Ok(is_instance_of(value, type_name)),
+        PyValue::Str(type_name) => Ok(is_instance_of(value, type_name)),
+        PyValue::Class(class) => Ok(match value {
+            PyValue::Exception(exc) => exc.is_instance(&class.name),
+            PyValue::Instance(instance) => instance.is_instance(&class.name),
+            _ => false,
+        }),
+        PyValue::Tuple(items) => {
+            for item in items {
+                if is_instance(value, item)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        other => Err(Error::exception(
+            "TypeError",
+            format!(
+                "isinstance() arg 2 must be a type or tuple of types, not {}",
+                other.type_name()
+            ),
+        )),
+    }
+}
 
-    let result = matches!(
-        (type_name, &args[0]),
+/// Whether `value` is an instance of the type named `type_name`. `bool` is
+/// a subtype of `int`.
+pub(crate) fn is_instance_of(value: &PyValue, type_name: &str) -> bool {
+    matches!(
+        (type_name, value),
         ("str", PyValue::Str(_))
-            | ("int", PyValue::Int(_))
-            | ("float", PyValue::Float(_) | PyValue::Int(_))
+            | ("bytes", PyValue::Bytes(_))
+            | ("bytearray", PyValue::ByteArray(_))
+            | (
+                "int",
+                PyValue::Int(_) | PyValue::BigInt(_) | PyValue::Bool(_)
+            )
+            | ("float", PyValue::Float(_))
             | ("bool", PyValue::Bool(_))
             | ("list", PyValue::List(_))
             | ("tuple", PyValue::Tuple(_))
             | ("dict", PyValue::Dict(_))
             | ("set", PyValue::Set(_))
+            | ("frozenset", PyValue::FrozenSet(_))
+            | ("range", PyValue::Range { .. })
+            | ("generator", PyValue::Generator(_))
+            | ("type", PyValue::Type(_) | PyValue::Class(_))
             | ("None" | "NoneType", PyValue::None)
-    );
-
-    Ok(PyValue::Bool(result))
+    ) || match value {
+        PyValue::Exception(exc) => exc.is_instance(type_name),
+        PyValue::Instance(instance) => instance.is_instance(type_name),
+        PyValue::Iterator(iterator) => iterator.kind == type_name,
+        _ => false,
+    }
 }
 
 fn builtin_type(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("type", &args, 1)?;
 
-    Ok(PyValue::Str(args[0].type_name().to_string()))
+    match &args[0] {
+        PyValue::Exception(exc) => Ok(PyValue::Class(Box::new(ClassValue::of_exception(exc)))),
+        PyValue::Instance(instance) => Ok(PyValue::Class(Box::new(instance.class.clone()))),
+        other => Ok(PyValue::Type(other.type_name().to_string())),
+    }
+}
+
+fn builtin_staticmethod(args: Vec<PyValue>) -> Result<PyValue> {
+    check_args("staticmethod", &args, 1)?;
+    match args.into_iter().next() {
+        Some(PyValue::Function(func)) => Ok(PyValue::StaticMethod(func)),
+        Some(other) => Err(Error::Type {
+            expected: "function".to_string(),
+            got: other.type_name().to_string(),
+        }),
+        None => unreachable!(),
+    }
+}
+
+fn builtin_classmethod(args: Vec<PyValue>) -> Result<PyValue> {
+    check_args("classmethod", &args, 1)?;
+    match args.into_iter().next() {
+        Some(PyValue::Function(func)) => Ok(PyValue::ClassMethod(func)),
+        Some(other) => Err(Error::Type {
+            expected: "function".to_string(),
+            got: other.type_name().to_string(),
+        }),
+        None => unreachable!(),
+    }
+}
+
+fn builtin_property(args: Vec<PyValue>) -> Result<PyValue> {
+    check_args("property", &args, 1)?;
+    match args.into_iter().next() {
+        Some(PyValue::Function(getter)) => Ok(PyValue::Property(getter)),
+        Some(other) => Err(Error::Type {
+            expected: "function".to_string(),
+            got: other.type_name().to_string(),
+        }),
+        None => unreachable!(),
+    }
 }
 
 fn builtin_tuple(args: Vec<PyValue>) -> Result<PyValue> {
@@ -406,25 +583,58 @@ fn builtin_tuple(args: Vec<PyValue>) -> Result<PyValue> {
 }
 
 fn builtin_set(args: Vec<PyValue>) -> Result<PyValue> {
-    if args.is_empty() {
-        return Ok(PyValue::Set(vec![]));
-    }
-    check_args("set", &args, 1)?;
-    let raw_items = to_iterable_items(&args[0])?;
-    // Deduplicate and check hashability
-    let mut items = Vec::new();
-    for elem in raw_items {
-        if !elem.is_hashable() {
-            return Err(Error::Runtime(format!(
-                "TypeError: unhashable type: '{}'",
-                elem.type_name()
-            )));
+    check_args_range("set", &args, 0, 1)?;
+    Ok(set_contents(&args)?.into())
+}
+
+fn builtin_frozenset(args: Vec<PyValue>) -> Result<PyValue> {
+    check_args_range("frozenset", &args, 0, 1)?;
+    Ok(PyValue::FrozenSet(set_contents(&args)?))
+}
+
+/// The contents of a new `set(iterable=())` or `frozenset(iterable=())`.
+fn set_contents(args: &[PyValue]) -> Result<Set> {
+    let Some(iterable) = args.first() else {
+        return Ok(Set::new());
+    };
+    let items = to_iterable_items(iterable)?;
+    items.iter().try_for_each(check_hashable)?;
+    Ok(items.into())
+}
+
+/// `dict(iterable=(), **kwargs)`: a dict from a mapping or an iterable of
+/// key-value pairs, updated with the keyword arguments.
+pub fn builtin_dict(args: Vec<PyValue>, kw_pairs: Vec<(String, PyValue)>) -> Result<PyValue> {
+    check_args_range("dict", &args, 0, 1)?;
+    let mut dict = Dict::new();
+    match args.first() {
+        Some(PyValue::Dict(source)) => {
+            dict = (*source.read()).clone();
         }
-        if !items.contains(&elem) {
-            items.push(elem);
+        Some(iterable) => {
+            for (i, item) in to_iterable_items(iterable)?.into_iter().enumerate() {
+                let item = item.into_tuple_view();
+                let pair = match &item {
+                    PyValue::Tuple(items) => items.clone(),
+                    PyValue::List(items) => items.read().to_vec(),
+                    _ => Vec::new(),
+                };
+                let [key, value] = <[PyValue; 2]>::try_from(pair).map_err(|_| {
+                    Error::exception(
+                        "ValueError",
+                        format!("dictionary update sequence element #{} has wrong length", i),
+                    )
+                })?;
+                check_hashable(&key)?;
+                dict.insert(key, value);
+            }
         }
+        None => {}
     }
-    Ok(PyValue::Set(items))
+    for (key, value) in kw_pairs {
+        dict.insert(PyValue::Str(key), value);
+    }
+    Ok(dict.into())
 }
 
 fn builtin_repr(args: Vec<PyValue>) -> Result<PyValue> {
@@ -432,143 +642,150 @@ fn builtin_repr(args: Vec<PyValue>) -> Result<PyValue> {
     Ok(PyValue::Str(format!("{}", args[0])))
 }
 
+fn builtin_format(args: Vec<PyValue>) -> Result<PyValue> {
+    check_args_range("format", &args, 1, 2)?;
+    let spec = match args.get(1) {
+        Some(spec) => arg_str(spec)?,
+        None => "",
+    };
+    Ok(PyValue::Str(format::format_value(&args[0], spec)?))
+}
+
 fn builtin_bin(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("bin", &args, 1)?;
-    match &args[0] {
-        PyValue::Int(n) => {
-            if *n < 0 {
-                Ok(PyValue::Str(format!("-0b{:b}", -n)))
-            } else {
-                Ok(PyValue::Str(format!("0b{:b}", n)))
-            }
-        }
-        PyValue::Bool(b) => Ok(PyValue::Str(format!("0b{:b}", *b as i64))),
-        other => Err(Error::Type {
-            expected: "int".to_string(),
-            got: other.type_name().to_string(),
-        }),
-    }
+    radix_literal(&args[0], 2, "0b")
 }
 
 fn builtin_hex(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("hex", &args, 1)?;
-    match &args[0] {
-        PyValue::Int(n) => {
-            if *n < 0 {
-                Ok(PyValue::Str(format!("-0x{:x}", -n)))
-            } else {
-                Ok(PyValue::Str(format!("0x{:x}", n)))
-            }
-        }
-        PyValue::Bool(b) => Ok(PyValue::Str(format!("0x{:x}", *b as i64))),
-        other => Err(Error::Type {
-            expected: "int".to_string(),
-            got: other.type_name().to_string(),
-        }),
-    }
+    radix_literal(&args[0], 16, "0x")
 }
 
 fn builtin_oct(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("oct", &args, 1)?;
-    match &args[0] {
-        PyValue::Int(n) => {
-            if *n < 0 {
-                Ok(PyValue::Str(format!("-0o{:o}", -n)))
-            } else {
-                Ok(PyValue::Str(format!("0o{:o}", n)))
+    radix_literal(&args[0], 8, "0o")
+}
+
+/// `bin()`, `hex()` and `oct()`.
+fn radix_literal(arg: &PyValue, radix: u32, prefix: &str) -> Result<PyValue> {
+    int::to_radix_literal(arg, radix, prefix)
+        .map(PyValue::Str)
+        .ok_or_else(|| Error::Type {
+            expected: "int".to_string(),
+            got: arg.type_name().to_string(),
+        })
+}
+
+fn builtin_chr(args: Vec<PyValue>) -> Result<PyValue> {
+    check_args("chr", &args, 1)?;
+    let code = match &args[0] {
+        PyValue::Int(_) | PyValue::Bool(_) => args[0].as_int().unwrap(),
+        PyValue::BigInt(_) => {
+            return Err(Error::exception(
+                "OverflowError",
+                "Python int too large to convert to C int",
+            ));
+        }
+        other => {
+            return Err(Error::exception(
+                "TypeError",
+                format!(
+                    "'{}' object cannot be interpreted as an integer",
+                    other.type_name()
+                ),
+            ));
+        }
+    };
+    if (0xD800..=0xDFFF).contains(&code) {
+        // Sandbox strings are UTF-8, which cannot hold a lone surrogate
+        return Err(Error::exception(
+            "ValueError",
+            format!(
+                "chr() arg {:#x} is a surrogate, which sandbox strings cannot hold",
+                code
+            ),
+        ));
+    }
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .map(|c| PyValue::Str(c.to_string()))
+        .ok_or_else(|| Error::exception("ValueError", "chr() arg not in range(0x110000)"))
+}
+
+fn builtin_ord(args: Vec<PyValue>) -> Result<PyValue> {
+    check_args("ord", &args, 1)?;
+    let (code, len) = match &args[0] {
+        PyValue::Str(s) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => (c as i64, 1),
+                _ => (0, s.chars().count()),
             }
         }
-        PyValue::Bool(b) => Ok(PyValue::Str(format!("0o{:o}", *b as i64))),
-        other => Err(Error::Type {
-            expected: "int".to_string(),
-            got: other.type_name().to_string(),
-        }),
+        PyValue::Bytes(_) | PyValue::ByteArray(_) => {
+            let data = args[0].as_bytes().unwrap();
+            match *data {
+                [byte] => (byte as i64, 1),
+                _ => (0, data.len()),
+            }
+        }
+        other => {
+            return Err(Error::exception(
+                "TypeError",
+                format!(
+                    "ord() expected string of length 1, but {} found",
+                    other.type_name()
+                ),
+            ));
+        }
+    };
+    if len != 1 {
+        return Err(Error::exception(
+            "TypeError",
+            format!(
+                "ord() expected a character, but string of length {} found",
+                len
+            ),
+        ));
     }
+    Ok(PyValue::Int(code))
 }
 
 fn builtin_divmod(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("divmod", &args, 2)?;
-    match (&args[0], &args[1]) {
-        (PyValue::Int(a), PyValue::Int(b)) => {
-            if *b == 0 {
-                return Err(Error::DivisionByZero);
-            }
-            let q = (*a as f64 / *b as f64).floor() as i64;
-            let r = a - q * b;
-            Ok(PyValue::Tuple(vec![PyValue::Int(q), PyValue::Int(r)]))
-        }
-        (a_val, b_val) => {
-            let a = arg_float(a_val)?;
-            let b = arg_float(b_val)?;
-            if b == 0.0 {
-                return Err(Error::DivisionByZero);
-            }
-            let q = (a / b).floor();
-            let r = a - q * b;
-            Ok(PyValue::Tuple(vec![PyValue::Float(q), PyValue::Float(r)]))
-        }
+    let (a_val, b_val) = (&args[0], &args[1]);
+    if let (Some(q), Some(r)) = (
+        int::binop(&BinOp::FloorDiv, a_val, b_val),
+        int::binop(&BinOp::Mod, a_val, b_val),
+    ) {
+        return Ok(PyValue::Tuple(vec![q?, r?]));
+    }
+    let a = arg_float(a_val)?;
+    let b = arg_float(b_val)?;
+    if b == 0.0 {
+        return Err(Error::DivisionByZero);
     }
+    let q = (a / b).floor();
+    let r = a - q * b;
+    Ok(PyValue::Tuple(vec![PyValue::Float(q), PyValue::Float(r)]))
 }
 
 fn builtin_pow(args: Vec<PyValue>) -> Result<PyValue> {
     match args.len() {
-        2 => {
-            // 2-arg pow: same as ** operator
-            match (&args[0], &args[1]) {
-                (PyValue::Int(base), PyValue::Int(exp)) => {
-                    if *exp < 0 {
-                        Ok(PyValue::Float((*base as f64).powi(*exp as i32)))
-                    } else {
-                        Ok(PyValue::Int(base.wrapping_pow(*exp as u32)))
-                    }
-                }
-                (a, b) => {
-                    let base = arg_float(a)?;
-                    let exp = arg_float(b)?;
-                    Ok(PyValue::Float(base.powf(exp)))
-                }
-            }
-        }
-        3 => {
-            // 3-arg pow: modular exponentiation, all ints
-            let base = arg_int(&args[0])?;
-            let exp = arg_int(&args[1])?;
-            let modulus = arg_int(&args[2])?;
-            if modulus == 0 {
-                return Err(Error::Runtime(
-                    "ValueError: pow() 3rd argument cannot be 0".to_string(),
-                ));
-            }
-            if exp < 0 {
-                return Err(Error::Runtime(
-                    "ValueError: pow() 2nd argument cannot be negative when 3rd argument specified"
-                        .to_string(),
-                ));
-            }
-            // Modular exponentiation by repeated squaring
-            let mut result: i64 = 1;
-            let mut base = base % modulus;
-            let mut exp = exp;
-            while exp > 0 {
-                if exp % 2 == 1 {
-                    result = ((result as i128 * base as i128) % modulus as i128) as i64;
-                }
-                exp /= 2;
-                base = ((base as i128 * base as i128) % modulus as i128) as i64;
-            }
-            Ok(PyValue::Int(((result % modulus) + modulus) % modulus))
-        }
-        _ => Err(Error::Runtime("pow() takes 2 or 3 arguments".to_string())),
+        // 2-arg pow: same as ** operator
+        2 => apply_binop(&BinOp::Pow, &args[0], &args[1]),
+        // 3-arg pow: modular exponentiation, all ints
+        3 => int::modpow(&args[0], &args[1], &args[2]),
+        _ => Err(Error::exception(
+            "TypeError",
+            format!("pow expected 2 or 3 arguments, got {}", args.len()),
+        )),
     }
 }
 
 fn builtin_hash(args: Vec<PyValue>) -> Result<PyValue> {
     check_args("hash", &args, 1)?;
-    if !args[0].is_hashable() {
-        return Err(Error::Runtime(format!(
-            "TypeError: unhashable type: '{}'",
-            args[0].type_name()
-        )));
-    }
+    check_hashable(&args[0])?;
     Ok(PyValue::Int(args[0].hash_value() as i64))
 }
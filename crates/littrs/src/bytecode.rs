@@ -5,7 +5,7 @@
 //! rustpython_parser) so that only the compiler depends on the parser crate.
 
 use crate::diagnostic::Span;
-use crate::value::PyValue;
+use crate::value::{Cell, PyValue};
 
 // ---------------------------------------------------------------------------
 // Operator enums
@@ -63,6 +63,19 @@ pub enum UnaryOp {
     Invert,
 }
 
+/// Conversion applied to a formatted value before its format spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conversion {
+    /// No conversion
+    None,
+    /// `!s`: `str(x)`
+    Str,
+    /// `!r`: `repr(x)`
+    Repr,
+    /// `!a`: `ascii(x)`
+    Ascii,
+}
+
 // ---------------------------------------------------------------------------
 // Bytecode instructions
 // ---------------------------------------------------------------------------
@@ -85,6 +98,12 @@ pub enum Op {
     /// Duplicate the top-of-stack value.
     Dup,
 
+    /// Duplicate the top two stack values: `[a, b]` becomes `[a, b, a, b]`.
+    ///
+    /// Used by augmented assignment to a subscript, which reads and then
+    /// stores `object[index]`.
+    DupTwo,
+
     /// Rotate the top N stack items so that TOS moves to position N.
     ///
     /// Used by chained comparisons to save intermediate values. For example,
@@ -97,16 +116,57 @@ pub enum Op {
     /// Lookup order: frame locals → globals. Produces a `NameError` if not found.
     LoadName(u32),
 
+    /// Push the value of local variable `names[i]`.
+    ///
+    /// Emitted inside a function for names the function binds; globals are
+    /// skipped. Produces an `UnboundLocalError` if the variable has no value
+    /// yet.
+    LoadFast(u32),
+
     /// Pop TOS and store it into variable `names[i]`.
     ///
     /// At the top level, variables go into globals (persistent across calls).
     /// Inside a function body, they go into the frame's locals.
     StoreName(u32),
 
+    /// Push the module-level global `names[i]`.
+    ///
+    /// Emitted for names declared `global` inside a function; frame locals
+    /// are skipped. Produces a `NameError` if not found.
+    LoadGlobal(u32),
+
+    /// Pop TOS and store it into the module-level global `names[i]`.
+    StoreGlobal(u32),
+
+    /// Push the value held in the cell for variable `names[i]`.
+    ///
+    /// Emitted for variables that are shared between a function and the
+    /// closures nested inside it (cell and free variables). Produces a
+    /// `NameError` if the cell is still empty.
+    LoadDeref(u32),
+
+    /// Pop TOS and store it into the cell for variable `names[i]`.
+    StoreDeref(u32),
+
+    /// Delete variable `names[i]` (`del x`): from the frame's locals inside
+    /// a function, from globals at the top level. Produces a `NameError` if
+    /// the variable is not bound.
+    DeleteName(u32),
+
+    /// Delete global variable `names[i]` (`del x` after `global x`).
+    DeleteGlobal(u32),
+
+    /// Empty the cell for variable `names[i]`.
+    DeleteDeref(u32),
+
     // --- Operators ---
     /// Pop two values (right then left), apply the binary operator, push result.
     BinaryOp(BinOp),
 
+    /// Like `BinaryOp`, for augmented assignment (`x += y`): mutable
+    /// containers on the left are updated in place and pushed back.
+    InplaceOp(BinOp),
+
     /// Pop one value, apply the unary operator, push result.
     UnaryOp(UnaryOp),
 
@@ -158,16 +218,37 @@ pub enum Op {
     /// Duplicate elements are removed. Elements must be hashable.
     BuildSet(u32),
 
+    /// Pop TOS (any iterable) and append its items to the list below it.
+    ///
+    /// Used with `BuildList` for displays and calls containing `*items`.
+    ListExtend,
+
+    /// Pop TOS (a dict) and merge its items into the dict below it, later
+    /// keys replacing earlier ones.
+    ///
+    /// Used with `BuildDict` for displays and calls containing `**mapping`.
+    DictUpdate,
+
+    /// Replace the list at TOS with a tuple of its items.
+    ListToTuple,
+
+    /// Replace the list at TOS with a set of its items.
+    ListToSet,
+
     // --- Subscript ---
     /// Pop index and collection, push `collection[index]`.
     ///
     /// Supports list (with negative indices), string, and dict subscript.
     BinarySubscript,
 
-    /// Pop value and index from the stack, mutate `names[i][index] = value`.
+    /// Pop index, object and value from the stack, and set
+    /// `object[index] = value`.
     ///
-    /// The variable is looked up by name and modified in place.
-    StoreSubscript(u32),
+    /// Stack layout: `[value, object, index]`.
+    StoreSubscript,
+
+    /// Pop index and object from the stack, and delete `object[index]`.
+    DeleteSubscript,
 
     // --- Slicing ---
     /// Pop step, stop, start, and object from the stack. Push `object[start:stop:step]`.
@@ -175,6 +256,15 @@ pub enum Op {
     /// Any of start/stop/step may be `PyValue::None` to indicate "unspecified".
     Slice,
 
+    /// Pop step, stop, start, object and value from the stack, and assign
+    /// the items of `value` to `object[start:stop:step]` in the list
+    /// `object`.
+    StoreSlice,
+
+    /// Pop step, stop, start, and object from the stack, and delete
+    /// `object[start:stop:step]` from the list `object`.
+    DeleteSlice,
+
     // --- Unpacking ---
     /// Pop TOS (must be a list), push its N elements onto the stack.
     ///
@@ -182,11 +272,19 @@ pub enum Op {
     /// This lets the compiler emit `StoreName` calls in forward target order.
     UnpackSequence(u32),
 
+    /// Pop TOS and unpack it for a starred assignment such as
+    /// `first, *rest, last = items`: the operands are the number of targets
+    /// before and after the starred one. The items in between are collected
+    /// into a list, and everything is pushed so that the first target's
+    /// value is TOS.
+    UnpackStarred(u32, u32),
+
     // --- Iteration ---
     /// Pop TOS, convert it to an iterator, and store it in the current frame.
     ///
-    /// Lists are used directly; strings are split into characters. The iterator
-    /// state is kept in `CallFrame::iterators`, not on the value stack.
+    /// Lists are used directly; strings are split into characters. Ranges and
+    /// generators are advanced lazily. The iterator state is kept in
+    /// `CallFrame::iterators`, not on the value stack.
     GetIter,
 
     /// Advance the current frame's topmost iterator.
@@ -200,6 +298,10 @@ pub enum Op {
     /// Used before `break` inside a `for` loop to clean up the iterator.
     PopIter,
 
+    /// Replace TOS, the exhausted iterable of a `yield from`, with the
+    /// expression's value: what a generator returned, otherwise `None`.
+    YieldFromResult,
+
     // --- Function calls ---
     /// Call a function by name with positional arguments.
     ///
@@ -223,22 +325,12 @@ pub enum Op {
     /// the appropriate method handler, pushes result.
     CallMethod(u32, u32),
 
-    /// Call a mutating method on a named variable.
-    ///
-    /// Stack layout: `[args...]`. Looks up `names[var_name_idx]`, gets a `&mut`
-    /// reference, and calls `mutate_list` or `mutate_dict`. Pushes the return
-    /// value (typically `None`).
-    ///
-    /// This exists because mutating methods (`append`, `pop`, etc.) need to
-    /// modify the variable in place rather than operating on a cloned value.
-    CallMutMethod(u32, u32, u32),
-
-    /// Call a mutating method on a named variable with keyword arguments.
+    /// Call a method with positional and keyword arguments.
     ///
-    /// Stack layout: `[pos_args..., kw_name0, kw_val0, kw_name1, kw_val1, ...]`.
-    /// `n_pos` positional args + `n_kw` keyword pairs (2 values each).
-    /// Used for `list.sort(key=..., reverse=...)`.
-    CallMutMethodKw(u32, u32, u32, u32),
+    /// Stack layout: `[object, pos_args..., kw_name0, kw_val0, ...]`.
+    /// Keyword arguments are passed to user-defined methods and functions;
+    /// built-in methods ignore them.
+    CallMethodKw(u32, u32, u32),
 
     /// Call a callable value on the stack with positional arguments.
     ///
@@ -252,20 +344,57 @@ pub enum Op {
     /// `n_pos` positional args + `n_kw` keyword pairs.
     CallValueKw(u32, u32),
 
+    /// Call a function by name with arguments unpacked at the call site
+    /// (`f(*args, **kwargs)`).
+    ///
+    /// Stack layout: `[args_list, kwargs_dict]`. The list and dict are
+    /// spread into positional and keyword arguments and the call proceeds
+    /// as for `CallFunctionKw`. `CallMethodEx`, `CallSuperMethodEx` and
+    /// `CallValueEx` do the same for the other kinds of call.
+    CallFunctionEx(u32),
+
+    /// `CallMethodKw` with unpacked arguments.
+    ///
+    /// Stack layout: `[object, args_list, kwargs_dict]`.
+    CallMethodEx(u32),
+
+    /// `CallSuperMethod` with unpacked arguments.
+    ///
+    /// Stack layout: `[self, class_name, args_list, kwargs_dict]`.
+    CallSuperMethodEx(u32),
+
+    /// `CallValueKw` with unpacked arguments.
+    ///
+    /// Stack layout: `[callable, args_list, kwargs_dict]`.
+    CallValueEx,
+
     // --- F-strings ---
-    /// Pop TOS, convert it to its print representation via `to_print_string()`,
-    /// push the resulting string.
-    FormatValue,
+    /// Format a value as an f-string field: apply the conversion, then the
+    /// format spec, and push the resulting string.
+    ///
+    /// Stack layout: `[value, spec]` if the bool is set, else `[value]`.
+    FormatValue(Conversion, bool),
 
     /// Pop N strings from the stack, concatenate them, push the result.
     BuildString(u32),
 
     // --- Function definitions ---
-    /// Register `functions[i]` as a user-defined callable and push `None`.
+    /// Push `functions[i]` as a function value, with `n_defaults` default
+    /// values for its positional parameters and `n_kw_defaults` for its
+    /// keyword-only parameters.
     ///
-    /// The function is stored in the VM's function table (keyed by name) and
-    /// can be called via `CallFunction`.
-    MakeFunction(u32),
+    /// Stack layout: `[defaults..., kw_name0, kw_default0, ...]`, the same
+    /// as the arguments of `CallFunctionKw`. Default values are evaluated
+    /// each time the `def` runs.
+    MakeFunction(u32, u32, u32),
+
+    /// Like `MakeFunction`, but the function is a closure over the current
+    /// frame's cells.
+    ///
+    /// For each name in the function's `freevars`, the cell of the same name
+    /// in the current frame is captured, so the closure sees later writes
+    /// made by the enclosing function (and vice versa).
+    MakeClosure(u32, u32, u32),
 
     /// Return from the current function.
     ///
@@ -273,13 +402,26 @@ pub enum Op {
     /// value onto the caller's stack. At the top level, this ends execution.
     ReturnValue,
 
+    /// Suspend the current generator frame, yielding TOS to its consumer.
+    ///
+    /// The frame is saved into the generator together with its operands.
+    /// When resumed, the value of the `yield` expression (always `None`) is
+    /// pushed onto the frame's stack.
+    YieldValue,
+
     // --- Exception handling ---
     /// Raise an exception.
     ///
-    /// Pop TOS (a string message), pop the exception-type name string below it,
-    /// and raise as an exception. The VM maps these to internal `Error` variants.
+    /// Pop TOS (an exception value, or a class to instantiate with no
+    /// arguments) and raise it.
     Raise,
 
+    /// Raise an exception with an explicit cause (`raise exc from cause`).
+    ///
+    /// Pop the cause (an exception or `None`), then the exception below it.
+    /// Sets `__cause__` and suppresses the implicit `__context__`.
+    RaiseFrom,
+
     /// Re-raise the current exception from inside an `except` handler.
     ///
     /// Used for bare `raise` statements. If there is no active exception on
@@ -288,10 +430,33 @@ pub enum Op {
 
     /// Check if the current exception matches a given type.
     ///
-    /// Pop the exception type name (a string) from TOS. Check it against the
-    /// active exception. Push `Bool(true)` if it matches, `Bool(false)` otherwise.
+    /// Pop the exception class (or a tuple of classes) from TOS. Check it
+    /// against the active exception, including base classes.
+    /// Push `Bool(true)` if it matches, `Bool(false)` otherwise.
     CheckExcMatch,
 
+    /// Push the active exception (for `except ... as name`).
+    LoadException,
+
+    // --- Classes ---
+    /// Create a class named `names[name_idx]` with `n_attrs` class attributes.
+    ///
+    /// Stack layout: `[base, name0, value0, ..., nameN, valueN]` where `base`
+    /// is a class or `None`. Pops all of them and pushes the new class.
+    MakeClass(u32, u32),
+
+    /// Set an attribute: `TOS.names[idx] = TOS1`.
+    ///
+    /// Pops the object, then the value.
+    StoreAttr(u32),
+
+    /// Call `super().names[method_idx](...)` with positional and keyword args.
+    ///
+    /// Stack layout: `[self, class_name, args..., kw_name0, kw_val0, ...]`.
+    /// The method is looked up in the base class of the class named
+    /// `class_name` in `self`'s class chain, and called bound to `self`.
+    CallSuperMethod(u32, u32, u32),
+
     /// Discard the current exception from the exception stack.
     ///
     /// Emitted at the end of each `except` handler body.
@@ -304,6 +469,33 @@ pub enum Op {
     /// Pop TOS, look up `names[idx]` in its attributes, push result.
     LoadAttr(u32),
 
+    // --- Pattern matching ---
+    /// Pop TOS, push whether it can match a sequence pattern (a list or
+    /// tuple, but not a string).
+    MatchSequence,
+
+    /// Pop TOS, push its length.
+    GetLen,
+
+    /// Match a mapping pattern's keys.
+    ///
+    /// Pops a tuple of keys and the subject. Pushes a tuple of the
+    /// corresponding values if the subject is a dict containing every key,
+    /// `None` otherwise.
+    MatchKeys,
+
+    /// Match a class pattern with `n_pos` positional sub-patterns.
+    ///
+    /// Pops a tuple of keyword attribute names, the class (or a built-in
+    /// type name) and the subject. Pushes a tuple of the positional then
+    /// keyword attribute values if the subject is an instance with those
+    /// attributes, `None` otherwise.
+    MatchClass(u32),
+
+    /// Pop a tuple of keys and a dict, push a copy of the dict without
+    /// those keys (for `**rest` in mapping patterns).
+    CopyDictWithoutKeys,
+
     // --- Misc ---
     /// No operation. Used as a placeholder or for `pass` statements.
     Nop,
@@ -315,8 +507,8 @@ pub enum Op {
 
 /// An entry in the exception table mapping an instruction range to a handler.
 ///
-/// When an error occurs at an instruction in `[start, end)`, the VM jumps to
-/// `handler` and optionally binds the exception to `names[var_name]`.
+/// When an error occurs at an instruction in `[start, end)`, the VM pushes
+/// the exception onto its exception stack and jumps to `handler`.
 #[derive(Debug, Clone)]
 pub struct ExceptionEntry {
     /// First instruction index covered (inclusive).
@@ -325,8 +517,13 @@ pub struct ExceptionEntry {
     pub end: u32,
     /// Instruction index of the handler to jump to.
     pub handler: u32,
-    /// If `Some`, the exception message is stored in `names[i]`.
-    pub var_name: Option<u32>,
+    /// Number of `for`-loop iterators active in the frame at `start`.
+    /// Iterators of loops exited by the exception are dropped.
+    pub iter_depth: u32,
+    /// Number of `except` handlers active in the frame at `start`.
+    /// Exceptions being handled by handlers exited by the exception are
+    /// discarded.
+    pub exc_depth: u32,
 }
 
 /// A compiled unit of code — either a top-level script or a function body.
@@ -360,8 +557,8 @@ pub struct CodeObject {
     /// Exception table for try/except handling.
     ///
     /// Each entry maps an instruction range to a handler location. When an
-    /// error occurs, the VM searches this table (last entry first) for a
-    /// matching range and jumps to the handler.
+    /// error occurs, the VM searches this table for the innermost (smallest)
+    /// range covering the faulting instruction and jumps to its handler.
     pub exception_table: Vec<ExceptionEntry>,
 }
 
@@ -382,23 +579,40 @@ impl CodeObject {
 
 /// A compiled function definition, stored inside a parent [`CodeObject`].
 ///
-/// When the VM encounters `MakeFunction(i)`, it registers `functions[i]` as
-/// a callable. When the function is later called, the VM creates a new call
-/// frame using `code` and binds arguments to `params`.
+/// When the VM encounters `MakeFunction(i, ..)`, it pushes `functions[i]`
+/// as a callable. When the function is later called, the VM creates a new
+/// call frame using `code` and binds arguments to the parameters.
 #[derive(Debug, Clone)]
 pub struct FunctionDef {
     /// The function name (used for error messages and as the key in the function table).
     pub name: String,
 
-    /// Parameter names, in order. Used to bind positional arguments to local variables.
+    /// The docstring: a string literal at the start of the body, exposed
+    /// as `__doc__`.
+    pub doc: Option<String>,
+
+    /// Positional parameter names, in order. Used to bind positional
+    /// arguments to local variables.
     pub params: Vec<String>,
 
+    /// How many of the leading `params` are positional-only (declared
+    /// before `/`).
+    pub posonly: usize,
+
     /// Default values for the last N parameters (same convention as CPython).
     ///
     /// `defaults[0]` corresponds to `params[params.len() - defaults.len()]`.
-    /// Parameters without defaults must be provided by the caller.
+    /// Parameters without defaults must be provided by the caller. Empty
+    /// in the compiled code; filled in by `MakeFunction`/`MakeClosure`.
     pub defaults: Vec<PyValue>,
 
+    /// Keyword-only parameter names (declared after `*` or `*args`).
+    pub kwonly: Vec<String>,
+
+    /// Default values of keyword-only parameters, by name. Filled in like
+    /// `defaults`.
+    pub kw_defaults: Vec<(String, PyValue)>,
+
     /// If present, the name of the `*args` parameter that collects excess
     /// positional arguments into a list.
     pub vararg: Option<String>,
@@ -407,6 +621,25 @@ pub struct FunctionDef {
     /// keyword arguments into a dict.
     pub kwarg: Option<String>,
 
+    /// Local variables that nested functions capture.
+    ///
+    /// These live in cells instead of the frame's plain locals and are
+    /// accessed with `LoadDeref`/`StoreDeref`.
+    pub cellvars: Vec<String>,
+
+    /// Variables captured from enclosing function scopes.
+    pub freevars: Vec<String>,
+
+    /// The captured cells, parallel to `freevars`.
+    ///
+    /// Empty in the compiled code; filled in by `MakeClosure` when the
+    /// function value is created at runtime.
+    pub closure: Vec<Cell>,
+
     /// The compiled function body.
     pub code: CodeObject,
+
+    /// Whether the body contains `yield`: calling the function creates a
+    /// generator instead of running the body.
+    pub is_generator: bool,
 }
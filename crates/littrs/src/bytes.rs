@@ -0,0 +1,363 @@
+//! Byte strings: codecs, hex conversion and searching.
+//!
+//! `bytes` values are [`PyValue::Bytes`] and `bytearray` values are
+//! [`PyValue::ByteArray`]. Both are sequences of ints in `range(0, 256)`;
+//! the helpers here are shared by their constructors, their methods,
+//! `str.encode` and binary-mode files.
+
+use crate::error::{Error, Result};
+use crate::text;
+use crate::value::{PyValue, check_sequence_len};
+
+/// The codecs the sandbox supports.
+#[derive(Clone, Copy, PartialEq)]
+enum Codec {
+    Utf8,
+    Ascii,
+    Latin1,
+}
+
+impl Codec {
+    /// Look up a codec by any of Python's names for it.
+    fn lookup(encoding: &str) -> Result<Self> {
+        match encoding.to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" | "u8" => Ok(Codec::Utf8),
+            "ascii" | "us-ascii" => Ok(Codec::Ascii),
+            "latin-1" | "latin1" | "iso-8859-1" | "iso8859-1" | "l1" => Ok(Codec::Latin1),
+            _ => Err(Error::exception(
+                "LookupError",
+                format!("unknown encoding: {}", encoding),
+            )),
+        }
+    }
+
+    /// The name Python uses in codec error messages.
+    fn name(self) -> &'static str {
+        match self {
+            Codec::Utf8 => "utf-8",
+            Codec::Ascii => "ascii",
+            Codec::Latin1 => "latin-1",
+        }
+    }
+}
+
+/// How a codec handles data it cannot convert.
+#[derive(Clone, Copy, PartialEq)]
+enum ErrorHandler {
+    Strict,
+    Ignore,
+    Replace,
+}
+
+impl ErrorHandler {
+    fn lookup(errors: &str) -> Result<Self> {
+        match errors {
+            "strict" => Ok(ErrorHandler::Strict),
+            "ignore" => Ok(ErrorHandler::Ignore),
+            "replace" => Ok(ErrorHandler::Replace),
+            _ => Err(Error::exception(
+                "LookupError",
+                format!("unknown error handler name '{}'", errors),
+            )),
+        }
+    }
+}
+
+/// Encode `text` as `str.encode(encoding, errors)` does.
+pub(crate) fn encode(text: &str, encoding: &str, errors: &str) -> Result<Vec<u8>> {
+    let codec = Codec::lookup(encoding)?;
+    let handler = ErrorHandler::lookup(errors)?;
+    let limit = match codec {
+        Codec::Utf8 => return Ok(text.as_bytes().to_vec()),
+        Codec::Ascii => 0x80,
+        Codec::Latin1 => 0x100,
+    };
+    let mut out = Vec::with_capacity(text.len());
+    for (position, c) in text.chars().enumerate() {
+        if (c as u32) < limit {
+            out.push(c as u32 as u8);
+            continue;
+        }
+        match handler {
+            ErrorHandler::Strict => {
+                return Err(Error::exception(
+                    "UnicodeEncodeError",
+                    format!(
+                        "'{}' codec can't encode character '{}' in position {}: ordinal not in range({})",
+                        codec.name(),
+                        text::escape_char(c),
+                        position,
+                        limit
+                    ),
+                ));
+            }
+            ErrorHandler::Ignore => {}
+            ErrorHandler::Replace => out.push(b'?'),
+        }
+    }
+    Ok(out)
+}
+
+/// Decode `data` as `bytes.decode(encoding, errors)` does.
+pub(crate) fn decode(data: &[u8], encoding: &str, errors: &str) -> Result<String> {
+    let codec = Codec::lookup(encoding)?;
+    let handler = ErrorHandler::lookup(errors)?;
+    match codec {
+        Codec::Utf8 => {
+            if handler == ErrorHandler::Strict {
+                return decode_utf8(data);
+            }
+            let mut out = String::with_capacity(data.len());
+            for chunk in data.utf8_chunks() {
+                out.push_str(chunk.valid());
+                if handler == ErrorHandler::Replace && !chunk.invalid().is_empty() {
+                    out.push(char::REPLACEMENT_CHARACTER);
+                }
+            }
+            Ok(out)
+        }
+        Codec::Latin1 => Ok(data.iter().map(|&byte| byte as char).collect()),
+        Codec::Ascii => {
+            let mut out = String::with_capacity(data.len());
+            for (position, &byte) in data.iter().enumerate() {
+                if byte.is_ascii() {
+                    out.push(byte as char);
+                    continue;
+                }
+                match handler {
+                    ErrorHandler::Strict => {
+                        return Err(Error::exception(
+                            "UnicodeDecodeError",
+                            format!(
+                                "'ascii' codec can't decode byte 0x{:02x} in position {}: ordinal not in range(128)",
+                                byte, position
+                            ),
+                        ));
+                    }
+                    ErrorHandler::Ignore => {}
+                    ErrorHandler::Replace => out.push(char::REPLACEMENT_CHARACTER),
+                }
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// Decode UTF-8 strictly, reporting the first invalid byte as CPython does.
+pub(crate) fn decode_utf8(data: &[u8]) -> Result<String> {
+    match std::str::from_utf8(data) {
+        Ok(text) => Ok(text.to_string()),
+        Err(err) => {
+            let start = err.valid_up_to();
+            let byte = data[start];
+            let message = match err.error_len() {
+                // The data ends in the middle of a character
+                None if data.len() - start > 1 => format!(
+                    "can't decode bytes in position {}-{}: unexpected end of data",
+                    start,
+                    data.len() - 1
+                ),
+                None => format!(
+                    "can't decode byte 0x{:02x} in position {}: unexpected end of data",
+                    byte, start
+                ),
+                Some(_) => {
+                    let reason = if matches!(byte, 0x80..=0xc1 | 0xf5..) {
+                        "invalid start byte"
+                    } else {
+                        "invalid continuation byte"
+                    };
+                    format!(
+                        "can't decode byte 0x{:02x} in position {}: {}",
+                        byte, start, reason
+                    )
+                }
+            };
+            Err(Error::exception(
+                "UnicodeDecodeError",
+                format!("'utf-8' codec {}", message),
+            ))
+        }
+    }
+}
+
+/// The lowercase hex digits of `data`, as `bytes.hex()` returns them.
+pub(crate) fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parse `bytes.fromhex(text)`: pairs of hex digits, optionally separated
+/// by whitespace.
+pub(crate) fn from_hex(text: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 2);
+    let mut chars = text.char_indices();
+    while let Some((position, c)) = chars.next() {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        let high = c.to_digit(16);
+        let low = chars.next().and_then(|(_, c)| c.to_digit(16));
+        match (high, low) {
+            (Some(high), Some(low)) => out.push((high * 16 + low) as u8),
+            _ => {
+                return Err(Error::exception(
+                    "ValueError",
+                    format!(
+                        "non-hexadecimal number found in fromhex() arg at position {}",
+                        if high.is_some() {
+                            position + 1
+                        } else {
+                            position
+                        }
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// The position of the first occurrence of `needle` in `haystack`.
+pub(crate) fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// The position of the last occurrence of `needle` in `haystack`.
+pub(crate) fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(haystack.len());
+    }
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+/// The number of non-overlapping occurrences of `needle` in `haystack`.
+pub(crate) fn count(haystack: &[u8], needle: &[u8]) -> usize {
+    if needle.is_empty() {
+        return haystack.len() + 1;
+    }
+    let mut count = 0;
+    let mut rest = haystack;
+    while let Some(position) = find(rest, needle) {
+        count += 1;
+        rest = &rest[position + needle.len()..];
+    }
+    count
+}
+
+/// Split `data` on `sep`, or on runs of ASCII whitespace when `sep` is
+/// `None`, as `bytes.split()` does.
+pub(crate) fn split(data: &[u8], sep: Option<&[u8]>) -> Result<Vec<Vec<u8>>> {
+    let Some(sep) = sep else {
+        return Ok(data
+            .split(|byte| byte.is_ascii_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect());
+    };
+    if sep.is_empty() {
+        return Err(Error::exception("ValueError", "empty separator"));
+    }
+    let mut parts = Vec::new();
+    let mut rest = data;
+    while let Some(position) = find(rest, sep) {
+        parts.push(rest[..position].to_vec());
+        rest = &rest[position + sep.len()..];
+    }
+    parts.push(rest.to_vec());
+    Ok(parts)
+}
+
+/// Replace every occurrence of `old` in `data` by `new`.
+pub(crate) fn replace(data: &[u8], old: &[u8], new: &[u8]) -> Vec<u8> {
+    if old.is_empty() {
+        // Like `str.replace`, insert `new` around every byte
+        let mut out = new.to_vec();
+        for &byte in data {
+            out.push(byte);
+            out.extend_from_slice(new);
+        }
+        return out;
+    }
+    let mut out = Vec::with_capacity(data.len());
+    let mut rest = data;
+    while let Some(position) = find(rest, old) {
+        out.extend_from_slice(&rest[..position]);
+        out.extend_from_slice(new);
+        rest = &rest[position + old.len()..];
+    }
+    out.extend_from_slice(rest);
+    out
+}
+
+/// An int that must fit in a byte, as stored in bytes or a bytearray.
+pub(crate) fn byte_value(value: &PyValue) -> Result<u8> {
+    let n = match value {
+        PyValue::Int(_) | PyValue::Bool(_) => value.as_int().unwrap(),
+        PyValue::BigInt(_) => -1,
+        other => {
+            return Err(Error::exception(
+                "TypeError",
+                format!(
+                    "'{}' object cannot be interpreted as an integer",
+                    other.type_name()
+                ),
+            ));
+        }
+    };
+    u8::try_from(n).map_err(|_| Error::exception("ValueError", "byte must be in range(0, 256)"))
+}
+
+/// The bytes of a bytes-like argument, or a `TypeError`.
+pub(crate) fn arg_bytes(value: &PyValue) -> Result<Vec<u8>> {
+    value
+        .as_bytes()
+        .map(|data| data.into_owned())
+        .ok_or_else(|| {
+            Error::exception(
+                "TypeError",
+                format!(
+                    "a bytes-like object is required, not '{}'",
+                    value.type_name()
+                ),
+            )
+        })
+}
+
+/// The contents of a new `bytes(source)` or `bytearray(source)`: a
+/// bytes-like value, a count of zero bytes, or a sequence of ints.
+/// `items` lists the items of any other iterable.
+pub(crate) fn from_source(
+    func: &str,
+    source: &PyValue,
+    items: impl FnOnce(&PyValue) -> Result<Vec<PyValue>>,
+) -> Result<Vec<u8>> {
+    if let Some(data) = source.as_bytes() {
+        return Ok(data.into_owned());
+    }
+    match source {
+        PyValue::Int(_) | PyValue::Bool(_) => match source.as_int().unwrap() {
+            n if n < 0 => Err(Error::exception("ValueError", "negative count")),
+            n => Ok(vec![0; check_sequence_len(Some(n as usize))?]),
+        },
+        PyValue::BigInt(_) => Err(Error::exception(
+            "OverflowError",
+            "cannot fit 'int' into an index-sized integer",
+        )),
+        PyValue::Str(_) => Err(Error::exception(
+            "TypeError",
+            "string argument without an encoding",
+        )),
+        PyValue::Float(_) | PyValue::None => Err(Error::exception(
+            "TypeError",
+            format!("cannot convert '{}' object to {}", source.type_name(), func),
+        )),
+        _ => items(source)?.iter().map(byte_value).collect(),
+    }
+}
@@ -0,0 +1,512 @@
+//! Classes and instances.
+//!
+//! Classes are created by `class` statements and by looking up the name of a
+//! built-in exception type (so `class RetryableError(Exception)` has a base to
+//! derive from). The object model is deliberately small: single inheritance,
+//! no metaclasses, and attributes stored in a flat [`Namespace`].
+//!
+//! Calling an exception class builds an [`ExceptionValue`] carrying the
+//! class's method resolution order, which is what `except` clauses match
+//! against. Calling any other class builds an [`InstanceValue`].
+//!
+//! Record classes (`@dataclass` and `typing.NamedTuple` subclasses) list
+//! their fields from the class body's annotations and get a generated
+//! `__init__`, `__eq__` and `__repr__`; dataclasses can also be ordered
+//! and frozen. They are converted to dicts when returned to the host.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::bytecode::FunctionDef;
+use crate::error::{Error, Result};
+use crate::exception::{ExceptionValue, builtin_mro};
+use crate::value::PyValue;
+
+/// A shared, mutable attribute table.
+///
+/// Clones share the same table, so an instance passed to a method (or
+/// stored in two variables) sees every attribute write. Equality is
+/// identity.
+#[derive(Clone, Default)]
+pub struct Namespace(Arc<Mutex<Vec<(String, PyValue)>>>);
+
+impl Namespace {
+    /// Create a namespace holding the given attributes.
+    pub fn new(attrs: Vec<(String, PyValue)>) -> Self {
+        Self(Arc::new(Mutex::new(attrs)))
+    }
+
+    /// Look up an attribute.
+    pub fn get(&self, name: &str) -> Option<PyValue> {
+        let attrs = self.0.lock().unwrap();
+        attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    /// Set an attribute, keeping its position if it already exists.
+    pub fn set(&self, name: &str, value: PyValue) {
+        let mut attrs = self.0.lock().unwrap();
+        match attrs.iter_mut().find(|(k, _)| k == name) {
+            Some((_, slot)) => *slot = value,
+            None => attrs.push((name.to_string(), value)),
+        }
+    }
+
+    /// Run `f` with mutable access to an attribute, if it exists.
+    pub fn with_attr_mut<R>(&self, name: &str, f: impl FnOnce(&mut PyValue) -> R) -> Option<R> {
+        let mut attrs = self.0.lock().unwrap();
+        attrs.iter_mut().find(|(k, _)| k == name).map(|(_, v)| f(v))
+    }
+
+    /// A snapshot of all attributes in definition order.
+    pub fn entries(&self) -> Vec<(String, PyValue)> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// An identity for hashing: the address of the shared table.
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl PartialEq for Namespace {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Objects can reference themselves, so only list attribute names.
+        let attrs = self.0.lock().unwrap();
+        f.debug_list()
+            .entries(attrs.iter().map(|(k, _)| k))
+            .finish()
+    }
+}
+
+/// The options of `@dataclass(...)` that change how instances behave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataclassOptions {
+    /// Instances compare equal when their fields are equal.
+    pub eq: bool,
+    /// Instances order like tuples of their fields.
+    pub order: bool,
+    /// Fields cannot be assigned after `__init__`, and instances with `eq`
+    /// are hashable.
+    pub frozen: bool,
+}
+
+impl Default for DataclassOptions {
+    fn default() -> Self {
+        Self {
+            eq: true,
+            order: false,
+            frozen: false,
+        }
+    }
+}
+
+/// The kind of a record class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    /// A class decorated with `@dataclass`.
+    Dataclass(DataclassOptions),
+    /// A `typing.NamedTuple` subclass: immutable and usable as a tuple.
+    NamedTuple,
+}
+
+/// A class object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassValue {
+    /// The class name (`__name__`).
+    pub name: String,
+    /// Class names from this class up through its bases.
+    pub mro: Vec<String>,
+    /// The base class, if any.
+    pub base: Option<Box<ClassValue>>,
+    /// Methods, properties and class variables defined in the class body.
+    pub attrs: Namespace,
+    /// Set for dataclasses and named tuples.
+    pub record: Option<RecordKind>,
+    /// Record field names in definition order (empty for other classes).
+    pub fields: Vec<String>,
+}
+
+impl ClassValue {
+    /// The class object for a built-in exception type, if `name` is one.
+    pub fn builtin_exception(name: &str) -> Option<Self> {
+        builtin_mro(name).map(|mro| Self {
+            name: name.to_string(),
+            mro,
+            base: None,
+            attrs: Namespace::default(),
+            record: None,
+            fields: Vec::new(),
+        })
+    }
+
+    /// The class of an exception instance (`type(e)`).
+    pub fn of_exception(exc: &ExceptionValue) -> Self {
+        Self {
+            name: exc.type_name().to_string(),
+            mro: exc.mro.clone(),
+            base: None,
+            attrs: Namespace::default(),
+            record: None,
+            fields: Vec::new(),
+        }
+    }
+
+    /// The `typing.NamedTuple` base class.
+    pub fn named_tuple() -> Self {
+        Self {
+            name: "NamedTuple".to_string(),
+            mro: vec!["NamedTuple".to_string()],
+            base: None,
+            attrs: Namespace::default(),
+            record: Some(RecordKind::NamedTuple),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Create a class from a `class` statement.
+    ///
+    /// Exception classes (those deriving from a built-in exception type)
+    /// cannot define attributes or methods.
+    pub(crate) fn new(
+        name: &str,
+        base: Option<&PyValue>,
+        attrs: Vec<(String, PyValue)>,
+    ) -> Result<Self> {
+        let base = match base {
+            None => None,
+            Some(PyValue::Class(base)) => Some(base.clone()),
+            Some(other) => {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!(
+                        "class '{}' cannot derive from '{}'",
+                        name,
+                        other.type_name()
+                    ),
+                ));
+            }
+        };
+        if base.as_ref().is_some_and(|b| b.is_exception()) && !attrs.is_empty() {
+            return Err(Error::Unsupported(format!(
+                "exception class '{}' cannot define attributes or methods",
+                name
+            )));
+        }
+
+        let mut class = Self {
+            name: name.to_string(),
+            mro: vec![name.to_string()],
+            base: None,
+            attrs: Namespace::new(attrs),
+            record: None,
+            fields: Vec::new(),
+        };
+        match base {
+            // `class P(NamedTuple)` defines a new named tuple type
+            Some(base) if base.is_named_tuple_base() => {
+                class.mro.push("tuple".to_string());
+                class.record = Some(RecordKind::NamedTuple);
+                class.fields = class.annotations();
+            }
+            Some(base) => {
+                class.mro.extend(base.mro.iter().cloned());
+                class.record = base.record;
+                class.fields = base.fields.clone();
+                class.base = Some(base);
+            }
+            None => {}
+        }
+        Ok(class)
+    }
+
+    /// Whether this is `typing.NamedTuple` itself.
+    fn is_named_tuple_base(&self) -> bool {
+        self.record == Some(RecordKind::NamedTuple) && self.mro == ["NamedTuple"]
+    }
+
+    /// Turn this class into a dataclass (the `@dataclass` decorator).
+    ///
+    /// Fields are inherited from dataclass bases, followed by the
+    /// annotated names in this class body. A field's default is the class
+    /// attribute of the same name, and as in the generated `__init__`,
+    /// fields without one cannot follow fields with one.
+    pub(crate) fn into_dataclass(mut self, options: DataclassOptions) -> Result<Self> {
+        if self.dataclass_options().is_none() {
+            self.fields.clear();
+        }
+        for field in self.annotations() {
+            if !self.fields.contains(&field) {
+                self.fields.push(field);
+            }
+        }
+        let mut has_default = false;
+        for field in &self.fields {
+            if self.lookup(field).is_some() {
+                has_default = true;
+            } else if has_default {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!("non-default argument '{}' follows default argument", field),
+                ));
+            }
+        }
+        self.record = Some(RecordKind::Dataclass(options));
+        Ok(self)
+    }
+
+    /// The options of a dataclass.
+    pub fn dataclass_options(&self) -> Option<DataclassOptions> {
+        match self.record {
+            Some(RecordKind::Dataclass(options)) => Some(options),
+            _ => None,
+        }
+    }
+
+    /// Whether instances compare equal by their field values: named tuples
+    /// and dataclasses with `eq`.
+    pub fn eq_by_fields(&self) -> bool {
+        match self.record {
+            Some(RecordKind::Dataclass(options)) => options.eq,
+            Some(RecordKind::NamedTuple) => true,
+            None => false,
+        }
+    }
+
+    /// A special method such as `__eq__` defined in this class or a base
+    /// class, if any.
+    pub(crate) fn special_method(&self, name: &str) -> Option<Box<FunctionDef>> {
+        match self.lookup(name) {
+            Some(PyValue::Function(func)) => Some(func),
+            _ => None,
+        }
+    }
+
+    /// The names annotated in this class body, in order.
+    fn annotations(&self) -> Vec<String> {
+        match self.attrs.get("__annotations__") {
+            Some(PyValue::Dict(dict)) => dict
+                .read()
+                .iter()
+                .filter_map(|(k, _)| k.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether this class derives from `BaseException`.
+    pub fn is_exception(&self) -> bool {
+        self.mro.iter().any(|c| c == "BaseException")
+    }
+
+    /// Look up a class attribute, searching base classes.
+    pub fn lookup(&self, name: &str) -> Option<PyValue> {
+        self.attrs
+            .get(name)
+            .or_else(|| self.base.as_ref().and_then(|base| base.lookup(name)))
+    }
+
+    /// Build an exception instance from positional arguments.
+    pub(crate) fn new_exception(&self, args: Vec<PyValue>) -> ExceptionValue {
+        ExceptionValue {
+            mro: self.mro.clone(),
+            args,
+            cause: None,
+            context: None,
+            suppress_context: false,
+        }
+    }
+}
+
+impl fmt::Display for ClassValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<class '{}'>", self.name)
+    }
+}
+
+/// An instance of a (non-exception) class.
+///
+/// Clones refer to the same object: attribute writes through one are seen
+/// by all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceValue {
+    /// The instance's class.
+    pub class: ClassValue,
+    /// Instance attributes (`self.x = ...`).
+    pub attrs: Namespace,
+}
+
+impl InstanceValue {
+    /// Create an instance with no attributes.
+    pub fn new(class: ClassValue) -> Self {
+        Self {
+            class,
+            attrs: Namespace::default(),
+        }
+    }
+
+    /// Whether this object is an instance of `class_name` or a subclass.
+    pub fn is_instance(&self, class_name: &str) -> bool {
+        self.class.mro.iter().any(|c| c == class_name)
+    }
+
+    /// The field values of a record, in field order.
+    pub fn field_values(&self) -> Option<Vec<PyValue>> {
+        self.class.record?;
+        Some(
+            self.class
+                .fields
+                .iter()
+                .map(|field| self.attrs.get(field).unwrap_or(PyValue::None))
+                .collect(),
+        )
+    }
+
+    /// The items of a named tuple, which behaves as a tuple.
+    pub fn tuple_items(&self) -> Option<Vec<PyValue>> {
+        match self.class.record {
+            Some(RecordKind::NamedTuple) => self.field_values(),
+            _ => None,
+        }
+    }
+
+    /// Set a record's fields from constructor arguments (the generated
+    /// `__init__`). Missing fields take the class attribute of the same
+    /// name as their default.
+    pub(crate) fn init_fields(
+        &self,
+        args: Vec<PyValue>,
+        kw_pairs: Vec<(String, PyValue)>,
+    ) -> Result<()> {
+        let class = &self.class;
+        if args.len() > class.fields.len() {
+            return Err(Error::exception(
+                "TypeError",
+                format!(
+                    "{}() takes {} positional arguments but {} were given",
+                    class.name,
+                    class.fields.len(),
+                    args.len()
+                ),
+            ));
+        }
+
+        let mut values: Vec<Option<PyValue>> = vec![None; class.fields.len()];
+        for (slot, arg) in values.iter_mut().zip(args) {
+            *slot = Some(arg);
+        }
+        for (key, value) in kw_pairs {
+            let Some(pos) = class.fields.iter().position(|f| *f == key) else {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!(
+                        "{}() got an unexpected keyword argument '{}'",
+                        class.name, key
+                    ),
+                ));
+            };
+            if values[pos].is_some() {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!(
+                        "{}() got multiple values for argument '{}'",
+                        class.name, key
+                    ),
+                ));
+            }
+            values[pos] = Some(value);
+        }
+
+        let mut missing = Vec::new();
+        for (field, value) in class.fields.iter().zip(values) {
+            match value.or_else(|| class.lookup(field)) {
+                Some(value) => self.attrs.set(field, value),
+                None => missing.push(format!("'{}'", field)),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(Error::exception(
+                "TypeError",
+                format!(
+                    "{}() missing {} required argument{}: {}",
+                    class.name,
+                    missing.len(),
+                    if missing.len() == 1 { "" } else { "s" },
+                    missing.join(", ")
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// A copy of a record with some fields replaced (`_replace`).
+    pub(crate) fn replace(&self, kw_pairs: Vec<(String, PyValue)>) -> Result<Self> {
+        let copy = Self {
+            class: self.class.clone(),
+            attrs: Namespace::new(self.attrs.entries()),
+        };
+        for (key, value) in kw_pairs {
+            if !self.class.fields.contains(&key) {
+                return Err(Error::exception(
+                    "ValueError",
+                    format!("got unexpected field name: '{}'", key),
+                ));
+            }
+            copy.attrs.set(&key, value);
+        }
+        Ok(copy)
+    }
+
+    /// A record's fields as a dict of field name to value.
+    pub fn to_dict(&self) -> Option<PyValue> {
+        let values = self.field_values()?;
+        Some(PyValue::dict(
+            self.class
+                .fields
+                .iter()
+                .map(|field| PyValue::Str(field.clone()))
+                .zip(values),
+        ))
+    }
+}
+
+impl fmt::Display for InstanceValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field_values() {
+            Some(values) => {
+                write!(f, "{}(", self.class.name)?;
+                for (i, (field, value)) in self.class.fields.iter().zip(values).enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}={}", field, value)?;
+                }
+                write!(f, ")")
+            }
+            None => write!(f, "<{} object>", self.class.name),
+        }
+    }
+}
+
+/// Replace records with dicts of their fields, and dict views with lists
+/// of their items, recursively.
+///
+/// Used for values handed to the host, which has no notion of classes. The
+/// result is a deep copy, so the host never shares a container with the
+/// sandbox.
+pub(crate) fn records_to_dicts(value: PyValue) -> PyValue {
+    value.deep_copy_with(&mut HashMap::new(), &|value| match value {
+        PyValue::Instance(instance) => instance.to_dict(),
+        PyValue::DictView(view) => Some(PyValue::list(view.items())),
+        _ => None,
+    })
+}
@@ -5,25 +5,69 @@
 //! span information is captured during compilation so the VM can produce error
 //! messages with accurate locations without ever touching the AST.
 
-use ruff_python_ast::{self as ast, BoolOp, Expr, Stmt, UnaryOp};
+use std::collections::HashSet;
+
+use ruff_python_ast::visitor::{self, Visitor};
+use ruff_python_ast::{self as ast, BoolOp, Expr, ExprContext, Stmt, UnaryOp};
 use ruff_python_parser::parse_module;
 use ruff_text_size::Ranged;
 
-use crate::bytecode::{self, BinOp, CodeObject, FunctionDef, Op};
+use crate::bytecode::{self, BinOp, CodeObject, Conversion, ExceptionEntry, FunctionDef, Op};
 use crate::diagnostic::Span;
 use crate::error::{Error, Result};
+use crate::int;
 use crate::value::PyValue;
 
-/// The set of method names that mutate a list in place.
-const LIST_MUTATING_METHODS: &[&str] = &[
-    "append", "extend", "pop", "clear", "insert", "remove", "reverse", "sort",
+/// Parameter of a generator expression's function that receives the
+/// outermost iterable.
+const GENEXPR_ITER: &str = ".0";
+
+/// The special methods the VM calls. A class defining any other is
+/// rejected rather than silently ignoring it.
+const SPECIAL_METHODS: &[&str] = &[
+    "__init__",
+    "__post_init__",
+    "__repr__",
+    "__str__",
+    "__eq__",
+    "__ne__",
+    "__enter__",
+    "__exit__",
 ];
 
-/// The set of method names that mutate a dict in place.
-const DICT_MUTATING_METHODS: &[&str] = &["update", "setdefault", "pop", "clear"];
+/// Variable resolution info for a single code object.
+///
+/// Names declared `global` are accessed with `LoadGlobal`/`StoreGlobal`.
+/// Names listed in `cellvars` (captured by a nested function) or `freevars`
+/// (captured from an enclosing function, including `nonlocal` names) live in
+/// shared cells and are accessed with `LoadDeref`/`StoreDeref`. Other names
+/// a function binds are loaded with `LoadFast`. Everything else is resolved
+/// by `LoadName`/`StoreName`.
+#[derive(Default)]
+struct Scope {
+    /// Whether this is a function scope (as opposed to the module scope).
+    is_function: bool,
+    /// Names bound in this scope (parameters, assignment targets, defs, imports).
+    bound: HashSet<String>,
+    /// Local names captured by nested functions.
+    cellvars: Vec<String>,
+    /// Names captured from enclosing function scopes.
+    freevars: Vec<String>,
+    /// Names bound in any enclosing function scope.
+    enclosing: HashSet<String>,
+    /// Names declared `global` in this scope.
+    globals: HashSet<String>,
+    /// For a method body: the class name and the name of the first
+    /// parameter, used by zero-argument `super()`.
+    method: Option<(String, String)>,
+}
 
-/// The set of method names that mutate a set in place.
-const SET_MUTATING_METHODS: &[&str] = &["add", "discard", "remove", "clear", "update", "pop"];
+impl Scope {
+    /// Whether `name` lives in a cell rather than a plain local/global slot.
+    fn is_deref(&self, name: &str) -> bool {
+        self.cellvars.iter().any(|n| n == name) || self.freevars.iter().any(|n| n == name)
+    }
+}
 
 /// Compiler state for tracking loops (used for break/continue resolution).
 struct LoopContext {
@@ -33,6 +77,48 @@ struct LoopContext {
     break_placeholders: Vec<usize>,
     /// Whether this is a `for` loop (needs `PopIter` before break).
     is_for_loop: bool,
+    /// Length of the unwind stack when the loop started; `break` and
+    /// `continue` unwind every block above it.
+    unwind_depth: usize,
+}
+
+/// What the innermost loop of a comprehension does with each element.
+#[derive(Clone, Copy)]
+enum CompElement<'a> {
+    /// Add it to the result variable `names[idx]` with the given method
+    /// (`append` or `add`).
+    Collect(u32, &'a str),
+    /// Yield it, in the body of a generator expression. The outermost
+    /// iterable is then the function's `.0` parameter.
+    Yield,
+}
+
+/// What has to happen when control leaves a block early.
+enum UnwindKind {
+    /// A `try` body guarded only by `except` handlers: nothing to run.
+    Try,
+    /// A region guarded by `finally`: the finally body is emitted again at
+    /// every early exit.
+    Finally(Vec<Stmt>),
+    /// The body of a `with` statement: `__exit__()` is called on the context
+    /// manager stored in `names[i]`.
+    With(u32),
+    /// An `except` handler (or the exception path of a `finally`): the
+    /// active exception is popped.
+    Handler,
+}
+
+/// A block on the compiler's unwind stack.
+///
+/// `break`, `continue` and `return` never leave a protected region by
+/// jumping past its cleanup code: the compiler emits the cleanup for every
+/// block being exited, innermost first, before the jump.
+struct UnwindBlock {
+    kind: UnwindKind,
+    /// Instruction ranges of cleanup code emitted for early exits out of
+    /// this block. That code runs *outside* the block, so these ranges are
+    /// cut out of the block's exception table coverage.
+    holes: Vec<(u32, u32)>,
 }
 
 /// Compiles Python source code into bytecode.
@@ -50,6 +136,12 @@ pub struct Compiler {
     comp_counter: usize,
     /// Counter for generating unique `with` statement temp variable names.
     with_counter: usize,
+    /// Counter for generating unique `match` statement temp variable names.
+    match_counter: usize,
+    /// Variable resolution info for the code object being compiled.
+    scope: Scope,
+    /// Blocks that need cleanup on `break`/`continue`/`return`.
+    unwind_stack: Vec<UnwindBlock>,
 }
 
 impl Compiler {
@@ -61,11 +153,20 @@ impl Compiler {
         let parsed = parse_module(source).map_err(|e| Error::Parse(e.to_string()))?;
         let module = parsed.into_syntax();
 
+        // The scope pass reports misused names anywhere in the module
+        let symbols = analyze_function(None, |collector| collector.visit_body(&module.body));
+        if let Some(message) = symbols.error {
+            return Err(Error::Parse(message));
+        }
+
         let mut compiler = Compiler {
             code: CodeObject::new(source.to_string()),
             loop_stack: Vec::new(),
             comp_counter: 0,
             with_counter: 0,
+            match_counter: 0,
+            scope: Scope::default(),
+            unwind_stack: Vec::new(),
         };
 
         let body_len = module.body.len();
@@ -116,9 +217,14 @@ impl Compiler {
     /// Add a constant to the constant pool and return its index.
     /// Deduplicates identical constants.
     fn add_const(&mut self, value: PyValue) -> u32 {
-        // Check for an existing identical constant
+        // Check for an existing identical constant. Equal numbers of
+        // different types (`1`, `1.0`, `True`) and `0.0`/`-0.0` are distinct.
         for (i, existing) in self.code.constants.iter().enumerate() {
-            if *existing == value {
+            let identical = match (existing, &value) {
+                (PyValue::Float(a), PyValue::Float(b)) => a.to_bits() == b.to_bits(),
+                _ => existing.type_name() == value.type_name() && *existing == value,
+            };
+            if identical {
                 return i as u32;
             }
         }
@@ -138,6 +244,124 @@ impl Compiler {
         idx
     }
 
+    /// Emit a load of variable `name`, using `LoadGlobal` for names declared
+    /// `global`, `LoadDeref` for cell and free variables, `LoadFast` for
+    /// other names a function binds and `LoadName` otherwise.
+    fn emit_load_name(&mut self, name: &str, span: Span) {
+        let idx = self.add_name(name);
+        if self.scope.globals.contains(name) {
+            self.emit(Op::LoadGlobal(idx), span);
+        } else if self.scope.is_deref(name) {
+            self.emit(Op::LoadDeref(idx), span);
+        } else if self.scope.is_function && self.scope.bound.contains(name) {
+            self.emit(Op::LoadFast(idx), span);
+        } else {
+            self.emit(Op::LoadName(idx), span);
+        }
+    }
+
+    /// Emit a store to variable `name`, using `StoreGlobal` for names
+    /// declared `global`, `StoreDeref` for cell and free variables and
+    /// `StoreName` otherwise.
+    fn emit_store_name(&mut self, name: &str, span: Span) {
+        let idx = self.add_name(name);
+        if self.scope.globals.contains(name) {
+            self.emit(Op::StoreGlobal(idx), span);
+        } else if self.scope.is_deref(name) {
+            self.emit(Op::StoreDeref(idx), span);
+        } else {
+            self.emit(Op::StoreName(idx), span);
+        }
+    }
+
+    /// Emit a deletion of variable `name`, using `DeleteGlobal` for names
+    /// declared `global`, `DeleteDeref` for cell and free variables and
+    /// `DeleteName` otherwise.
+    fn emit_delete_name(&mut self, name: &str, span: Span) {
+        let idx = self.add_name(name);
+        if self.scope.globals.contains(name) {
+            self.emit(Op::DeleteGlobal(idx), span);
+        } else if self.scope.is_deref(name) {
+            self.emit(Op::DeleteDeref(idx), span);
+        } else {
+            self.emit(Op::DeleteName(idx), span);
+        }
+    }
+
+    /// Create a compiler for a nested function body with the given scope.
+    fn nested(&self, scope: Scope) -> Compiler {
+        Compiler {
+            code: CodeObject::new(self.code.source.clone()),
+            loop_stack: Vec::new(),
+            comp_counter: self.comp_counter,
+            with_counter: self.with_counter,
+            match_counter: self.match_counter,
+            scope,
+            unwind_stack: Vec::new(),
+        }
+    }
+
+    /// Compute the scope of a nested function from its collected symbols.
+    ///
+    /// A free name becomes a free variable of the nested function if some
+    /// enclosing *function* binds it; otherwise it resolves as a global at
+    /// runtime. Module-level names are never captured, and neither are names
+    /// an enclosing function declares `global`.
+    fn child_scope(&self, symbols: FunctionSymbols) -> Result<Scope> {
+        let mut enclosing = HashSet::new();
+        if self.scope.is_function {
+            enclosing.extend(
+                self.scope
+                    .enclosing
+                    .iter()
+                    .filter(|name| !self.scope.globals.contains(*name))
+                    .cloned(),
+            );
+            enclosing.extend(self.scope.bound.iter().cloned());
+        }
+
+        let mut nonlocals: Vec<&String> = symbols.nonlocals.iter().collect();
+        nonlocals.sort();
+        if let Some(name) = nonlocals
+            .into_iter()
+            .find(|name| !enclosing.contains(*name))
+        {
+            return Err(Error::Parse(format!(
+                "no binding for nonlocal '{}' found",
+                name
+            )));
+        }
+
+        let mut freevars: Vec<String> = symbols
+            .free
+            .into_iter()
+            .filter(|name| enclosing.contains(name))
+            .collect();
+        freevars.sort();
+        let mut cellvars: Vec<String> = symbols.captured.into_iter().collect();
+        cellvars.sort();
+
+        // `:=` in a generator expression binds in the enclosing function,
+        // or in the module when there is none.
+        let mut globals = symbols.globals;
+        globals.extend(
+            symbols
+                .outer_bound
+                .into_iter()
+                .filter(|name| !enclosing.contains(name)),
+        );
+
+        Ok(Scope {
+            is_function: true,
+            bound: symbols.bound,
+            cellvars,
+            freevars,
+            enclosing,
+            globals,
+            method: None,
+        })
+    }
+
     /// Get the source span of an AST expression.
     fn expr_span(&self, expr: &Expr) -> Span {
         let range = expr.range();
@@ -193,14 +417,7 @@ impl Compiler {
 
             Stmt::AugAssign(aug) => {
                 let span = self.stmt_span(stmt);
-                // Load current value of target
-                self.compile_expr(&aug.target)?;
-                // Load the RHS
-                self.compile_expr(&aug.value)?;
-                // Apply the operator
-                self.emit(Op::BinaryOp(translate_binop(&aug.op)), span);
-                // Store back
-                self.compile_store_target(&aug.target)?;
+                self.compile_aug_assign(aug, span)?;
                 if is_last {
                     let none_idx = self.add_const(PyValue::None);
                     self.emit(Op::LoadConst(none_idx), span);
@@ -220,6 +437,7 @@ impl Compiler {
                     continue_target: loop_start,
                     break_placeholders: Vec::new(),
                     is_for_loop: false,
+                    unwind_depth: self.unwind_stack.len(),
                 });
 
                 self.compile_expr(&while_stmt.test)?;
@@ -228,11 +446,14 @@ impl Compiler {
                 self.compile_body(&while_stmt.body, false)?;
                 self.emit(Op::Jump(loop_start), span);
 
+                // The `else` block runs when the condition turns false,
+                // but `break` jumps past it.
+                self.patch_jump(exit_jump, self.current_offset());
+                let ctx = self.loop_stack.pop().unwrap();
+                self.compile_body(&while_stmt.orelse, false)?;
                 let break_target = self.current_offset();
-                self.patch_jump(exit_jump, break_target);
 
                 // Patch all break placeholders
-                let ctx = self.loop_stack.pop().unwrap();
                 for placeholder in ctx.break_placeholders {
                     self.patch_jump(placeholder, break_target);
                 }
@@ -255,6 +476,7 @@ impl Compiler {
                     continue_target: loop_start,
                     break_placeholders: Vec::new(),
                     is_for_loop: true,
+                    unwind_depth: self.unwind_stack.len(),
                 });
 
                 let exit_jump = self.emit_jump(Op::ForIter, span);
@@ -265,11 +487,14 @@ impl Compiler {
                 self.compile_body(&for_stmt.body, false)?;
                 self.emit(Op::Jump(loop_start), span);
 
+                // The `else` block runs once the iterator is exhausted, but
+                // `break` jumps past it.
+                self.patch_jump(exit_jump, self.current_offset());
+                let ctx = self.loop_stack.pop().unwrap();
+                self.compile_body(&for_stmt.orelse, false)?;
                 let break_target = self.current_offset();
-                self.patch_jump(exit_jump, break_target);
 
                 // Patch all break placeholders
-                let ctx = self.loop_stack.pop().unwrap();
                 for placeholder in ctx.break_placeholders {
                     self.patch_jump(placeholder, break_target);
                 }
@@ -280,6 +505,31 @@ impl Compiler {
                 }
             }
 
+            Stmt::Delete(delete) => {
+                let span = self.stmt_span(stmt);
+                for target in &delete.targets {
+                    self.compile_delete_target(target)?;
+                }
+                if is_last {
+                    let none_idx = self.add_const(PyValue::None);
+                    self.emit(Op::LoadConst(none_idx), span);
+                }
+            }
+
+            Stmt::Global(_) | Stmt::Nonlocal(_) => {
+                // Declarations only affect scope analysis; they emit no code.
+                let span = self.stmt_span(stmt);
+                if matches!(stmt, Stmt::Nonlocal(_)) && !self.scope.is_function {
+                    return Err(Error::Parse(
+                        "nonlocal declaration not allowed at module level".to_string(),
+                    ));
+                }
+                if is_last {
+                    let none_idx = self.add_const(PyValue::None);
+                    self.emit(Op::LoadConst(none_idx), span);
+                }
+            }
+
             Stmt::Pass(_) => {
                 let span = self.stmt_span(stmt);
                 self.emit(Op::Nop, span);
@@ -294,6 +544,8 @@ impl Compiler {
                 if self.loop_stack.is_empty() {
                     return Err(Error::Unsupported("'break' outside loop".to_string()));
                 }
+                let depth = self.loop_stack.last().unwrap().unwind_depth;
+                self.emit_unwind(depth, span)?;
                 // If inside a for-loop, clean up the iterator
                 if self.loop_stack.last().unwrap().is_for_loop {
                     self.emit(Op::PopIter, span);
@@ -311,6 +563,8 @@ impl Compiler {
                 if self.loop_stack.is_empty() {
                     return Err(Error::Unsupported("'continue' outside loop".to_string()));
                 }
+                let depth = self.loop_stack.last().unwrap().unwind_depth;
+                self.emit_unwind(depth, span)?;
                 let target = self.loop_stack.last().unwrap().continue_target;
                 self.emit(Op::Jump(target), span);
             }
@@ -324,6 +578,8 @@ impl Compiler {
                         self.emit(Op::LoadConst(idx), span);
                     }
                 }
+                // The return value stays on the stack while cleanup runs
+                self.emit_unwind(0, span)?;
                 self.emit(Op::ReturnValue, span);
             }
 
@@ -336,6 +592,15 @@ impl Compiler {
                 }
             }
 
+            Stmt::ClassDef(class_def) => {
+                let span = self.stmt_span(stmt);
+                self.compile_class_def(class_def, span)?;
+                if is_last {
+                    let none_idx = self.add_const(PyValue::None);
+                    self.emit(Op::LoadConst(none_idx), span);
+                }
+            }
+
             Stmt::Try(try_stmt) => {
                 let span = self.stmt_span(stmt);
                 self.compile_try(try_stmt, span, is_last)?;
@@ -359,8 +624,7 @@ impl Compiler {
                         .as_ref()
                         .map(|n| n.as_str())
                         .unwrap_or(module_name);
-                    let store_idx = self.add_name(store_name);
-                    self.emit(Op::StoreName(store_idx), span);
+                    self.emit_store_name(store_name, span);
                 }
                 if is_last {
                     let none_idx = self.add_const(PyValue::None);
@@ -401,8 +665,7 @@ impl Compiler {
                         .as_ref()
                         .map(|n| n.as_str())
                         .unwrap_or(attr_name);
-                    let store_idx = self.add_name(store_name);
-                    self.emit(Op::StoreName(store_idx), span);
+                    self.emit_store_name(store_name, span);
                 }
                 // Pop the leftover module from the stack
                 self.emit(Op::Pop, span);
@@ -432,6 +695,11 @@ impl Compiler {
                 self.compile_with(&with_stmt.items, &with_stmt.body, span, is_last)?;
             }
 
+            Stmt::Match(match_stmt) => {
+                let span = self.stmt_span(stmt);
+                self.compile_match(match_stmt, span, is_last)?;
+            }
+
             _ => {
                 return Err(Error::Unsupported(format!(
                     "Statement type not supported: {:?}",
@@ -457,44 +725,129 @@ impl Compiler {
     // Assignment target compilation
     // -----------------------------------------------------------------------
 
+    /// Compile an augmented assignment (`target op= value`).
+    ///
+    /// The object and index of a subscript or attribute target are
+    /// evaluated once, and used both to read the current value and to store
+    /// the result.
+    fn compile_aug_assign(&mut self, aug: &ast::StmtAugAssign, span: Span) -> Result<()> {
+        let op = Op::InplaceOp(translate_binop(&aug.op));
+        match aug.target.as_ref() {
+            Expr::Subscript(sub) => {
+                // [object, index] -> [object, index, result] -> [result, object, index]
+                self.compile_expr(&sub.value)?;
+                self.compile_expr(&sub.slice)?;
+                self.emit(Op::DupTwo, span);
+                self.emit(Op::BinarySubscript, span);
+                self.compile_expr(&aug.value)?;
+                self.emit(op, span);
+                self.emit(Op::RotN(3), span);
+                self.emit(Op::StoreSubscript, span);
+            }
+            Expr::Attribute(attr) => {
+                // [object] -> [object, result] -> [result, object]
+                let attr_idx = self.add_name(attr.attr.as_str());
+                self.compile_expr(&attr.value)?;
+                self.emit(Op::Dup, span);
+                self.emit(Op::LoadAttr(attr_idx), span);
+                self.compile_expr(&aug.value)?;
+                self.emit(op, span);
+                self.emit(Op::RotN(2), span);
+                self.emit(Op::StoreAttr(attr_idx), span);
+            }
+            target => {
+                self.compile_expr(target)?;
+                self.compile_expr(&aug.value)?;
+                self.emit(op, span);
+                self.compile_store_target(target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile a `del` target.
+    fn compile_delete_target(&mut self, target: &Expr) -> Result<()> {
+        let span = self.expr_span(target);
+        match target {
+            Expr::Name(name) => self.emit_delete_name(name.id.as_str(), span),
+            Expr::Subscript(sub) => {
+                self.compile_expr(&sub.value)?;
+                if let Expr::Slice(slice_expr) = sub.slice.as_ref() {
+                    self.compile_slice_bounds(slice_expr, span)?;
+                    self.emit(Op::DeleteSlice, span);
+                } else {
+                    self.compile_expr(&sub.slice)?;
+                    self.emit(Op::DeleteSubscript, span);
+                }
+            }
+            Expr::Tuple(ast::ExprTuple { elts, .. }) | Expr::List(ast::ExprList { elts, .. }) => {
+                for elt in elts {
+                    self.compile_delete_target(elt)?;
+                }
+            }
+            _ => {
+                return Err(Error::Unsupported(
+                    "Deletion target not supported".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// Compile an assignment target. Assumes the value to assign is on TOS.
     fn compile_store_target(&mut self, target: &Expr) -> Result<()> {
         match target {
             Expr::Name(name) => {
-                let idx = self.add_name(name.id.as_str());
-                self.emit(Op::StoreName(idx), self.expr_span(target));
+                self.emit_store_name(name.id.as_str(), self.expr_span(target));
             }
 
             Expr::Subscript(sub) => {
-                // Stack has value on top. We need to push the index, then
-                // StoreSubscript pops value + index and mutates the variable.
+                // Stack has value on top; push the object and index (or
+                // slice bounds), and store.
                 let span = self.expr_span(target);
-                if let Expr::Name(name) = sub.value.as_ref() {
-                    let var_idx = self.add_name(name.id.as_str());
-                    self.compile_expr(&sub.slice)?;
-                    self.emit(Op::StoreSubscript(var_idx), span);
+                self.compile_expr(&sub.value)?;
+                if let Expr::Slice(slice_expr) = sub.slice.as_ref() {
+                    self.compile_slice_bounds(slice_expr, span)?;
+                    self.emit(Op::StoreSlice, span);
                 } else {
-                    return Err(Error::Unsupported(
-                        "Subscript assignment only supported on named variables".to_string(),
-                    ));
+                    self.compile_expr(&sub.slice)?;
+                    self.emit(Op::StoreSubscript, span);
                 }
             }
 
-            Expr::Tuple(tuple) => {
+            Expr::Attribute(attr) => {
+                // Stack has value on top; push the object and store.
                 let span = self.expr_span(target);
-                let n = tuple.elts.len() as u32;
-                self.emit(Op::UnpackSequence(n), span);
-                for elt in &tuple.elts {
-                    self.compile_store_target(elt)?;
-                }
+                self.compile_expr(&attr.value)?;
+                let attr_idx = self.add_name(attr.attr.as_str());
+                self.emit(Op::StoreAttr(attr_idx), span);
             }
 
-            Expr::List(list) => {
+            Expr::Tuple(ast::ExprTuple { elts, .. }) | Expr::List(ast::ExprList { elts, .. }) => {
                 let span = self.expr_span(target);
-                let n = list.elts.len() as u32;
-                self.emit(Op::UnpackSequence(n), span);
-                for elt in &list.elts {
-                    self.compile_store_target(elt)?;
+                let starred: Vec<usize> = elts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, elt)| matches!(elt, Expr::Starred(_)))
+                    .map(|(i, _)| i)
+                    .collect();
+                match starred.as_slice() {
+                    [] => self.emit(Op::UnpackSequence(elts.len() as u32), span),
+                    &[i] => {
+                        let after = elts.len() - i - 1;
+                        self.emit(Op::UnpackStarred(i as u32, after as u32), span)
+                    }
+                    _ => {
+                        return Err(Error::Parse(
+                            "multiple starred expressions in assignment".to_string(),
+                        ));
+                    }
+                };
+                for elt in elts {
+                    match elt {
+                        Expr::Starred(starred) => self.compile_store_target(&starred.value)?,
+                        _ => self.compile_store_target(elt)?,
+                    }
                 }
             }
 
@@ -528,12 +881,13 @@ impl Compiler {
 
             Expr::NumberLiteral(n) => {
                 let value = match &n.value {
-                    ast::Number::Int(i) => {
-                        let val: i64 = i
-                            .as_i64()
-                            .ok_or_else(|| Error::Runtime("Integer too large".to_string()))?;
-                        PyValue::Int(val)
-                    }
+                    ast::Number::Int(i) => match i.as_i64() {
+                        Some(val) => PyValue::Int(val),
+                        // Large literals keep their source text, radix prefix included
+                        None => int::parse(&i.to_string(), 0).ok_or_else(|| {
+                            Error::Runtime(format!("invalid integer literal: {}", i))
+                        })?,
+                    },
                     ast::Number::Float(f) => PyValue::Float(*f),
                     ast::Number::Complex { .. } => {
                         return Err(Error::Unsupported("Complex numbers".to_string()));
@@ -548,8 +902,9 @@ impl Compiler {
                 self.emit(Op::LoadConst(idx), span);
             }
 
-            Expr::BytesLiteral(_) => {
-                return Err(Error::Unsupported("Bytes literals".to_string()));
+            Expr::BytesLiteral(b) => {
+                let idx = self.add_const(PyValue::Bytes(b.value.bytes().collect()));
+                self.emit(Op::LoadConst(idx), span);
             }
 
             Expr::EllipsisLiteral(_) => {
@@ -557,8 +912,11 @@ impl Compiler {
             }
 
             Expr::Name(name) => {
-                let idx = self.add_name(name.id.as_str());
-                self.emit(Op::LoadName(idx), span);
+                self.emit_load_name(name.id.as_str(), span);
+            }
+
+            Expr::List(list) if has_starred(&list.elts) => {
+                self.compile_unpacking_list(&list.elts, span)?;
             }
 
             Expr::List(list) => {
@@ -568,6 +926,11 @@ impl Compiler {
                 self.emit(Op::BuildList(list.elts.len() as u32), span);
             }
 
+            Expr::Tuple(tuple) if has_starred(&tuple.elts) => {
+                self.compile_unpacking_list(&tuple.elts, span)?;
+                self.emit(Op::ListToTuple, span);
+            }
+
             Expr::Tuple(tuple) => {
                 for elt in &tuple.elts {
                     self.compile_expr(elt)?;
@@ -576,16 +939,30 @@ impl Compiler {
             }
 
             Expr::Dict(dict) => {
+                // Runs of `key: value` pairs are built into dicts, and each
+                // `**mapping` is merged into the dict built so far.
+                let mut pending = 0;
+                let mut started = false;
                 for item in &dict.items {
                     match &item.key {
-                        Some(k) => self.compile_expr(k)?,
+                        Some(key) => {
+                            self.compile_expr(key)?;
+                            self.compile_expr(&item.value)?;
+                            pending += 1;
+                        }
                         None => {
-                            return Err(Error::Unsupported("Dict unpacking".to_string()));
+                            self.emit_dict_chunk(&mut pending, &mut started, span);
+                            self.compile_expr(&item.value)?;
+                            self.emit(Op::DictUpdate, span);
                         }
                     }
-                    self.compile_expr(&item.value)?;
                 }
-                self.emit(Op::BuildDict(dict.items.len() as u32), span);
+                self.emit_dict_chunk(&mut pending, &mut started, span);
+            }
+
+            Expr::Set(set) if has_starred(&set.elts) => {
+                self.compile_unpacking_list(&set.elts, span)?;
+                self.emit(Op::ListToSet, span);
             }
 
             Expr::Set(set) => {
@@ -657,14 +1034,54 @@ impl Compiler {
             }
 
             Expr::Generator(genexp) => {
-                // Treat generator expressions as eager list comprehensions
-                self.compile_list_comprehension(&genexp.elt, &genexp.generators, span)?;
+                self.compile_generator_expr(genexp, span)?;
+            }
+
+            Expr::Yield(yield_expr) => {
+                if !self.scope.is_function {
+                    return Err(Error::Parse("'yield' outside function".to_string()));
+                }
+                match &yield_expr.value {
+                    Some(value) => self.compile_expr(value)?,
+                    None => {
+                        let idx = self.add_const(PyValue::None);
+                        self.emit(Op::LoadConst(idx), span);
+                    }
+                }
+                self.emit(Op::YieldValue, span);
+            }
+
+            Expr::YieldFrom(yield_from) => {
+                if !self.scope.is_function {
+                    return Err(Error::Parse("'yield' outside function".to_string()));
+                }
+                // Yield each item of the iterable in turn. The iterable stays
+                // on the stack for the value of the expression itself, which
+                // is what a generator returned.
+                self.compile_expr(&yield_from.value)?;
+                self.emit(Op::Dup, span);
+                self.emit(Op::GetIter, span);
+                let loop_start = self.current_offset();
+                let exit_jump = self.emit_jump(Op::ForIter, span);
+                self.emit(Op::YieldValue, span);
+                self.emit(Op::Pop, span);
+                self.emit(Op::Jump(loop_start), span);
+                self.patch_jump(exit_jump, self.current_offset());
+                self.emit(Op::YieldFromResult, span);
             }
 
             Expr::Lambda(lambda) => {
                 self.compile_lambda(lambda, span)?;
             }
 
+            Expr::Named(named) => {
+                // Assign the value and leave a copy as the expression's
+                // result.
+                self.compile_expr(&named.value)?;
+                self.emit(Op::Dup, span);
+                self.compile_store_target(&named.target)?;
+            }
+
             Expr::FString(fstring) => {
                 let mut n_parts = 0u32;
                 for part in fstring.value.iter() {
@@ -672,27 +1089,12 @@ impl Compiler {
                         ast::FStringPart::Literal(s) => {
                             let idx = self.add_const(PyValue::Str(s.value.to_string()));
                             self.emit(Op::LoadConst(idx), span);
-                            self.emit(Op::FormatValue, span);
-                            n_parts += 1;
                         }
                         ast::FStringPart::FString(fs) => {
-                            for element in &fs.elements {
-                                match element {
-                                    ast::InterpolatedStringElement::Literal(lit) => {
-                                        let idx =
-                                            self.add_const(PyValue::Str(lit.value.to_string()));
-                                        self.emit(Op::LoadConst(idx), span);
-                                        self.emit(Op::FormatValue, span);
-                                    }
-                                    ast::InterpolatedStringElement::Interpolation(interp) => {
-                                        self.compile_expr(&interp.expression)?;
-                                        self.emit(Op::FormatValue, span);
-                                    }
-                                }
-                                n_parts += 1;
-                            }
+                            self.compile_fstring_elements(&fs.elements, span)?;
                         }
                     }
+                    n_parts += 1;
                 }
                 self.emit(Op::BuildString(n_parts), span);
             }
@@ -876,6 +1278,54 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compile the elements of an f-string (or of a format spec inside
+    /// one), leaving a single string on the stack.
+    fn compile_fstring_elements(
+        &mut self,
+        elements: &ast::InterpolatedStringElements,
+        span: Span,
+    ) -> Result<()> {
+        let mut n_parts = 0u32;
+        for element in elements {
+            match element {
+                ast::InterpolatedStringElement::Literal(lit) => {
+                    let idx = self.add_const(PyValue::Str(lit.value.to_string()));
+                    self.emit(Op::LoadConst(idx), span);
+                }
+                ast::InterpolatedStringElement::Interpolation(interp) => {
+                    // `f"{x=}"` shows the expression's source before its value
+                    let mut conversion = match interp.conversion {
+                        ast::ConversionFlag::None => Conversion::None,
+                        ast::ConversionFlag::Str => Conversion::Str,
+                        ast::ConversionFlag::Repr => Conversion::Repr,
+                        ast::ConversionFlag::Ascii => Conversion::Ascii,
+                    };
+                    if let Some(debug) = &interp.debug_text {
+                        let source = &self.code.source[interp.expression.range()];
+                        let text = format!("{}{}{}", debug.leading, source, debug.trailing);
+                        let idx = self.add_const(PyValue::Str(text));
+                        self.emit(Op::LoadConst(idx), span);
+                        n_parts += 1;
+                        if conversion == Conversion::None && interp.format_spec.is_none() {
+                            conversion = Conversion::Repr;
+                        }
+                    }
+                    self.compile_expr(&interp.expression)?;
+                    if let Some(spec) = &interp.format_spec {
+                        self.compile_fstring_elements(&spec.elements, span)?;
+                    }
+                    self.emit(
+                        Op::FormatValue(conversion, interp.format_spec.is_some()),
+                        span,
+                    );
+                }
+            }
+            n_parts += 1;
+        }
+        self.emit(Op::BuildString(n_parts), span);
+        Ok(())
+    }
+
     /// Compile a function/method/builtin call.
     fn compile_call(&mut self, call: &ast::ExprCall, span: Span) -> Result<()> {
         // Check if this is a method call (object.method(args))
@@ -883,11 +1333,20 @@ impl Compiler {
             return self.compile_method_call(attr, call, span);
         }
 
-        // Named function call — use CallFunction/CallFunctionKw (by-name dispatch)
-        if let Expr::Name(name) = call.func.as_ref() {
+        // Named function call — use CallFunction/CallFunctionKw (by-name dispatch).
+        // Cell and free variables are loaded as values and called below.
+        if let Expr::Name(name) = call.func.as_ref()
+            && !self.scope.is_deref(name.id.as_str())
+        {
             let func_name = name.id.to_string();
             let name_idx = self.add_name(&func_name);
 
+            if has_call_unpacking(call) {
+                self.compile_unpacking_call_args(call, span)?;
+                self.emit(Op::CallFunctionEx(name_idx), span);
+                return Ok(());
+            }
+
             // Compile positional arguments
             for arg in &call.arguments.args {
                 self.compile_expr(arg)?;
@@ -925,6 +1384,12 @@ impl Compiler {
         // Compile the callable expression
         self.compile_expr(&call.func)?;
 
+        if has_call_unpacking(call) {
+            self.compile_unpacking_call_args(call, span)?;
+            self.emit(Op::CallValueEx, span);
+            return Ok(());
+        }
+
         // Compile positional arguments
         for arg in &call.arguments.args {
             self.compile_expr(arg)?;
@@ -962,67 +1427,149 @@ impl Compiler {
     ) -> Result<()> {
         let method_name = attr.attr.as_str();
         let method_idx = self.add_name(method_name);
-
-        // Check if this is a mutating method on a named variable
-        if let Expr::Name(name) = attr.value.as_ref() {
-            let is_list_mut = LIST_MUTATING_METHODS.contains(&method_name);
-            let is_dict_mut = DICT_MUTATING_METHODS.contains(&method_name);
-            let is_set_mut = SET_MUTATING_METHODS.contains(&method_name);
-
-            if is_list_mut || is_dict_mut || is_set_mut {
-                let var_idx = self.add_name(name.id.as_str());
-                // Compile positional arguments
-                for arg in &call.arguments.args {
-                    self.compile_expr(arg)?;
-                }
-
-                if call.arguments.keywords.is_empty() {
-                    self.emit(
-                        Op::CallMutMethod(var_idx, method_idx, call.arguments.args.len() as u32),
-                        span,
-                    );
-                } else {
-                    // Compile keyword arguments: push name string then value
-                    for kw in &call.arguments.keywords {
-                        if let Some(ref arg_name) = kw.arg {
-                            let kw_name_idx =
-                                self.add_const(PyValue::Str(arg_name.as_str().to_string()));
-                            self.emit(Op::LoadConst(kw_name_idx), span);
-                            self.compile_expr(&kw.value)?;
-                        }
-                    }
-                    self.emit(
-                        Op::CallMutMethodKw(
-                            var_idx,
-                            method_idx,
-                            call.arguments.args.len() as u32,
-                            call.arguments.keywords.len() as u32,
-                        ),
-                        span,
-                    );
-                }
-                return Ok(());
+        let n_pos = call.arguments.args.len() as u32;
+        let n_kw = call.arguments.keywords.len() as u32;
+
+        // `super().method(...)` inside a method: push the receiver and the
+        // defining class name, then look the method up past that class.
+        if let Expr::Call(inner) = attr.value.as_ref()
+            && let Expr::Name(func) = inner.func.as_ref()
+            && func.id.as_str() == "super"
+            && inner.arguments.is_empty()
+        {
+            let Some((class_name, self_name)) = self.scope.method.clone() else {
+                return Err(Error::exception("RuntimeError", "super(): no arguments"));
+            };
+            self.emit_load_name(&self_name, span);
+            let class_idx = self.add_const(PyValue::Str(class_name));
+            self.emit(Op::LoadConst(class_idx), span);
+            if has_call_unpacking(call) {
+                self.compile_unpacking_call_args(call, span)?;
+                self.emit(Op::CallSuperMethodEx(method_idx), span);
+            } else {
+                self.compile_call_args(call, span)?;
+                self.emit(Op::CallSuperMethod(method_idx, n_pos, n_kw), span);
             }
+            return Ok(());
         }
 
-        // Non-mutating method: push object, then args, then call
+        // Push object, then args, then call
         self.compile_expr(&attr.value)?;
+        if has_call_unpacking(call) {
+            self.compile_unpacking_call_args(call, span)?;
+            self.emit(Op::CallMethodEx(method_idx), span);
+        } else if n_kw == 0 {
+            self.compile_call_args(call, span)?;
+            self.emit(Op::CallMethod(method_idx, n_pos), span);
+        } else {
+            self.compile_call_args(call, span)?;
+            self.emit(Op::CallMethodKw(method_idx, n_pos, n_kw), span);
+        }
+
+        Ok(())
+    }
+
+    /// Push a call's positional arguments, then a name/value pair for each
+    /// keyword argument.
+    fn compile_call_args(&mut self, call: &ast::ExprCall, span: Span) -> Result<()> {
         for arg in &call.arguments.args {
             self.compile_expr(arg)?;
         }
-        self.emit(
-            Op::CallMethod(method_idx, call.arguments.args.len() as u32),
-            span,
-        );
+        for kw in &call.arguments.keywords {
+            let Some(ref arg_name) = kw.arg else {
+                return Err(Error::Unsupported("Keyword argument unpacking".to_string()));
+            };
+            let kw_name_idx = self.add_const(PyValue::Str(arg_name.as_str().to_string()));
+            self.emit(Op::LoadConst(kw_name_idx), span);
+            self.compile_expr(&kw.value)?;
+        }
+        Ok(())
+    }
+
+    /// Push the arguments of a call that uses `*args` or `**kwargs`: a list
+    /// of the positional arguments and a dict of the keyword arguments, for
+    /// one of the `Call*Ex` instructions.
+    fn compile_unpacking_call_args(&mut self, call: &ast::ExprCall, span: Span) -> Result<()> {
+        self.compile_unpacking_list(&call.arguments.args, span)?;
+
+        let mut pending = 0;
+        let mut started = false;
+        for kw in &call.arguments.keywords {
+            match &kw.arg {
+                Some(arg_name) => {
+                    let kw_name_idx = self.add_const(PyValue::Str(arg_name.as_str().to_string()));
+                    self.emit(Op::LoadConst(kw_name_idx), span);
+                    self.compile_expr(&kw.value)?;
+                    pending += 1;
+                }
+                None => {
+                    self.emit_dict_chunk(&mut pending, &mut started, span);
+                    self.compile_expr(&kw.value)?;
+                    self.emit(Op::DictUpdate, span);
+                }
+            }
+        }
+        self.emit_dict_chunk(&mut pending, &mut started, span);
+        Ok(())
+    }
 
+    /// Push a list of `elts`, spreading the items of each `*iterable` into
+    /// it.
+    fn compile_unpacking_list(&mut self, elts: &[Expr], span: Span) -> Result<()> {
+        let mut pending = 0;
+        let mut started = false;
+        for elt in elts {
+            if let Expr::Starred(starred) = elt {
+                self.emit_list_chunk(&mut pending, &mut started, span);
+                self.compile_expr(&starred.value)?;
+                self.emit(Op::ListExtend, span);
+            } else {
+                self.compile_expr(elt)?;
+                pending += 1;
+            }
+        }
+        self.emit_list_chunk(&mut pending, &mut started, span);
         Ok(())
     }
 
+    /// Build a list of the `pending` items on the stack. The first chunk
+    /// starts the result; later ones are appended to it.
+    fn emit_list_chunk(&mut self, pending: &mut u32, started: &mut bool, span: Span) {
+        if *started && *pending == 0 {
+            return;
+        }
+        self.emit(Op::BuildList(*pending), span);
+        if *started {
+            self.emit(Op::ListExtend, span);
+        }
+        *pending = 0;
+        *started = true;
+    }
+
+    /// Build a dict of the `pending` key/value pairs on the stack. The
+    /// first chunk starts the result; later ones are merged into it.
+    fn emit_dict_chunk(&mut self, pending: &mut u32, started: &mut bool, span: Span) {
+        if *started && *pending == 0 {
+            return;
+        }
+        self.emit(Op::BuildDict(*pending), span);
+        if *started {
+            self.emit(Op::DictUpdate, span);
+        }
+        *pending = 0;
+        *started = true;
+    }
+
     /// Compile a slice expression (`list[start:stop:step]`).
     fn compile_slice(&mut self, value: &Expr, slice: &ast::ExprSlice, span: Span) -> Result<()> {
         self.compile_expr(value)?;
+        self.compile_slice_bounds(slice, span)?;
+        self.emit(Op::Slice, span);
+        Ok(())
+    }
 
-        // Push start, stop, step (None if absent)
+    /// Push the start, stop and step of a slice (`None` if absent).
+    fn compile_slice_bounds(&mut self, slice: &ast::ExprSlice, span: Span) -> Result<()> {
         match &slice.lower {
             Some(expr) => self.compile_expr(expr)?,
             None => {
@@ -1044,12 +1591,10 @@ impl Compiler {
                 self.emit(Op::LoadConst(idx), span);
             }
         }
-
-        self.emit(Op::Slice, span);
         Ok(())
     }
 
-    /// Compile a list comprehension or generator expression.
+    /// Compile a list comprehension.
     ///
     /// Uses a synthetic temp variable (`__comp_N`) to accumulate results,
     /// with nested for-loops and filter conditions compiled inline.
@@ -1069,7 +1614,13 @@ impl Compiler {
         self.emit(Op::StoreName(comp_var_idx), span);
 
         // Compile the generators recursively
-        self.compile_comprehension_generators(elt, generators, 0, comp_var_idx, "append", span)?;
+        self.compile_comprehension_generators(
+            elt,
+            generators,
+            0,
+            CompElement::Collect(comp_var_idx, "append"),
+            span,
+        )?;
 
         // Load the result
         self.emit(Op::LoadName(comp_var_idx), span);
@@ -1083,8 +1634,7 @@ impl Compiler {
         elt: &Expr,
         generators: &[ast::Comprehension],
         gen_index: usize,
-        comp_var_idx: u32,
-        append_method: &str,
+        element: CompElement,
         span: Span,
     ) -> Result<()> {
         let generator = &generators[gen_index];
@@ -1094,7 +1644,11 @@ impl Compiler {
         }
 
         // Compile the iterable and set up iteration
-        self.compile_expr(&generator.iter)?;
+        if gen_index == 0 && matches!(element, CompElement::Yield) {
+            self.emit_load_name(GENEXPR_ITER, span);
+        } else {
+            self.compile_expr(&generator.iter)?;
+        }
         self.emit(Op::GetIter, span);
 
         let loop_start = self.current_offset();
@@ -1113,20 +1667,23 @@ impl Compiler {
 
         if gen_index + 1 < generators.len() {
             // Recurse for nested generators
-            self.compile_comprehension_generators(
-                elt,
-                generators,
-                gen_index + 1,
-                comp_var_idx,
-                append_method,
-                span,
-            )?;
+            self.compile_comprehension_generators(elt, generators, gen_index + 1, element, span)?;
         } else {
-            // Innermost generator: evaluate element and append/add to result
-            let method_idx = self.add_name(append_method);
-            self.compile_expr(elt)?;
-            self.emit(Op::CallMutMethod(comp_var_idx, method_idx, 1), span);
-            self.emit(Op::Pop, span); // discard None from append/add
+            // Innermost generator: evaluate element and append/add it to
+            // the result, or yield it
+            match element {
+                CompElement::Collect(comp_var_idx, method) => {
+                    self.emit(Op::LoadName(comp_var_idx), span);
+                    self.compile_expr(elt)?;
+                    let method_idx = self.add_name(method);
+                    self.emit(Op::CallMethod(method_idx, 1), span);
+                }
+                CompElement::Yield => {
+                    self.compile_expr(elt)?;
+                    self.emit(Op::YieldValue, span);
+                }
+            }
+            self.emit(Op::Pop, span); // discard None from append/add/yield
         }
 
         // Skip targets: jump back to loop start
@@ -1142,13 +1699,66 @@ impl Compiler {
         Ok(())
     }
 
-    /// Compile a dict comprehension: `{k: v for k, v in items if cond}`.
-    fn compile_dict_comprehension(
-        &mut self,
-        key: &Expr,
-        value: &Expr,
-        generators: &[ast::Comprehension],
-        span: Span,
+    /// Compile a generator expression: `(x * 2 for x in items)`.
+    ///
+    /// The expression becomes a nested generator function whose `.0`
+    /// parameter receives the outermost iterable, evaluated eagerly in the
+    /// enclosing scope as in CPython. The function is called right away,
+    /// leaving the generator object on the stack.
+    fn compile_generator_expr(&mut self, genexp: &ast::ExprGenerator, span: Span) -> Result<()> {
+        let symbols = analyze_function(None, |collector| {
+            collector.bound.insert(GENEXPR_ITER.to_string());
+            collector.visit_genexp_body(genexp);
+        });
+        let mut sub_compiler = self.nested(self.child_scope(symbols)?);
+
+        sub_compiler.compile_comprehension_generators(
+            &genexp.elt,
+            &genexp.generators,
+            0,
+            CompElement::Yield,
+            span,
+        )?;
+        let none_idx = sub_compiler.add_const(PyValue::None);
+        sub_compiler.emit(Op::LoadConst(none_idx), span);
+        sub_compiler.emit(Op::ReturnValue, span);
+
+        // Propagate the comprehension counter
+        self.comp_counter = sub_compiler.comp_counter;
+        self.with_counter = sub_compiler.with_counter;
+
+        self.emit_make_function(
+            FunctionDef {
+                name: "<genexpr>".to_string(),
+                doc: None,
+                params: vec![GENEXPR_ITER.to_string()],
+                posonly: 0,
+                defaults: Vec::new(),
+                kwonly: Vec::new(),
+                kw_defaults: Vec::new(),
+                vararg: None,
+                kwarg: None,
+                cellvars: sub_compiler.scope.cellvars,
+                freevars: sub_compiler.scope.freevars,
+                closure: Vec::new(),
+                code: sub_compiler.code,
+                is_generator: true,
+            },
+            (0, 0),
+            span,
+        );
+        self.compile_expr(&genexp.generators[0].iter)?;
+        self.emit(Op::CallValue(1), span);
+        Ok(())
+    }
+
+    /// Compile a dict comprehension: `{k: v for k, v in items if cond}`.
+    fn compile_dict_comprehension(
+        &mut self,
+        key: &Expr,
+        value: &Expr,
+        generators: &[ast::Comprehension],
+        span: Span,
     ) -> Result<()> {
         let comp_var = format!("__comp_{}", self.comp_counter);
         self.comp_counter += 1;
@@ -1208,10 +1818,11 @@ impl Compiler {
                 span,
             )?;
         } else {
-            // Innermost: StoreSubscript pops index (key) then value from stack
+            // Innermost: StoreSubscript pops the key, the dict and the value
             self.compile_expr(value)?;
+            self.emit(Op::LoadName(comp_var_idx), span);
             self.compile_expr(key)?;
-            self.emit(Op::StoreSubscript(comp_var_idx), span);
+            self.emit(Op::StoreSubscript, span);
         }
 
         for skip in &skip_jumps {
@@ -1240,7 +1851,13 @@ impl Compiler {
         self.emit(Op::StoreName(comp_var_idx), span);
 
         // Compile the generators recursively (using "add" instead of "append")
-        self.compile_comprehension_generators(elt, generators, 0, comp_var_idx, "add", span)?;
+        self.compile_comprehension_generators(
+            elt,
+            generators,
+            0,
+            CompElement::Collect(comp_var_idx, "add"),
+            span,
+        )?;
 
         // Load the result
         self.emit(Op::LoadName(comp_var_idx), span);
@@ -1255,18 +1872,14 @@ impl Compiler {
         // If true, jump past the raise
         let ok_label = self.emit_jump(Op::PopJumpIfTrue, span);
 
-        // Push exception type
-        let type_idx = self.add_const(PyValue::Str("AssertionError".to_string()));
-        self.emit(Op::LoadConst(type_idx), span);
-
-        // Push message (or None)
+        // Construct the AssertionError (with the message, if any) and raise it
+        let type_idx = self.add_name("AssertionError");
         if let Some(ref msg) = assert_stmt.msg {
             self.compile_expr(msg)?;
+            self.emit(Op::CallFunction(type_idx, 1), span);
         } else {
-            let none_idx = self.add_const(PyValue::None);
-            self.emit(Op::LoadConst(none_idx), span);
+            self.emit(Op::CallFunction(type_idx, 0), span);
         }
-
         self.emit(Op::Raise, span);
 
         // ok_label: assertion passed
@@ -1275,7 +1888,60 @@ impl Compiler {
         Ok(())
     }
 
-    /// Compile a try/except statement.
+    /// Compile a try statement.
+    ///
+    /// `try`/`except`/`else` is handled by `compile_try_except`. A `finally`
+    /// clause wraps all of it in another protected region:
+    ///
+    /// ```text
+    /// start:
+    ///     <try/except/else, or just the try body>
+    ///     (early exits emit a copy of <finally body> before jumping out)
+    /// end:
+    ///     <finally body>          (normal exit)
+    ///     Jump → after
+    /// finally_handler:
+    ///     <finally body>          (exceptional exit)
+    ///     Reraise
+    /// after:
+    /// ```
+    fn compile_try(&mut self, try_stmt: &ast::StmtTry, span: Span, is_last: bool) -> Result<()> {
+        if try_stmt.finalbody.is_empty() {
+            return self.compile_try_except(try_stmt, span, is_last);
+        }
+
+        let start = self.current_offset();
+        let depths = self.unwind_depths();
+
+        self.push_unwind(UnwindKind::Finally(try_stmt.finalbody.clone()));
+        let result = if try_stmt.handlers.is_empty() {
+            self.compile_body(&try_stmt.body, is_last)
+        } else {
+            self.compile_try_except(try_stmt, span, is_last)
+        };
+        let block = self.unwind_stack.pop().unwrap();
+        result?;
+        let end = self.current_offset();
+
+        // Normal exit
+        self.compile_body(&try_stmt.finalbody, false)?;
+        let after_jump = self.emit_jump(Op::Jump, span);
+
+        // Exceptional exit: run the finally body, then let the exception
+        // continue propagating.
+        let handler = self.current_offset();
+        self.push_unwind(UnwindKind::Handler);
+        let result = self.compile_body(&try_stmt.finalbody, false);
+        self.unwind_stack.pop();
+        result?;
+        self.emit(Op::Reraise, span);
+
+        self.add_exception_entries(start, end, &block.holes, handler, depths);
+        self.patch_jump(after_jump, self.current_offset());
+        Ok(())
+    }
+
+    /// Compile the `try`/`except`/`else` part of a try statement.
     ///
     /// Emits the try body, records exception table entries pointing to
     /// handlers, and emits each handler with `CheckExcMatch` / `PopException`.
@@ -1297,20 +1963,21 @@ impl Compiler {
     ///     <else body if any>
     /// end:
     /// ```
-    fn compile_try(&mut self, try_stmt: &ast::StmtTry, span: Span, is_last: bool) -> Result<()> {
-        use crate::bytecode::ExceptionEntry;
-
-        if !try_stmt.finalbody.is_empty() {
-            return Err(Error::Unsupported(
-                "try/finally is not yet supported".to_string(),
-            ));
-        }
-
+    fn compile_try_except(
+        &mut self,
+        try_stmt: &ast::StmtTry,
+        span: Span,
+        is_last: bool,
+    ) -> Result<()> {
         // Record the start of the try body
         let try_start = self.current_offset();
+        let depths = self.unwind_depths();
 
         // Compile the try body
-        self.compile_body(&try_stmt.body, is_last)?;
+        self.push_unwind(UnwindKind::Try);
+        let result = self.compile_body(&try_stmt.body, is_last);
+        let block = self.unwind_stack.pop().unwrap();
+        result?;
 
         // Jump past all handlers (to else or end)
         let try_end_jump = self.emit_jump(Op::Jump, span);
@@ -1335,35 +2002,19 @@ impl Compiler {
             let _ = i; // suppress unused warning
 
             if let Some(ref type_expr) = h.type_ {
-                let type_name = match type_expr.as_ref() {
-                    Expr::Name(name) => name.id.to_string(),
-                    _ => {
-                        return Err(Error::Unsupported(
-                            "Only named exception types are supported".to_string(),
-                        ));
-                    }
-                };
-
-                let type_name_idx = self.add_const(PyValue::Str(type_name));
-                self.emit(Op::LoadConst(type_name_idx), span);
+                self.compile_expr(type_expr)?;
                 self.emit(Op::CheckExcMatch, span);
 
                 // If no match, jump to next handler (or reraise)
                 let no_match_jump = self.emit_jump(Op::PopJumpIfFalse, span);
 
-                // Optionally bind exception to variable
+                // Optionally bind the exception object to a variable
                 if let Some(ref name) = h.name {
-                    // The exception message is already stored by handle_exception
-                    // via var_name in the exception entry. But since we use a
-                    // single entry, we need to store it here too.
-                    // Actually, the `as e` binding is handled differently now:
-                    // We load the exc message from the exception stack.
-                    // For simplicity, we'll handle this via a special approach:
-                    // nothing extra needed — handle_exception already stores it.
-                    let _ = name;
+                    self.emit(Op::LoadException, span);
+                    self.emit_store_name(name.as_str(), span);
                 }
 
-                self.compile_body(&h.body, is_last)?;
+                self.compile_handler_body(&h.body, is_last)?;
                 self.emit(Op::PopException, span);
                 let end_jump = self.emit_jump(Op::Jump, span);
                 handler_end_jumps.push(end_jump);
@@ -1373,7 +2024,11 @@ impl Compiler {
             } else {
                 // Bare except: catches everything
                 has_bare_except = true;
-                self.compile_body(&h.body, is_last)?;
+                if let Some(ref name) = h.name {
+                    self.emit(Op::LoadException, span);
+                    self.emit_store_name(name.as_str(), span);
+                }
+                self.compile_handler_body(&h.body, is_last)?;
                 self.emit(Op::PopException, span);
                 let end_jump = self.emit_jump(Op::Jump, span);
                 handler_end_jumps.push(end_jump);
@@ -1386,22 +2041,13 @@ impl Compiler {
         }
 
         // Build the exception table entry — single entry pointing to first handler.
-        // The `as e` variable binding is handled per-handler, but we use the
-        // first handler's `as` binding in the exception entry for the initial store.
-        let first_var_name = try_stmt.handlers.first().and_then(|h| {
-            let ast::ExceptHandler::ExceptHandler(handler) = h;
-            handler
-                .name
-                .as_ref()
-                .map(|name| self.add_name(name.as_str()))
-        });
-
-        self.code.exception_table.push(ExceptionEntry {
-            start: try_start,
-            end: try_end,
-            handler: first_handler_offset,
-            var_name: first_var_name,
-        });
+        self.add_exception_entries(
+            try_start,
+            try_end,
+            &block.holes,
+            first_handler_offset,
+            depths,
+        );
 
         // Patch the try-end jump to the else body (or end)
         self.patch_jump(try_end_jump, self.current_offset());
@@ -1420,104 +2066,338 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compile the body of an `except` handler. Early exits out of the
+    /// handler pop the active exception.
+    fn compile_handler_body(&mut self, body: &[Stmt], is_last: bool) -> Result<()> {
+        self.push_unwind(UnwindKind::Handler);
+        let result = self.compile_body(body, is_last);
+        self.unwind_stack.pop();
+        result
+    }
+
+    /// Push a block onto the unwind stack.
+    fn push_unwind(&mut self, kind: UnwindKind) {
+        self.unwind_stack.push(UnwindBlock {
+            kind,
+            holes: Vec::new(),
+        });
+    }
+
+    /// Emit the cleanup for every unwind block above `depth`, innermost
+    /// first. Used by `break`, `continue` and `return`.
+    fn emit_unwind(&mut self, depth: usize, span: Span) -> Result<()> {
+        let mut i = self.unwind_stack.len();
+        while i > depth {
+            i -= 1;
+            let start = self.current_offset();
+            match &self.unwind_stack[i].kind {
+                UnwindKind::Try => {}
+                UnwindKind::Handler => self.emit(Op::PopException, span),
+                UnwindKind::With(temp_idx) => {
+                    let temp_idx = *temp_idx;
+                    let exit_idx = self.add_name("__exit__");
+                    self.emit(Op::LoadName(temp_idx), span);
+                    self.emit(Op::CallMethod(exit_idx, 0), span);
+                    self.emit(Op::Pop, span);
+                }
+                UnwindKind::Finally(body) => {
+                    // The copy runs outside this block, so it only sees the
+                    // blocks below it (e.g. a `return` inside `finally`).
+                    let body = body.clone();
+                    let inner = self.unwind_stack.split_off(i);
+                    let result = self.compile_body(&body, false);
+                    self.unwind_stack.extend(inner);
+                    result?;
+                }
+            }
+            let end = self.current_offset();
+            if end > start {
+                for block in &mut self.unwind_stack[i..] {
+                    block.holes.push((start, end));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The `for`-loop iterator depth and active handler depth at this point
+    /// of the current frame, restored by the VM when an exception is caught.
+    fn unwind_depths(&self) -> (u32, u32) {
+        let iter_depth = self.loop_stack.iter().filter(|l| l.is_for_loop).count();
+        let exc_depth = self
+            .unwind_stack
+            .iter()
+            .filter(|b| matches!(b.kind, UnwindKind::Handler))
+            .count();
+        (iter_depth as u32, exc_depth as u32)
+    }
+
+    /// Register `[start, end)` minus `holes` in the exception table.
+    fn add_exception_entries(
+        &mut self,
+        start: u32,
+        end: u32,
+        holes: &[(u32, u32)],
+        handler: u32,
+        (iter_depth, exc_depth): (u32, u32),
+    ) {
+        let mut holes = holes.to_vec();
+        holes.sort_unstable();
+
+        let mut ranges = Vec::new();
+        let mut cursor = start;
+        for (hole_start, hole_end) in holes {
+            if hole_start > cursor {
+                ranges.push((cursor, hole_start));
+            }
+            cursor = cursor.max(hole_end);
+        }
+        if cursor < end {
+            ranges.push((cursor, end));
+        }
+
+        for (start, end) in ranges {
+            self.code.exception_table.push(ExceptionEntry {
+                start,
+                end,
+                handler,
+                iter_depth,
+                exc_depth,
+            });
+        }
+    }
+
     /// Compile a raise statement.
     ///
-    /// - `raise ExceptionType("message")` — calls the constructor and raises
-    /// - `raise ExceptionType` — raises with the type name as the message
+    /// - `raise ExceptionType(...)` / `raise exc` — raises the exception value
+    /// - `raise ExceptionType` — instantiates the class with no arguments
+    /// - `raise exc from cause` — also sets `__cause__`
     /// - `raise` (bare) — re-raises the current exception
     fn compile_raise(&mut self, raise_stmt: &ast::StmtRaise, span: Span) -> Result<()> {
-        if raise_stmt.cause.is_some() {
-            return Err(Error::Unsupported(
-                "Exception chaining (raise ... from ...) is not supported".to_string(),
-            ));
+        let Some(expr) = &raise_stmt.exc else {
+            self.emit(Op::Reraise, span);
+            return Ok(());
+        };
+
+        self.compile_expr(expr)?;
+        if let Some(cause) = &raise_stmt.cause {
+            self.compile_expr(cause)?;
+            self.emit(Op::RaiseFrom, span);
+        } else {
+            self.emit(Op::Raise, span);
         }
+        Ok(())
+    }
 
-        match &raise_stmt.exc {
-            None => {
-                // Bare `raise` — re-raise current exception
-                self.emit(Op::Reraise, span);
+    /// Compile a class definition.
+    ///
+    /// Supports single inheritance and a body of methods (optionally
+    /// `@property`), class variables, `pass` and docstrings. Methods are
+    /// compiled as functions of the enclosing scope, so the class body does
+    /// not introduce a scope of its own. Emits the base (or `None`), then a
+    /// name/value pair per class attribute, then `MakeClass`.
+    fn compile_class_def(&mut self, class_def: &ast::StmtClassDef, span: Span) -> Result<()> {
+        let class_name = class_def.name.as_str();
+        if class_def.type_params.is_some() {
+            return Err(Error::Unsupported("Generic classes".to_string()));
+        }
+
+        let bases: &[Expr] = match class_def.arguments.as_deref() {
+            Some(arguments) => {
+                if !arguments.keywords.is_empty() {
+                    return Err(Error::Unsupported(
+                        "Class keyword arguments (metaclasses)".to_string(),
+                    ));
+                }
+                &arguments.args
             }
-            Some(expr) => {
-                // Check if it's a call like `ValueError("message")`
-                if let Expr::Call(call) = expr.as_ref()
-                    && let Expr::Name(name) = call.func.as_ref()
-                {
-                    let type_name = name.id.to_string();
-                    let type_name_idx = self.add_const(PyValue::Str(type_name));
-                    self.emit(Op::LoadConst(type_name_idx), span);
+            None => &[],
+        };
+        // Decorators are evaluated before the bases and applied after
+        for decorator in &class_def.decorator_list {
+            self.compile_expr(&decorator.expression)?;
+        }
 
-                    // Compile the first argument as the message
-                    if let Some(arg) = call.arguments.args.first() {
-                        self.compile_expr(arg)?;
-                    } else {
-                        let none_idx = self.add_const(PyValue::None);
-                        self.emit(Op::LoadConst(none_idx), span);
-                    }
+        match bases {
+            [] => {
+                let none_idx = self.add_const(PyValue::None);
+                self.emit(Op::LoadConst(none_idx), span);
+            }
+            [Expr::Name(name)] if name.id.as_str() == "object" => {
+                let none_idx = self.add_const(PyValue::None);
+                self.emit(Op::LoadConst(none_idx), span);
+            }
+            [base] => self.compile_expr(base)?,
+            _ => return Err(Error::Unsupported("Multiple inheritance".to_string())),
+        }
 
-                    self.emit(Op::Raise, span);
-                    return Ok(());
+        // Annotated names are recorded (as source text) in `__annotations__`;
+        // record classes take their fields from it.
+        let mut annotations = Vec::new();
+        let mut n_attrs = 0;
+        for stmt in &class_def.body {
+            let (name, value) = match stmt {
+                Stmt::Pass(_) => continue,
+                Stmt::Expr(expr_stmt) if matches!(*expr_stmt.value, Expr::StringLiteral(_)) => {
+                    continue;
                 }
-
-                // `raise ExceptionType` (without call)
-                if let Expr::Name(name) = expr.as_ref() {
-                    let type_name = name.id.to_string();
-                    let type_idx = self.add_const(PyValue::Str(type_name));
-                    self.emit(Op::LoadConst(type_idx), span);
-                    let none_idx = self.add_const(PyValue::None);
-                    self.emit(Op::LoadConst(none_idx), span);
-                    self.emit(Op::Raise, span);
-                    return Ok(());
+                Stmt::Assign(assign) => match assign.targets.as_slice() {
+                    [Expr::Name(target)] => (target.id.as_str(), &*assign.value),
+                    _ => {
+                        return Err(Error::Unsupported(
+                            "Class attributes must be assigned to a single name".to_string(),
+                        ));
+                    }
+                },
+                Stmt::AnnAssign(ann) => match (ann.target.as_ref(), &ann.value) {
+                    (Expr::Name(target), value) => {
+                        let annotation = &self.code.source[ann.annotation.range()];
+                        annotations.push((
+                            PyValue::Str(target.id.to_string()),
+                            PyValue::Str(annotation.to_string()),
+                        ));
+                        match value {
+                            Some(value) => (target.id.as_str(), &**value),
+                            None => continue,
+                        }
+                    }
+                    _ => {
+                        return Err(Error::Unsupported(
+                            "Class attributes must be assigned to a single name".to_string(),
+                        ));
+                    }
+                },
+                Stmt::FunctionDef(func_def) => {
+                    let method = func_def.name.as_str();
+                    if method.starts_with("__")
+                        && method.ends_with("__")
+                        && !SPECIAL_METHODS.contains(&method)
+                    {
+                        return Err(Error::Unsupported(format!(
+                            "Special method '{}' in class '{}'",
+                            method, class_name
+                        )));
+                    }
+                    let stmt_span = self.stmt_span(stmt);
+                    let name_idx = self.add_const(PyValue::Str(func_def.name.to_string()));
+                    self.emit(Op::LoadConst(name_idx), stmt_span);
+                    self.compile_method(func_def, class_name, stmt_span)?;
+                    n_attrs += 1;
+                    continue;
                 }
+                _ => {
+                    return Err(Error::Unsupported(format!(
+                        "Statement not supported in class body of '{}'",
+                        class_name
+                    )));
+                }
+            };
+            let name_idx = self.add_const(PyValue::Str(name.to_string()));
+            self.emit(Op::LoadConst(name_idx), span);
+            self.compile_expr(value)?;
+            n_attrs += 1;
+        }
 
-                return Err(Error::Unsupported(
-                    "Only `raise ExceptionType(...)` is supported".to_string(),
-                ));
-            }
+        if !annotations.is_empty() {
+            let key_idx = self.add_const(PyValue::Str("__annotations__".to_string()));
+            self.emit(Op::LoadConst(key_idx), span);
+            let annotations_idx = self.add_const(PyValue::dict(annotations));
+            self.emit(Op::LoadConst(annotations_idx), span);
+            n_attrs += 1;
         }
+
+        let name_idx = self.add_name(class_name);
+        self.emit(Op::MakeClass(name_idx, n_attrs), span);
+        for _ in &class_def.decorator_list {
+            self.emit(Op::CallValue(1), span);
+        }
+        self.emit_store_name(class_name, span);
         Ok(())
     }
 
+    /// Compile a method in a class body, leaving the (decorated) function
+    /// on the stack.
+    fn compile_method(
+        &mut self,
+        func_def: &ast::StmtFunctionDef,
+        class_name: &str,
+        span: Span,
+    ) -> Result<()> {
+        self.compile_decorated(&func_def.decorator_list, span, |compiler| {
+            compiler.compile_function_value(func_def, Some(class_name), span)
+        })
+    }
+
     /// Compile a function definition (`def name(params): body`).
-    ///
-    /// Handles positional parameters with optional default values.
-    /// Default values are evaluated as constant expressions at definition time.
     fn compile_function_def(&mut self, func_def: &ast::StmtFunctionDef, span: Span) -> Result<()> {
-        let name = func_def.name.to_string();
-        let params: Vec<String> = func_def
-            .parameters
-            .args
-            .iter()
-            .map(|a| a.parameter.name.to_string())
-            .collect();
+        self.compile_decorated(&func_def.decorator_list, span, |compiler| {
+            compiler.compile_function_value(func_def, None, span)
+        })?;
+        self.emit_store_name(func_def.name.as_str(), span);
+        Ok(())
+    }
 
-        // Collect default values from the trailing parameters that have them.
-        let mut defaults = Vec::new();
-        for arg in &func_def.parameters.args {
-            if let Some(ref default_expr) = arg.default {
-                let val = eval_const_expr(default_expr)?;
-                defaults.push(val);
+    /// Compile a value with `compile_value` and apply `decorators` to it,
+    /// innermost first.
+    ///
+    /// Decorator expressions are evaluated before the value. A decorator
+    /// that is a plain name is called by name instead, which also reaches
+    /// builtins (`@property`) and tools.
+    fn compile_decorated(
+        &mut self,
+        decorators: &[ast::Decorator],
+        span: Span,
+        compile_value: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        let by_name = |decorator: &ast::Decorator, compiler: &Self| match &decorator.expression {
+            Expr::Name(name) if !compiler.scope.is_deref(name.id.as_str()) => {
+                Some(name.id.to_string())
+            }
+            _ => None,
+        };
+        for decorator in decorators {
+            if by_name(decorator, self).is_none() {
+                self.compile_expr(&decorator.expression)?;
             }
         }
+        compile_value(self)?;
+        for decorator in decorators.iter().rev() {
+            match by_name(decorator, self) {
+                Some(name) => {
+                    let name_idx = self.add_name(&name);
+                    self.emit(Op::CallFunction(name_idx, 1), span);
+                }
+                None => self.emit(Op::CallValue(1), span),
+            }
+        }
+        Ok(())
+    }
 
-        // Read *args and **kwargs parameter names
-        let vararg = func_def
-            .parameters
-            .vararg
-            .as_ref()
-            .map(|v| v.name.to_string());
-        let kwarg = func_def
-            .parameters
-            .kwarg
-            .as_ref()
-            .map(|v| v.name.to_string());
+    /// Compile a function body and leave the function value on the stack.
+    ///
+    /// Default values are evaluated in the enclosing scope each time the
+    /// `def` runs. `method_of` names the class when compiling a method, for
+    /// `super()`.
+    fn compile_function_value(
+        &mut self,
+        func_def: &ast::StmtFunctionDef,
+        method_of: Option<&str>,
+        span: Span,
+    ) -> Result<()> {
+        let name = func_def.name.to_string();
+        let params = ParamNames::of(Some(&func_def.parameters));
+        let n_defaults = self.compile_defaults(Some(&func_def.parameters), span)?;
 
         // Compile the function body into a separate CodeObject
-        let mut sub_compiler = Compiler {
-            code: CodeObject::new(self.code.source.clone()),
-            loop_stack: Vec::new(),
-            comp_counter: self.comp_counter,
-            with_counter: self.with_counter,
-        };
+        let symbols = analyze_function(Some(&func_def.parameters), |collector| {
+            collector.visit_body(&func_def.body)
+        });
+        let is_generator = symbols.is_generator;
+        let mut scope = self.child_scope(symbols)?;
+        if let (Some(class_name), Some(first)) = (method_of, params.params.first()) {
+            scope.method = Some((class_name.to_string(), first.clone()));
+        }
+        let mut sub_compiler = self.nested(scope);
 
         let body_len = func_def.body.len();
         for (i, stmt) in func_def.body.iter().enumerate() {
@@ -1546,19 +2426,26 @@ impl Compiler {
         self.comp_counter = sub_compiler.comp_counter;
         self.with_counter = sub_compiler.with_counter;
 
-        let func_idx = self.code.functions.len() as u32;
-        self.code.functions.push(FunctionDef {
-            name: name.clone(),
-            params,
-            defaults,
-            vararg,
-            kwarg,
-            code: sub_compiler.code,
-        });
-
-        self.emit(Op::MakeFunction(func_idx), span);
-        let name_idx = self.add_name(&name);
-        self.emit(Op::StoreName(name_idx), span);
+        self.emit_make_function(
+            FunctionDef {
+                name: name.clone(),
+                doc: docstring(&func_def.body),
+                params: params.params,
+                posonly: params.posonly,
+                defaults: Vec::new(),
+                kwonly: params.kwonly,
+                kw_defaults: Vec::new(),
+                vararg: params.vararg,
+                kwarg: params.kwarg,
+                cellvars: sub_compiler.scope.cellvars,
+                freevars: sub_compiler.scope.freevars,
+                closure: Vec::new(),
+                code: sub_compiler.code,
+                is_generator,
+            },
+            n_defaults,
+            span,
+        );
 
         Ok(())
     }
@@ -1569,37 +2456,16 @@ impl Compiler {
     /// (not a block of statements), and the result is left on the stack
     /// (no `StoreName`).
     fn compile_lambda(&mut self, lambda: &ast::ExprLambda, span: Span) -> Result<()> {
-        // Extract parameters (None means zero-param lambda)
-        let (params, defaults, vararg, kwarg) = if let Some(ref parameters) = lambda.parameters {
-            let params: Vec<String> = parameters
-                .args
-                .iter()
-                .map(|a| a.parameter.name.to_string())
-                .collect();
-
-            let mut defaults = Vec::new();
-            for arg in &parameters.args {
-                if let Some(ref default_expr) = arg.default {
-                    let val = eval_const_expr(default_expr)?;
-                    defaults.push(val);
-                }
-            }
-
-            let vararg = parameters.vararg.as_ref().map(|v| v.name.to_string());
-            let kwarg = parameters.kwarg.as_ref().map(|v| v.name.to_string());
-
-            (params, defaults, vararg, kwarg)
-        } else {
-            (Vec::new(), Vec::new(), None, None)
-        };
+        // `parameters` is None for a zero-parameter lambda
+        let params = ParamNames::of(lambda.parameters.as_deref());
+        let n_defaults = self.compile_defaults(lambda.parameters.as_deref(), span)?;
 
         // Compile the lambda body into a separate CodeObject
-        let mut sub_compiler = Compiler {
-            code: CodeObject::new(self.code.source.clone()),
-            loop_stack: Vec::new(),
-            comp_counter: self.comp_counter,
-            with_counter: self.with_counter,
-        };
+        let symbols = analyze_function(lambda.parameters.as_deref(), |collector| {
+            collector.visit_expr(&lambda.body)
+        });
+        let is_generator = symbols.is_generator;
+        let mut sub_compiler = self.nested(self.child_scope(symbols)?);
 
         sub_compiler.compile_expr(&lambda.body)?;
         sub_compiler.emit(Op::ReturnValue, span);
@@ -1608,22 +2474,77 @@ impl Compiler {
         self.comp_counter = sub_compiler.comp_counter;
         self.with_counter = sub_compiler.with_counter;
 
-        let func_idx = self.code.functions.len() as u32;
-        self.code.functions.push(FunctionDef {
-            name: "<lambda>".to_string(),
-            params,
-            defaults,
-            vararg,
-            kwarg,
-            code: sub_compiler.code,
-        });
-
         // Leave the function value on the stack (no StoreName for lambdas)
-        self.emit(Op::MakeFunction(func_idx), span);
+        self.emit_make_function(
+            FunctionDef {
+                name: "<lambda>".to_string(),
+                doc: None,
+                params: params.params,
+                posonly: params.posonly,
+                defaults: Vec::new(),
+                kwonly: params.kwonly,
+                kw_defaults: Vec::new(),
+                vararg: params.vararg,
+                kwarg: params.kwarg,
+                cellvars: sub_compiler.scope.cellvars,
+                freevars: sub_compiler.scope.freevars,
+                closure: Vec::new(),
+                code: sub_compiler.code,
+                is_generator,
+            },
+            n_defaults,
+            span,
+        );
 
         Ok(())
     }
 
+    /// Push the default values of a function's parameters for
+    /// `MakeFunction`: positional defaults, then a name/value pair for each
+    /// keyword-only default. Returns how many of each were pushed.
+    fn compile_defaults(
+        &mut self,
+        parameters: Option<&ast::Parameters>,
+        span: Span,
+    ) -> Result<(u32, u32)> {
+        let Some(parameters) = parameters else {
+            return Ok((0, 0));
+        };
+        let mut n_defaults = 0;
+        for param in parameters.posonlyargs.iter().chain(&parameters.args) {
+            if let Some(default) = &param.default {
+                self.compile_expr(default)?;
+                n_defaults += 1;
+            }
+        }
+        let mut n_kw_defaults = 0;
+        for param in &parameters.kwonlyargs {
+            if let Some(default) = &param.default {
+                let name_idx = self.add_const(PyValue::Str(param.parameter.name.to_string()));
+                self.emit(Op::LoadConst(name_idx), span);
+                self.compile_expr(default)?;
+                n_kw_defaults += 1;
+            }
+        }
+        Ok((n_defaults, n_kw_defaults))
+    }
+
+    /// Register a compiled function and emit the op that creates it at
+    /// runtime: `MakeClosure` if it captures variables, `MakeFunction` otherwise.
+    /// `n_defaults` counts the default values on the stack, as returned by
+    /// [`Compiler::compile_defaults`].
+    fn emit_make_function(&mut self, func: FunctionDef, n_defaults: (u32, u32), span: Span) {
+        let func_idx = self.code.functions.len() as u32;
+        let is_closure = !func.freevars.is_empty();
+        self.code.functions.push(func);
+        let (n_pos, n_kw) = n_defaults;
+        if is_closure {
+            self.emit(Op::MakeClosure(func_idx, n_pos, n_kw), span);
+        } else {
+            self.emit(Op::MakeFunction(func_idx, n_pos, n_kw), span);
+        }
+    }
+
     /// Compile a `with` statement.
     ///
     /// Desugars `with expr as var: body` into:
@@ -1639,6 +2560,285 @@ impl Compiler {
     /// ```
     ///
     /// Multiple items are handled by recursion (first item = outermost).
+    /// Compile a `match` statement.
+    ///
+    /// The subject is stored in a hidden variable and each case's pattern is
+    /// tested against it in turn. A failed pattern (or guard) jumps to the
+    /// next case; names captured by a partially matched pattern stay bound,
+    /// as in CPython.
+    fn compile_match(
+        &mut self,
+        match_stmt: &ast::StmtMatch,
+        span: Span,
+        is_last: bool,
+    ) -> Result<()> {
+        self.compile_expr(&match_stmt.subject)?;
+        let subject = self.match_temp();
+        self.emit_store_name(&subject, span);
+
+        let mut end_jumps = Vec::new();
+        for case in &match_stmt.cases {
+            let mut fail_jumps = Vec::new();
+            self.emit_load_name(&subject, span);
+            self.compile_pattern(&case.pattern, &mut fail_jumps, span)?;
+            if let Some(guard) = &case.guard {
+                self.compile_expr(guard)?;
+                fail_jumps.push(self.emit_jump(Op::PopJumpIfFalse, span));
+            }
+            self.compile_body(&case.body, is_last)?;
+            end_jumps.push(self.emit_jump(Op::Jump, span));
+            let next_case = self.current_offset();
+            for jump in fail_jumps {
+                self.patch_jump(jump, next_case);
+            }
+        }
+
+        // No case matched
+        if is_last {
+            let none_idx = self.add_const(PyValue::None);
+            self.emit(Op::LoadConst(none_idx), span);
+        }
+        let end = self.current_offset();
+        for jump in end_jumps {
+            self.patch_jump(jump, end);
+        }
+        Ok(())
+    }
+
+    /// A fresh hidden variable name for a `match` subject or sub-subject.
+    fn match_temp(&mut self) -> String {
+        let name = format!("__match_{}", self.match_counter);
+        self.match_counter += 1;
+        name
+    }
+
+    /// Compile a pattern that consumes the value on top of the stack.
+    ///
+    /// Captured names are bound as the pattern is tested. On failure,
+    /// execution jumps (with the stack as it was before the value was
+    /// pushed) to a target patched later through `fail_jumps`.
+    fn compile_pattern(
+        &mut self,
+        pattern: &ast::Pattern,
+        fail_jumps: &mut Vec<usize>,
+        span: Span,
+    ) -> Result<()> {
+        match pattern {
+            ast::Pattern::MatchValue(p) => {
+                self.compile_expr(&p.value)?;
+                self.emit(Op::CompareOp(bytecode::CmpOp::Eq), span);
+                fail_jumps.push(self.emit_jump(Op::PopJumpIfFalse, span));
+            }
+            ast::Pattern::MatchSingleton(p) => {
+                let value = match p.value {
+                    ast::Singleton::None => PyValue::None,
+                    ast::Singleton::True => PyValue::Bool(true),
+                    ast::Singleton::False => PyValue::Bool(false),
+                };
+                let idx = self.add_const(value);
+                self.emit(Op::LoadConst(idx), span);
+                self.emit(Op::CompareOp(bytecode::CmpOp::Is), span);
+                fail_jumps.push(self.emit_jump(Op::PopJumpIfFalse, span));
+            }
+            ast::Pattern::MatchAs(p) => match (&p.pattern, &p.name) {
+                // `_`
+                (None, None) => self.emit(Op::Pop, span),
+                // capture: `name`
+                (None, Some(name)) => self.emit_store_name(name.as_str(), span),
+                // `pattern as name`
+                (Some(inner), Some(name)) => {
+                    let subject = self.match_temp();
+                    self.emit_store_name(&subject, span);
+                    self.emit_load_name(&subject, span);
+                    self.compile_pattern(inner, fail_jumps, span)?;
+                    self.emit_load_name(&subject, span);
+                    self.emit_store_name(name.as_str(), span);
+                }
+                (Some(inner), None) => self.compile_pattern(inner, fail_jumps, span)?,
+            },
+            ast::Pattern::MatchOr(p) => {
+                let subject = self.match_temp();
+                self.emit_store_name(&subject, span);
+                let mut success_jumps = Vec::new();
+                for (i, alternative) in p.patterns.iter().enumerate() {
+                    self.emit_load_name(&subject, span);
+                    if i + 1 == p.patterns.len() {
+                        self.compile_pattern(alternative, fail_jumps, span)?;
+                    } else {
+                        let mut alt_fail_jumps = Vec::new();
+                        self.compile_pattern(alternative, &mut alt_fail_jumps, span)?;
+                        success_jumps.push(self.emit_jump(Op::Jump, span));
+                        let next = self.current_offset();
+                        for jump in alt_fail_jumps {
+                            self.patch_jump(jump, next);
+                        }
+                    }
+                }
+                let end = self.current_offset();
+                for jump in success_jumps {
+                    self.patch_jump(jump, end);
+                }
+            }
+            ast::Pattern::MatchSequence(p) => {
+                self.compile_sequence_pattern(&p.patterns, fail_jumps, span)?;
+            }
+            ast::Pattern::MatchMapping(p) => {
+                let subject = self.match_temp();
+                self.emit_store_name(&subject, span);
+
+                // values = MatchKeys(subject, keys); fail if None
+                self.emit_load_name(&subject, span);
+                self.compile_pattern_keys(&p.keys)?;
+                self.emit(Op::MatchKeys, span);
+                let values = self.compile_store_match_values(fail_jumps, span);
+
+                for (i, sub_pattern) in p.patterns.iter().enumerate() {
+                    self.emit_match_value_item(&values, i as i64, span);
+                    self.compile_pattern(sub_pattern, fail_jumps, span)?;
+                }
+                if let Some(rest) = &p.rest {
+                    self.emit_load_name(&subject, span);
+                    self.compile_pattern_keys(&p.keys)?;
+                    self.emit(Op::CopyDictWithoutKeys, span);
+                    self.emit_store_name(rest.as_str(), span);
+                }
+            }
+            ast::Pattern::MatchClass(p) => {
+                let subject = self.match_temp();
+                self.emit_store_name(&subject, span);
+
+                self.emit_load_name(&subject, span);
+                self.compile_expr(&p.cls)?;
+                let kw_names: Vec<PyValue> = p
+                    .arguments
+                    .keywords
+                    .iter()
+                    .map(|kw| PyValue::Str(kw.attr.to_string()))
+                    .collect();
+                let kw_idx = self.add_const(PyValue::Tuple(kw_names));
+                self.emit(Op::LoadConst(kw_idx), span);
+                self.emit(Op::MatchClass(p.arguments.patterns.len() as u32), span);
+                let values = self.compile_store_match_values(fail_jumps, span);
+
+                let sub_patterns = p
+                    .arguments
+                    .patterns
+                    .iter()
+                    .chain(p.arguments.keywords.iter().map(|kw| &kw.pattern));
+                for (i, sub_pattern) in sub_patterns.enumerate() {
+                    self.emit_match_value_item(&values, i as i64, span);
+                    self.compile_pattern(sub_pattern, fail_jumps, span)?;
+                }
+            }
+            ast::Pattern::MatchStar(_) => {
+                return Err(Error::Parse(
+                    "starred pattern outside a sequence pattern".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile a sequence pattern (`[a, b, *rest]` or `(a, b)`).
+    fn compile_sequence_pattern(
+        &mut self,
+        patterns: &[ast::Pattern],
+        fail_jumps: &mut Vec<usize>,
+        span: Span,
+    ) -> Result<()> {
+        let star = patterns
+            .iter()
+            .position(|p| matches!(p, ast::Pattern::MatchStar(_)));
+        let n_fixed = patterns.len() - usize::from(star.is_some());
+
+        let subject = self.match_temp();
+        self.emit_store_name(&subject, span);
+
+        self.emit_load_name(&subject, span);
+        self.emit(Op::MatchSequence, span);
+        fail_jumps.push(self.emit_jump(Op::PopJumpIfFalse, span));
+
+        // Check the length: exact, or at least the fixed items with a star
+        self.emit_load_name(&subject, span);
+        self.emit(Op::GetLen, span);
+        let len_idx = self.add_const(PyValue::Int(n_fixed as i64));
+        self.emit(Op::LoadConst(len_idx), span);
+        let cmp = if star.is_some() {
+            bytecode::CmpOp::GtE
+        } else {
+            bytecode::CmpOp::Eq
+        };
+        self.emit(Op::CompareOp(cmp), span);
+        fail_jumps.push(self.emit_jump(Op::PopJumpIfFalse, span));
+
+        for (i, sub_pattern) in patterns.iter().enumerate() {
+            // Items after the star are indexed from the end
+            let index = match star {
+                Some(star) if i > star => i as i64 - patterns.len() as i64,
+                _ => i as i64,
+            };
+            if let ast::Pattern::MatchStar(p) = sub_pattern {
+                let Some(name) = &p.name else { continue };
+                // name = list(subject[i:len - after])
+                let after = (patterns.len() - i - 1) as i64;
+                self.emit_load_name(&subject, span);
+                let start_idx = self.add_const(PyValue::Int(i as i64));
+                self.emit(Op::LoadConst(start_idx), span);
+                let stop = if after == 0 {
+                    PyValue::None
+                } else {
+                    PyValue::Int(-after)
+                };
+                let stop_idx = self.add_const(stop);
+                self.emit(Op::LoadConst(stop_idx), span);
+                let none_idx = self.add_const(PyValue::None);
+                self.emit(Op::LoadConst(none_idx), span);
+                self.emit(Op::Slice, span);
+                let list_idx = self.add_name("list");
+                self.emit(Op::CallFunction(list_idx, 1), span);
+                self.emit_store_name(name.as_str(), span);
+                continue;
+            }
+            self.emit_load_name(&subject, span);
+            let index_idx = self.add_const(PyValue::Int(index));
+            self.emit(Op::LoadConst(index_idx), span);
+            self.emit(Op::BinarySubscript, span);
+            self.compile_pattern(sub_pattern, fail_jumps, span)?;
+        }
+        Ok(())
+    }
+
+    /// Push the keys of a mapping pattern as a tuple.
+    fn compile_pattern_keys(&mut self, keys: &[Expr]) -> Result<()> {
+        for key in keys {
+            self.compile_expr(key)?;
+        }
+        let span = keys.first().map_or(Span::new(0, 0), |k| self.expr_span(k));
+        self.emit(Op::BuildTuple(keys.len() as u32), span);
+        Ok(())
+    }
+
+    /// Store the result of `MatchKeys`/`MatchClass` in a hidden variable,
+    /// failing the pattern if it is `None`. Returns the variable name.
+    fn compile_store_match_values(&mut self, fail_jumps: &mut Vec<usize>, span: Span) -> String {
+        let values = self.match_temp();
+        self.emit_store_name(&values, span);
+        self.emit_load_name(&values, span);
+        let none_idx = self.add_const(PyValue::None);
+        self.emit(Op::LoadConst(none_idx), span);
+        self.emit(Op::CompareOp(bytecode::CmpOp::IsNot), span);
+        fail_jumps.push(self.emit_jump(Op::PopJumpIfFalse, span));
+        values
+    }
+
+    /// Push `values[index]`.
+    fn emit_match_value_item(&mut self, values: &str, index: i64, span: Span) {
+        self.emit_load_name(values, span);
+        let idx = self.add_const(PyValue::Int(index));
+        self.emit(Op::LoadConst(idx), span);
+        self.emit(Op::BinarySubscript, span);
+    }
+
     fn compile_with(
         &mut self,
         items: &[ast::WithItem],
@@ -1646,8 +2846,6 @@ impl Compiler {
         span: Span,
         is_last: bool,
     ) -> Result<()> {
-        use crate::bytecode::ExceptionEntry;
-
         if items.is_empty() {
             return self.compile_body(body, is_last);
         }
@@ -1677,12 +2875,16 @@ impl Compiler {
 
         // try: body (including inner with items)
         let try_start = self.current_offset();
+        let depths = self.unwind_depths();
 
-        if rest.is_empty() {
-            self.compile_body(body, is_last)?;
+        self.push_unwind(UnwindKind::With(temp_idx));
+        let result = if rest.is_empty() {
+            self.compile_body(body, is_last)
         } else {
-            self.compile_with(rest, body, span, is_last)?;
-        }
+            self.compile_with(rest, body, span, is_last)
+        };
+        let block = self.unwind_stack.pop().unwrap();
+        result?;
 
         // Jump past the exception handler (normal path)
         let try_end_jump = self.emit_jump(Op::Jump, span);
@@ -1696,13 +2898,8 @@ impl Compiler {
         self.emit(Op::Pop, span);
         self.emit(Op::Reraise, span);
 
-        // Register exception table entry
-        self.code.exception_table.push(ExceptionEntry {
-            start: try_start,
-            end: try_end,
-            handler: handler_offset,
-            var_name: None,
-        });
+        // Register exception table entries
+        self.add_exception_entries(try_start, try_end, &block.holes, handler_offset, depths);
 
         // Normal path continues here: __with_ctx_N.__exit__()
         self.patch_jump(try_end_jump, self.current_offset());
@@ -1720,62 +2917,407 @@ impl Compiler {
 }
 
 // ---------------------------------------------------------------------------
-// Standalone helpers (no &self)
+// Scope analysis
 // ---------------------------------------------------------------------------
 
-/// Evaluate a constant expression at compile time (for default parameter values).
+/// The parameter names of a `def` or `lambda`, as [`FunctionDef`] stores
+/// them.
+struct ParamNames {
+    params: Vec<String>,
+    posonly: usize,
+    kwonly: Vec<String>,
+    vararg: Option<String>,
+    kwarg: Option<String>,
+}
+
+impl ParamNames {
+    fn of(parameters: Option<&ast::Parameters>) -> Self {
+        let Some(parameters) = parameters else {
+            return Self {
+                params: Vec::new(),
+                posonly: 0,
+                kwonly: Vec::new(),
+                vararg: None,
+                kwarg: None,
+            };
+        };
+        let name = |param: &ast::ParameterWithDefault| param.parameter.name.to_string();
+        Self {
+            params: parameters
+                .posonlyargs
+                .iter()
+                .chain(&parameters.args)
+                .map(name)
+                .collect(),
+            posonly: parameters.posonlyargs.len(),
+            kwonly: parameters.kwonlyargs.iter().map(name).collect(),
+            vararg: parameters.vararg.as_ref().map(|v| v.name.to_string()),
+            kwarg: parameters.kwarg.as_ref().map(|v| v.name.to_string()),
+        }
+    }
+}
+
+/// Names collected from a function body by [`analyze_function`].
+struct FunctionSymbols {
+    /// Names bound in the function.
+    bound: HashSet<String>,
+    /// Names referenced but not bound in the function (including names
+    /// that nested functions reference from outside themselves).
+    free: HashSet<String>,
+    /// Names bound in the function that nested functions reference.
+    captured: HashSet<String>,
+    /// Names declared `global`.
+    globals: HashSet<String>,
+    /// Names declared `nonlocal`.
+    nonlocals: HashSet<String>,
+    /// Names bound by `:=` inside a generator expression, which bind in
+    /// the enclosing scope rather than the generator's own.
+    outer_bound: HashSet<String>,
+    /// Whether the function body contains `yield`.
+    is_generator: bool,
+    /// The first misuse of a name found in the function or the functions
+    /// nested in it, as a `SyntaxError` message.
+    error: Option<String>,
+}
+
+/// Collect the names a function body binds and references.
 ///
-/// Supports: literals (`42`, `"hello"`, `True`, `None`), unary minus (`-1`),
-/// empty collections (`[]`, `{}`), and tuples of constants.
-fn eval_const_expr(expr: &Expr) -> Result<PyValue> {
-    match expr {
-        Expr::NoneLiteral(_) => Ok(PyValue::None),
-        Expr::BooleanLiteral(b) => Ok(PyValue::Bool(b.value)),
-        Expr::NumberLiteral(n) => match &n.value {
-            ast::Number::Int(i) => {
-                let val: i64 = i
-                    .as_i64()
-                    .ok_or_else(|| Error::Runtime("Integer too large".to_string()))?;
-                Ok(PyValue::Int(val))
-            }
-            ast::Number::Float(f) => Ok(PyValue::Float(*f)),
-            ast::Number::Complex { .. } => Err(Error::Unsupported("Complex numbers".to_string())),
-        },
-        Expr::StringLiteral(s) => Ok(PyValue::Str(s.value.to_string())),
-        Expr::UnaryOp(u) => {
-            let operand = eval_const_expr(&u.operand)?;
-            match u.op {
-                ast::UnaryOp::USub => match operand {
-                    PyValue::Int(i) => Ok(PyValue::Int(-i)),
-                    PyValue::Float(f) => Ok(PyValue::Float(-f)),
-                    _ => Err(Error::Runtime(
-                        "Unary minus on non-numeric default value".to_string(),
-                    )),
-                },
-                ast::UnaryOp::UAdd => Ok(operand),
-                ast::UnaryOp::Not => Ok(PyValue::Bool(!operand.is_truthy())),
-                _ => Err(Error::Unsupported(
-                    "Complex default value expression".to_string(),
-                )),
-            }
-        }
-        Expr::Name(name) => match name.id.as_str() {
-            "True" => Ok(PyValue::Bool(true)),
-            "False" => Ok(PyValue::Bool(false)),
-            "None" => Ok(PyValue::None),
-            _ => Err(Error::Unsupported(
-                "Non-constant default parameter value".to_string(),
-            )),
+/// Nested functions and lambdas are analyzed recursively: their free names
+/// are treated as references from this scope, which is what makes a variable
+/// visible across several levels of nesting.
+fn analyze_function(
+    parameters: Option<&ast::Parameters>,
+    visit_body: impl FnOnce(&mut SymbolCollector),
+) -> FunctionSymbols {
+    let mut collector = SymbolCollector::default();
+    if let Some(parameters) = parameters {
+        for param in parameters.iter() {
+            collector.bound.insert(param.name().to_string());
+            collector.params.insert(param.name().to_string());
+        }
+    }
+    visit_body(&mut collector);
+
+    // Declared names are never local to this function: `global` names resolve
+    // in the module, `nonlocal` names in an enclosing function.
+    for name in collector.globals.iter().chain(&collector.nonlocals) {
+        collector.bound.remove(name);
+    }
+
+    let captured = collector
+        .bound
+        .intersection(&collector.child_free)
+        .cloned()
+        .collect();
+    let free = collector
+        .used
+        .union(&collector.child_free)
+        .chain(&collector.nonlocals)
+        .chain(&collector.outer_bound)
+        .filter(|name| !collector.bound.contains(*name) && !collector.globals.contains(*name))
+        .cloned()
+        .collect();
+    FunctionSymbols {
+        bound: collector.bound,
+        free,
+        captured,
+        globals: collector.globals,
+        nonlocals: collector.nonlocals,
+        outer_bound: collector.outer_bound,
+        is_generator: collector.is_generator,
+        error: collector.error,
+    }
+}
+
+/// AST visitor that records name bindings and uses within one scope.
+#[derive(Default)]
+struct SymbolCollector {
+    bound: HashSet<String>,
+    used: HashSet<String>,
+    params: HashSet<String>,
+    /// Free names of nested functions defined in this scope.
+    child_free: HashSet<String>,
+    globals: HashSet<String>,
+    nonlocals: HashSet<String>,
+    /// See [`FunctionSymbols::outer_bound`].
+    outer_bound: HashSet<String>,
+    /// Whether this scope is a generator expression's function.
+    in_genexp: bool,
+    is_generator: bool,
+    /// The iteration variables of the comprehensions being visited, which
+    /// `:=` cannot rebind.
+    comprehension_targets: Vec<String>,
+    /// See [`FunctionSymbols::error`].
+    error: Option<String>,
+}
+
+impl SymbolCollector {
+    /// Analyze a nested function and record the names it needs from outside.
+    fn visit_nested(
+        &mut self,
+        parameters: Option<&ast::Parameters>,
+        visit_body: impl FnOnce(&mut SymbolCollector),
+    ) {
+        // Defaults are evaluated in the enclosing scope.
+        if let Some(parameters) = parameters {
+            for param in parameters.iter() {
+                if let Some(default) = param.default() {
+                    self.visit_expr(default);
+                }
+            }
+        }
+        let symbols = analyze_function(parameters, visit_body);
+        if self.error.is_none() {
+            self.error = symbols.error;
+        }
+        self.child_free.extend(symbols.free);
+        if self.in_genexp {
+            self.outer_bound.extend(symbols.outer_bound);
+        } else {
+            self.bound.extend(symbols.outer_bound);
+        }
+    }
+
+    /// Visit the part of a generator expression that runs in its own
+    /// function: everything except the outermost iterable.
+    fn visit_genexp_body(&mut self, genexp: &ast::ExprGenerator) {
+        self.in_genexp = true;
+        self.visit_comprehension(&genexp.generators, true, &[&genexp.elt]);
+    }
+
+    /// Visit the clauses of a comprehension and then its elements, noting
+    /// the iteration variables while inside it. `skip_first_iter` skips the
+    /// outermost iterable, which a generator expression evaluates outside.
+    fn visit_comprehension<'a>(
+        &mut self,
+        generators: &'a [ast::Comprehension],
+        skip_first_iter: bool,
+        elts: &[&'a Expr],
+    ) {
+        let depth = self.comprehension_targets.len();
+        for (i, comprehension) in generators.iter().enumerate() {
+            if i > 0 || !skip_first_iter {
+                self.visit_expr(&comprehension.iter);
+            }
+            target_names(&comprehension.target, &mut self.comprehension_targets);
+            self.visit_expr(&comprehension.target);
+            for condition in &comprehension.ifs {
+                self.visit_expr(condition);
+            }
+        }
+        for elt in elts {
+            self.visit_expr(elt);
+        }
+        self.comprehension_targets.truncate(depth);
+    }
+
+    /// Check a `global` or `nonlocal` declaration: the names cannot be
+    /// parameters or be used earlier in the scope.
+    fn check_declaration(&mut self, names: &[ast::Identifier], keyword: &str) {
+        for name in names {
+            let name = name.as_str();
+            let misuse = if self.params.contains(name) {
+                format!("name '{}' is parameter and {}", name, keyword)
+            } else if self.bound.contains(name) {
+                format!(
+                    "name '{}' is assigned to before {} declaration",
+                    name, keyword
+                )
+            } else if self.used.contains(name) {
+                format!("name '{}' is used prior to {} declaration", name, keyword)
+            } else {
+                continue;
+            };
+            self.syntax_error(misuse);
+        }
+    }
+
+    /// Record a `SyntaxError`, keeping the first one found.
+    fn syntax_error(&mut self, message: String) {
+        if self.error.is_none() {
+            self.error = Some(message);
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for SymbolCollector {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::FunctionDef(func_def) => {
+                self.bound.insert(func_def.name.to_string());
+                for decorator in &func_def.decorator_list {
+                    self.visit_decorator(decorator);
+                }
+                self.visit_nested(Some(&func_def.parameters), |collector| {
+                    collector.visit_body(&func_def.body)
+                });
+            }
+            Stmt::ClassDef(class_def) => {
+                // The class body is evaluated in the enclosing scope and
+                // binds nothing there except the class name itself.
+                self.bound.insert(class_def.name.to_string());
+                for decorator in &class_def.decorator_list {
+                    self.visit_decorator(decorator);
+                }
+                if let Some(arguments) = &class_def.arguments {
+                    self.visit_arguments(arguments);
+                }
+                for stmt in &class_def.body {
+                    match stmt {
+                        Stmt::FunctionDef(func_def) => {
+                            for decorator in &func_def.decorator_list {
+                                self.visit_decorator(decorator);
+                            }
+                            self.visit_nested(Some(&func_def.parameters), |collector| {
+                                collector.visit_body(&func_def.body)
+                            });
+                        }
+                        Stmt::Assign(assign) => self.visit_expr(&assign.value),
+                        Stmt::AnnAssign(ann) => {
+                            if let Some(value) = &ann.value {
+                                self.visit_expr(value);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Stmt::Import(import) => {
+                for alias in &import.names {
+                    let name = match &alias.asname {
+                        Some(asname) => asname.as_str(),
+                        None => alias.name.split('.').next().unwrap_or_default(),
+                    };
+                    self.bound.insert(name.to_string());
+                }
+            }
+            Stmt::ImportFrom(import) => {
+                for alias in &import.names {
+                    let name = alias.asname.as_ref().unwrap_or(&alias.name);
+                    self.bound.insert(name.to_string());
+                }
+            }
+            Stmt::Global(global) => {
+                self.check_declaration(&global.names, "global");
+                self.globals
+                    .extend(global.names.iter().map(|name| name.to_string()));
+            }
+            Stmt::Nonlocal(nonlocal) => {
+                self.check_declaration(&nonlocal.names, "nonlocal");
+                self.nonlocals
+                    .extend(nonlocal.names.iter().map(|name| name.to_string()));
+            }
+            _ => visitor::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::Name(name) => match name.ctx {
+                ExprContext::Load => {
+                    self.used.insert(name.id.to_string());
+                }
+                _ => {
+                    self.bound.insert(name.id.to_string());
+                }
+            },
+            Expr::Lambda(lambda) => {
+                self.visit_nested(lambda.parameters.as_deref(), |collector| {
+                    collector.visit_expr(&lambda.body)
+                });
+            }
+            Expr::Generator(genexp) => {
+                self.visit_expr(&genexp.generators[0].iter);
+                self.visit_nested(None, |collector| collector.visit_genexp_body(genexp));
+            }
+            Expr::ListComp(comp) => self.visit_comprehension(&comp.generators, false, &[&comp.elt]),
+            Expr::SetComp(comp) => self.visit_comprehension(&comp.generators, false, &[&comp.elt]),
+            Expr::DictComp(comp) => {
+                self.visit_comprehension(&comp.generators, false, &[&comp.key, &comp.value])
+            }
+            Expr::Named(named) => {
+                if let Expr::Name(name) = named.target.as_ref()
+                    && self.comprehension_targets.iter().any(|t| *t == name.id)
+                {
+                    self.syntax_error(format!(
+                        "assignment expression cannot rebind comprehension iteration variable '{}'",
+                        name.id
+                    ));
+                }
+                if !self.in_genexp {
+                    visitor::walk_expr(self, expr);
+                } else {
+                    if let Expr::Name(name) = named.target.as_ref() {
+                        self.outer_bound.insert(name.id.to_string());
+                    }
+                    self.visit_expr(&named.value);
+                }
+            }
+            Expr::Yield(_) | Expr::YieldFrom(_) => {
+                self.is_generator = true;
+                visitor::walk_expr(self, expr);
+            }
+            _ => visitor::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_pattern(&mut self, pattern: &'a ast::Pattern) {
+        let name = match pattern {
+            ast::Pattern::MatchAs(p) => p.name.as_ref(),
+            ast::Pattern::MatchStar(p) => p.name.as_ref(),
+            ast::Pattern::MatchMapping(p) => p.rest.as_ref(),
+            _ => None,
+        };
+        if let Some(name) = name {
+            self.bound.insert(name.to_string());
+        }
+        visitor::walk_pattern(self, pattern);
+    }
+
+    fn visit_except_handler(&mut self, except_handler: &'a ast::ExceptHandler) {
+        let ast::ExceptHandler::ExceptHandler(handler) = except_handler;
+        if let Some(name) = &handler.name {
+            self.bound.insert(name.to_string());
+        }
+        visitor::walk_except_handler(self, except_handler);
+    }
+
+    fn visit_annotation(&mut self, _expr: &'a Expr) {}
+}
+
+// ---------------------------------------------------------------------------
+// Standalone helpers (no &self)
+// ---------------------------------------------------------------------------
+
+/// Collect the names a `for` target binds.
+fn target_names(target: &Expr, names: &mut Vec<String>) {
+    match target {
+        Expr::Name(name) => names.push(name.id.to_string()),
+        Expr::Tuple(tuple) => tuple.elts.iter().for_each(|elt| target_names(elt, names)),
+        Expr::List(list) => list.elts.iter().for_each(|elt| target_names(elt, names)),
+        Expr::Starred(starred) => target_names(&starred.value, names),
+        _ => {}
+    }
+}
+
+/// Whether a display contains `*iterable` elements.
+fn has_starred(elts: &[Expr]) -> bool {
+    elts.iter().any(|elt| matches!(elt, Expr::Starred(_)))
+}
+
+/// Whether a call passes `*args` or `**kwargs`.
+fn has_call_unpacking(call: &ast::ExprCall) -> bool {
+    has_starred(&call.arguments.args) || call.arguments.keywords.iter().any(|kw| kw.arg.is_none())
+}
+
+/// The docstring of a function body: its leading string literal, if any.
+fn docstring(body: &[Stmt]) -> Option<String> {
+    match body.first()? {
+        Stmt::Expr(expr_stmt) => match &*expr_stmt.value {
+            Expr::StringLiteral(s) => Some(s.value.to_string()),
+            _ => None,
         },
-        Expr::List(l) if l.elts.is_empty() => Ok(PyValue::List(Vec::new())),
-        Expr::Dict(d) if d.items.is_empty() => Ok(PyValue::Dict(Vec::new())),
-        Expr::Tuple(t) => {
-            let items: Result<Vec<PyValue>> = t.elts.iter().map(eval_const_expr).collect();
-            Ok(PyValue::Tuple(items?))
-        }
-        _ => Err(Error::Unsupported(
-            "Non-constant default parameter value".to_string(),
-        )),
+        _ => None,
     }
 }
 
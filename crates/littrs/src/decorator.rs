@@ -0,0 +1,382 @@
+//! Host-provided decorators.
+//!
+//! A host registers a decorator factory with
+//! [`Sandbox::register_decorator`](crate::Sandbox::register_decorator). Python
+//! code applies it bare (`@cached`) or with arguments (`@cached(ttl=60)`);
+//! either way the factory returns a [`HostDecorator`] whose hooks run around
+//! every call of the decorated function. A `before` hook can answer a call
+//! without running the function, which is how a memoizing decorator serves
+//! results from host storage.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::class::records_to_dicts;
+use crate::dict::{Dict, check_hashable};
+use crate::error::{Error, Result};
+use crate::value::PyValue;
+
+/// A call of a decorated function, as seen by the decorator's hooks.
+///
+/// Arguments are copies: containers are never shared with the sandbox, and
+/// records (dataclass and `NamedTuple` instances) are converted to dicts.
+#[derive(Debug, Clone)]
+pub struct DecoratedCall {
+    /// The name of the decorated function.
+    pub function: String,
+    /// Positional arguments.
+    pub args: Vec<PyValue>,
+    /// Keyword arguments, in call order.
+    pub kwargs: Vec<(String, PyValue)>,
+}
+
+impl DecoratedCall {
+    /// A string describing the call, such as `fib(10)` or
+    /// `search('rust', limit=5)`, for logging. Different arguments can have
+    /// the same repr, so it does not identify a call.
+    pub fn key(&self) -> String {
+        let args = self.args.iter().map(|arg| arg.to_string());
+        let kwargs = self
+            .kwargs
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value));
+        let all: Vec<String> = args.chain(kwargs).collect();
+        format!("{}({})", self.function, all.join(", "))
+    }
+}
+
+type BeforeHook = Arc<dyn Fn(&DecoratedCall) -> Option<PyValue> + Send + Sync>;
+type AfterHook = Arc<dyn Fn(&DecoratedCall, &PyValue) + Send + Sync>;
+type ApplyHook = Arc<dyn Fn(PyValue) -> Result<PyValue> + Send + Sync>;
+
+/// Creates a [`HostDecorator`] from the arguments of `@name(...)`. Bare
+/// `@name` passes no arguments.
+pub type DecoratorFactory =
+    Arc<dyn Fn(&[PyValue], &[(String, PyValue)]) -> HostDecorator + Send + Sync>;
+
+/// The hooks a host decorator runs around each call of the function it
+/// decorates.
+///
+/// # Example
+///
+/// ```
+/// use littrs::{HostDecorator, PyValue};
+///
+/// let logged = HostDecorator::new()
+///     .after(|call, result| println!("{} -> {}", call.key(), result));
+/// ```
+#[derive(Clone, Default)]
+pub struct HostDecorator {
+    before: Option<BeforeHook>,
+    after: Option<AfterHook>,
+    /// Whether the hooks see the sandbox's own values rather than copies
+    /// (for built-in decorators such as `functools.lru_cache`).
+    raw: bool,
+    /// Whether calls with unhashable arguments raise `TypeError`, as they
+    /// do for `functools.lru_cache`.
+    hashable_args: bool,
+    /// Replaces the decorated value when the decorator is applied (for
+    /// built-in class decorators such as `dataclasses.dataclass`).
+    apply: Option<ApplyHook>,
+}
+
+impl HostDecorator {
+    /// A decorator without hooks; it leaves functions unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` before each call. Returning `Some(value)` skips the call and
+    /// makes `value` its result.
+    pub fn before<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&DecoratedCall) -> Option<PyValue> + Send + Sync + 'static,
+    {
+        self.before = Some(Arc::new(f));
+        self
+    }
+
+    /// Run `f` with the result of each call of the function that returns
+    /// normally. Calls answered by the `before` hook skip it.
+    pub fn after<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&DecoratedCall, &PyValue) + Send + Sync + 'static,
+    {
+        self.after = Some(Arc::new(f));
+        self
+    }
+
+    /// Replace the decorated value with the result of `f`.
+    pub(crate) fn apply<F>(mut self, f: F) -> Self
+    where
+        F: Fn(PyValue) -> Result<PyValue> + Send + Sync + 'static,
+    {
+        self.apply = Some(Arc::new(f));
+        self
+    }
+
+    fn has_hooks(&self) -> bool {
+        self.before.is_some() || self.after.is_some()
+    }
+
+    /// Copy a value handed to the hooks.
+    pub(crate) fn convert(&self, value: &PyValue) -> PyValue {
+        if self.raw {
+            value.clone()
+        } else {
+            to_host(value)
+        }
+    }
+
+    /// Run the `before` hook, returning the result it supplies.
+    pub(crate) fn run_before(&self, call: &DecoratedCall) -> Result<Option<PyValue>> {
+        if self.hashable_args {
+            let kwargs = call.kwargs.iter().map(|(_, value)| value);
+            call.args
+                .iter()
+                .chain(kwargs)
+                .try_for_each(check_hashable)?;
+        }
+        let Some(value) = self.before.as_ref().and_then(|before| before(call)) else {
+            return Ok(None);
+        };
+        Ok(Some(if self.raw { value } else { value.deep_copy() }))
+    }
+
+    /// Run the `after` hook with the result of a call.
+    pub(crate) fn run_after(&self, call: &DecoratedCall, result: &PyValue) {
+        if let Some(after) = &self.after {
+            after(call, &self.convert(result));
+        }
+    }
+}
+
+/// How far a decorator value has been applied.
+enum DecoratorState {
+    /// The registered factory: `@name` or `@name(...)`.
+    Factory(DecoratorFactory),
+    /// The result of `@name(...)`, waiting for the function.
+    Configured(HostDecorator),
+    /// A decorated function.
+    Wrapped {
+        hooks: HostDecorator,
+        func: Box<PyValue>,
+    },
+}
+
+/// A host decorator, at any stage of being applied. Clones refer to the same
+/// decorator.
+#[derive(Clone)]
+pub struct DecoratorValue {
+    /// The name the decorator was registered under.
+    pub name: String,
+    state: Arc<DecoratorState>,
+}
+
+/// What calling a [`DecoratorValue`] does.
+pub(crate) enum DecoratorCall {
+    /// Produce this value (a configured or wrapped decorator, or the
+    /// function itself).
+    Value(PyValue),
+    /// Call the decorated function with these hooks around it.
+    Invoke { hooks: HostDecorator, func: PyValue },
+}
+
+impl DecoratorValue {
+    pub(crate) fn factory(name: &str, factory: DecoratorFactory) -> Self {
+        Self::with_state(name, DecoratorState::Factory(factory))
+    }
+
+    pub(crate) fn configured(name: &str, hooks: HostDecorator) -> Self {
+        Self::with_state(name, DecoratorState::Configured(hooks))
+    }
+
+    fn with_state(name: &str, state: DecoratorState) -> Self {
+        Self {
+            name: name.to_string(),
+            state: Arc::new(state),
+        }
+    }
+
+    /// Decide what a call with the given arguments does.
+    ///
+    /// A factory called with a single callable is the bare `@name` form and
+    /// decorates it directly; any other call configures the decorator.
+    pub(crate) fn call(
+        &self,
+        args: &[PyValue],
+        kwargs: &[(String, PyValue)],
+    ) -> Result<DecoratorCall> {
+        match &*self.state {
+            DecoratorState::Factory(factory) => match (args, kwargs) {
+                ([func], []) if is_callable(func) => {
+                    Ok(DecoratorCall::Value(self.wrap(factory(&[], &[]), func)?))
+                }
+                _ => {
+                    let args: Vec<PyValue> = args.iter().map(to_host).collect();
+                    let kwargs: Vec<(String, PyValue)> = kwargs
+                        .iter()
+                        .map(|(name, value)| (name.clone(), to_host(value)))
+                        .collect();
+                    let hooks = factory(&args, &kwargs);
+                    Ok(DecoratorCall::Value(PyValue::Decorator(Self::configured(
+                        &self.name, hooks,
+                    ))))
+                }
+            },
+            DecoratorState::Configured(hooks) => match (args, kwargs) {
+                ([func], []) if is_callable(func) => {
+                    Ok(DecoratorCall::Value(self.wrap(hooks.clone(), func)?))
+                }
+                _ => Err(Error::exception(
+                    "TypeError",
+                    format!("{}() takes a single function to decorate", self.name),
+                )),
+            },
+            DecoratorState::Wrapped { hooks, func } => Ok(DecoratorCall::Invoke {
+                hooks: hooks.clone(),
+                func: (**func).clone(),
+            }),
+        }
+    }
+
+    fn wrap(&self, hooks: HostDecorator, func: &PyValue) -> Result<PyValue> {
+        let func = match &hooks.apply {
+            Some(apply) => apply(func.clone())?,
+            None => func.clone(),
+        };
+        if !hooks.has_hooks() {
+            return Ok(func);
+        }
+        Ok(PyValue::Decorator(Self::with_state(
+            &self.name,
+            DecoratorState::Wrapped {
+                hooks,
+                func: Box::new(func),
+            },
+        )))
+    }
+
+    /// The decorated function, once the decorator has been applied.
+    pub fn wrapped(&self) -> Option<&PyValue> {
+        match &*self.state {
+            DecoratorState::Wrapped { func, .. } => Some(func),
+            _ => None,
+        }
+    }
+}
+
+/// The name a decorated callable is reported under in [`DecoratedCall`].
+pub(crate) fn callable_name(func: &PyValue) -> String {
+    match func {
+        PyValue::Function(func) | PyValue::BoundMethod { func, .. } => func.name.clone(),
+        PyValue::Class(class) => class.name.clone(),
+        PyValue::Type(name) => name.clone(),
+        PyValue::MethodDescriptor { method, .. } => method.clone(),
+        PyValue::NativeFunction(key) => key.rsplit("__").next().unwrap_or(key).to_string(),
+        PyValue::Decorator(decorator) => match decorator.wrapped() {
+            Some(func) => callable_name(func),
+            None => decorator.name.clone(),
+        },
+        other => other.type_name().to_string(),
+    }
+}
+
+/// The docstring of a callable, looking through applied decorators.
+pub(crate) fn callable_doc(func: &PyValue) -> Option<String> {
+    match func {
+        PyValue::Function(func) | PyValue::BoundMethod { func, .. } => func.doc.clone(),
+        PyValue::Decorator(decorator) => decorator.wrapped().and_then(callable_doc),
+        _ => None,
+    }
+}
+
+/// Copy a value for the host, converting records to dicts.
+fn to_host(value: &PyValue) -> PyValue {
+    records_to_dicts(value.clone())
+}
+
+fn is_callable(value: &PyValue) -> bool {
+    matches!(
+        value,
+        PyValue::Function(_)
+            | PyValue::BoundMethod { .. }
+            | PyValue::Class(_)
+            | PyValue::Type(_)
+            | PyValue::NativeFunction(_)
+            | PyValue::Decorator(_)
+    )
+}
+
+impl PartialEq for DecoratorValue {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl fmt::Debug for DecoratorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for DecoratorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.wrapped() {
+            Some(func) => write!(f, "{}", func),
+            None => write!(f, "<built-in function {}>", self.name),
+        }
+    }
+}
+
+/// `functools.lru_cache` and `functools.cache`: memoize calls by their
+/// arguments, which must be hashable. The cache is a [`Dict`] keyed by the
+/// arguments; a used entry moves to the end, so the least recently used
+/// entry is always first.
+pub(crate) fn lru_cache(maxsize: Option<usize>) -> HostDecorator {
+    let cache: Arc<Mutex<Dict>> = Arc::default();
+    let lookup = {
+        let cache = cache.clone();
+        move |call: &DecoratedCall| {
+            let mut cache = cache.lock().unwrap();
+            let key = cache_key(call);
+            let result = cache.remove(&key)?;
+            cache.insert(key, result.clone());
+            Some(result)
+        }
+    };
+    let store = move |call: &DecoratedCall, result: &PyValue| {
+        if maxsize == Some(0) {
+            return;
+        }
+        let mut cache = cache.lock().unwrap();
+        cache.insert(cache_key(call), result.clone());
+        if let Some(maxsize) = maxsize
+            && cache.len() > maxsize
+        {
+            let oldest = cache.keys().next().cloned();
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+    };
+    HostDecorator {
+        raw: true,
+        hashable_args: true,
+        ..HostDecorator::new().before(lookup).after(store)
+    }
+}
+
+/// The arguments of a call as a hashable cache key: a tuple of the
+/// positional arguments and the `(name, value)` keyword pairs.
+fn cache_key(call: &DecoratedCall) -> PyValue {
+    let kwargs = call
+        .kwargs
+        .iter()
+        .map(|(name, value)| PyValue::Tuple(vec![PyValue::Str(name.clone()), value.clone()]))
+        .collect();
+    PyValue::Tuple(vec![
+        PyValue::Tuple(call.args.clone()),
+        PyValue::Tuple(kwargs),
+    ])
+}
@@ -0,0 +1,685 @@
+//! Insertion-ordered hash tables: the storage of dicts and sets.
+//!
+//! [`Dict`] and [`Set`] keep their entries in insertion order, as Python's
+//! do, and index them by [`PyValue::hash_value`], so lookups, insertions
+//! and removals take constant time on average. Keys must be hashable:
+//! inserting an unhashable key panics and looking one up finds nothing, so
+//! code handling sandbox values checks them with [`check_hashable`] first.
+//!
+//! A [`DictView`] is the live view of a dict that `dict.keys()`,
+//! `dict.values()` and `dict.items()` return.
+
+use std::fmt;
+
+use hashbrown::HashTable;
+
+use crate::error::{Error, Result};
+use crate::value::{PyValue, Shared};
+
+/// Raise Python's `TypeError` for a value that cannot be a dict key or a
+/// set item.
+pub(crate) fn check_hashable(value: &PyValue) -> Result<()> {
+    if value.is_hashable() {
+        Ok(())
+    } else {
+        Err(Error::exception(
+            "TypeError",
+            format!("unhashable type: '{}'", value.class_name()),
+        ))
+    }
+}
+
+#[derive(Clone)]
+struct Entry<V> {
+    hash: u64,
+    key: PyValue,
+    value: V,
+}
+
+/// The table behind [`Dict`] (`V = PyValue`) and [`Set`] (`V = ()`).
+#[derive(Clone)]
+struct Table<V> {
+    /// Entries in insertion order. Removing an entry leaves a hole, and
+    /// the holes are dropped once they outnumber the live entries. The
+    /// last entry is never a hole.
+    entries: Vec<Option<Entry<V>>>,
+    /// The positions in `entries` of the live entries, by key hash.
+    indices: HashTable<usize>,
+    /// Every entry before this position is a hole.
+    start: usize,
+    /// Counts the entries added and removed, so that iterators can tell
+    /// when the keys changed under them.
+    version: u64,
+}
+
+/// The live entry at `pos`.
+fn live<V>(entries: &[Option<Entry<V>>], pos: usize) -> &Entry<V> {
+    entries[pos].as_ref().expect("indexed entries are live")
+}
+
+impl<V> Table<V> {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+            indices: HashTable::with_capacity(capacity),
+            start: 0,
+            version: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// The position of `key`'s entry.
+    fn position(&self, key: &PyValue) -> Option<usize> {
+        if !key.is_hashable() {
+            return None;
+        }
+        let entries = &self.entries;
+        self.indices
+            .find(key.hash_value(), |&pos| live(entries, pos).key == *key)
+            .copied()
+    }
+
+    fn get(&self, key: &PyValue) -> Option<&Entry<V>> {
+        self.position(key).map(|pos| live(&self.entries, pos))
+    }
+
+    /// Set `key` to `value`, keeping the position (and the key object) of
+    /// an existing entry. Returns the previous value.
+    fn insert(&mut self, key: PyValue, value: V) -> Option<V> {
+        let hash = key.hash_value();
+        let entries = &mut self.entries;
+        if let Some(&pos) = self
+            .indices
+            .find(hash, |&pos| live(entries, pos).key == key)
+        {
+            let entry = entries[pos].as_mut().expect("indexed entries are live");
+            return Some(std::mem::replace(&mut entry.value, value));
+        }
+        let pos = entries.len();
+        entries.push(Some(Entry { hash, key, value }));
+        self.indices
+            .insert_unique(hash, pos, |&pos| live(entries, pos).hash);
+        self.version += 1;
+        None
+    }
+
+    fn remove(&mut self, key: &PyValue) -> Option<(PyValue, V)> {
+        let pos = self.position(key)?;
+        Some(self.remove_at(pos))
+    }
+
+    /// Remove the first entry in insertion order.
+    fn pop_first(&mut self) -> Option<(PyValue, V)> {
+        let offset = self.entries[self.start..]
+            .iter()
+            .position(Option::is_some)?;
+        self.start += offset;
+        Some(self.remove_at(self.start))
+    }
+
+    /// Remove the last entry in insertion order.
+    fn pop_last(&mut self) -> Option<(PyValue, V)> {
+        let pos = self.entries.len().checked_sub(1)?;
+        Some(self.remove_at(pos))
+    }
+
+    /// Remove the live entry at `pos`.
+    fn remove_at(&mut self, pos: usize) -> (PyValue, V) {
+        let hash = live(&self.entries, pos).hash;
+        if let Ok(index) = self.indices.find_entry(hash, |&i| i == pos) {
+            index.remove();
+        }
+        let entry = self.entries[pos].take().expect("indexed entries are live");
+        self.version += 1;
+        while matches!(self.entries.last(), Some(None)) {
+            self.entries.pop();
+        }
+        if self.entries.len() > 2 * self.len() + 8 {
+            self.compact();
+        }
+        (entry.key, entry.value)
+    }
+
+    /// Drop the holes left by removed entries.
+    fn compact(&mut self) {
+        self.entries.retain(Option::is_some);
+        self.start = 0;
+        self.indices.clear();
+        for pos in 0..self.entries.len() {
+            let hash = live(&self.entries, pos).hash;
+            let entries = &self.entries;
+            self.indices
+                .insert_unique(hash, pos, |&pos| live(entries, pos).hash);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.indices.clear();
+        self.start = 0;
+        self.version += 1;
+    }
+
+    fn iter(&self) -> impl DoubleEndedIterator<Item = &Entry<V>> {
+        self.entries[self.start..].iter().flatten()
+    }
+
+    /// The first live entry at or after position `pos`, with its position.
+    fn entry_from(&self, pos: usize) -> Option<(usize, &Entry<V>)> {
+        let from = pos.max(self.start);
+        self.entries
+            .get(from..)?
+            .iter()
+            .enumerate()
+            .find_map(|(offset, entry)| Some((from + offset, entry.as_ref()?)))
+    }
+}
+
+impl<V: PartialEq> PartialEq for Table<V> {
+    /// Tables are equal when they hold equal entries, in any order.
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.iter().all(|entry| {
+                other
+                    .get(&entry.key)
+                    .is_some_and(|o| o.value == entry.value)
+            })
+    }
+}
+
+// ============================================================================
+// Dict
+// ============================================================================
+
+/// The contents of a dict: key-value pairs in insertion order, indexed by
+/// key hash.
+///
+/// ```
+/// use littrs::{Dict, PyValue};
+///
+/// let mut dict = Dict::new();
+/// dict.insert(PyValue::from("a"), PyValue::Int(1));
+/// dict.insert(PyValue::from("b"), PyValue::Int(2));
+/// assert_eq!(dict.get(&PyValue::from("b")), Some(&PyValue::Int(2)));
+/// assert_eq!(dict.keys().collect::<Vec<_>>(), [&PyValue::from("a"), &PyValue::from("b")]);
+/// ```
+#[derive(Clone)]
+pub struct Dict(Table<PyValue>);
+
+impl Dict {
+    /// An empty dict.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// An empty dict with room for `capacity` pairs.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(Table::with_capacity(capacity))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value for `key`, if present.
+    pub fn get(&self, key: &PyValue) -> Option<&PyValue> {
+        self.0.get(key).map(|entry| &entry.value)
+    }
+
+    pub fn contains_key(&self, key: &PyValue) -> bool {
+        self.0.position(key).is_some()
+    }
+
+    /// Set `key` to `value`, keeping the position of an existing key, and
+    /// return the previous value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is unhashable.
+    pub fn insert(&mut self, key: PyValue, value: PyValue) -> Option<PyValue> {
+        self.0.insert(key, value)
+    }
+
+    /// Remove `key` and return its value, if present.
+    pub fn remove(&mut self, key: &PyValue) -> Option<PyValue> {
+        self.0.remove(key).map(|(_, value)| value)
+    }
+
+    /// Remove and return the most recently inserted pair, as
+    /// `dict.popitem()` does.
+    pub fn pop_last(&mut self) -> Option<(PyValue, PyValue)> {
+        self.0.pop_last()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// The pairs in insertion order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&PyValue, &PyValue)> {
+        self.0.iter().map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// The keys in insertion order.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &PyValue> {
+        self.0.iter().map(|entry| &entry.key)
+    }
+
+    /// The values in insertion order.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &PyValue> {
+        self.0.iter().map(|entry| &entry.value)
+    }
+
+    /// Changes whenever a key is added or removed.
+    pub(crate) fn version(&self) -> u64 {
+        self.0.version
+    }
+
+    /// The first pair at or after position `pos` in insertion order, with
+    /// its position. Positions stay valid while [`Dict::version`] does.
+    pub(crate) fn pair_from(&self, pos: usize) -> Option<(usize, &PyValue, &PyValue)> {
+        self.0
+            .entry_from(pos)
+            .map(|(pos, entry)| (pos, &entry.key, &entry.value))
+    }
+}
+
+impl Default for Dict {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for Dict {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl fmt::Debug for Dict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Later pairs overwrite earlier ones with the same key, as in a dict
+/// display.
+///
+/// # Panics
+///
+/// Panics if a key is unhashable.
+impl FromIterator<(PyValue, PyValue)> for Dict {
+    fn from_iter<I: IntoIterator<Item = (PyValue, PyValue)>>(pairs: I) -> Self {
+        let mut dict = Dict::new();
+        dict.extend(pairs);
+        dict
+    }
+}
+
+impl Extend<(PyValue, PyValue)> for Dict {
+    fn extend<I: IntoIterator<Item = (PyValue, PyValue)>>(&mut self, pairs: I) {
+        for (key, value) in pairs {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl From<Vec<(PyValue, PyValue)>> for Dict {
+    fn from(pairs: Vec<(PyValue, PyValue)>) -> Self {
+        pairs.into_iter().collect()
+    }
+}
+
+// ============================================================================
+// Set
+// ============================================================================
+
+/// The contents of a set or frozenset: distinct items in insertion order,
+/// indexed by hash.
+///
+/// ```
+/// use littrs::{PyValue, Set};
+///
+/// let set: Set = [1, 2, 1].into_iter().map(PyValue::Int).collect();
+/// assert_eq!(set.len(), 2);
+/// assert!(set.contains(&PyValue::Int(2)));
+/// ```
+#[derive(Clone)]
+pub struct Set(Table<()>);
+
+impl Set {
+    /// An empty set.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// An empty set with room for `capacity` items.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(Table::with_capacity(capacity))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, item: &PyValue) -> bool {
+        self.0.position(item).is_some()
+    }
+
+    /// Add `item`, returning whether it was not already present.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `item` is unhashable.
+    pub fn insert(&mut self, item: PyValue) -> bool {
+        self.0.insert(item, ()).is_none()
+    }
+
+    /// Remove `item`, returning whether it was present.
+    pub fn remove(&mut self, item: &PyValue) -> bool {
+        self.0.remove(item).is_some()
+    }
+
+    /// Remove and return the oldest item, as `set.pop()` does.
+    pub fn pop_first(&mut self) -> Option<PyValue> {
+        self.0.pop_first().map(|(item, _)| item)
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// The items in insertion order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &PyValue> {
+        self.0.iter().map(|entry| &entry.key)
+    }
+
+    /// The first item at or after position `pos` in insertion order, with
+    /// its position.
+    pub(crate) fn item_from(&self, pos: usize) -> Option<(usize, &PyValue)> {
+        self.0.entry_from(pos).map(|(pos, entry)| (pos, &entry.key))
+    }
+
+    /// Whether every item is also in `other`.
+    pub fn is_subset(&self, other: &Set) -> bool {
+        self.len() <= other.len() && self.iter().all(|item| other.contains(item))
+    }
+
+    /// Whether no item is also in `other`.
+    pub fn is_disjoint(&self, other: &Set) -> bool {
+        let (small, large) = if self.len() <= other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        !small.iter().any(|item| large.contains(item))
+    }
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for Set {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl fmt::Debug for Set {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Duplicates after the first are dropped.
+///
+/// # Panics
+///
+/// Panics if an item is unhashable.
+impl FromIterator<PyValue> for Set {
+    fn from_iter<I: IntoIterator<Item = PyValue>>(items: I) -> Self {
+        let mut set = Set::new();
+        set.extend(items);
+        set
+    }
+}
+
+impl Extend<PyValue> for Set {
+    fn extend<I: IntoIterator<Item = PyValue>>(&mut self, items: I) {
+        for item in items {
+            self.insert(item);
+        }
+    }
+}
+
+impl From<Vec<PyValue>> for Set {
+    fn from(items: Vec<PyValue>) -> Self {
+        items.into_iter().collect()
+    }
+}
+
+// ============================================================================
+// Dict views
+// ============================================================================
+
+/// Which entries of a dict a [`DictView`] shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DictViewKind {
+    Keys,
+    Values,
+    Items,
+}
+
+/// The object `dict.keys()`, `dict.values()` or `dict.items()` returns: a
+/// live view of the dict, which sees later changes to it.
+#[derive(Clone, Debug)]
+pub struct DictView {
+    dict: Shared<Dict>,
+    kind: DictViewKind,
+}
+
+impl DictView {
+    pub(crate) fn new(dict: Shared<Dict>, kind: DictViewKind) -> Self {
+        Self { dict, kind }
+    }
+
+    /// The dict the view shows.
+    pub(crate) fn dict(&self) -> &Shared<Dict> {
+        &self.dict
+    }
+
+    pub fn kind(&self) -> DictViewKind {
+        self.kind
+    }
+
+    /// The Python type name: `dict_keys`, `dict_values` or `dict_items`.
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            DictViewKind::Keys => "dict_keys",
+            DictViewKind::Values => "dict_values",
+            DictViewKind::Items => "dict_items",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dict.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The keys, values or `(key, value)` tuples, in the dict's order.
+    pub fn items(&self) -> Vec<PyValue> {
+        let dict = self.dict.read();
+        match self.kind {
+            DictViewKind::Keys => dict.keys().cloned().collect(),
+            DictViewKind::Values => dict.values().cloned().collect(),
+            DictViewKind::Items => dict
+                .iter()
+                .map(|(key, value)| PyValue::Tuple(vec![key.clone(), value.clone()]))
+                .collect(),
+        }
+    }
+
+    /// Whether `value` is one of the view's items, as `in` tests.
+    pub(crate) fn contains(&self, value: &PyValue) -> bool {
+        let dict = self.dict.read();
+        match (self.kind, value) {
+            (DictViewKind::Keys, key) => dict.contains_key(key),
+            (DictViewKind::Values, value) => dict.values().any(|v| v == value),
+            (DictViewKind::Items, PyValue::Tuple(pair)) => match pair.as_slice() {
+                [key, value] => dict.get(key) == Some(value),
+                _ => false,
+            },
+            (DictViewKind::Items, _) => false,
+        }
+    }
+
+    /// The contents of a keys view, or of an items view whose values are
+    /// all hashable, as a set. Those views support the set operators.
+    pub(crate) fn as_set(&self) -> Option<Set> {
+        match self.kind {
+            DictViewKind::Keys => Some(self.dict.read().keys().cloned().collect()),
+            DictViewKind::Items if self.dict.read().values().all(PyValue::is_hashable) => {
+                Some(self.items().into_iter().collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// The repr, such as `dict_keys(['a', 'b'])`.
+    pub(crate) fn repr(&self) -> String {
+        format!("{}({})", self.type_name(), PyValue::list(self.items()))
+    }
+}
+
+/// Keys and items views are equal when they hold the same items, in any
+/// order. Values views are only equal to themselves in Python, and views
+/// keep no identity of their own, so they are never equal here.
+impl PartialEq for DictView {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.kind != DictViewKind::Values
+            && self.as_set().is_some_and(|set| Some(set) == other.as_set())
+    }
+}
+
+// ============================================================================
+// Serialization
+// ============================================================================
+
+// Dicts serialize as lists of `[key, value]` pairs and sets as lists of
+// items, as they did when they were stored as vectors.
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Dict {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Dict {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let pairs = Vec::<(PyValue, PyValue)>::deserialize(deserializer)?;
+        if let Some((key, _)) = pairs.iter().find(|(key, _)| !key.is_hashable()) {
+            return Err(serde::de::Error::custom(format!(
+                "unhashable dict key: '{}'",
+                key.type_name()
+            )));
+        }
+        Ok(pairs.into())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Set {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Set {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let items = Vec::<PyValue>::deserialize(deserializer)?;
+        if let Some(item) = items.iter().find(|item| !item.is_hashable()) {
+            return Err(serde::de::Error::custom(format!(
+                "unhashable set item: '{}'",
+                item.type_name()
+            )));
+        }
+        Ok(items.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(i: i64) -> PyValue {
+        PyValue::Int(i)
+    }
+
+    #[test]
+    fn test_dict_keeps_insertion_order_across_removals() {
+        let mut dict: Dict = (0..100).map(|i| (int(i), int(i * 10))).collect();
+        for i in (0..100).filter(|i| i % 3 != 0) {
+            assert_eq!(dict.remove(&int(i)), Some(int(i * 10)));
+        }
+        dict.insert(int(1), int(-1));
+        dict.insert(int(0), int(0));
+        let keys: Vec<i64> = dict.keys().map(|k| k.as_int().unwrap()).collect();
+        let mut expected: Vec<i64> = (0..100).filter(|i| i % 3 == 0).collect();
+        expected.push(1);
+        assert_eq!(keys, expected);
+        assert_eq!(dict.get(&int(99)), Some(&int(990)));
+        assert_eq!(dict.get(&int(98)), None);
+        assert_eq!(dict.pop_last(), Some((int(1), int(-1))));
+    }
+
+    #[test]
+    fn test_set_pop_first_and_equality() {
+        let mut set: Set = (0..50).map(int).collect();
+        for i in 0..40 {
+            assert_eq!(set.pop_first(), Some(int(i)));
+        }
+        assert_eq!(set.len(), 10);
+        let reversed: Set = (40..50).rev().map(int).collect();
+        assert_eq!(set, reversed);
+        assert!(set.is_subset(&reversed) && !set.is_disjoint(&reversed));
+        assert!(!set.contains(&PyValue::list(vec![])));
+    }
+
+    #[test]
+    fn test_equal_floats_share_a_key() {
+        let mut dict = Dict::new();
+        dict.insert(PyValue::Float(0.0), int(1));
+        assert_eq!(dict.get(&PyValue::Float(-0.0)), Some(&int(1)));
+    }
+}
@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 use crate::diagnostic::Diagnostic;
+use crate::exception::ExceptionValue;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -10,6 +11,11 @@ pub enum Error {
     #[error("Runtime error: {0}")]
     Runtime(String),
 
+    /// A Python exception raised by sandbox code (or by a builtin) that was
+    /// not caught.
+    #[error("Runtime error: {0}")]
+    Exception(Box<ExceptionValue>),
+
     #[error("Type error: expected {expected}, got {got}")]
     Type { expected: String, got: String },
 
@@ -34,6 +40,12 @@ pub enum Error {
 }
 
 impl Error {
+    /// Create a built-in Python exception with a message, such as
+    /// `Error::exception("ValueError", "empty separator")`.
+    pub fn exception(type_name: &str, message: impl Into<String>) -> Self {
+        Error::Exception(Box::new(ExceptionValue::with_message(type_name, message)))
+    }
+
     /// Create a new diagnostic error.
     pub fn diagnostic(diagnostic: Diagnostic) -> Self {
         Error::Diagnostic(diagnostic)
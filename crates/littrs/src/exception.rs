@@ -0,0 +1,240 @@
+//! Exception values and the built-in exception hierarchy.
+//!
+//! Raised exceptions are first-class values ([`ExceptionValue`]) carrying
+//! their class chain, constructor arguments and chaining information
+//! (`__cause__` / `__context__`). `except` clauses match against the class
+//! chain, so `except LookupError` catches both `KeyError` and `IndexError`.
+
+use std::fmt;
+
+use crate::error::Error;
+use crate::value::PyValue;
+
+/// Built-in exception classes and their direct bases, mirroring CPython.
+const BUILTIN_EXCEPTIONS: &[(&str, &[&str])] = &[
+    ("BaseException", &[]),
+    ("SystemExit", &["BaseException"]),
+    ("KeyboardInterrupt", &["BaseException"]),
+    ("GeneratorExit", &["BaseException"]),
+    ("Exception", &["BaseException"]),
+    ("ArithmeticError", &["Exception"]),
+    ("FloatingPointError", &["ArithmeticError"]),
+    ("OverflowError", &["ArithmeticError"]),
+    ("ZeroDivisionError", &["ArithmeticError"]),
+    ("AssertionError", &["Exception"]),
+    ("AttributeError", &["Exception"]),
+    ("FrozenInstanceError", &["AttributeError"]),
+    ("BufferError", &["Exception"]),
+    ("EOFError", &["Exception"]),
+    ("ImportError", &["Exception"]),
+    ("ModuleNotFoundError", &["ImportError"]),
+    ("LookupError", &["Exception"]),
+    ("IndexError", &["LookupError"]),
+    ("KeyError", &["LookupError"]),
+    ("MemoryError", &["Exception"]),
+    ("NameError", &["Exception"]),
+    ("UnboundLocalError", &["NameError"]),
+    ("OSError", &["Exception"]),
+    ("FileExistsError", &["OSError"]),
+    ("FileNotFoundError", &["OSError"]),
+    ("IsADirectoryError", &["OSError"]),
+    ("NotADirectoryError", &["OSError"]),
+    ("PermissionError", &["OSError"]),
+    ("TimeoutError", &["OSError"]),
+    ("ConnectionError", &["OSError"]),
+    ("UnsupportedOperation", &["OSError", "ValueError"]),
+    ("RuntimeError", &["Exception"]),
+    ("NotImplementedError", &["RuntimeError"]),
+    ("RecursionError", &["RuntimeError"]),
+    ("StopIteration", &["Exception"]),
+    ("SyntaxError", &["Exception"]),
+    ("TypeError", &["Exception"]),
+    ("ValueError", &["Exception"]),
+    ("UnicodeError", &["ValueError"]),
+    ("UnicodeDecodeError", &["UnicodeError"]),
+    ("UnicodeEncodeError", &["UnicodeError"]),
+];
+
+/// Whether `name` is a built-in exception class.
+pub fn is_builtin_exception(name: &str) -> bool {
+    builtin_bases(name).is_some()
+}
+
+/// The direct bases of a built-in exception class.
+fn builtin_bases(name: &str) -> Option<&'static [&'static str]> {
+    BUILTIN_EXCEPTIONS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, bases)| *bases)
+}
+
+/// The method resolution order of a built-in exception class, starting with
+/// the class itself and ending with `BaseException`.
+pub fn builtin_mro(name: &str) -> Option<Vec<String>> {
+    fn walk(name: &str, out: &mut Vec<String>) {
+        out.push(name.to_string());
+        for base in builtin_bases(name).unwrap_or_default() {
+            walk(base, out);
+        }
+    }
+
+    builtin_bases(name)?;
+    let mut order = Vec::new();
+    walk(name, &mut order);
+    // Keep the last occurrence of each class so shared bases come after
+    // every class that derives from them (only matters for diamonds like
+    // `UnsupportedOperation`).
+    let mut mro: Vec<String> = Vec::new();
+    for (i, class) in order.iter().enumerate() {
+        if !order[i + 1..].contains(class) {
+            mro.push(class.clone());
+        }
+    }
+    Some(mro)
+}
+
+/// A raised (or constructed) exception.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExceptionValue {
+    /// Class names from the exception's own class up to `BaseException`.
+    pub mro: Vec<String>,
+    /// Constructor arguments (`e.args`).
+    pub args: Vec<PyValue>,
+    /// Explicit cause set by `raise ... from cause` (`e.__cause__`).
+    pub cause: Option<Box<ExceptionValue>>,
+    /// The exception being handled when this one was raised (`e.__context__`).
+    pub context: Option<Box<ExceptionValue>>,
+    /// Set by `raise ... from None` (`e.__suppress_context__`).
+    pub suppress_context: bool,
+}
+
+impl ExceptionValue {
+    /// Create an instance of the built-in exception class `type_name`.
+    ///
+    /// Unknown names are treated as direct subclasses of `Exception`.
+    pub fn new(type_name: &str, args: Vec<PyValue>) -> Self {
+        let mro = builtin_mro(type_name).unwrap_or_else(|| {
+            vec![
+                type_name.to_string(),
+                "Exception".to_string(),
+                "BaseException".to_string(),
+            ]
+        });
+        Self {
+            mro,
+            args,
+            cause: None,
+            context: None,
+            suppress_context: false,
+        }
+    }
+
+    /// Create an exception with a single string message argument.
+    pub fn with_message(type_name: &str, message: impl Into<String>) -> Self {
+        Self::new(type_name, vec![PyValue::Str(message.into())])
+    }
+
+    /// The name of the exception's class.
+    pub fn type_name(&self) -> &str {
+        &self.mro[0]
+    }
+
+    /// The nearest built-in class in the exception's class chain.
+    pub fn builtin_type_name(&self) -> &'static str {
+        self.mro
+            .iter()
+            .find_map(|class| {
+                BUILTIN_EXCEPTIONS
+                    .iter()
+                    .find(|(n, _)| n == class)
+                    .map(|(n, _)| *n)
+            })
+            .unwrap_or("Exception")
+    }
+
+    /// Whether this exception is an instance of the class `class_name`
+    /// (directly or through inheritance).
+    pub fn is_instance(&self, class_name: &str) -> bool {
+        self.mro.iter().any(|c| c == class_name)
+    }
+
+    /// `str(e)`: empty for no arguments, the argument itself for one, and
+    /// the argument tuple otherwise. `KeyError` shows its key as a repr.
+    pub fn message(&self) -> String {
+        match self.args.as_slice() {
+            [] => String::new(),
+            [arg] if self.is_instance("KeyError") => arg.to_string(),
+            [arg] => arg.to_print_string(),
+            args => PyValue::Tuple(args.to_vec()).to_string(),
+        }
+    }
+
+    /// `repr(e)`, e.g. `ValueError('bad input')`.
+    pub fn repr(&self) -> String {
+        let args: Vec<String> = self.args.iter().map(|a| a.to_string()).collect();
+        format!("{}({})", self.type_name(), args.join(", "))
+    }
+
+    /// Look up an exception attribute such as `args` or `__cause__`.
+    pub fn get_attr(&self, name: &str) -> Option<PyValue> {
+        let chained = |exc: &Option<Box<ExceptionValue>>| match exc {
+            Some(exc) => PyValue::Exception(exc.clone()),
+            None => PyValue::None,
+        };
+        match name {
+            "args" => Some(PyValue::Tuple(self.args.clone())),
+            "__cause__" => Some(chained(&self.cause)),
+            "__context__" => Some(chained(&self.context)),
+            "__suppress_context__" => Some(PyValue::Bool(self.suppress_context)),
+            // What the generator that raised it returned
+            "value" if self.is_instance("StopIteration") => {
+                Some(self.args.first().cloned().unwrap_or(PyValue::None))
+            }
+            _ => None,
+        }
+    }
+
+    /// Convert an internal error into the exception value Python code sees.
+    ///
+    /// Builtins and methods raise typed exceptions ([`Error::exception`]);
+    /// any other runtime error is a `RuntimeError`.
+    pub(crate) fn from_error(err: &Error) -> Self {
+        match err {
+            Error::Exception(exc) => (**exc).clone(),
+            // Without the "Type error: " prefix the host sees
+            Error::Type { expected, got } => {
+                Self::with_message("TypeError", format!("expected {}, got {}", expected, got))
+            }
+            Error::DivisionByZero => Self::with_message("ZeroDivisionError", "division by zero"),
+            Error::NameError(name) => {
+                Self::with_message("NameError", format!("name '{}' is not defined", name))
+            }
+            Error::Runtime(msg) => Self::with_message("RuntimeError", msg.clone()),
+            Error::Parse(msg) => Self::with_message("SyntaxError", msg.clone()),
+            Error::Unsupported(_)
+            | Error::Diagnostic(_)
+            | Error::InstructionLimitExceeded(_)
+            | Error::RecursionLimitExceeded(_) => {
+                Self::with_message("RuntimeError", err.to_string())
+            }
+        }
+    }
+}
+
+impl fmt::Display for ExceptionValue {
+    /// Formats as the last line of a traceback: `KeyError: 'missing'`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.message();
+        if message.is_empty() {
+            write!(f, "{}", self.type_name())
+        } else {
+            write!(f, "{}: {}", self.type_name(), message)
+        }
+    }
+}
+
+impl From<ExceptionValue> for Error {
+    fn from(exc: ExceptionValue) -> Self {
+        Error::Exception(Box::new(exc))
+    }
+}
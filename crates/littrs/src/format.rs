@@ -0,0 +1,947 @@
+//! String formatting: the format-spec mini-language and `%`-formatting.
+//!
+//! [`format_value`] implements `format(value, spec)` for the built-in types
+//! and is shared by f-strings, `format()`, `str.format` and `%`. Templates
+//! for `str.format` are parsed here ([`parse_template`]) but their fields are
+//! resolved by the VM, since looking up an attribute or showing an instance
+//! may run sandbox code.
+
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive};
+
+use crate::bytecode::Conversion;
+use crate::error::{Error, Result};
+use crate::exception::ExceptionValue;
+use crate::int;
+use crate::text;
+use crate::value::PyValue;
+
+/// The largest width or precision a format spec may request. Guards the
+/// host against `f"{1:>2000000000}"` and similar memory bombs.
+const MAX_WIDTH: usize = 1 << 20;
+
+/// A parsed format spec:
+/// `[[fill]align][sign][z][#][0][width][grouping][.precision][type]`.
+#[derive(Clone, Copy)]
+struct FormatSpec {
+    fill: char,
+    align: Option<char>,
+    sign: Option<char>,
+    alternate: bool,
+    /// The `0` flag: zero-padding unless a fill or alignment is given.
+    zero: bool,
+    width: usize,
+    grouping: Option<char>,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+impl FormatSpec {
+    fn parse(spec: &str, type_name: &str) -> Result<Self> {
+        let chars: Vec<char> = spec.chars().collect();
+        let is_align = |c: Option<&char>| matches!(c, Some('<' | '>' | '^' | '='));
+        let mut parsed = FormatSpec {
+            fill: ' ',
+            align: None,
+            sign: None,
+            alternate: false,
+            zero: false,
+            width: 0,
+            grouping: None,
+            precision: None,
+            kind: None,
+        };
+        let mut i = 0;
+        let mut fill_given = false;
+        if is_align(chars.get(1)) {
+            parsed.fill = chars[0];
+            parsed.align = Some(chars[1]);
+            fill_given = true;
+            i = 2;
+        } else if is_align(chars.first()) {
+            parsed.align = Some(chars[0]);
+            i = 1;
+        }
+        if let Some(&c @ ('+' | '-' | ' ')) = chars.get(i) {
+            parsed.sign = Some(c);
+            i += 1;
+        }
+        // `z` (coerce negative zero) is accepted; zeros are never negative here
+        if chars.get(i) == Some(&'z') {
+            i += 1;
+        }
+        if chars.get(i) == Some(&'#') {
+            parsed.alternate = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'0') {
+            parsed.zero = true;
+            if !fill_given {
+                parsed.fill = '0';
+            }
+            i += 1;
+        }
+        parsed.width = take_number(&chars, &mut i)?.unwrap_or(0);
+        if let Some(&c @ (',' | '_')) = chars.get(i) {
+            parsed.grouping = Some(c);
+            i += 1;
+            if let Some(',' | '_') = chars.get(i) {
+                return Err(Error::exception(
+                    "ValueError",
+                    "Cannot specify both ',' and '_'.",
+                ));
+            }
+        }
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            parsed.precision = Some(take_number(&chars, &mut i)?.ok_or_else(|| {
+                Error::exception("ValueError", "Format specifier missing precision")
+            })?);
+        }
+        match &chars[i..] {
+            [] => {}
+            [kind] => parsed.kind = Some(*kind),
+            _ => {
+                return Err(Error::exception(
+                    "ValueError",
+                    format!(
+                        "Invalid format specifier '{}' for object of type '{}'",
+                        spec, type_name
+                    ),
+                ));
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Pad `prefix` (sign and base prefix) and `digits` to the width.
+    /// `=` alignment puts the padding between them.
+    fn pad(&self, prefix: &str, body: &str, default_align: char) -> String {
+        let len = prefix.chars().count() + body.chars().count();
+        if len >= self.width {
+            return format!("{}{}", prefix, body);
+        }
+        let pad = self.width - len;
+        let fill = |n: usize| self.fill.to_string().repeat(n);
+        match self.align.unwrap_or(default_align) {
+            '<' => format!("{}{}{}", prefix, body, fill(pad)),
+            '^' => format!("{}{}{}{}", fill(pad / 2), prefix, body, fill(pad - pad / 2)),
+            '=' => format!("{}{}{}", prefix, fill(pad), body),
+            _ => format!("{}{}{}", fill(pad), prefix, body),
+        }
+    }
+
+    /// The default alignment for numbers: after the sign when zero-padding.
+    fn number_align(&self) -> char {
+        if self.zero && self.align.is_none() {
+            '='
+        } else {
+            '>'
+        }
+    }
+
+    fn sign_prefix(&self, negative: bool) -> &'static str {
+        match (negative, self.sign) {
+            (true, _) => "-",
+            (false, Some('+')) => "+",
+            (false, Some(' ')) => " ",
+            _ => "",
+        }
+    }
+
+    /// Insert grouping separators into `digits` every `interval` digits,
+    /// followed by `rest` (a fraction or exponent). With zero-padding the
+    /// padding zeros are grouped too, as in `format(1234, "010,")`.
+    fn group(&self, prefix: &str, digits: &str, rest: &str, interval: usize) -> String {
+        let Some(separator) = self.grouping else {
+            return format!("{}{}", digits, rest);
+        };
+        let mut digits = digits.to_string();
+        let zero_pad = self.fill == '0' && self.align.unwrap_or(self.number_align()) == '=';
+        loop {
+            let grouped = insert_separators(&digits, separator, interval);
+            let len = prefix.len() + grouped.chars().count() + rest.chars().count();
+            if !zero_pad || len >= self.width {
+                return format!("{}{}", grouped, rest);
+            }
+            digits.insert(0, '0');
+        }
+    }
+}
+
+/// Read a width or precision, failing past [`MAX_WIDTH`].
+fn take_number(chars: &[char], i: &mut usize) -> Result<Option<usize>> {
+    let start = *i;
+    while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+        *i += 1;
+    }
+    if start == *i {
+        return Ok(None);
+    }
+    let digits: String = chars[start..*i].iter().collect();
+    let n = digits.parse().unwrap_or(usize::MAX);
+    check_width(n).map(Some)
+}
+
+fn check_width(n: usize) -> Result<usize> {
+    if n > MAX_WIDTH {
+        Err(Error::exception(
+            "ValueError",
+            format!(
+                "format width or precision exceeds the sandbox limit of {}",
+                MAX_WIDTH
+            ),
+        ))
+    } else {
+        Ok(n)
+    }
+}
+
+fn insert_separators(digits: &str, separator: char, interval: usize) -> String {
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(interval) {
+            out.push(separator);
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// `format(value, spec)` for built-in types.
+pub(crate) fn format_value(value: &PyValue, spec: &str) -> Result<String> {
+    if spec.is_empty() {
+        return Ok(value.to_print_string());
+    }
+    match value {
+        PyValue::Int(n) => format_int(&BigInt::from(*n), &FormatSpec::parse(spec, "int")?),
+        PyValue::BigInt(n) => format_int(n, &FormatSpec::parse(spec, "int")?),
+        PyValue::Bool(b) => format_int(&BigInt::from(*b as i64), &FormatSpec::parse(spec, "bool")?),
+        PyValue::Float(f) => format_float(*f, &FormatSpec::parse(spec, "float")?, "float"),
+        PyValue::Str(s) => format_str(s, &FormatSpec::parse(spec, "str")?),
+        other => Err(unsupported_spec(other.type_name())),
+    }
+}
+
+/// The error for a non-empty format spec on a type without `__format__`.
+pub(crate) fn unsupported_spec(type_name: &str) -> Error {
+    Error::exception(
+        "TypeError",
+        format!(
+            "unsupported format string passed to {}.__format__",
+            type_name
+        ),
+    )
+}
+
+fn unknown_code(kind: char, type_name: &str) -> Error {
+    Error::exception(
+        "ValueError",
+        format!(
+            "Unknown format code '{}' for object of type '{}'",
+            kind, type_name
+        ),
+    )
+}
+
+fn format_int(n: &BigInt, spec: &FormatSpec) -> Result<String> {
+    let (base, prefix) = match spec.kind {
+        None | Some('d' | 'n') => (10, ""),
+        Some('b') => (2, "0b"),
+        Some('o') => (8, "0o"),
+        Some('x') => (16, "0x"),
+        Some('X') => (16, "0X"),
+        Some('c') => {
+            if spec.sign.is_some() {
+                return Err(Error::exception(
+                    "ValueError",
+                    "Sign not allowed with integer format specifier 'c'",
+                ));
+            }
+            let c = n.to_u32().and_then(char::from_u32).ok_or_else(|| {
+                Error::exception("OverflowError", "%c arg not in range(0x110000)")
+            })?;
+            return Ok(spec.pad("", &c.to_string(), '<'));
+        }
+        Some('e' | 'E' | 'f' | 'F' | 'g' | 'G' | '%') => {
+            return format_float(int::to_f64(n)?, spec, "int");
+        }
+        Some(kind) => return Err(unknown_code(kind, "int")),
+    };
+    if spec.precision.is_some() {
+        return Err(Error::exception(
+            "ValueError",
+            "Precision not allowed in integer format specifier",
+        ));
+    }
+    if spec.grouping == Some(',') && base != 10 {
+        return Err(Error::exception(
+            "ValueError",
+            format!("Cannot specify ',' with '{}'.", spec.kind.unwrap_or('d')),
+        ));
+    }
+    let digits = int::magnitude_digits(n, base);
+    let digits = if spec.kind == Some('X') {
+        digits.to_uppercase()
+    } else {
+        digits
+    };
+    let prefix = format!(
+        "{}{}",
+        spec.sign_prefix(n.is_negative()),
+        if spec.alternate { prefix } else { "" }
+    );
+    let digits = spec.group(&prefix, &digits, "", if base == 10 { 3 } else { 4 });
+    Ok(spec.pad(&prefix, &digits, spec.number_align()))
+}
+
+fn format_float(x: f64, spec: &FormatSpec, type_name: &str) -> Result<String> {
+    let kind = spec.kind;
+    if let Some(kind) = kind
+        && !matches!(kind, 'e' | 'E' | 'f' | 'F' | 'g' | 'G' | 'n' | '%')
+    {
+        return Err(unknown_code(kind, type_name));
+    }
+    let negative = x.is_sign_negative() && !x.is_nan();
+    let abs = x.abs();
+    let upper = matches!(kind, Some('E' | 'F' | 'G'));
+    let alt = spec.alternate;
+
+    let body = if !abs.is_finite() {
+        let name = if abs.is_nan() { "nan" } else { "inf" };
+        let name = if kind == Some('%') {
+            format!("{}%", name)
+        } else {
+            name.to_string()
+        };
+        let body = if upper { name.to_uppercase() } else { name };
+        return Ok(spec.pad(spec.sign_prefix(negative), &body, spec.number_align()));
+    } else {
+        match kind {
+            Some('f' | 'F') => fixed(abs, spec.precision.unwrap_or(6), alt),
+            Some('%') => format!("{}%", fixed(abs * 100.0, spec.precision.unwrap_or(6), alt)),
+            Some('e' | 'E') => scientific(abs, spec.precision.unwrap_or(6), alt),
+            Some(_) => general(abs, spec.precision.unwrap_or(6), alt, false),
+            None => match spec.precision {
+                Some(precision) => general(abs, precision, alt, true),
+                None => PyValue::Float(abs).to_print_string(),
+            },
+        }
+    };
+    let body = if upper { body.to_uppercase() } else { body };
+    let prefix = spec.sign_prefix(negative);
+    let (digits, rest) = body.split_at(
+        body.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(body.len()),
+    );
+    let body = spec.group(prefix, digits, rest, 3);
+    Ok(spec.pad(prefix, &body, spec.number_align()))
+}
+
+/// `x` with `precision` digits after the point (`#` keeps the point).
+fn fixed(x: f64, precision: usize, alt: bool) -> String {
+    let mut s = format!("{:.*}", precision, x);
+    if alt && precision == 0 {
+        s.push('.');
+    }
+    s
+}
+
+/// `x` in scientific notation with `precision` digits after the point and
+/// an exponent of at least two digits: `1.500000e+03`.
+fn scientific(x: f64, precision: usize, alt: bool) -> String {
+    let s = format!("{:.*e}", precision, x);
+    let (mantissa, exponent) = s.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let point = if alt && precision == 0 { "." } else { "" };
+    format!(
+        "{}{}e{}{:02}",
+        mantissa,
+        point,
+        if exponent < 0 { '-' } else { '+' },
+        exponent.abs()
+    )
+}
+
+/// The `g` presentation: `precision` significant digits in fixed or
+/// scientific notation, without trailing zeros unless `alt`. `add_dot_0`
+/// is the type-less presentation with a precision, which keeps one digit
+/// after the point and switches to scientific notation one digit sooner.
+fn general(x: f64, precision: usize, alt: bool, add_dot_0: bool) -> String {
+    let precision = precision.max(1);
+    let exponent: i32 = if x == 0.0 {
+        0
+    } else {
+        let s = format!("{:.*e}", precision - 1, x);
+        s.split_once('e').unwrap().1.parse().unwrap()
+    };
+    let threshold = if add_dot_0 {
+        precision as i32 - 1
+    } else {
+        precision as i32
+    };
+    if exponent < -4 || exponent >= threshold {
+        let s = scientific(x, precision - 1, alt);
+        if alt {
+            return s;
+        }
+        let (mantissa, exponent) = s.split_once('e').unwrap();
+        format!("{}e{}", strip_zeros(mantissa), exponent)
+    } else {
+        let s = fixed(x, (precision as i32 - 1 - exponent) as usize, alt);
+        if alt {
+            return s;
+        }
+        let s = strip_zeros(&s).to_string();
+        if add_dot_0 && !s.contains('.') {
+            s + ".0"
+        } else {
+            s
+        }
+    }
+}
+
+fn strip_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+fn format_str(s: &str, spec: &FormatSpec) -> Result<String> {
+    if let Some(kind) = spec.kind
+        && kind != 's'
+    {
+        return Err(unknown_code(kind, "str"));
+    }
+    if spec.sign.is_some() {
+        return Err(Error::exception(
+            "ValueError",
+            "Sign not allowed in string format specifier",
+        ));
+    }
+    if spec.alternate {
+        return Err(Error::exception(
+            "ValueError",
+            "Alternate form (#) not allowed in string format specifier",
+        ));
+    }
+    if let Some(grouping) = spec.grouping {
+        return Err(Error::exception(
+            "ValueError",
+            format!("Cannot specify '{}' with 's'.", grouping),
+        ));
+    }
+    if spec.align == Some('=') {
+        return Err(Error::exception(
+            "ValueError",
+            "'=' alignment not allowed in string format specifier",
+        ));
+    }
+    let text: String = match spec.precision {
+        Some(precision) => s.chars().take(precision).collect(),
+        None => s.to_string(),
+    };
+    Ok(spec.pad("", &text, '<'))
+}
+
+/// `ascii(value)`: the repr with non-ASCII characters escaped.
+pub(crate) fn ascii_escape(repr: &str) -> String {
+    let mut out = String::new();
+    for c in repr.chars() {
+        if c.is_ascii() {
+            out.push(c);
+        } else {
+            out.push_str(&text::escape_char(c));
+        }
+    }
+    out
+}
+
+/// Apply a conversion (`!s`, `!r`, `!a`) given the value's `str()` and
+/// `repr()`, for values that are not instances.
+pub(crate) fn convert(value: &PyValue, conversion: Conversion) -> PyValue {
+    match conversion {
+        Conversion::None => value.clone(),
+        Conversion::Str => PyValue::Str(value.to_print_string()),
+        Conversion::Repr => PyValue::Str(value.to_string()),
+        Conversion::Ascii => PyValue::Str(ascii_escape(&value.to_string())),
+    }
+}
+
+// ============================================================================
+// str.format templates
+// ============================================================================
+
+/// A piece of a `str.format` template.
+pub(crate) enum Piece<'a> {
+    Literal(String),
+    Field(Field<'a>),
+}
+
+/// A replacement field: `{arg.attr[key]!r:spec}`.
+pub(crate) struct Field<'a> {
+    pub(crate) arg: FieldArg<'a>,
+    pub(crate) accessors: Vec<Accessor<'a>>,
+    pub(crate) conversion: Conversion,
+    /// The format spec, which may itself contain replacement fields.
+    pub(crate) spec: &'a str,
+}
+
+/// Field numbering of a `str.format` template: `{}` fields count up
+/// automatically, and cannot be mixed with numbered `{0}` fields.
+#[derive(Default)]
+pub(crate) enum Numbering {
+    #[default]
+    Unused,
+    Auto(usize),
+    Manual,
+}
+
+impl Numbering {
+    /// The index of the next `{}` field.
+    pub(crate) fn next(&mut self) -> Result<usize> {
+        let index = match self {
+            Numbering::Unused => 0,
+            Numbering::Auto(next) => *next,
+            Numbering::Manual => {
+                return Err(format_error(
+                    "cannot switch from manual field specification to automatic field numbering",
+                ));
+            }
+        };
+        *self = Numbering::Auto(index + 1);
+        Ok(index)
+    }
+
+    /// Record a numbered `{index}` field.
+    pub(crate) fn manual(&mut self, index: usize) -> Result<usize> {
+        if let Numbering::Auto(_) = self {
+            return Err(format_error(
+                "cannot switch from automatic field numbering to manual field specification",
+            ));
+        }
+        *self = Numbering::Manual;
+        Ok(index)
+    }
+}
+
+/// Which argument a replacement field refers to.
+pub(crate) enum FieldArg<'a> {
+    /// `{}`: the next positional argument.
+    Auto,
+    Index(usize),
+    Name(&'a str),
+}
+
+/// An attribute (`.name`) or item (`[key]`) lookup in a replacement field.
+pub(crate) enum Accessor<'a> {
+    Attr(&'a str),
+    Item(PyValue),
+}
+
+fn format_error(message: &str) -> Error {
+    Error::exception("ValueError", message)
+}
+
+/// Split a `str.format` template into literal text and replacement fields.
+pub(crate) fn parse_template(template: &str) -> Result<Vec<Piece<'_>>> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().map(|&(_, c)| c) == Some('{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '{' if chars.peek().is_none() => {
+                return Err(format_error("Single '{' encountered in format string"));
+            }
+            '{' => {
+                // Find the matching `}`, allowing nested fields in the spec
+                let start = i + 1;
+                let mut depth = 1;
+                let mut end = None;
+                for (j, c) in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                end = Some(j);
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                let end = end.ok_or_else(|| unterminated_field(&template[start..]))?;
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                }
+                pieces.push(Piece::Field(parse_field(&template[start..end])?));
+            }
+            '}' if chars.peek().map(|&(_, c)| c) == Some('}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '}' => return Err(format_error("Single '}' encountered in format string")),
+            _ => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    Ok(pieces)
+}
+
+/// The error for a replacement field without its closing `}`, worded by
+/// how far the field got.
+fn unterminated_field(field: &str) -> Error {
+    match field.find([':', '!']) {
+        None => format_error("expected '}' before end of string"),
+        Some(i) if &field[i..] == "!" => {
+            format_error("end of string while looking for conversion specifier")
+        }
+        Some(_) => format_error("unmatched '{' in format spec"),
+    }
+}
+
+fn parse_field(field: &str) -> Result<Field<'_>> {
+    // The field name ends at the first `!` or `:` outside brackets
+    let mut in_brackets = false;
+    let name_end = field
+        .char_indices()
+        .find(|&(_, c)| {
+            match c {
+                '[' => in_brackets = true,
+                ']' => in_brackets = false,
+                _ => {}
+            }
+            !in_brackets && (c == '!' || c == ':')
+        })
+        .map_or(field.len(), |(i, _)| i);
+    let (name, mut rest) = field.split_at(name_end);
+
+    let mut conversion = Conversion::None;
+    if let Some(after) = rest.strip_prefix('!') {
+        let mut chars = after.chars();
+        conversion = match chars.next() {
+            Some('s') => Conversion::Str,
+            Some('r') => Conversion::Repr,
+            Some('a') => Conversion::Ascii,
+            Some(c) => {
+                return Err(format_error(&format!("Unknown conversion specifier {}", c)));
+            }
+            None => {
+                return Err(format_error(
+                    "end of string while looking for conversion specifier",
+                ));
+            }
+        };
+        rest = chars.as_str();
+        if !rest.is_empty() && !rest.starts_with(':') {
+            return Err(format_error("expected ':' after conversion specifier"));
+        }
+    }
+    let spec = rest.strip_prefix(':').unwrap_or(rest);
+
+    let arg_end = name.find(['.', '[']).unwrap_or(name.len());
+    let (arg, mut path) = name.split_at(arg_end);
+    let arg = if arg.is_empty() {
+        FieldArg::Auto
+    } else if let Ok(index) = arg.parse() {
+        FieldArg::Index(index)
+    } else {
+        FieldArg::Name(arg)
+    };
+
+    let mut accessors = Vec::new();
+    while !path.is_empty() {
+        if let Some(after) = path.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(format_error("Empty attribute in format string"));
+            }
+            accessors.push(Accessor::Attr(&after[..end]));
+            path = &after[end..];
+        } else if let Some(after) = path.strip_prefix('[') {
+            let end = after
+                .find(']')
+                .ok_or_else(|| format_error("Missing ']' in format string"))?;
+            let key = &after[..end];
+            if key.is_empty() {
+                return Err(format_error("Empty attribute in format string"));
+            }
+            accessors.push(Accessor::Item(match key.parse() {
+                Ok(index) => PyValue::Int(index),
+                Err(_) => PyValue::Str(key.to_string()),
+            }));
+            path = &after[end + 1..];
+        } else {
+            return Err(format_error(
+                "Only '.' or '[' may follow ']' in format field specifier",
+            ));
+        }
+    }
+
+    Ok(Field {
+        arg,
+        accessors,
+        conversion,
+        spec,
+    })
+}
+
+// ============================================================================
+// %-formatting
+// ============================================================================
+
+/// `template % args`. `render` gives the `str()`, `repr()` or `ascii()` of
+/// a value for `%s`, `%r` and `%a`.
+pub(crate) fn percent_format(
+    template: &str,
+    args: &PyValue,
+    render: &mut dyn FnMut(&PyValue, Conversion) -> Result<String>,
+) -> Result<String> {
+    let (items, mapping): (Vec<PyValue>, Option<&PyValue>) = match args {
+        PyValue::Tuple(items) => (items.clone(), None),
+        PyValue::Dict(_) => (vec![args.clone()], Some(args)),
+        other => (vec![other.clone()], None),
+    };
+    let mut next_item = 0;
+    let mut next_arg = || -> Result<PyValue> {
+        let item = items.get(next_item).cloned().ok_or_else(|| {
+            Error::exception("TypeError", "not enough arguments for format string")
+        })?;
+        next_item += 1;
+        Ok(item)
+    };
+
+    let mut out = String::new();
+    let chars: Vec<char> = template.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '%' {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+
+        // %(key)
+        let mut keyed = None;
+        if chars.get(i) == Some(&'(') {
+            let Some(mapping) = mapping else {
+                return Err(Error::exception("TypeError", "format requires a mapping"));
+            };
+            let close = chars[i..]
+                .iter()
+                .position(|&c| c == ')')
+                .ok_or_else(|| format_error("incomplete format key"))?;
+            let key: String = chars[i + 1..i + close].iter().collect();
+            i += close + 1;
+            let PyValue::Dict(dict) = mapping else {
+                unreachable!()
+            };
+            let key = PyValue::Str(key);
+            let value = dict
+                .read()
+                .get(&key)
+                .cloned()
+                .ok_or_else(|| Error::from(ExceptionValue::new("KeyError", vec![key])))?;
+            keyed = Some(value);
+        }
+
+        let mut spec = FormatSpec {
+            fill: ' ',
+            align: Some('>'),
+            sign: None,
+            alternate: false,
+            zero: false,
+            width: 0,
+            grouping: None,
+            precision: None,
+            kind: None,
+        };
+        while let Some(&c @ ('-' | '+' | ' ' | '#' | '0')) = chars.get(i) {
+            match c {
+                '-' => spec.align = Some('<'),
+                '+' => spec.sign = Some('+'),
+                ' ' if spec.sign.is_none() => spec.sign = Some(' '),
+                '#' => spec.alternate = true,
+                '0' => spec.zero = true,
+                _ => {}
+            }
+            i += 1;
+        }
+        if chars.get(i) == Some(&'*') {
+            i += 1;
+            let width = next_arg()?
+                .as_int()
+                .ok_or_else(|| Error::exception("TypeError", "* wants int"))?;
+            if width < 0 {
+                spec.align = Some('<');
+            }
+            spec.width = check_width(width.unsigned_abs() as usize)?;
+        } else {
+            spec.width = take_number(&chars, &mut i)?.unwrap_or(0);
+        }
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            if chars.get(i) == Some(&'*') {
+                i += 1;
+                let precision = next_arg()?
+                    .as_int()
+                    .ok_or_else(|| Error::exception("TypeError", "* wants int"))?;
+                spec.precision = Some(check_width(precision.max(0) as usize)?);
+            } else {
+                spec.precision = Some(take_number(&chars, &mut i)?.unwrap_or(0));
+            }
+        }
+        // Length modifiers are accepted and ignored
+        while let Some('h' | 'l' | 'L') = chars.get(i) {
+            i += 1;
+        }
+
+        let Some(&kind) = chars.get(i) else {
+            return Err(format_error("incomplete format"));
+        };
+        i += 1;
+        if kind == '%' {
+            out.push('%');
+            continue;
+        }
+        let value = match keyed {
+            Some(value) => value,
+            None => next_arg()?,
+        };
+        let numeric = !matches!(kind, 's' | 'r' | 'a' | 'c');
+        if spec.zero && spec.align == Some('>') && numeric {
+            spec.fill = '0';
+            spec.align = Some('=');
+        }
+
+        let text = match kind {
+            's' | 'r' | 'a' => {
+                let conversion = match kind {
+                    's' => Conversion::Str,
+                    'r' => Conversion::Repr,
+                    _ => Conversion::Ascii,
+                };
+                let text = render(&value, conversion)?;
+                let text: String = match spec.precision {
+                    Some(precision) => text.chars().take(precision).collect(),
+                    None => text,
+                };
+                spec.pad("", &text, '>')
+            }
+            'c' => {
+                let c = match &value {
+                    PyValue::Str(s) if s.chars().count() == 1 => s.clone(),
+                    PyValue::Int(n) => u32::try_from(*n)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| {
+                            Error::exception("OverflowError", "%c arg not in range(0x110000)")
+                        })?
+                        .to_string(),
+                    _ => {
+                        return Err(Error::exception(
+                            "TypeError",
+                            "%c requires an int or a unicode character",
+                        ));
+                    }
+                };
+                spec.pad("", &c, '>')
+            }
+            'd' | 'i' | 'u' | 'o' | 'x' | 'X' => {
+                let n = match &value {
+                    PyValue::Float(f) if matches!(kind, 'd' | 'i' | 'u') => {
+                        int::from_f64(*f)?.as_bigint()
+                    }
+                    other => other.as_bigint(),
+                };
+                let n = match n {
+                    Some(n) => n,
+                    None => {
+                        let wanted = if matches!(kind, 'd' | 'i' | 'u') {
+                            "a real number"
+                        } else {
+                            "an integer"
+                        };
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!(
+                                "%{} format: {} is required, not {}",
+                                kind,
+                                wanted,
+                                value.type_name()
+                            ),
+                        ));
+                    }
+                };
+                percent_int(&n, kind, &spec)
+            }
+            'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+                let x = match &value {
+                    PyValue::Int(n) => *n as f64,
+                    PyValue::BigInt(n) => int::to_f64(n)?,
+                    PyValue::Bool(b) => *b as i64 as f64,
+                    PyValue::Float(f) => *f,
+                    other => {
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!("must be real number, not {}", other.type_name()),
+                        ));
+                    }
+                };
+                spec.kind = Some(kind);
+                spec.precision = Some(spec.precision.unwrap_or(6));
+                format_float(x, &spec, "float")?
+            }
+            _ => {
+                return Err(format_error(&format!(
+                    "unsupported format character '{}' ({:#x}) at index {}",
+                    kind,
+                    kind as u32,
+                    i - 1
+                )));
+            }
+        };
+        out.push_str(&text);
+    }
+
+    if mapping.is_none() && next_item < items.len() {
+        return Err(Error::exception(
+            "TypeError",
+            "not all arguments converted during string formatting",
+        ));
+    }
+    Ok(out)
+}
+
+/// `%d`, `%o` and `%x`: the precision is a minimum number of digits.
+fn percent_int(n: &BigInt, kind: char, spec: &FormatSpec) -> String {
+    let mut digits = match kind {
+        'o' => int::magnitude_digits(n, 8),
+        'x' => int::magnitude_digits(n, 16),
+        'X' => int::magnitude_digits(n, 16).to_uppercase(),
+        _ => int::magnitude_digits(n, 10),
+    };
+    if let Some(precision) = spec.precision
+        && digits.len() < precision
+    {
+        digits = "0".repeat(precision - digits.len()) + &digits;
+    }
+    let base_prefix = match (spec.alternate, kind) {
+        (true, 'o') => "0o",
+        (true, 'x') => "0x",
+        (true, 'X') => "0X",
+        _ => "",
+    };
+    let prefix = format!("{}{}", spec.sign_prefix(n.is_negative()), base_prefix);
+    spec.pad(&prefix, &digits, '>')
+}
@@ -0,0 +1,132 @@
+//! Generator objects.
+//!
+//! Calling a function whose body contains `yield` (or evaluating a generator
+//! expression) does not run any code: it creates a [`GeneratorValue`] that
+//! owns the function's call frame. Each time the generator is advanced the
+//! VM resumes the frame until the next `yield`, then saves the frame — with
+//! its slice of the value stack and any exceptions being handled — back into
+//! the generator.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Error, Result};
+use crate::exception::ExceptionValue;
+use crate::value::PyValue;
+use crate::vm::CallFrame;
+
+/// A call frame suspended at a `yield` (or not yet started).
+pub(crate) struct SuspendedFrame {
+    pub(crate) frame: CallFrame,
+    /// The frame's operands, saved from the VM's value stack.
+    pub(crate) stack: Vec<PyValue>,
+    /// Exceptions being handled by the frame, saved from the VM.
+    pub(crate) exceptions: Vec<ExceptionValue>,
+    /// Whether the frame has run up to a `yield`. A started frame expects
+    /// the value of the `yield` expression when resumed.
+    pub(crate) started: bool,
+}
+
+/// Execution state of a generator.
+enum GeneratorState {
+    Suspended(Box<SuspendedFrame>),
+    Running,
+    /// Finished by returning a value that has not been collected yet.
+    Returned(PyValue),
+    Finished,
+}
+
+/// A generator object. Clones refer to the same generator.
+#[derive(Clone)]
+pub struct GeneratorValue {
+    /// The generator function's name (`<genexpr>` for generator expressions).
+    pub name: String,
+    state: Arc<Mutex<GeneratorState>>,
+}
+
+impl GeneratorValue {
+    /// Create a generator that will run `frame` from its first instruction.
+    pub(crate) fn new(name: &str, frame: CallFrame) -> Self {
+        let suspended = SuspendedFrame {
+            frame,
+            stack: Vec::new(),
+            exceptions: Vec::new(),
+            started: false,
+        };
+        Self {
+            name: name.to_string(),
+            state: Arc::new(Mutex::new(GeneratorState::Suspended(Box::new(suspended)))),
+        }
+    }
+
+    /// Take the suspended frame out to resume it, marking the generator as
+    /// running. Returns `None` once the generator has finished.
+    pub(crate) fn take(&self) -> Result<Option<Box<SuspendedFrame>>> {
+        let mut state = self.state.lock().unwrap();
+        match std::mem::replace(&mut *state, GeneratorState::Running) {
+            GeneratorState::Suspended(frame) => Ok(Some(frame)),
+            GeneratorState::Running => Err(Error::exception(
+                "ValueError",
+                "generator already executing",
+            )),
+            GeneratorState::Returned(_) | GeneratorState::Finished => {
+                *state = GeneratorState::Finished;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Store the frame back after it yielded.
+    pub(crate) fn suspend(&self, frame: Box<SuspendedFrame>) {
+        *self.state.lock().unwrap() = GeneratorState::Suspended(frame);
+    }
+
+    /// Whether the generator is suspended at a `yield` (as opposed to
+    /// running or finished).
+    pub(crate) fn is_suspended(&self) -> bool {
+        matches!(*self.state.lock().unwrap(), GeneratorState::Suspended(_))
+    }
+
+    /// Mark the generator as exhausted (it returned or raised).
+    pub(crate) fn finish(&self) {
+        *self.state.lock().unwrap() = GeneratorState::Finished;
+    }
+
+    /// Mark the generator as exhausted by returning `value`, which
+    /// [`GeneratorValue::take_return_value`] then collects.
+    pub(crate) fn finish_returning(&self, value: PyValue) {
+        *self.state.lock().unwrap() = GeneratorState::Returned(value);
+    }
+
+    /// The value the generator returned, once: the value of `yield from`
+    /// and of `StopIteration.value`. `None` if it is not finished, or the
+    /// value was already collected.
+    pub(crate) fn take_return_value(&self) -> PyValue {
+        let mut state = self.state.lock().unwrap();
+        match std::mem::replace(&mut *state, GeneratorState::Finished) {
+            GeneratorState::Returned(value) => value,
+            other => {
+                *state = other;
+                PyValue::None
+            }
+        }
+    }
+}
+
+impl PartialEq for GeneratorValue {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl fmt::Debug for GeneratorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<generator object {}>", self.name)
+    }
+}
+
+impl fmt::Display for GeneratorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<generator object {}>", self.name)
+    }
+}
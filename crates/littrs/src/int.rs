@@ -0,0 +1,429 @@
+//! Arbitrary-precision integer arithmetic.
+//!
+//! Python ints are unbounded. The sandbox keeps ints that fit in an `i64`
+//! as [`PyValue::Int`] and promotes a result to [`PyValue::BigInt`] only
+//! when it overflows, so everyday arithmetic stays on machine integers.
+//! Every int produced here goes through [`normalize`], which maintains the
+//! invariant that a `BigInt` never holds a value that fits in an `i64`.
+
+use std::cmp::Ordering;
+
+use num_bigint::{BigInt, BigUint};
+use num_integer::Integer;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+
+use crate::bytecode::BinOp;
+use crate::error::{Error, Result};
+use crate::value::PyValue;
+
+/// The largest int, in bits, that an operation may produce. Guards the host
+/// against `10 ** 10 ** 9` and similar memory bombs.
+pub(crate) const MAX_BITS: u64 = 1 << 20;
+
+/// The int value of `big`, as a `PyValue::Int` when it fits in an `i64`.
+pub(crate) fn normalize(big: BigInt) -> PyValue {
+    match big.to_i64() {
+        Some(n) => PyValue::Int(n),
+        None => PyValue::BigInt(Box::new(big)),
+    }
+}
+
+/// The value of an int operand (`int`, big int or `bool`).
+pub(crate) fn to_bigint(value: &PyValue) -> Option<BigInt> {
+    match value {
+        PyValue::Int(n) => Some(BigInt::from(*n)),
+        PyValue::Bool(b) => Some(BigInt::from(*b as i64)),
+        PyValue::BigInt(big) => Some((**big).clone()),
+        _ => None,
+    }
+}
+
+/// The nearest float to `big`, infinite if it is out of range.
+pub(crate) fn to_f64_lossy(big: &BigInt) -> f64 {
+    big.to_f64().unwrap_or(if big.is_negative() {
+        f64::NEG_INFINITY
+    } else {
+        f64::INFINITY
+    })
+}
+
+/// Convert a big int to a float, as `float(n)` does.
+pub(crate) fn to_f64(big: &BigInt) -> Result<f64> {
+    let x = to_f64_lossy(big);
+    if x.is_finite() {
+        Ok(x)
+    } else {
+        Err(Error::exception(
+            "OverflowError",
+            "int too large to convert to float",
+        ))
+    }
+}
+
+/// The int part of `x`, as `int(x)` computes it.
+pub(crate) fn from_f64(x: f64) -> Result<PyValue> {
+    if x.is_nan() {
+        return Err(Error::exception(
+            "ValueError",
+            "cannot convert float NaN to integer",
+        ));
+    }
+    if x.is_infinite() {
+        return Err(Error::exception(
+            "OverflowError",
+            "cannot convert float infinity to integer",
+        ));
+    }
+    let x = x.trunc();
+    if x >= i64::MIN as f64 && x < i64::MAX as f64 {
+        return Ok(PyValue::Int(x as i64));
+    }
+    // Floats this large are integers, so the conversion is exact
+    Ok(normalize(BigInt::from_f64(x).expect("finite float")))
+}
+
+/// The int equal to `x`, if `x` is a finite float with no fractional part.
+pub(crate) fn from_integral_f64(x: f64) -> Option<PyValue> {
+    if x.is_finite() && x.fract() == 0.0 {
+        from_f64(x).ok()
+    } else {
+        None
+    }
+}
+
+/// Parse `text` as `int(text, base)` does: surrounding whitespace, a sign,
+/// a `0x`/`0o`/`0b` prefix matching `base` (or choosing it when `base` is
+/// 0) and single underscores between digits.
+pub(crate) fn parse(text: &str, base: u32) -> Option<PyValue> {
+    let text = text.trim();
+    let (negative, text) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let prefixed = |prefix: char| {
+        let mut chars = text.chars();
+        chars.next() == Some('0')
+            && chars
+                .next()
+                .is_some_and(|c| c.eq_ignore_ascii_case(&prefix))
+    };
+    let (base, digits) = match base {
+        0 | 16 if prefixed('x') => (16, text[2..].strip_prefix('_').unwrap_or(&text[2..])),
+        0 | 8 if prefixed('o') => (8, text[2..].strip_prefix('_').unwrap_or(&text[2..])),
+        0 | 2 if prefixed('b') => (2, text[2..].strip_prefix('_').unwrap_or(&text[2..])),
+        // Base 0 rejects leading zeros, which would be ambiguous
+        0 if text.starts_with('0') && text.chars().any(|c| c != '0' && c != '_') => {
+            return None;
+        }
+        0 => (10, text),
+        _ => (base, text),
+    };
+    if digits.is_empty()
+        || digits.starts_with('_')
+        || digits.ends_with('_')
+        || digits.contains("__")
+    {
+        return None;
+    }
+    let digits = digits.replace('_', "");
+    if !digits.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let magnitude = BigInt::parse_bytes(digits.as_bytes(), base)?;
+    Some(normalize(if negative { -magnitude } else { magnitude }))
+}
+
+/// The digits of `|big|` in `radix`, without a sign or prefix.
+pub(crate) fn magnitude_digits(big: &BigInt, radix: u32) -> String {
+    big.magnitude().to_str_radix(radix)
+}
+
+/// `bin()`, `oct()` and `hex()`: `n` in `radix` with a `0b`-style prefix.
+pub(crate) fn to_radix_literal(value: &PyValue, radix: u32, prefix: &str) -> Option<String> {
+    let big = to_bigint(value)?;
+    let sign = if big.is_negative() { "-" } else { "" };
+    Some(format!(
+        "{}{}{}",
+        sign,
+        prefix,
+        magnitude_digits(&big, radix)
+    ))
+}
+
+/// The ordering of two ints (`int`, big int or `bool`).
+pub(crate) fn compare(left: &PyValue, right: &PyValue) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (left.as_int(), right.as_int()) {
+        return Some(a.cmp(&b));
+    }
+    Some(to_bigint(left)?.cmp(&to_bigint(right)?))
+}
+
+/// `-value` for an int.
+pub(crate) fn neg(value: &PyValue) -> Option<PyValue> {
+    match value.as_int() {
+        Some(n) => Some(match n.checked_neg() {
+            Some(negated) => PyValue::Int(negated),
+            None => normalize(-BigInt::from(n)),
+        }),
+        None => Some(normalize(-to_bigint(value)?)),
+    }
+}
+
+/// `abs(value)` for an int.
+pub(crate) fn abs(value: &PyValue) -> Option<PyValue> {
+    match value.as_int() {
+        Some(n) => Some(match n.checked_abs() {
+            Some(magnitude) => PyValue::Int(magnitude),
+            None => normalize(BigInt::from(n).abs()),
+        }),
+        None => Some(normalize(to_bigint(value)?.abs())),
+    }
+}
+
+/// `~value` for an int.
+pub(crate) fn invert(value: &PyValue) -> Option<PyValue> {
+    match value.as_int() {
+        Some(n) => Some(PyValue::Int(!n)),
+        None => Some(normalize(!to_bigint(value)?)),
+    }
+}
+
+/// Apply an arithmetic or bitwise operator to two ints, or `None` if
+/// either operand is not an int. Results that overflow an `i64` are
+/// promoted to big ints.
+pub(crate) fn binop(op: &BinOp, left: &PyValue, right: &PyValue) -> Option<Result<PyValue>> {
+    // Bitwise operators on two bools give a bool
+    if let (PyValue::Bool(a), PyValue::Bool(b)) = (left, right) {
+        match op {
+            BinOp::BitAnd => return Some(Ok(PyValue::Bool(a & b))),
+            BinOp::BitOr => return Some(Ok(PyValue::Bool(a | b))),
+            BinOp::BitXor => return Some(Ok(PyValue::Bool(a ^ b))),
+            _ => {}
+        }
+    }
+    if let (Some(a), Some(b)) = (left.as_int(), right.as_int())
+        && let Some(result) = small_binop(op, a, b)
+    {
+        return Some(result);
+    }
+    let (a, b) = (to_bigint(left)?, to_bigint(right)?);
+    Some(big_binop(op, &a, &b))
+}
+
+/// [`binop`] on machine ints, or `None` if the result overflows.
+fn small_binop(op: &BinOp, a: i64, b: i64) -> Option<Result<PyValue>> {
+    let result = match op {
+        BinOp::Add => PyValue::Int(a.checked_add(b)?),
+        BinOp::Sub => PyValue::Int(a.checked_sub(b)?),
+        BinOp::Mult => PyValue::Int(a.checked_mul(b)?),
+        BinOp::Div => {
+            if b == 0 {
+                return Some(Err(Error::DivisionByZero));
+            }
+            // Exact as floats, so the quotient is correctly rounded
+            const EXACT: i64 = 1 << 53;
+            if !(-EXACT..=EXACT).contains(&a) || !(-EXACT..=EXACT).contains(&b) {
+                return None;
+            }
+            PyValue::Float(a as f64 / b as f64)
+        }
+        BinOp::FloorDiv => {
+            if b == 0 {
+                return Some(Err(Error::DivisionByZero));
+            }
+            PyValue::Int(a.checked_div(b)? - ((a % b != 0 && (a < 0) != (b < 0)) as i64))
+        }
+        BinOp::Mod => {
+            if b == 0 {
+                return Some(Err(Error::DivisionByZero));
+            }
+            let r = a.checked_rem(b)?;
+            PyValue::Int(if r != 0 && (r < 0) != (b < 0) {
+                r + b
+            } else {
+                r
+            })
+        }
+        BinOp::Pow => {
+            if b < 0 {
+                return Some(negative_power(a as f64, b as f64));
+            }
+            PyValue::Int(a.checked_pow(u32::try_from(b).ok()?)?)
+        }
+        BinOp::LShift => {
+            if b < 0 {
+                return Some(Err(negative_shift()));
+            }
+            if a == 0 {
+                return Some(Ok(PyValue::Int(0)));
+            }
+            if b >= 63 || (a << b) >> b != a {
+                return None;
+            }
+            PyValue::Int(a << b)
+        }
+        BinOp::RShift => {
+            if b < 0 {
+                return Some(Err(negative_shift()));
+            }
+            PyValue::Int(a >> b.min(63))
+        }
+        BinOp::BitAnd => PyValue::Int(a & b),
+        BinOp::BitOr => PyValue::Int(a | b),
+        BinOp::BitXor => PyValue::Int(a ^ b),
+    };
+    Some(Ok(result))
+}
+
+/// [`binop`] on big ints.
+fn big_binop(op: &BinOp, a: &BigInt, b: &BigInt) -> Result<PyValue> {
+    let result = match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mult => {
+            check_bits(a.bits() + b.bits())?;
+            a * b
+        }
+        BinOp::Div => return true_divide(a, b),
+        BinOp::FloorDiv => {
+            if b.is_zero() {
+                return Err(Error::DivisionByZero);
+            }
+            a.div_floor(b)
+        }
+        BinOp::Mod => {
+            if b.is_zero() {
+                return Err(Error::DivisionByZero);
+            }
+            a.mod_floor(b)
+        }
+        BinOp::Pow => {
+            if b.is_negative() {
+                return negative_power(to_f64_lossy(a), to_f64_lossy(b));
+            }
+            if a.is_zero() || a.is_one() {
+                return Ok(normalize(a.clone()));
+            }
+            if *a == -BigInt::one() {
+                return Ok(PyValue::Int(if b.is_even() { 1 } else { -1 }));
+            }
+            let exponent = b.to_u64().ok_or_else(too_large)?;
+            check_bits(a.bits().saturating_mul(exponent))?;
+            a.pow(exponent as u32)
+        }
+        BinOp::LShift => {
+            if b.is_negative() {
+                return Err(negative_shift());
+            }
+            if a.is_zero() {
+                return Ok(PyValue::Int(0));
+            }
+            let shift = b.to_u64().ok_or_else(too_large)?;
+            check_bits(a.bits().saturating_add(shift))?;
+            a << shift
+        }
+        BinOp::RShift => {
+            if b.is_negative() {
+                return Err(negative_shift());
+            }
+            match b.to_u64() {
+                Some(shift) if shift < a.bits() => a >> shift,
+                // Everything is shifted out
+                _ => BigInt::from(if a.is_negative() { -1 } else { 0 }),
+            }
+        }
+        BinOp::BitAnd => a & b,
+        BinOp::BitOr => a | b,
+        BinOp::BitXor => a ^ b,
+    };
+    Ok(normalize(result))
+}
+
+/// `a / b` for big ints, correctly rounded to a float.
+fn true_divide(a: &BigInt, b: &BigInt) -> Result<PyValue> {
+    if b.is_zero() {
+        return Err(Error::DivisionByZero);
+    }
+    // Scale the dividend so the integer quotient has 64 significant bits,
+    // keeping a sticky bit for any remainder, then round once
+    let (a_mag, b_mag) = (a.magnitude(), b.magnitude());
+    let shift = 64 + b_mag.bits() as i64 - a_mag.bits() as i64;
+    let (q, r) = if shift >= 0 {
+        (a_mag << shift as u64).div_rem(b_mag)
+    } else {
+        a_mag.div_rem(&(b_mag << shift.unsigned_abs()))
+    };
+    let q = if r.is_zero() { q } else { q | BigUint::one() };
+    // Apply the scale in two steps so neither factor over- or underflows
+    let half = (shift / 2) as i32;
+    let quotient =
+        q.to_f64().unwrap_or(f64::INFINITY) * 2f64.powi(-half) * 2f64.powi(-(shift as i32 - half));
+    if !quotient.is_finite() {
+        return Err(Error::exception(
+            "OverflowError",
+            "integer division result too large for a float",
+        ));
+    }
+    let negative = a.is_negative() != b.is_negative();
+    Ok(PyValue::Float(if negative { -quotient } else { quotient }))
+}
+
+/// `base ** exponent` for a negative int exponent, which gives a float.
+fn negative_power(base: f64, exponent: f64) -> Result<PyValue> {
+    if base == 0.0 {
+        return Err(Error::exception(
+            "ZeroDivisionError",
+            "0.0 cannot be raised to a negative power",
+        ));
+    }
+    Ok(PyValue::Float(base.powf(exponent)))
+}
+
+/// Three-argument `pow(base, exponent, modulus)`.
+pub(crate) fn modpow(base: &PyValue, exponent: &PyValue, modulus: &PyValue) -> Result<PyValue> {
+    let operand = |value: &PyValue| {
+        to_bigint(value).ok_or_else(|| {
+            Error::exception(
+                "TypeError",
+                "pow() 3rd argument not allowed unless all arguments are integers",
+            )
+        })
+    };
+    let (base, exponent, modulus) = (operand(base)?, operand(exponent)?, operand(modulus)?);
+    if modulus.is_zero() {
+        return Err(Error::exception(
+            "ValueError",
+            "pow() 3rd argument cannot be 0",
+        ));
+    }
+    if exponent.is_negative() {
+        return Err(Error::exception(
+            "ValueError",
+            "pow() 2nd argument cannot be negative when 3rd argument specified",
+        ));
+    }
+    // Rounds like `%`: the result takes the sign of the modulus
+    let result = base.modpow(&exponent, &modulus);
+    Ok(normalize(result))
+}
+
+/// Fail if an int of `bits` bits would exceed [`MAX_BITS`].
+pub(crate) fn check_bits(bits: u64) -> Result<()> {
+    if bits > MAX_BITS {
+        Err(too_large())
+    } else {
+        Ok(())
+    }
+}
+
+fn too_large() -> Error {
+    Error::exception(
+        "OverflowError",
+        format!("int result exceeds the sandbox limit of {} bits", MAX_BITS),
+    )
+}
+
+fn negative_shift() -> Error {
+    Error::exception("ValueError", "negative shift count")
+}
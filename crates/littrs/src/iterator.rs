@@ -0,0 +1,236 @@
+//! Iterators and iterator objects.
+//!
+//! [`IterState`] is the state of one iteration: a `for` loop keeps it in its
+//! call frame, and an [`IteratorValue`] (from `iter()`, `zip()`,
+//! `enumerate()`, `map()` or `filter()`) shares it between every reference
+//! to the iterator object. Advancing an iterator may run sandbox code (a
+//! generator body or a `map()` function), so that is left to the VM.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::dict::{Dict, DictViewKind, Set};
+use crate::error::{Error, Result};
+use crate::generator::GeneratorValue;
+use crate::value::{PyValue, Shared};
+
+/// State of a single iterator.
+pub(crate) enum IterState {
+    /// A list, read at each step so that items appended during the loop
+    /// are visited too.
+    List {
+        list: Shared<Vec<PyValue>>,
+        index: usize,
+    },
+    /// The items of a tuple, frozenset or string.
+    Items(std::vec::IntoIter<PyValue>),
+    /// The keys, values or items of a dict, read at each step. Adding or
+    /// removing keys during the loop makes the next step fail.
+    Dict {
+        dict: Shared<Dict>,
+        kind: DictViewKind,
+        pos: usize,
+        len: usize,
+        version: u64,
+    },
+    /// A set, read at each step. Changing its size during the loop makes
+    /// the next step fail.
+    Set {
+        set: Shared<Set>,
+        pos: usize,
+        len: usize,
+    },
+    /// A range, advanced without materializing its items.
+    Range { next: i64, stop: i64, step: i64 },
+    /// A generator, resumed for each item.
+    Generator(GeneratorValue),
+    /// An iterator object, advanced in place.
+    Shared(IteratorValue),
+    /// `enumerate(iterable, start)`.
+    Enumerate { inner: Box<IterState>, count: i64 },
+    /// `zip(*iterables)`: stops at the shortest iterable.
+    Zip(Vec<IterState>),
+    /// `map(func, *iterables)`.
+    Map {
+        func: PyValue,
+        iters: Vec<IterState>,
+    },
+    /// `filter(func, iterable)`; a `None` function keeps truthy items.
+    Filter {
+        func: PyValue,
+        inner: Box<IterState>,
+    },
+}
+
+impl IterState {
+    /// Start iterating over `value`.
+    pub(crate) fn new(value: PyValue) -> Result<Self> {
+        let items = match value.into_tuple_view() {
+            PyValue::List(list) => return Ok(IterState::List { list, index: 0 }),
+            PyValue::Tuple(items) => items,
+            PyValue::Set(set) => {
+                let len = set.read().len();
+                return Ok(IterState::Set { set, pos: 0, len });
+            }
+            PyValue::FrozenSet(set) => set.iter().cloned().collect(),
+            PyValue::Dict(dict) => return Ok(IterState::dict(dict, DictViewKind::Keys)),
+            PyValue::DictView(view) => {
+                return Ok(IterState::dict(view.dict().clone(), view.kind()));
+            }
+            PyValue::Str(s) => s.chars().map(|c| PyValue::Str(c.to_string())).collect(),
+            PyValue::Bytes(data) => data.iter().map(|&byte| PyValue::Int(byte as i64)).collect(),
+            PyValue::ByteArray(data) => data
+                .read()
+                .iter()
+                .map(|&byte| PyValue::Int(byte as i64))
+                .collect(),
+            PyValue::Range { start, stop, step } => {
+                return Ok(IterState::Range {
+                    next: start,
+                    stop,
+                    step,
+                });
+            }
+            PyValue::Generator(generator) => return Ok(IterState::Generator(generator)),
+            PyValue::Iterator(iterator) => return Ok(IterState::Shared(iterator)),
+            other => {
+                return Err(Error::Type {
+                    expected: "iterable".to_string(),
+                    got: other.type_name().to_string(),
+                });
+            }
+        };
+        Ok(IterState::Items(items.into_iter()))
+    }
+
+    fn dict(dict: Shared<Dict>, kind: DictViewKind) -> Self {
+        let (len, version) = {
+            let contents = dict.read();
+            (contents.len(), contents.version())
+        };
+        IterState::Dict {
+            dict,
+            kind,
+            pos: 0,
+            len,
+            version,
+        }
+    }
+
+    /// Advance a dict or set iteration, raising `RuntimeError` if the dict
+    /// or set changed during the loop, as Python does.
+    pub(crate) fn next_entry(&mut self) -> Result<Option<PyValue>> {
+        match self {
+            IterState::Dict {
+                dict,
+                kind,
+                pos,
+                len,
+                version,
+            } => {
+                let dict = dict.read();
+                if dict.len() != *len {
+                    return Err(changed_during_iteration("dictionary changed size"));
+                }
+                if dict.version() != *version {
+                    return Err(changed_during_iteration("dictionary keys changed"));
+                }
+                let Some((at, key, value)) = dict.pair_from(*pos) else {
+                    return Ok(None);
+                };
+                *pos = at + 1;
+                Ok(Some(match kind {
+                    DictViewKind::Keys => key.clone(),
+                    DictViewKind::Values => value.clone(),
+                    DictViewKind::Items => PyValue::Tuple(vec![key.clone(), value.clone()]),
+                }))
+            }
+            IterState::Set { set, pos, len } => {
+                let set = set.read();
+                if set.len() != *len {
+                    return Err(changed_during_iteration("Set changed size"));
+                }
+                let Some((at, item)) = set.item_from(*pos) else {
+                    return Ok(None);
+                };
+                *pos = at + 1;
+                Ok(Some(item.clone()))
+            }
+            _ => unreachable!("only dict and set iterations have entries"),
+        }
+    }
+}
+
+fn changed_during_iteration(what: &str) -> Error {
+    Error::exception("RuntimeError", format!("{} during iteration", what))
+}
+
+/// An iterator object. Clones refer to the same iterator.
+#[derive(Clone)]
+pub struct IteratorValue {
+    /// The Python type name (`list_iterator`, `zip`, `map`, ...).
+    pub kind: &'static str,
+    /// `None` while the iterator is being advanced.
+    state: Arc<Mutex<Option<IterState>>>,
+}
+
+impl IteratorValue {
+    pub(crate) fn new(kind: &'static str, state: IterState) -> Self {
+        Self {
+            kind,
+            state: Arc::new(Mutex::new(Some(state))),
+        }
+    }
+
+    /// Create the iterator `iter(value)` returns.
+    pub(crate) fn over(value: PyValue) -> Result<Self> {
+        let kind = match &value {
+            PyValue::List(_) => "list_iterator",
+            PyValue::Tuple(_) => "tuple_iterator",
+            PyValue::Set(_) | PyValue::FrozenSet(_) => "set_iterator",
+            PyValue::Dict(_) => "dict_keyiterator",
+            PyValue::DictView(view) => match view.kind() {
+                DictViewKind::Keys => "dict_keyiterator",
+                DictViewKind::Values => "dict_valueiterator",
+                DictViewKind::Items => "dict_itemiterator",
+            },
+            PyValue::Str(_) => "str_iterator",
+            PyValue::Range { .. } => "range_iterator",
+            _ => "iterator",
+        };
+        Ok(Self::new(kind, IterState::new(value)?))
+    }
+
+    /// Take the state out to advance it. The caller puts it back with
+    /// [`IteratorValue::restore`].
+    pub(crate) fn take(&self) -> Result<IterState> {
+        self.state
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| Error::exception("ValueError", "iterator already executing"))
+    }
+
+    /// Put the state back after advancing it.
+    pub(crate) fn restore(&self, state: IterState) {
+        *self.state.lock().unwrap() = Some(state);
+    }
+}
+
+impl PartialEq for IteratorValue {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl fmt::Debug for IteratorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} object>", self.kind)
+    }
+}
+
+impl fmt::Display for IteratorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} object>", self.kind)
+    }
+}
@@ -21,7 +21,7 @@
 //! // Register a tool
 //! sandbox.register_fn("fetch_data", |args| {
 //!     let id = args[0].as_int().unwrap_or(0);
-//!     PyValue::Dict(vec![
+//!     PyValue::dict(vec![
 //!         (PyValue::Str("id".to_string()), PyValue::Int(id)),
 //!         (PyValue::Str("name".to_string()), PyValue::Str("Example".to_string())),
 //!     ])
@@ -37,8 +37,10 @@
 //! # Supported Python Features
 //!
 //! ## Types
-//! - `None`, `bool`, `int`, `float`, `str`
-//! - `list`, `dict` (string keys only)
+//! - `None`, `bool`, `int` (arbitrary precision), `float`, `str`
+//! - `list`, `dict` (string keys only), shared by reference as in Python
+//! - Lazy `range` objects, generators and iterators (`iter()`, `next()`, `zip()`,
+//!   `enumerate()`, `map()`, `filter()`)
 //!
 //! ## Operators
 //! - Arithmetic: `+`, `-`, `*`, `/`, `//`, `%`, `**`
@@ -46,20 +48,41 @@
 //! - Boolean: `and`, `or`, `not`
 //! - Bitwise: `|`, `^`, `&`, `<<`, `>>`
 //!
+//! ## String Formatting
+//! - f-strings with format specs (`f"{price:>10,.2f}"`), conversions (`!r`)
+//!   and self-documenting expressions (`f"{x=}"`)
+//! - `format()`, `str.format()` with keyword and attribute fields, and
+//!   `%`-style formatting (`"%.2f" % x`)
+//!
 //! ## Control Flow
 //! - `if`/`elif`/`else`
-//! - `for` loops (over lists and strings) with `break`/`continue`
+//! - `for` loops (over lists, strings, ranges, generators and iterators) with `break`/`continue`
 //! - `while` loops with `break`/`continue`
+//! - `match`/`case` structural pattern matching
 //!
 //! ## Functions
 //! - `def` with positional parameters, default values, `*args`, `**kwargs`
 //! - Recursive calls
-//! - Nested function definitions
+//! - Nested function definitions and closures
+//! - `global` and `nonlocal` declarations
+//! - Generator functions (`yield`, `yield from`) and lazy generator expressions
+//! - Decorators, including host decorators registered with
+//!   [`Sandbox::register_decorator`]
 //!
 //! ## Error Handling
 //! - `try`/`except` with typed handlers and `as` binding
-//! - `raise` with exception type and message
+//! - `raise` with exception type and message, `raise ... from ...`
+//! - Exception objects (`e.args`, `str(e)`, `__cause__`, `__context__`)
+//! - Built-in exception hierarchy (`except LookupError` catches `KeyError`)
+//! - Custom exception classes (`class RetryableError(Exception): pass`)
 //! - `else` clause on try blocks
+//! - `finally` blocks (run on normal exit, exceptions, `break`, `continue` and `return`)
+//!
+//! ## Classes
+//! - `class` definitions with `__init__`, methods and class variables
+//! - Single inheritance with `super()`
+//! - `@property`, `__repr__` and `__str__`
+//! - `@dataclass` and `typing.NamedTuple` records (returned to the host as dicts)
 //!
 //! ## Resource Limits
 //! - Configurable instruction count limit (prevents infinite loops)
@@ -73,14 +96,15 @@
 //! ## Imports
 //! - `import module` / `import module as alias`
 //! - `from module import name` / `from module import name as alias`
-//! - Built-in modules: `json` (loads/dumps), `math` (constants + functions), `typing`
+//! - Built-in modules: `json` (loads/dumps), `math` (constants + functions), `typing`,
+//!   `dataclasses`, `functools` (`lru_cache`, `cache`, `wraps`)
 //! - Custom module registration via [`Sandbox::module`]
 //!
 //! # Not Supported
 //!
-//! - Class definitions
+//! - Multiple inheritance and metaclasses
+//! - Generator `send()`, `throw()` and `close()`
 //! - Async/await
-//! - `finally` blocks
 //! - Relative imports
 //!
 //! ## Virtual Filesystem
@@ -91,14 +115,24 @@
 
 mod builtins;
 mod bytecode;
+mod bytes;
+mod class;
 mod compiler;
+mod decorator;
 mod diagnostic;
+mod dict;
 mod error;
+mod exception;
+mod format;
+mod generator;
+mod int;
+mod iterator;
 mod methods;
 pub(crate) mod modules;
 mod operators;
 mod sandbox;
 mod slice;
+mod text;
 mod tool;
 mod value;
 mod vm;
@@ -108,11 +142,18 @@ mod wasm_error;
 #[cfg(feature = "wasm")]
 mod wasm_sandbox;
 
+pub use class::{ClassValue, DataclassOptions, InstanceValue, Namespace, RecordKind};
+pub use decorator::{DecoratedCall, DecoratorFactory, DecoratorValue, HostDecorator};
 pub use diagnostic::{Diagnostic, FunctionCallDiagnostic, Label, Span};
+pub use dict::{Dict, DictView, DictViewKind, Set};
 pub use error::{Error, Result};
+pub use exception::ExceptionValue;
+pub use generator::GeneratorValue;
+pub use iterator::IteratorValue;
+pub use num_bigint::BigInt;
 pub use sandbox::{Limits, ModuleBuilder, Output, Sandbox};
 pub use tool::{ArgInfo, Tool, ToolCallError, ToolInfo};
-pub use value::{FromPyValue, PyValue, TypeError};
+pub use value::{FromPyValue, PyValue, Shared, TypeError};
 
 // Re-export the macro when the macros feature is enabled
 #[cfg(feature = "macros")]
@@ -1,9 +1,33 @@
 //! Method implementations for Python types.
 //!
-//! This module contains the implementations of methods for str, list, and dict types.
+//! This module contains the implementations of methods for str, bytes, list, dict and set types.
 
+use std::sync::Arc;
+
+use crate::bytes;
+use crate::dict::{Dict, DictView, DictViewKind, Set, check_hashable};
 use crate::error::{Error, Result};
-use crate::value::{PyValue, SetIndex};
+use crate::exception::ExceptionValue;
+use crate::text;
+use crate::value::{PyValue, Shared};
+
+/// The set of method names that mutate a list in place.
+pub(crate) const LIST_MUTATING_METHODS: &[&str] = &[
+    "append", "extend", "pop", "clear", "insert", "remove", "reverse", "sort",
+];
+
+/// The set of method names that mutate a bytearray in place.
+pub(crate) const BYTEARRAY_MUTATING_METHODS: &[&str] = &[
+    "append", "extend", "pop", "clear", "insert", "remove", "reverse",
+];
+
+/// The set of method names that mutate a dict in place.
+pub(crate) const DICT_MUTATING_METHODS: &[&str] =
+    &["update", "setdefault", "pop", "popitem", "clear"];
+
+/// The set of method names that mutate a set in place.
+pub(crate) const SET_MUTATING_METHODS: &[&str] =
+    &["add", "discard", "remove", "clear", "update", "pop"];
 
 // ============================================================================
 // Argument validation helpers
@@ -22,7 +46,7 @@ pub(crate) fn check_args(func: &str, args: &[PyValue], n: usize) -> Result<()> {
                 if n == 1 { "" } else { "s" }
             )
         };
-        Err(Error::Runtime(msg))
+        Err(Error::exception("TypeError", msg))
     } else {
         Ok(())
     }
@@ -31,10 +55,10 @@ pub(crate) fn check_args(func: &str, args: &[PyValue], n: usize) -> Result<()> {
 /// Check that `args.len()` is in `[min, max]`, or return an error naming `func`.
 pub(crate) fn check_args_range(func: &str, args: &[PyValue], min: usize, max: usize) -> Result<()> {
     if args.len() < min || args.len() > max {
-        Err(Error::Runtime(format!(
-            "{}() takes {} to {} arguments",
-            func, min, max
-        )))
+        Err(Error::exception(
+            "TypeError",
+            format!("{}() takes {} to {} arguments", func, min, max),
+        ))
     } else {
         Ok(())
     }
@@ -48,8 +72,15 @@ pub(crate) fn arg_str(arg: &PyValue) -> Result<&str> {
     })
 }
 
-/// Extract an `i64` from a `PyValue`, or return a type error.
+/// Extract an `i64` from a `PyValue`, or return a type error (an overflow
+/// error for an int that doesn't fit).
 pub(crate) fn arg_int(arg: &PyValue) -> Result<i64> {
+    if let PyValue::BigInt(_) = arg {
+        return Err(Error::exception(
+            "OverflowError",
+            "Python int too large to convert to C ssize_t",
+        ));
+    }
     arg.as_int().ok_or_else(|| Error::Type {
         expected: "int".to_string(),
         got: arg.type_name().to_string(),
@@ -79,26 +110,29 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
         }
         "strip" => {
             check_args("strip", &args, 0)?;
-            Ok(PyValue::Str(s.trim().to_string()))
+            Ok(PyValue::Str(s.trim_matches(text::is_space).to_string()))
         }
         "lstrip" => {
             check_args("lstrip", &args, 0)?;
-            Ok(PyValue::Str(s.trim_start().to_string()))
+            Ok(PyValue::Str(
+                s.trim_start_matches(text::is_space).to_string(),
+            ))
         }
         "rstrip" => {
             check_args("rstrip", &args, 0)?;
-            Ok(PyValue::Str(s.trim_end().to_string()))
+            Ok(PyValue::Str(s.trim_end_matches(text::is_space).to_string()))
         }
         "split" => {
             let sep = args.first().and_then(|v| v.as_str());
             let parts: Vec<PyValue> = if let Some(sep) = sep {
                 s.split(sep).map(|p| PyValue::Str(p.to_string())).collect()
             } else {
-                s.split_whitespace()
+                s.split(text::is_space)
+                    .filter(|p| !p.is_empty())
                     .map(|p| PyValue::Str(p.to_string()))
                     .collect()
             };
-            Ok(PyValue::List(parts))
+            Ok(PyValue::list(parts))
         }
         "join" => {
             check_args("join", &args, 1)?;
@@ -112,6 +146,7 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
                 }
             };
             let strings: Result<Vec<String>> = items
+                .read()
                 .iter()
                 .map(|v| match v {
                     PyValue::Str(s) => Ok(s.clone()),
@@ -137,33 +172,74 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
             check_args("endswith", &args, 1)?;
             Ok(PyValue::Bool(s.ends_with(arg_str(&args[0])?)))
         }
-        "find" => {
-            check_args("find", &args, 1)?;
-            Ok(PyValue::Int(
-                s.find(arg_str(&args[0])?).map(|i| i as i64).unwrap_or(-1),
-            ))
+        "find" | "rfind" | "index" | "rindex" => {
+            check_args_range(method, &args, 1, 3)?;
+            let sub = arg_str(&args[0])?;
+            let position = text::find(s, sub, &args, method.starts_with('r'))?;
+            match position {
+                Some(position) => Ok(PyValue::Int(position as i64)),
+                None if method.ends_with("index") => {
+                    Err(Error::exception("ValueError", "substring not found"))
+                }
+                None => Ok(PyValue::Int(-1)),
+            }
         }
         "count" => {
-            check_args("count", &args, 1)?;
-            Ok(PyValue::Int(s.matches(arg_str(&args[0])?).count() as i64))
-        }
-        "isdigit" => {
-            check_args("isdigit", &args, 0)?;
-            Ok(PyValue::Bool(
-                !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()),
-            ))
-        }
-        "isalpha" => {
-            check_args("isalpha", &args, 0)?;
-            Ok(PyValue::Bool(
-                !s.is_empty() && s.chars().all(|c| c.is_alphabetic()),
-            ))
-        }
-        "isalnum" => {
-            check_args("isalnum", &args, 0)?;
-            Ok(PyValue::Bool(
-                !s.is_empty() && s.chars().all(|c| c.is_alphanumeric()),
-            ))
+            check_args_range("count", &args, 1, 3)?;
+            let sub = arg_str(&args[0])?;
+            Ok(PyValue::Int(text::count(s, sub, &args)? as i64))
+        }
+        "isdigit" | "isdecimal" | "isnumeric" | "isalpha" | "isalnum" | "isspace" => {
+            check_args(method, &args, 0)?;
+            let class: fn(char) -> bool = match method {
+                "isdigit" => text::is_digit,
+                "isdecimal" => text::is_decimal,
+                "isnumeric" => char::is_numeric,
+                "isalpha" => text::is_alpha,
+                "isalnum" => |c| text::is_alpha(c) || c.is_numeric(),
+                _ => text::is_space,
+            };
+            Ok(PyValue::Bool(!s.is_empty() && s.chars().all(class)))
+        }
+        // Unlike the other classes, the empty string is printable
+        "isprintable" => {
+            check_args(method, &args, 0)?;
+            Ok(PyValue::Bool(s.chars().all(text::is_printable)))
+        }
+        // A string is upper case when it has cased characters and all of
+        // them are upper case (and the same for lower case)
+        "isupper" | "islower" => {
+            check_args(method, &args, 0)?;
+            let case: fn(char) -> bool = if method == "isupper" {
+                char::is_uppercase
+            } else {
+                char::is_lowercase
+            };
+            let mut cased = s.chars().filter(|&c| text::is_cased(c)).peekable();
+            Ok(PyValue::Bool(cased.peek().is_some() && cased.all(case)))
+        }
+        // Upper and title case characters must start words, and lower
+        // case characters must continue them
+        "istitle" => {
+            check_args("istitle", &args, 0)?;
+            let mut cased = false;
+            let mut in_word = false;
+            for c in s.chars() {
+                if c.is_lowercase() {
+                    if !in_word {
+                        return Ok(PyValue::Bool(false));
+                    }
+                } else if text::is_cased(c) {
+                    if in_word {
+                        return Ok(PyValue::Bool(false));
+                    }
+                    in_word = true;
+                    cased = true;
+                } else {
+                    in_word = false;
+                }
+            }
+            Ok(PyValue::Bool(cased))
         }
         "title" => {
             check_args("title", &args, 0)?;
@@ -194,7 +270,6 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
             };
             Ok(PyValue::Str(result))
         }
-        "format" => str_format(s, args),
         "removeprefix" => {
             check_args("removeprefix", &args, 1)?;
             let prefix = arg_str(&args[0])?;
@@ -274,18 +349,20 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
             if start < len {
                 lines.push(PyValue::Str(s[start..].to_string()));
             }
-            Ok(PyValue::List(lines))
+            Ok(PyValue::list(lines))
         }
         "center" => {
             check_args_range("center", &args, 1, 2)?;
-            let width = arg_int(&args[0])? as usize;
+            let width = arg_int(&args[0])?.max(0) as usize;
             let fill = parse_fill_char(&args)?;
             let slen = s.chars().count();
             if slen >= width {
                 Ok(PyValue::Str(s.to_string()))
             } else {
+                // Like CPython, put an odd pad's extra fill on the left
+                // when the width is odd
                 let total_pad = width - slen;
-                let left_pad = total_pad / 2;
+                let left_pad = total_pad / 2 + (total_pad & width & 1);
                 let right_pad = total_pad - left_pad;
                 let mut result = String::with_capacity(width);
                 for _ in 0..left_pad {
@@ -300,7 +377,7 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
         }
         "ljust" => {
             check_args_range("ljust", &args, 1, 2)?;
-            let width = arg_int(&args[0])? as usize;
+            let width = arg_int(&args[0])?.max(0) as usize;
             let fill = parse_fill_char(&args)?;
             let slen = s.chars().count();
             if slen >= width {
@@ -315,7 +392,7 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
         }
         "rjust" => {
             check_args_range("rjust", &args, 1, 2)?;
-            let width = arg_int(&args[0])? as usize;
+            let width = arg_int(&args[0])?.max(0) as usize;
             let fill = parse_fill_char(&args)?;
             let slen = s.chars().count();
             if slen >= width {
@@ -331,7 +408,7 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
         }
         "zfill" => {
             check_args("zfill", &args, 1)?;
-            let width = arg_int(&args[0])? as usize;
+            let width = arg_int(&args[0])?.max(0) as usize;
             let slen = s.chars().count();
             if slen >= width {
                 Ok(PyValue::Str(s.to_string()))
@@ -371,6 +448,12 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
             check_args("casefold", &args, 0)?;
             Ok(PyValue::Str(s.to_lowercase()))
         }
+        "encode" => {
+            check_args_range("encode", &args, 0, 2)?;
+            let encoding = args.first().map(arg_str).transpose()?.unwrap_or("utf-8");
+            let errors = args.get(1).map(arg_str).transpose()?.unwrap_or("strict");
+            Ok(PyValue::Bytes(bytes::encode(s, encoding, errors)?))
+        }
         _ => Err(Error::Unsupported(format!(
             "String method '{}' not implemented",
             method
@@ -378,6 +461,327 @@ pub fn call_str_method(s: &str, method: &str, args: Vec<PyValue>) -> Result<PyVa
     }
 }
 
+/// Call a method on a bytes or bytearray value (non-mutating). Methods
+/// that return new bytes return a bytearray when called on one.
+pub fn call_bytes_method(
+    data: &[u8],
+    bytearray: bool,
+    method: &str,
+    args: Vec<PyValue>,
+) -> Result<PyValue> {
+    let wrap = |data: Vec<u8>| {
+        if bytearray {
+            PyValue::bytearray(data)
+        } else {
+            PyValue::Bytes(data)
+        }
+    };
+    match method {
+        "decode" => {
+            check_args_range("decode", &args, 0, 2)?;
+            let encoding = args.first().map(arg_str).transpose()?.unwrap_or("utf-8");
+            let errors = args.get(1).map(arg_str).transpose()?.unwrap_or("strict");
+            Ok(PyValue::Str(bytes::decode(data, encoding, errors)?))
+        }
+        "hex" => {
+            check_args("hex", &args, 0)?;
+            Ok(PyValue::Str(bytes::to_hex(data)))
+        }
+        "find" | "rfind" | "index" => {
+            check_args(method, &args, 1)?;
+            let needle = arg_needle(&args[0])?;
+            let position = if method == "rfind" {
+                bytes::rfind(data, &needle)
+            } else {
+                bytes::find(data, &needle)
+            };
+            match position {
+                Some(position) => Ok(PyValue::Int(position as i64)),
+                None if method == "index" => {
+                    Err(Error::exception("ValueError", "subsection not found"))
+                }
+                None => Ok(PyValue::Int(-1)),
+            }
+        }
+        "count" => {
+            check_args("count", &args, 1)?;
+            let needle = arg_needle(&args[0])?;
+            Ok(PyValue::Int(bytes::count(data, &needle) as i64))
+        }
+        "split" => {
+            check_args_range("split", &args, 0, 1)?;
+            let sep = match args.first() {
+                None | Some(PyValue::None) => None,
+                Some(sep) => Some(bytes::arg_bytes(sep)?),
+            };
+            Ok(PyValue::list(
+                bytes::split(data, sep.as_deref())?
+                    .into_iter()
+                    .map(wrap)
+                    .collect(),
+            ))
+        }
+        "join" => {
+            check_args("join", &args, 1)?;
+            let items = match &args[0] {
+                PyValue::List(items) => items.read().to_vec(),
+                PyValue::Tuple(items) => items.clone(),
+                other => {
+                    return Err(Error::Type {
+                        expected: "list".to_string(),
+                        got: other.type_name().to_string(),
+                    });
+                }
+            };
+            let mut result = Vec::new();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    result.extend_from_slice(data);
+                }
+                result.extend_from_slice(&bytes::arg_bytes(item)?);
+            }
+            Ok(wrap(result))
+        }
+        "replace" => {
+            check_args("replace", &args, 2)?;
+            let old = bytes::arg_bytes(&args[0])?;
+            let new = bytes::arg_bytes(&args[1])?;
+            Ok(wrap(bytes::replace(data, &old, &new)))
+        }
+        "startswith" => {
+            check_args("startswith", &args, 1)?;
+            Ok(PyValue::Bool(
+                data.starts_with(&bytes::arg_bytes(&args[0])?),
+            ))
+        }
+        "endswith" => {
+            check_args("endswith", &args, 1)?;
+            Ok(PyValue::Bool(data.ends_with(&bytes::arg_bytes(&args[0])?)))
+        }
+        "strip" | "lstrip" | "rstrip" => {
+            check_args_range(method, &args, 0, 1)?;
+            let chars = match args.first() {
+                None | Some(PyValue::None) => b" \t\n\r\x0b\x0c".to_vec(),
+                Some(chars) => bytes::arg_bytes(chars)?,
+            };
+            let keep = |byte: &u8| !chars.contains(byte);
+            let start = match method {
+                "rstrip" => 0,
+                _ => data.iter().position(keep).unwrap_or(data.len()),
+            };
+            let end = match method {
+                "lstrip" => data.len(),
+                _ => data.iter().rposition(keep).map_or(start, |i| i + 1),
+            };
+            Ok(wrap(data[start..end.max(start)].to_vec()))
+        }
+        "upper" => {
+            check_args("upper", &args, 0)?;
+            Ok(wrap(data.to_ascii_uppercase()))
+        }
+        "lower" => {
+            check_args("lower", &args, 0)?;
+            Ok(wrap(data.to_ascii_lowercase()))
+        }
+        "copy" if bytearray => {
+            check_args("copy", &args, 0)?;
+            Ok(wrap(data.to_vec()))
+        }
+        _ => Err(Error::Unsupported(format!(
+            "Bytes method '{}' not implemented",
+            method
+        ))),
+    }
+}
+
+/// Merge the keyword arguments of `str.encode` / `bytes.decode`
+/// (`encoding=`, `errors=`) into their positional arguments.
+pub(crate) fn codec_args(
+    method: &str,
+    mut args: Vec<PyValue>,
+    kw_pairs: Vec<(String, PyValue)>,
+) -> Result<Vec<PyValue>> {
+    for (name, value) in kw_pairs {
+        let position = match name.as_str() {
+            "encoding" => 0,
+            "errors" => 1,
+            _ => {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!("{}() got an unexpected keyword argument '{}'", method, name),
+                ));
+            }
+        };
+        if args.len() > position {
+            return Err(Error::exception(
+                "TypeError",
+                format!(
+                    "argument for {}() given by name ('{}') and position ({})",
+                    method,
+                    name,
+                    position + 1
+                ),
+            ));
+        }
+        // An omitted encoding before `errors=` takes its default
+        while args.len() < position {
+            args.push(PyValue::Str("utf-8".to_string()));
+        }
+        args.push(value);
+    }
+    Ok(args)
+}
+
+/// Call a method on a built-in type object, such as `bytes.fromhex(s)`.
+pub fn call_type_method(type_name: &str, method: &str, args: Vec<PyValue>) -> Result<PyValue> {
+    match (type_name, method) {
+        ("bytes" | "bytearray", "fromhex") => {
+            check_args("fromhex", &args, 1)?;
+            let data = bytes::from_hex(arg_str(&args[0])?)?;
+            Ok(if type_name == "bytes" {
+                PyValue::Bytes(data)
+            } else {
+                PyValue::bytearray(data)
+            })
+        }
+        _ => Err(Error::exception(
+            "AttributeError",
+            format!("type object '{}' has no attribute '{}'", type_name, method),
+        )),
+    }
+}
+
+/// The built-in types whose instance methods can be looked up on the type
+/// object.
+const METHOD_DESCRIPTOR_TYPES: &[&str] = &[
+    "str",
+    "bytes",
+    "bytearray",
+    "list",
+    "tuple",
+    "dict",
+    "set",
+    "frozenset",
+];
+
+/// A method of the built-in type's instances looked up on the type object,
+/// such as `str.lower` in `sorted(names, key=str.lower)`.
+pub(crate) fn method_descriptor(type_name: &str, method: &str) -> Option<PyValue> {
+    let is_type_method = matches!((type_name, method), ("bytes" | "bytearray", "fromhex"));
+    (METHOD_DESCRIPTOR_TYPES.contains(&type_name) && !is_type_method && !method.starts_with("__"))
+        .then(|| PyValue::MethodDescriptor {
+            type_name: type_name.to_string(),
+            method: method.to_string(),
+        })
+}
+
+/// The subsequence searched for by `find`, `index` and `count`: bytes or a
+/// single byte value.
+fn arg_needle(arg: &PyValue) -> Result<Vec<u8>> {
+    match arg {
+        PyValue::Int(_) | PyValue::Bool(_) | PyValue::BigInt(_) => {
+            Ok(vec![bytes::byte_value(arg)?])
+        }
+        _ => bytes::arg_bytes(arg),
+    }
+}
+
+/// Mutating bytearray methods (append, extend, pop, etc.)
+pub fn mutate_bytearray(
+    data: &Shared<Vec<u8>>,
+    method: &str,
+    args: Vec<PyValue>,
+) -> Result<PyValue> {
+    match method {
+        "append" => {
+            check_args("append", &args, 1)?;
+            let byte = bytes::byte_value(&args[0])?;
+            data.write(|data| data.push(byte));
+            Ok(PyValue::None)
+        }
+        "extend" => {
+            check_args("extend", &args, 1)?;
+            let new_data = match &args[0] {
+                PyValue::List(items) => items
+                    .read()
+                    .iter()
+                    .map(bytes::byte_value)
+                    .collect::<Result<Vec<u8>>>()?,
+                PyValue::Tuple(items) => items
+                    .iter()
+                    .map(bytes::byte_value)
+                    .collect::<Result<Vec<u8>>>()?,
+                other => bytes::arg_bytes(other)?,
+            };
+            data.write(|data| data.extend_from_slice(&new_data));
+            Ok(PyValue::None)
+        }
+        "pop" => {
+            check_args_range("pop", &args, 0, 1)?;
+            let index = args.first().map(arg_int).transpose()?;
+            data.write(|data| {
+                if data.is_empty() {
+                    return Err(Error::exception("IndexError", "pop from empty bytearray"));
+                }
+                let len = data.len() as i64;
+                let idx = match index {
+                    None => len - 1,
+                    Some(i) if i < 0 => len + i,
+                    Some(i) => i,
+                };
+                if !(0..len).contains(&idx) {
+                    return Err(Error::exception("IndexError", "pop index out of range"));
+                }
+                Ok(PyValue::Int(data.remove(idx as usize) as i64))
+            })
+        }
+        "clear" => {
+            check_args("clear", &args, 0)?;
+            data.write(|data| data.clear());
+            Ok(PyValue::None)
+        }
+        "insert" => {
+            check_args("insert", &args, 2)?;
+            let index = arg_int(&args[0])?;
+            let byte = bytes::byte_value(&args[1])?;
+            data.write(|data| {
+                let len = data.len() as i64;
+                let idx = if index < 0 {
+                    (len + index).max(0) as usize
+                } else {
+                    (index as usize).min(data.len())
+                };
+                data.insert(idx, byte);
+            });
+            Ok(PyValue::None)
+        }
+        "remove" => {
+            check_args("remove", &args, 1)?;
+            let byte = bytes::byte_value(&args[0])?;
+            let pos = data.read().iter().position(|&b| b == byte);
+            match pos {
+                Some(idx) => {
+                    data.write(|data| data.remove(idx));
+                    Ok(PyValue::None)
+                }
+                None => Err(Error::exception(
+                    "ValueError",
+                    "value not found in bytearray",
+                )),
+            }
+        }
+        "reverse" => {
+            check_args("reverse", &args, 0)?;
+            data.write(|data| data.reverse());
+            Ok(PyValue::None)
+        }
+        _ => Err(Error::Unsupported(format!(
+            "Bytearray method '{}' not implemented",
+            method
+        ))),
+    }
+}
+
 /// Call a method on a tuple value (non-mutating).
 pub fn call_tuple_method(items: &[PyValue], method: &str, args: Vec<PyValue>) -> Result<PyValue> {
     match method {
@@ -388,7 +792,10 @@ pub fn call_tuple_method(items: &[PyValue], method: &str, args: Vec<PyValue>) ->
                     return Ok(PyValue::Int(i as i64));
                 }
             }
-            Err(Error::Runtime("value not in tuple".to_string()))
+            Err(Error::exception(
+                "ValueError",
+                "tuple.index(x): x not in tuple",
+            ))
         }
         "count" => {
             check_args("count", &args, 1)?;
@@ -412,7 +819,10 @@ pub fn call_list_method(items: &[PyValue], method: &str, args: Vec<PyValue>) ->
                     return Ok(PyValue::Int(i as i64));
                 }
             }
-            Err(Error::Runtime("value not in list".to_string()))
+            Err(Error::exception(
+                "ValueError",
+                format!("{} is not in list", args[0]),
+            ))
         }
         "count" => {
             check_args("count", &args, 1)?;
@@ -421,7 +831,7 @@ pub fn call_list_method(items: &[PyValue], method: &str, args: Vec<PyValue>) ->
         }
         "copy" => {
             check_args("copy", &args, 0)?;
-            Ok(PyValue::List(items.to_vec()))
+            Ok(PyValue::list(items.to_vec()))
         }
         _ => Err(Error::Unsupported(format!(
             "List method '{}' not implemented",
@@ -431,46 +841,31 @@ pub fn call_list_method(items: &[PyValue], method: &str, args: Vec<PyValue>) ->
 }
 
 /// Call a method on a dict value (non-mutating).
-pub fn call_dict_method(
-    pairs: &[(PyValue, PyValue)],
-    method: &str,
-    args: Vec<PyValue>,
-) -> Result<PyValue> {
+pub fn call_dict_method(dict: &Shared<Dict>, method: &str, args: Vec<PyValue>) -> Result<PyValue> {
+    let view = |kind| Ok(PyValue::DictView(DictView::new(dict.clone(), kind)));
     match method {
         "get" => {
             check_args_range("get", &args, 1, 2)?;
             let key = &args[0];
+            check_hashable(key)?;
             let default = args.get(1).cloned().unwrap_or(PyValue::None);
-            Ok(pairs
-                .iter()
-                .find(|(k, _)| k == key)
-                .map(|(_, v)| v.clone())
-                .unwrap_or(default))
+            Ok(dict.read().get(key).cloned().unwrap_or(default))
         }
         "keys" => {
             check_args("keys", &args, 0)?;
-            Ok(PyValue::List(
-                pairs.iter().map(|(k, _)| k.clone()).collect(),
-            ))
+            view(DictViewKind::Keys)
         }
         "values" => {
             check_args("values", &args, 0)?;
-            Ok(PyValue::List(
-                pairs.iter().map(|(_, v)| v.clone()).collect(),
-            ))
+            view(DictViewKind::Values)
         }
         "items" => {
             check_args("items", &args, 0)?;
-            Ok(PyValue::List(
-                pairs
-                    .iter()
-                    .map(|(k, v)| PyValue::Tuple(vec![k.clone(), v.clone()]))
-                    .collect(),
-            ))
+            view(DictViewKind::Items)
         }
         "copy" => {
             check_args("copy", &args, 0)?;
-            Ok(PyValue::Dict(pairs.to_vec()))
+            Ok(Dict::clone(&dict.read()).into())
         }
         _ => Err(Error::Unsupported(format!(
             "Dict method '{}' not implemented",
@@ -480,27 +875,33 @@ pub fn call_dict_method(
 }
 
 /// Mutating list methods (append, extend, pop, etc.)
-#[allow(dead_code)]
-pub fn mutate_list(items: &mut Vec<PyValue>, method: &str, args: Vec<PyValue>) -> Result<PyValue> {
+///
+/// `sort` is handled by the VM, which may need to call a key function.
+pub fn mutate_list(
+    list: &Shared<Vec<PyValue>>,
+    method: &str,
+    args: Vec<PyValue>,
+) -> Result<PyValue> {
     match method {
         "append" => {
             check_args("append", &args, 1)?;
-            items.push(args.into_iter().next().unwrap());
+            let item = args.into_iter().next().unwrap();
+            list.write(|items| items.push(item));
             Ok(PyValue::None)
         }
         "extend" => {
             check_args("extend", &args, 1)?;
-            match &args[0] {
-                PyValue::List(new_items) => {
-                    items.extend(new_items.clone());
-                }
+            // Snapshot first: `a.extend(a)` reads the list being extended
+            let new_items = match &args[0] {
+                PyValue::List(new_items) => new_items.read(),
                 _ => {
                     return Err(Error::Type {
                         expected: "list".to_string(),
                         got: args[0].type_name().to_string(),
                     });
                 }
-            }
+            };
+            list.write(|items| items.extend_from_slice(&new_items));
             Ok(PyValue::None)
         }
         "pop" => {
@@ -509,64 +910,60 @@ pub fn mutate_list(items: &mut Vec<PyValue>, method: &str, args: Vec<PyValue>) -
             } else {
                 Some(arg_int(&args[0])?)
             };
-            if items.is_empty() {
-                return Err(Error::Runtime("pop from empty list".to_string()));
-            }
-            let idx = match index {
-                None => items.len() - 1,
-                Some(i) => {
-                    let len = items.len() as i64;
-                    (if i < 0 { len + i } else { i }) as usize
+            list.write(|items| {
+                if items.is_empty() {
+                    return Err(Error::exception("IndexError", "pop from empty list"));
                 }
-            };
-            if idx >= items.len() {
-                return Err(Error::Runtime("pop index out of range".to_string()));
-            }
-            Ok(items.remove(idx))
+                let idx = match index {
+                    None => items.len() - 1,
+                    Some(i) => {
+                        let len = items.len() as i64;
+                        (if i < 0 { len + i } else { i }) as usize
+                    }
+                };
+                if idx >= items.len() {
+                    return Err(Error::exception("IndexError", "pop index out of range"));
+                }
+                Ok(items.remove(idx))
+            })
         }
         "clear" => {
             check_args("clear", &args, 0)?;
-            items.clear();
+            list.write(|items| items.clear());
             Ok(PyValue::None)
         }
         "insert" => {
             check_args("insert", &args, 2)?;
             let index = arg_int(&args[0])?;
-            let len = items.len() as i64;
-            let idx = if index < 0 {
-                (len + index).max(0) as usize
-            } else {
-                (index as usize).min(items.len())
-            };
-            items.insert(idx, args[1].clone());
+            let item = args[1].clone();
+            list.write(|items| {
+                let len = items.len() as i64;
+                let idx = if index < 0 {
+                    (len + index).max(0) as usize
+                } else {
+                    (index as usize).min(items.len())
+                };
+                items.insert(idx, item);
+            });
             Ok(PyValue::None)
         }
         "remove" => {
             check_args("remove", &args, 1)?;
-            let pos = items.iter().position(|x| x == &args[0]);
+            let pos = list.read().iter().position(|x| x == &args[0]);
             match pos {
                 Some(idx) => {
-                    items.remove(idx);
+                    list.write(|items| items.remove(idx));
                     Ok(PyValue::None)
                 }
-                None => Err(Error::Runtime("value not in list".to_string())),
+                None => Err(Error::exception(
+                    "ValueError",
+                    "list.remove(x): x not in list",
+                )),
             }
         }
         "reverse" => {
             check_args("reverse", &args, 0)?;
-            items.reverse();
-            Ok(PyValue::None)
-        }
-        "sort" => {
-            check_args("sort", &args, 0)?;
-            items.sort_by(|a, b| match (a, b) {
-                (PyValue::Int(x), PyValue::Int(y)) => x.cmp(y),
-                (PyValue::Float(x), PyValue::Float(y)) => {
-                    x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal)
-                }
-                (PyValue::Str(x), PyValue::Str(y)) => x.cmp(y),
-                _ => std::cmp::Ordering::Equal,
-            });
+            list.write(|items| items.reverse());
             Ok(PyValue::None)
         }
         _ => Err(Error::Unsupported(format!(
@@ -576,82 +973,75 @@ pub fn mutate_list(items: &mut Vec<PyValue>, method: &str, args: Vec<PyValue>) -
     }
 }
 
-/// Call a method on a set value (non-mutating).
-pub fn call_set_method(items: &[PyValue], method: &str, args: Vec<PyValue>) -> Result<PyValue> {
+/// Call a method on a set or frozenset value (non-mutating). Results
+/// that are sets have the receiver's type.
+pub fn call_set_method(
+    items: &Set,
+    frozen: bool,
+    method: &str,
+    args: Vec<PyValue>,
+) -> Result<PyValue> {
     match method {
         "copy" => {
             check_args("copy", &args, 0)?;
-            Ok(PyValue::Set(items.to_vec()))
+            Ok(PyValue::set_of_kind(frozen, items.clone()))
         }
         "union" => {
             check_args("union", &args, 1)?;
-            let other = to_set_items(&args[0])?;
-            let idx = SetIndex::new(items);
-            let mut result = items.to_vec();
-            for v in other {
-                if !idx.contains(&v) {
-                    result.push(v);
-                }
-            }
-            Ok(PyValue::Set(result))
+            let other = to_set(&args[0])?;
+            let mut result = items.clone();
+            result.extend(other.iter().cloned());
+            Ok(PyValue::set_of_kind(frozen, result))
         }
         "intersection" => {
             if args.len() != 1 {
-                return Err(Error::Runtime(
-                    "intersection() takes exactly 1 argument".to_string(),
+                return Err(Error::exception(
+                    "TypeError",
+                    "intersection() takes exactly 1 argument",
                 ));
             }
-            let other = to_set_items(&args[0])?;
-            let idx = SetIndex::new(&other);
-            let result: Vec<PyValue> = items.iter().filter(|v| idx.contains(v)).cloned().collect();
-            Ok(PyValue::Set(result))
+            let other = to_set(&args[0])?;
+            let result = items.iter().filter(|v| other.contains(v)).cloned();
+            Ok(PyValue::set_of_kind(frozen, result.collect()))
         }
         "difference" => {
             if args.len() != 1 {
-                return Err(Error::Runtime(
-                    "difference() takes exactly 1 argument".to_string(),
+                return Err(Error::exception(
+                    "TypeError",
+                    "difference() takes exactly 1 argument",
                 ));
             }
-            let other = to_set_items(&args[0])?;
-            let idx = SetIndex::new(&other);
-            let result: Vec<PyValue> = items.iter().filter(|v| !idx.contains(v)).cloned().collect();
-            Ok(PyValue::Set(result))
+            let other = to_set(&args[0])?;
+            let result = items.iter().filter(|v| !other.contains(v)).cloned();
+            Ok(PyValue::set_of_kind(frozen, result.collect()))
         }
         "symmetric_difference" => {
             check_args("symmetric_difference", &args, 1)?;
-            let other = to_set_items(&args[0])?;
-            let idx_other = SetIndex::new(&other);
-            let idx_self = SetIndex::new(items);
-            let mut result: Vec<PyValue> = items
+            let other = to_set(&args[0])?;
+            let mut result: Set = items
                 .iter()
-                .filter(|v| !idx_other.contains(v))
+                .filter(|v| !other.contains(v))
                 .cloned()
                 .collect();
-            for v in &other {
-                if !idx_self.contains(v) {
-                    result.push(v.clone());
-                }
-            }
-            Ok(PyValue::Set(result))
+            result.extend(other.iter().filter(|v| !items.contains(v)).cloned());
+            Ok(PyValue::set_of_kind(frozen, result))
         }
         "issubset" => {
             check_args("issubset", &args, 1)?;
-            let other = to_set_items(&args[0])?;
-            let idx = SetIndex::new(&other);
-            Ok(PyValue::Bool(items.iter().all(|v| idx.contains(v))))
+            Ok(PyValue::Bool(items.is_subset(&*to_set(&args[0])?)))
         }
         "issuperset" => {
             check_args("issuperset", &args, 1)?;
-            let other = to_set_items(&args[0])?;
-            let idx = SetIndex::new(items);
-            Ok(PyValue::Bool(other.iter().all(|v| idx.contains(v))))
+            Ok(PyValue::Bool(to_set(&args[0])?.is_subset(items)))
         }
         "isdisjoint" => {
             check_args("isdisjoint", &args, 1)?;
-            let other = to_set_items(&args[0])?;
-            let idx = SetIndex::new(&other);
-            Ok(PyValue::Bool(!items.iter().any(|v| idx.contains(v))))
+            Ok(PyValue::Bool(items.is_disjoint(&*to_set(&args[0])?)))
         }
+        _ if frozen => Err(Error::exception(
+            "AttributeError",
+            format!("'frozenset' object has no attribute '{}'", method),
+        )),
         _ => Err(Error::Unsupported(format!(
             "Set method '{}' not implemented",
             method
@@ -666,8 +1056,9 @@ fn parse_fill_char(args: &[PyValue]) -> Result<char> {
     if args.len() > 1 {
         let f = arg_str(&args[1])?;
         if f.chars().count() != 1 {
-            return Err(Error::Runtime(
-                "TypeError: The fill character must be exactly one character long".to_string(),
+            return Err(Error::exception(
+                "TypeError",
+                "The fill character must be exactly one character long",
             ));
         }
         Ok(f.chars().next().unwrap())
@@ -676,147 +1067,66 @@ fn parse_fill_char(args: &[PyValue]) -> Result<char> {
     }
 }
 
-/// Implement `str.format(*args)` — basic positional and indexed substitution.
-fn str_format(s: &str, args: Vec<PyValue>) -> Result<PyValue> {
-    let mut result = String::new();
-    let mut auto_idx = 0usize;
-    let chars: Vec<char> = s.chars().collect();
-    let len = chars.len();
-    let mut i = 0;
-
-    while i < len {
-        if chars[i] == '{' {
-            if i + 1 < len && chars[i + 1] == '{' {
-                // Escaped brace: {{ → {
-                result.push('{');
-                i += 2;
-                continue;
-            }
-            // Find closing }
-            let start = i + 1;
-            let mut end = start;
-            while end < len && chars[end] != '}' {
-                end += 1;
-            }
-            if end >= len {
-                return Err(Error::Runtime(
-                    "ValueError: Single '{' encountered in format string".to_string(),
-                ));
-            }
-            let field: String = chars[start..end].iter().collect();
-            let val = if field.is_empty() {
-                // Auto-indexed: {}
-                let idx = auto_idx;
-                auto_idx += 1;
-                args.get(idx).ok_or_else(|| {
-                    Error::Runtime("IndexError: Replacement index out of range".to_string())
-                })?
-            } else if let Ok(idx) = field.parse::<usize>() {
-                // Explicit index: {0}, {1}, ...
-                args.get(idx).ok_or_else(|| {
-                    Error::Runtime("IndexError: Replacement index out of range".to_string())
-                })?
-            } else {
-                // Named field — not supported without kwargs
-                return Err(Error::Runtime(format!("KeyError: '{}'", field)));
-            };
-            result.push_str(&val.to_print_string());
-            i = end + 1;
-        } else if chars[i] == '}' {
-            if i + 1 < len && chars[i + 1] == '}' {
-                // Escaped brace: }} → }
-                result.push('}');
-                i += 2;
-            } else {
-                return Err(Error::Runtime(
-                    "ValueError: Single '}' encountered in format string".to_string(),
-                ));
-            }
-        } else {
-            result.push(chars[i]);
-            i += 1;
-        }
-    }
-
-    Ok(PyValue::Str(result))
-}
-
-/// Convert a PyValue to a Vec for set operations (accepts set, list, tuple).
-fn to_set_items(value: &PyValue) -> Result<Vec<PyValue>> {
-    match value {
-        PyValue::Set(items) | PyValue::List(items) | PyValue::Tuple(items) => Ok(items.clone()),
-        _ => Err(Error::Type {
-            expected: "iterable".to_string(),
-            got: value.type_name().to_string(),
-        }),
+/// The items of a set operation's argument (a set, frozenset, list or
+/// tuple) as a set.
+fn to_set(value: &PyValue) -> Result<Arc<Set>> {
+    if let Some(set) = value.as_set() {
+        return Ok(set);
     }
+    let items = match value {
+        PyValue::List(items) => items.read(),
+        PyValue::Tuple(items) => Arc::new(items.clone()),
+        _ => {
+            return Err(Error::Type {
+                expected: "iterable".to_string(),
+                got: value.type_name().to_string(),
+            });
+        }
+    };
+    items.iter().try_for_each(check_hashable)?;
+    Ok(Arc::new(items.iter().cloned().collect()))
 }
 
 /// Mutating set methods (add, discard, remove, clear, update, pop)
-#[allow(dead_code)]
-pub fn mutate_set(items: &mut Vec<PyValue>, method: &str, args: Vec<PyValue>) -> Result<PyValue> {
+pub fn mutate_set(set: &Shared<Set>, method: &str, args: Vec<PyValue>) -> Result<PyValue> {
     match method {
         "add" => {
             check_args("add", &args, 1)?;
-            let elem = &args[0];
-            if !elem.is_hashable() {
-                return Err(Error::Runtime(format!(
-                    "TypeError: unhashable type: '{}'",
-                    elem.type_name()
-                )));
-            }
-            let idx = SetIndex::new(items);
-            if !idx.contains(elem) {
-                items.push(elem.clone());
-            }
+            check_hashable(&args[0])?;
+            let elem = args.into_iter().next().unwrap();
+            set.write(|items| items.insert(elem));
             Ok(PyValue::None)
         }
         "discard" => {
             check_args("discard", &args, 1)?;
-            if let Some(pos) = items.iter().position(|v| v == &args[0]) {
-                items.remove(pos);
-            }
+            check_hashable(&args[0])?;
+            set.write(|items| items.remove(&args[0]));
             Ok(PyValue::None)
         }
         "remove" => {
             check_args("remove", &args, 1)?;
-            if let Some(pos) = items.iter().position(|v| v == &args[0]) {
-                items.remove(pos);
+            check_hashable(&args[0])?;
+            if set.write(|items| items.remove(&args[0])) {
                 Ok(PyValue::None)
             } else {
-                Err(Error::Runtime(format!("KeyError: {}", args[0])))
+                Err(ExceptionValue::new("KeyError", vec![args[0].clone()]).into())
             }
         }
         "clear" => {
             check_args("clear", &args, 0)?;
-            items.clear();
+            set.write(|items| items.clear());
             Ok(PyValue::None)
         }
         "update" => {
             check_args("update", &args, 1)?;
-            let other = to_set_items(&args[0])?;
-            // Validate hashability first
-            for v in &other {
-                if !v.is_hashable() {
-                    return Err(Error::Runtime(format!(
-                        "TypeError: unhashable type: '{}'",
-                        v.type_name()
-                    )));
-                }
-            }
-            // Collect new items, then extend
-            let idx = SetIndex::new(items);
-            let new_items: Vec<PyValue> = other.into_iter().filter(|v| !idx.contains(v)).collect();
-            items.extend(new_items);
+            let other = to_set(&args[0])?;
+            set.write(|items| items.extend(other.iter().cloned()));
             Ok(PyValue::None)
         }
         "pop" => {
             check_args("pop", &args, 0)?;
-            if items.is_empty() {
-                Err(Error::Runtime("pop from an empty set".to_string()))
-            } else {
-                Ok(items.remove(0))
-            }
+            set.write(|items| items.pop_first())
+                .ok_or_else(|| Error::exception("KeyError", "pop from an empty set"))
         }
         _ => Err(Error::Unsupported(format!(
             "Set method '{}' not implemented",
@@ -825,63 +1135,60 @@ pub fn mutate_set(items: &mut Vec<PyValue>, method: &str, args: Vec<PyValue>) ->
     }
 }
 
-/// Mutating dict methods (update, setdefault, pop, clear)
-#[allow(dead_code)]
-pub fn mutate_dict(
-    pairs: &mut Vec<(PyValue, PyValue)>,
-    method: &str,
-    args: Vec<PyValue>,
-) -> Result<PyValue> {
+/// Mutating dict methods (update, setdefault, pop, popitem, clear)
+pub fn mutate_dict(dict: &Shared<Dict>, method: &str, args: Vec<PyValue>) -> Result<PyValue> {
     match method {
         "update" => {
             check_args("update", &args, 1)?;
-            match &args[0] {
-                PyValue::Dict(new_pairs) => {
-                    for (k, v) in new_pairs {
-                        if let Some(existing) = pairs.iter_mut().find(|(ek, _)| ek == k) {
-                            existing.1 = v.clone();
-                        } else {
-                            pairs.push((k.clone(), v.clone()));
-                        }
-                    }
-                }
+            let new_pairs = match &args[0] {
+                PyValue::Dict(new_pairs) => new_pairs.read(),
                 _ => {
                     return Err(Error::Type {
                         expected: "dict".to_string(),
                         got: args[0].type_name().to_string(),
                     });
                 }
-            }
+            };
+            dict.write(|pairs| pairs.extend(new_pairs.iter().map(|(k, v)| (k.clone(), v.clone()))));
             Ok(PyValue::None)
         }
         "setdefault" => {
             check_args_range("setdefault", &args, 1, 2)?;
             let key = &args[0];
+            check_hashable(key)?;
             let default = args.get(1).cloned().unwrap_or(PyValue::None);
-
-            if let Some((_, v)) = pairs.iter().find(|(k, _)| k == key) {
-                return Ok(v.clone());
-            }
-            pairs.push((key.clone(), default.clone()));
-            Ok(default)
+            Ok(dict.write(|pairs| match pairs.get(key) {
+                Some(value) => value.clone(),
+                None => {
+                    pairs.insert(key.clone(), default.clone());
+                    default
+                }
+            }))
         }
         "pop" => {
             check_args_range("pop", &args, 1, 2)?;
             let key = &args[0];
-            let default = args.get(1).cloned();
-
-            if let Some(pos) = pairs.iter().position(|(k, _)| k == key) {
-                let (_, v) = pairs.remove(pos);
-                return Ok(v);
+            check_hashable(key)?;
+            match (dict.write(|pairs| pairs.remove(key)), args.get(1)) {
+                (Some(value), _) => Ok(value),
+                (None, Some(default)) => Ok(default.clone()),
+                (None, None) => Err(ExceptionValue::new("KeyError", vec![key.clone()]).into()),
             }
-            match default {
-                Some(d) => Ok(d),
-                None => Err(Error::Runtime(format!("KeyError: {}", key))),
+        }
+        "popitem" => {
+            check_args("popitem", &args, 0)?;
+            match dict.write(|pairs| pairs.pop_last()) {
+                Some((key, value)) => Ok(PyValue::Tuple(vec![key, value])),
+                None => Err(ExceptionValue::new(
+                    "KeyError",
+                    vec![PyValue::Str("popitem(): dictionary is empty".to_string())],
+                )
+                .into()),
             }
         }
         "clear" => {
             check_args("clear", &args, 0)?;
-            pairs.clear();
+            dict.write(|pairs| pairs.clear());
             Ok(PyValue::None)
         }
         _ => Err(Error::Unsupported(format!(
@@ -890,3 +1197,9 @@ pub fn mutate_dict(
         ))),
     }
 }
+
+/// Set `dict[key] = value`, keeping the position of an existing key.
+/// `key` must be hashable.
+pub(crate) fn dict_set_item(dict: &Shared<Dict>, key: PyValue, value: PyValue) {
+    dict.write(|pairs| pairs.insert(key, value));
+}
@@ -1,16 +1,27 @@
-//! Built-in modules: json, math, typing.
+//! Built-in modules: json, math, typing, dataclasses, functools.
 //!
 //! These modules are registered by [`crate::Sandbox::with_builtins()`] and provide
 //! commonly-used Python standard library functionality for LLM-generated code.
 
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::One;
+
+use crate::class::{ClassValue, DataclassOptions};
+use crate::decorator::{self, DecoratorValue, HostDecorator};
+use crate::error::{Error, Result};
+use crate::exception::ExceptionValue;
+use crate::int;
 use crate::sandbox::Sandbox;
-use crate::value::PyValue;
+use crate::value::{PyValue, ReprGuard, range_len};
 
 /// Register all built-in modules on the given sandbox.
 pub fn register_builtins(sandbox: &mut Sandbox) {
     register_json(sandbox);
     register_math(sandbox);
     register_typing(sandbox);
+    register_dataclasses(sandbox);
+    register_functools(sandbox);
 }
 
 // ============================================================================
@@ -20,7 +31,7 @@ pub fn register_builtins(sandbox: &mut Sandbox) {
 fn register_json(sandbox: &mut Sandbox) {
     sandbox.module("json", |m| {
         m.function("loads", json_loads);
-        m.function("dumps", json_dumps);
+        m.try_function("dumps", json_dumps);
     });
 }
 
@@ -35,16 +46,16 @@ fn json_loads(args: Vec<PyValue>) -> PyValue {
     }
 }
 
-fn json_dumps(args: Vec<PyValue>) -> PyValue {
+fn json_dumps(args: Vec<PyValue>) -> Result<PyValue> {
     let val = match args.first() {
         Some(v) => v,
-        None => return PyValue::Str("null".to_string()),
+        None => return Ok(PyValue::Str("null".to_string())),
     };
-    let json_val = pyvalue_to_json_value(val);
-    match serde_json::to_string(&json_val) {
+    let json_val = pyvalue_to_json_value(val)?;
+    Ok(match serde_json::to_string(&json_val) {
         Ok(s) => PyValue::Str(s),
         Err(_) => PyValue::None,
-    }
+    })
 }
 
 fn json_value_to_pyvalue(val: &serde_json::Value) -> PyValue {
@@ -54,57 +65,103 @@ fn json_value_to_pyvalue(val: &serde_json::Value) -> PyValue {
         serde_json::Value::Number(n) => {
             if let Some(i) = n.as_i64() {
                 PyValue::Int(i)
-            } else if let Some(f) = n.as_f64() {
-                PyValue::Float(f)
+            } else if let Ok(big) = n.as_str().parse::<BigInt>() {
+                // An integer too large for an i64 keeps all of its digits
+                PyValue::from(big)
             } else {
-                PyValue::None
+                // Like Python, an out-of-range float such as 1e400 is inf
+                n.as_str().parse().map_or(PyValue::None, PyValue::Float)
             }
         }
         serde_json::Value::String(s) => PyValue::Str(s.clone()),
         serde_json::Value::Array(arr) => {
-            PyValue::List(arr.iter().map(json_value_to_pyvalue).collect())
+            PyValue::list(arr.iter().map(json_value_to_pyvalue).collect())
         }
-        serde_json::Value::Object(obj) => PyValue::Dict(
+        serde_json::Value::Object(obj) => PyValue::dict(
             obj.iter()
-                .map(|(k, v)| (PyValue::Str(k.clone()), json_value_to_pyvalue(v)))
-                .collect(),
+                .map(|(k, v)| (PyValue::Str(k.clone()), json_value_to_pyvalue(v))),
         ),
     }
 }
 
-fn pyvalue_to_json_value(val: &PyValue) -> serde_json::Value {
-    match val {
+/// The `ValueError` `json.dumps` raises for a container that contains itself.
+fn circular_reference() -> Error {
+    ExceptionValue::new(
+        "ValueError",
+        vec![PyValue::Str("Circular reference detected".to_string())],
+    )
+    .into()
+}
+
+fn json_array<'a>(items: impl IntoIterator<Item = &'a PyValue>) -> Result<serde_json::Value> {
+    items
+        .into_iter()
+        .map(pyvalue_to_json_value)
+        .collect::<Result<_>>()
+        .map(serde_json::Value::Array)
+}
+
+fn pyvalue_to_json_value(val: &PyValue) -> Result<serde_json::Value> {
+    Ok(match val {
         PyValue::None => serde_json::Value::Null,
         PyValue::Bool(b) => serde_json::Value::Bool(*b),
         PyValue::Int(i) => serde_json::Value::Number((*i).into()),
+        // JSON numbers carry every digit of a big int
+        PyValue::BigInt(big) => serde_json::Value::Number(
+            big.to_string()
+                .parse()
+                .expect("an int is a valid JSON number"),
+        ),
         PyValue::Float(f) => serde_json::Number::from_f64(*f)
             .map(serde_json::Value::Number)
             .unwrap_or(serde_json::Value::Null),
         PyValue::Str(s) => serde_json::Value::String(s.clone()),
-        PyValue::List(items) | PyValue::Tuple(items) => {
-            serde_json::Value::Array(items.iter().map(pyvalue_to_json_value).collect())
+        PyValue::List(items) => {
+            let Some(_guard) = ReprGuard::enter(items.id()) else {
+                return Err(circular_reference());
+            };
+            json_array(items.read().iter())?
         }
+        PyValue::Set(set) => json_array(set.read().iter())?,
+        PyValue::FrozenSet(set) => json_array(set.iter())?,
+        PyValue::Tuple(items) => json_array(items)?,
+        PyValue::DictView(view) => json_array(&view.items())?,
         PyValue::Dict(pairs) => {
-            let map: serde_json::Map<String, serde_json::Value> = pairs
-                .iter()
-                .filter_map(|(k, v)| {
-                    if let PyValue::Str(key) = k {
-                        Some((key.clone(), pyvalue_to_json_value(v)))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+            let Some(_guard) = ReprGuard::enter(pairs.id()) else {
+                return Err(circular_reference());
+            };
+            let mut map = serde_json::Map::new();
+            for (k, v) in pairs.read().iter() {
+                if let PyValue::Str(key) = k {
+                    map.insert(key.clone(), pyvalue_to_json_value(v)?);
+                }
+            }
             serde_json::Value::Object(map)
         }
-        PyValue::Set(items) => {
-            serde_json::Value::Array(items.iter().map(pyvalue_to_json_value).collect())
-        }
-        PyValue::Function(_)
+        PyValue::Range { start, stop, step } => serde_json::Value::Array(
+            (0..range_len(*start, *stop, *step))
+                .map(|i| serde_json::Value::Number((start + i * step).into()))
+                .collect(),
+        ),
+        PyValue::Bytes(_)
+        | PyValue::ByteArray(_)
+        | PyValue::Function(_)
         | PyValue::Module { .. }
         | PyValue::NativeFunction(_)
-        | PyValue::File(_) => serde_json::Value::Null,
-    }
+        | PyValue::File(_)
+        | PyValue::Exception(_)
+        | PyValue::Class(_)
+        | PyValue::Type(_)
+        | PyValue::Instance(_)
+        | PyValue::Property(_)
+        | PyValue::StaticMethod(_)
+        | PyValue::ClassMethod(_)
+        | PyValue::BoundMethod { .. }
+        | PyValue::MethodDescriptor { .. }
+        | PyValue::Generator(_)
+        | PyValue::Iterator(_)
+        | PyValue::Decorator(_) => serde_json::Value::Null,
+    })
 }
 
 // ============================================================================
@@ -124,12 +181,8 @@ fn register_math(sandbox: &mut Sandbox) {
         m.function("sqrt", |args| {
             float_arg(&args).map_or(PyValue::None, |x| PyValue::Float(x.sqrt()))
         });
-        m.function("floor", |args| {
-            float_arg(&args).map_or(PyValue::None, |x| PyValue::Int(x.floor() as i64))
-        });
-        m.function("ceil", |args| {
-            float_arg(&args).map_or(PyValue::None, |x| PyValue::Int(x.ceil() as i64))
-        });
+        m.function("floor", |args| round_to_int(&args, f64::floor));
+        m.function("ceil", |args| round_to_int(&args, f64::ceil));
         m.function("log", |args| {
             let x = match float_arg(&args) {
                 Some(v) => v,
@@ -210,33 +263,62 @@ fn register_math(sandbox: &mut Sandbox) {
         m.function("radians", |args| {
             float_arg(&args).map_or(PyValue::None, |x| PyValue::Float(x.to_radians()))
         });
-        m.function("trunc", |args| {
-            float_arg(&args).map_or(PyValue::None, |x| PyValue::Int(x.trunc() as i64))
-        });
+        m.function("trunc", |args| round_to_int(&args, f64::trunc));
         m.function("gcd", |args| {
-            let a = match args.first().and_then(|v| v.as_int()) {
-                Some(v) => v.unsigned_abs(),
+            let a = match args.first().and_then(|v| v.as_bigint()) {
+                Some(v) => v,
                 None => return PyValue::None,
             };
-            let b = match args.get(1).and_then(|v| v.as_int()) {
-                Some(v) => v.unsigned_abs(),
+            let b = match args.get(1).and_then(|v| v.as_bigint()) {
+                Some(v) => v,
                 None => return PyValue::None,
             };
-            PyValue::Int(gcd(a, b) as i64)
+            PyValue::from(a.gcd(&b))
         });
-        m.function("factorial", |args| {
-            let n = match args.first().and_then(|v| v.as_int()) {
-                Some(v) if v >= 0 => v as u64,
-                _ => return PyValue::None,
+        m.try_function("factorial", |args| {
+            let n = match args.first() {
+                Some(v @ (PyValue::Int(_) | PyValue::Bool(_))) => v.as_int().unwrap_or_default(),
+                Some(PyValue::BigInt(_)) => {
+                    return Err(Error::exception(
+                        "OverflowError",
+                        format!("factorial() argument should not exceed {}", i64::MAX),
+                    ));
+                }
+                Some(other) => {
+                    return Err(Error::exception(
+                        "TypeError",
+                        format!(
+                            "'{}' object cannot be interpreted as an integer",
+                            other.type_name()
+                        ),
+                    ));
+                }
+                None => {
+                    return Err(Error::exception(
+                        "TypeError",
+                        "factorial() takes exactly one argument (0 given)",
+                    ));
+                }
             };
-            if n > 20 {
-                return PyValue::None; // overflow protection
+            if n < 0 {
+                return Err(Error::exception(
+                    "ValueError",
+                    "factorial() not defined for negative values",
+                ));
+            }
+            // Estimate the size first, so an oversized result fails before
+            // any big multiplication
+            let mut bits = 0.0;
+            for i in 2..=n as u64 {
+                bits += (i as f64).log2();
+                int::check_bits(bits as u64)?;
             }
-            let mut result: u64 = 1;
-            for i in 2..=n {
-                result = result.saturating_mul(i);
+            let mut result = BigInt::one();
+            for i in 2..=n as u64 {
+                result *= i;
             }
-            PyValue::Int(result as i64)
+            int::check_bits(result.bits())?;
+            Ok(PyValue::from(result))
         });
     });
 }
@@ -251,13 +333,16 @@ fn float_arg_at(args: &[PyValue], idx: usize) -> Option<f64> {
     args.get(idx).and_then(|v| v.as_float())
 }
 
-fn gcd(mut a: u64, mut b: u64) -> u64 {
-    while b != 0 {
-        let t = b;
-        b = a % b;
-        a = t;
+/// `math.floor`, `math.ceil` and `math.trunc`: ints are returned as is,
+/// floats are rounded by `round` and converted exactly.
+fn round_to_int(args: &[PyValue], round: fn(f64) -> f64) -> PyValue {
+    match args.first() {
+        Some(n @ (PyValue::Int(_) | PyValue::BigInt(_))) => n.clone(),
+        Some(PyValue::Bool(b)) => PyValue::Int(*b as i64),
+        _ => float_arg(args)
+            .and_then(|x| int::from_f64(round(x)).ok())
+            .unwrap_or(PyValue::None),
     }
-    a
 }
 
 // ============================================================================
@@ -290,7 +375,6 @@ fn register_typing(sandbox: &mut Sandbox) {
             "TypeVar",
             "Generic",
             "Protocol",
-            "NamedTuple",
             "TypedDict",
             "Annotated",
             "TypeAlias",
@@ -307,5 +391,111 @@ fn register_typing(sandbox: &mut Sandbox) {
         for name in typing_names {
             m.constant(name, PyValue::None);
         }
+        // `class P(NamedTuple)` defines a record type usable as a tuple
+        m.constant(
+            "NamedTuple",
+            PyValue::Class(Box::new(ClassValue::named_tuple())),
+        );
+    });
+}
+
+// ============================================================================
+// dataclasses module
+// ============================================================================
+
+fn register_dataclasses(sandbox: &mut Sandbox) {
+    sandbox.module("dataclasses", |m| {
+        m.decorator("dataclass", dataclass);
+        m.constant(
+            "FrozenInstanceError",
+            PyValue::Class(Box::new(
+                ClassValue::builtin_exception("FrozenInstanceError").unwrap(),
+            )),
+        );
+        m.function("asdict", asdict);
+    });
+}
+
+/// `@dataclass` and `@dataclass(...)`. The `eq`, `order` and `frozen`
+/// options are supported; other options raise `TypeError` unless given
+/// their default value.
+fn dataclass(_args: &[PyValue], kwargs: &[(String, PyValue)]) -> HostDecorator {
+    let mut options = DataclassOptions::default();
+    let mut error = None;
+    for (name, value) in kwargs {
+        let enabled = value.is_truthy();
+        match name.as_str() {
+            "eq" => options.eq = enabled,
+            "order" => options.order = enabled,
+            "frozen" => options.frozen = enabled,
+            "init" | "repr" | "match_args" if enabled => {}
+            "unsafe_hash" | "kw_only" | "slots" | "weakref_slot" if !enabled => {}
+            _ => {
+                error = Some((
+                    "TypeError",
+                    format!("dataclass() option '{}={}' is not supported", name, value),
+                ));
+            }
+        }
+    }
+    if options.order && !options.eq {
+        error = Some(("ValueError", "eq must be true if order is true".to_string()));
+    }
+    HostDecorator::new().apply(move |value| {
+        if let Some((type_name, message)) = &error {
+            return Err(Error::exception(type_name, message.clone()));
+        }
+        match value {
+            PyValue::Class(class) => Ok(PyValue::Class(Box::new(class.into_dataclass(options)?))),
+            other => Err(Error::exception(
+                "TypeError",
+                format!("dataclass() expects a class, not '{}'", other.type_name()),
+            )),
+        }
+    })
+}
+
+/// `asdict(obj)`. Records are already converted to dicts when passed to
+/// native functions, so this returns its argument.
+fn asdict(args: Vec<PyValue>) -> PyValue {
+    match args.into_iter().next() {
+        Some(dict @ PyValue::Dict(_)) => dict,
+        _ => PyValue::None,
+    }
+}
+
+// ============================================================================
+// functools module
+// ============================================================================
+
+fn register_functools(sandbox: &mut Sandbox) {
+    sandbox.module("functools", |m| {
+        m.decorator("lru_cache", |args, kwargs| {
+            let maxsize = args
+                .first()
+                .or_else(|| kwargs.iter().find(|(k, _)| k == "maxsize").map(|(_, v)| v));
+            let maxsize = match maxsize {
+                None => Some(128),
+                Some(value) => value.as_int().map(|n| n.max(0) as usize),
+            };
+            decorator::lru_cache(maxsize)
+        });
+        m.decorator("cache", |_, _| decorator::lru_cache(None));
+        m.function("wraps", wraps);
+    });
+}
+
+/// `@wraps(func)`: the decorator it returns copies `func`'s `__name__` and
+/// `__doc__` onto the wrapper function.
+fn wraps(args: Vec<PyValue>) -> PyValue {
+    let wrapped = args.into_iter().next();
+    let hooks = HostDecorator::new().apply(move |wrapper| match (&wrapped, wrapper) {
+        (Some(wrapped), PyValue::Function(mut func)) => {
+            func.name = decorator::callable_name(wrapped);
+            func.doc = decorator::callable_doc(wrapped);
+            Ok(PyValue::Function(func))
+        }
+        (_, wrapper) => Ok(wrapper),
     });
+    PyValue::Decorator(DecoratorValue::configured("wraps", hooks))
 }
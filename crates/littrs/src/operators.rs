@@ -5,94 +5,102 @@
 //! - Bitwise: |, ^, &, <<, >>
 //! - Comparison: ==, !=, <, <=, >, >=, in, not in, is, is not
 
+use std::sync::Arc;
+
 use crate::bytecode::{BinOp, CmpOp};
+use crate::bytes;
+use crate::dict::{DictViewKind, Set, check_hashable};
 use crate::error::{Error, Result};
-use crate::value::{PyValue, SetIndex};
+use crate::format;
+use crate::int;
+use crate::value::{
+    CompareGuard, PyValue, check_sequence_len, checked_compare, compare_recursion_error, range_len,
+    values_equal,
+};
 
 /// Apply a binary operator to two values.
 ///
 /// Handles arithmetic (+, -, *, /, //, %, **), bitwise (|, ^, &, <<, >>),
 /// and special cases like string concatenation, string/list repetition,
-/// and list concatenation.
+/// and list concatenation. Int results that overflow an `i64` are promoted
+/// to big ints.
 pub fn apply_binop(op: &BinOp, left: &PyValue, right: &PyValue) -> Result<PyValue> {
+    // Named tuples concatenate and repeat as plain tuples
+    let (left, right) = (&*left.tuple_view(), &*right.tuple_view());
+    if let Some(result) = int::binop(op, left, right) {
+        return result;
+    }
+    // A bool mixed with a float acts as an int, and a big int as a float
+    match (left, right) {
+        (PyValue::Bool(b), other @ PyValue::Float(_)) => {
+            return apply_binop(op, &PyValue::Int(*b as i64), other);
+        }
+        (other @ PyValue::Float(_), PyValue::Bool(b)) => {
+            return apply_binop(op, other, &PyValue::Int(*b as i64));
+        }
+        (PyValue::BigInt(big), other @ PyValue::Float(_)) => {
+            return apply_binop(op, &PyValue::Float(int::to_f64(big)?), other);
+        }
+        (other @ PyValue::Float(_), PyValue::BigInt(big)) => {
+            return apply_binop(op, other, &PyValue::Float(int::to_f64(big)?));
+        }
+        _ => {}
+    }
     match op {
         BinOp::Add => match (left, right) {
-            (PyValue::Int(a), PyValue::Int(b)) => Ok(PyValue::Int(a + b)),
             (PyValue::Float(a), PyValue::Float(b)) => Ok(PyValue::Float(a + b)),
             (PyValue::Int(a), PyValue::Float(b)) => Ok(PyValue::Float(*a as f64 + b)),
             (PyValue::Float(a), PyValue::Int(b)) => Ok(PyValue::Float(a + *b as f64)),
             (PyValue::Str(a), PyValue::Str(b)) => Ok(PyValue::Str(format!("{}{}", a, b))),
+            (
+                PyValue::Bytes(_) | PyValue::ByteArray(_),
+                PyValue::Bytes(_) | PyValue::ByteArray(_),
+            ) => {
+                let mut result = left.as_bytes().unwrap().into_owned();
+                result.extend_from_slice(&right.as_bytes().unwrap());
+                Ok(same_byte_type(left, result))
+            }
             (PyValue::List(a), PyValue::List(b)) => {
-                let mut result = a.clone();
-                result.extend(b.clone());
-                Ok(PyValue::List(result))
+                let mut result = a.read().to_vec();
+                result.extend_from_slice(&b.read());
+                Ok(PyValue::list(result))
             }
             (PyValue::Tuple(a), PyValue::Tuple(b)) => {
                 let mut result = a.clone();
                 result.extend(b.clone());
                 Ok(PyValue::Tuple(result))
             }
-            _ => Err(Error::Type {
-                expected: "compatible types for +".to_string(),
-                got: format!("{} and {}", left.type_name(), right.type_name()),
-            }),
+            _ => Err(unsupported_operands(op, left, right)),
         },
-        BinOp::Sub => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(b);
-                let items: Vec<PyValue> = a.iter().filter(|v| !idx.contains(v)).cloned().collect();
-                Ok(PyValue::Set(items))
+        BinOp::Sub => match set_operands(left, right) {
+            Some((frozen, a, b)) => {
+                let items = a.iter().filter(|v| !b.contains(v)).cloned();
+                Ok(PyValue::set_of_kind(frozen, items.collect()))
             }
-            _ => numeric_binop(left, right, |a, b| a - b, |a, b| a - b),
+            None => numeric_binop(op, left, right, |a, b| a - b),
         },
         BinOp::Mult => match (left, right) {
-            (PyValue::Int(a), PyValue::Int(b)) => Ok(PyValue::Int(a * b)),
             (PyValue::Float(a), PyValue::Float(b)) => Ok(PyValue::Float(a * b)),
             (PyValue::Int(a), PyValue::Float(b)) => Ok(PyValue::Float(*a as f64 * b)),
             (PyValue::Float(a), PyValue::Int(b)) => Ok(PyValue::Float(a * *b as f64)),
-            (PyValue::Str(s), PyValue::Int(n)) | (PyValue::Int(n), PyValue::Str(s)) => {
-                if *n <= 0 {
-                    Ok(PyValue::Str(String::new()))
-                } else {
-                    Ok(PyValue::Str(s.repeat(*n as usize)))
-                }
-            }
-            (PyValue::List(l), PyValue::Int(n)) | (PyValue::Int(n), PyValue::List(l)) => {
-                if *n <= 0 {
-                    Ok(PyValue::List(vec![]))
-                } else {
-                    let mut result = Vec::new();
-                    for _ in 0..*n {
-                        result.extend(l.clone());
-                    }
-                    Ok(PyValue::List(result))
-                }
-            }
-            (PyValue::Tuple(t), PyValue::Int(n)) | (PyValue::Int(n), PyValue::Tuple(t)) => {
-                if *n <= 0 {
-                    Ok(PyValue::Tuple(vec![]))
-                } else {
-                    let mut result = Vec::new();
-                    for _ in 0..*n {
-                        result.extend(t.clone());
-                    }
-                    Ok(PyValue::Tuple(result))
-                }
+            (sequence, count) | (count, sequence)
+                if is_repeatable(sequence)
+                    && matches!(
+                        count,
+                        PyValue::Int(_) | PyValue::Bool(_) | PyValue::BigInt(_)
+                    ) =>
+            {
+                repeat(sequence, count)
             }
-            _ => Err(Error::Type {
-                expected: "compatible types for *".to_string(),
-                got: format!("{} and {}", left.type_name(), right.type_name()),
-            }),
+            _ => Err(unsupported_operands(op, left, right)),
         },
         BinOp::Div => {
-            let a = left.as_float().ok_or_else(|| Error::Type {
-                expected: "number".to_string(),
-                got: left.type_name().to_string(),
-            })?;
-            let b = right.as_float().ok_or_else(|| Error::Type {
-                expected: "number".to_string(),
-                got: right.type_name().to_string(),
-            })?;
+            let a = left
+                .as_float()
+                .ok_or_else(|| unsupported_operands(op, left, right))?;
+            let b = right
+                .as_float()
+                .ok_or_else(|| unsupported_operands(op, left, right))?;
             if b == 0.0 {
                 Err(Error::DivisionByZero)
             } else {
@@ -100,42 +108,31 @@ pub fn apply_binop(op: &BinOp, left: &PyValue, right: &PyValue) -> Result<PyValu
             }
         }
         BinOp::FloorDiv => {
-            let a = left.as_float().ok_or_else(|| Error::Type {
-                expected: "number".to_string(),
-                got: left.type_name().to_string(),
-            })?;
-            let b = right.as_float().ok_or_else(|| Error::Type {
-                expected: "number".to_string(),
-                got: right.type_name().to_string(),
-            })?;
+            let a = left
+                .as_float()
+                .ok_or_else(|| unsupported_operands(op, left, right))?;
+            let b = right
+                .as_float()
+                .ok_or_else(|| unsupported_operands(op, left, right))?;
             if b == 0.0 {
                 Err(Error::DivisionByZero)
             } else {
-                let result = (a / b).floor();
-                if matches!(left, PyValue::Int(_)) && matches!(right, PyValue::Int(_)) {
-                    Ok(PyValue::Int(result as i64))
-                } else {
-                    Ok(PyValue::Float(result))
-                }
+                Ok(PyValue::Float((a / b).floor()))
             }
         }
         BinOp::Mod => match (left, right) {
-            (PyValue::Int(a), PyValue::Int(b)) => {
-                if *b == 0 {
-                    Err(Error::DivisionByZero)
-                } else {
-                    Ok(PyValue::Int(a % b))
-                }
-            }
+            (PyValue::Str(template), args) => Ok(PyValue::Str(format::percent_format(
+                template,
+                args,
+                &mut |value, conversion| Ok(format::convert(value, conversion).to_print_string()),
+            )?)),
             _ => {
-                let a = left.as_float().ok_or_else(|| Error::Type {
-                    expected: "number".to_string(),
-                    got: left.type_name().to_string(),
-                })?;
-                let b = right.as_float().ok_or_else(|| Error::Type {
-                    expected: "number".to_string(),
-                    got: right.type_name().to_string(),
-                })?;
+                let a = left
+                    .as_float()
+                    .ok_or_else(|| unsupported_operands(op, left, right))?;
+                let b = right
+                    .as_float()
+                    .ok_or_else(|| unsupported_operands(op, left, right))?;
                 if b == 0.0 {
                     Err(Error::DivisionByZero)
                 } else {
@@ -144,119 +141,204 @@ pub fn apply_binop(op: &BinOp, left: &PyValue, right: &PyValue) -> Result<PyValu
             }
         },
         BinOp::Pow => {
-            let a = left.as_float().ok_or_else(|| Error::Type {
-                expected: "number".to_string(),
-                got: left.type_name().to_string(),
-            })?;
-            let b = right.as_float().ok_or_else(|| Error::Type {
-                expected: "number".to_string(),
-                got: right.type_name().to_string(),
-            })?;
-            let result = a.powf(b);
-            if matches!(left, PyValue::Int(_))
-                && matches!(right, PyValue::Int(_))
-                && result.fract() == 0.0
-                && result >= i64::MIN as f64
-                && result <= i64::MAX as f64
-            {
-                Ok(PyValue::Int(result as i64))
-            } else {
-                Ok(PyValue::Float(result))
-            }
+            let a = left
+                .as_float()
+                .ok_or_else(|| unsupported_operands(op, left, right))?;
+            let b = right
+                .as_float()
+                .ok_or_else(|| unsupported_operands(op, left, right))?;
+            Ok(PyValue::Float(a.powf(b)))
         }
-        BinOp::BitOr => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(a);
-                let mut items = a.clone();
-                for v in b {
-                    if !idx.contains(v) {
-                        items.push(v.clone());
-                    }
-                }
-                Ok(PyValue::Set(items))
+        BinOp::BitOr => match set_operands(left, right) {
+            Some((frozen, a, b)) => {
+                let mut items = Arc::unwrap_or_clone(a);
+                items.extend(b.iter().cloned());
+                Ok(PyValue::set_of_kind(frozen, items))
             }
-            _ => int_binop(left, right, |a, b| a | b),
+            None => Err(unsupported_operands(op, left, right)),
         },
-        BinOp::BitXor => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx_b = SetIndex::new(b);
-                let idx_a = SetIndex::new(a);
-                let mut items: Vec<PyValue> =
-                    a.iter().filter(|v| !idx_b.contains(v)).cloned().collect();
-                for v in b {
-                    if !idx_a.contains(v) {
-                        items.push(v.clone());
-                    }
-                }
-                Ok(PyValue::Set(items))
+        BinOp::BitXor => match set_operands(left, right) {
+            Some((frozen, a, b)) => {
+                let mut items: Set = a.iter().filter(|v| !b.contains(v)).cloned().collect();
+                items.extend(b.iter().filter(|v| !a.contains(v)).cloned());
+                Ok(PyValue::set_of_kind(frozen, items))
             }
-            _ => int_binop(left, right, |a, b| a ^ b),
+            None => Err(unsupported_operands(op, left, right)),
         },
-        BinOp::BitAnd => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(b);
-                let items: Vec<PyValue> = a.iter().filter(|v| idx.contains(v)).cloned().collect();
-                Ok(PyValue::Set(items))
+        BinOp::BitAnd => match set_operands(left, right) {
+            Some((frozen, a, b)) => {
+                let items = a.iter().filter(|v| b.contains(v)).cloned();
+                Ok(PyValue::set_of_kind(frozen, items.collect()))
             }
-            _ => int_binop(left, right, |a, b| a & b),
+            None => Err(unsupported_operands(op, left, right)),
         },
-        BinOp::LShift => int_binop(left, right, |a, b| a << b),
-        BinOp::RShift => int_binop(left, right, |a, b| a >> b),
+        BinOp::LShift | BinOp::RShift => Err(unsupported_operands(op, left, right)),
+    }
+}
+
+/// Whether `value` is a sequence that `*` repeats.
+fn is_repeatable(value: &PyValue) -> bool {
+    matches!(
+        value,
+        PyValue::Str(_)
+            | PyValue::Bytes(_)
+            | PyValue::ByteArray(_)
+            | PyValue::List(_)
+            | PyValue::Tuple(_)
+    )
+}
+
+/// `sequence * count`, where `count` is an int or bool.
+fn repeat(sequence: &PyValue, count: &PyValue) -> Result<PyValue> {
+    Ok(match sequence {
+        PyValue::Str(s) => PyValue::Str(s.repeat(repeat_count(s.len(), count)?)),
+        PyValue::Bytes(_) | PyValue::ByteArray(_) => {
+            let data = sequence.as_bytes().unwrap();
+            let result = data.repeat(repeat_count(data.len(), count)?);
+            same_byte_type(sequence, result)
+        }
+        PyValue::List(l) => {
+            let items = l.read();
+            PyValue::list(repeat_items(&items, repeat_count(items.len(), count)?))
+        }
+        PyValue::Tuple(t) => PyValue::Tuple(repeat_items(t, repeat_count(t.len(), count)?)),
+        _ => unreachable!(),
+    })
+}
+
+fn repeat_items(items: &[PyValue], n: usize) -> Vec<PyValue> {
+    let mut result = Vec::with_capacity(items.len() * n);
+    for _ in 0..n {
+        result.extend_from_slice(items);
+    }
+    result
+}
+
+/// How many times to repeat a sequence of `len` items, failing if `count`
+/// is a big int or the result would exceed the sandbox's size limit.
+fn repeat_count(len: usize, count: &PyValue) -> Result<usize> {
+    if matches!(count, PyValue::BigInt(_)) {
+        return Err(Error::exception(
+            "OverflowError",
+            "cannot fit 'int' into an index-sized integer",
+        ));
+    }
+    let n = count.as_int().unwrap().max(0);
+    let n = usize::try_from(n).unwrap_or(usize::MAX);
+    check_sequence_len(len.checked_mul(n))?;
+    Ok(n)
+}
+
+/// The contents of two set or frozenset operands, and whether the left
+/// one, which decides the type of a result, is a frozenset.
+fn set_operands(left: &PyValue, right: &PyValue) -> Option<(bool, Arc<Set>, Arc<Set>)> {
+    let frozen = matches!(left, PyValue::FrozenSet(_));
+    Some((frozen, left.as_set()?, right.as_set()?))
+}
+
+/// Apply the operator of an augmented assignment (`left op= right`).
+///
+/// Lists, sets and bytearrays are updated in place, so every reference to
+/// them sees the change; other values fall back to [`apply_binop`].
+pub fn apply_inplace_op(op: &BinOp, left: &PyValue, right: &PyValue) -> Result<PyValue> {
+    match (op, left, right) {
+        (BinOp::Add, PyValue::List(list), PyValue::List(_) | PyValue::Tuple(_)) => {
+            let items = match right {
+                PyValue::List(other) => other.read().to_vec(),
+                PyValue::Tuple(other) => other.clone(),
+                _ => unreachable!(),
+            };
+            list.write(|v| v.extend(items));
+        }
+        (BinOp::Add, PyValue::ByteArray(data), PyValue::Bytes(_) | PyValue::ByteArray(_)) => {
+            // Snapshot first: `b += b` reads the bytearray being extended
+            let extra = right.as_bytes().unwrap().into_owned();
+            data.write(|v| v.extend_from_slice(&extra));
+        }
+        (BinOp::Mult, PyValue::List(list), PyValue::Int(_) | PyValue::Bool(_)) => {
+            let n = repeat_count(list.read().len(), right)?;
+            list.write(|v| *v = repeat_items(v, n));
+        }
+        (
+            BinOp::BitOr | BinOp::BitAnd | BinOp::Sub | BinOp::BitXor,
+            PyValue::Set(set),
+            PyValue::Set(_) | PyValue::FrozenSet(_),
+        ) => {
+            let PyValue::Set(result) = apply_binop(op, left, right)? else {
+                unreachable!()
+            };
+            let items = Arc::unwrap_or_clone(result.read());
+            set.write(|v| *v = items);
+        }
+        _ => return apply_binop(op, left, right),
     }
+    Ok(left.clone())
 }
 
 /// Apply a comparison operator to two values.
 ///
 /// Returns a boolean result. For `In`/`NotIn`, checks membership in lists,
-/// strings, and dicts. For `Is`/`IsNot`, only `None is None` is true.
+/// strings, and dicts. For `Is`/`IsNot`, `None` and booleans compare by
+/// value, classes and types by equality, and generators, iterators, lists,
+/// dicts and sets by identity.
 pub fn apply_cmpop(op: &CmpOp, left: &PyValue, right: &PyValue) -> Result<bool> {
-    match op {
-        CmpOp::Eq => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(b);
-                Ok(a.len() == b.len() && a.iter().all(|v| idx.contains(v)))
-            }
-            _ => Ok(left == right),
-        },
-        CmpOp::NotEq => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(b);
-                Ok(a.len() != b.len() || !a.iter().all(|v| idx.contains(v)))
-            }
-            _ => Ok(left != right),
-        },
-        CmpOp::Lt => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(b);
-                Ok(a.len() < b.len() && a.iter().all(|v| idx.contains(v)))
+    // Named tuples order and test membership as plain tuples
+    let (left, right) = (&*left.tuple_view(), &*right.tuple_view());
+    if let Some(symbol) = ordering_symbol(op)
+        && let (PyValue::Instance(_), _) | (_, PyValue::Instance(_)) = (left, right)
+    {
+        // Dataclasses with `order=True` order like tuples of their fields
+        return match (left, right) {
+            (PyValue::Instance(a), PyValue::Instance(b))
+                if a.class.attrs == b.class.attrs
+                    && a.class.dataclass_options().is_some_and(|o| o.order) =>
+            {
+                let a = PyValue::Tuple(a.field_values().unwrap_or_default());
+                let b = PyValue::Tuple(b.field_values().unwrap_or_default());
+                apply_cmpop(op, &a, &b)
             }
-            _ => compare_values(left, right, |a, b| a < b, |a, b| a < b),
+            _ => Err(Error::exception(
+                "TypeError",
+                format!(
+                    "'{}' not supported between instances of '{}' and '{}'",
+                    symbol,
+                    left.class_name(),
+                    right.class_name()
+                ),
+            )),
+        };
+    }
+    match op {
+        CmpOp::Eq => values_equal(left, right),
+        CmpOp::NotEq => Ok(!values_equal(left, right)?),
+        // Sets order by inclusion
+        CmpOp::Lt => match set_operands(left, right) {
+            Some((_, a, b)) => Ok(a.len() < b.len() && a.is_subset(&b)),
+            None => compare_values(left, right, |a, b| a < b, |a, b| a < b),
         },
-        CmpOp::LtE => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(b);
-                Ok(a.iter().all(|v| idx.contains(v)))
-            }
-            _ => compare_values(left, right, |a, b| a <= b, |a, b| a <= b),
+        CmpOp::LtE => match set_operands(left, right) {
+            Some((_, a, b)) => Ok(a.is_subset(&b)),
+            None => compare_values(left, right, |a, b| a <= b, |a, b| a <= b),
         },
-        CmpOp::Gt => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(a);
-                Ok(a.len() > b.len() && b.iter().all(|v| idx.contains(v)))
-            }
-            _ => compare_values(left, right, |a, b| a > b, |a, b| a > b),
+        CmpOp::Gt => match set_operands(left, right) {
+            Some((_, a, b)) => Ok(a.len() > b.len() && b.is_subset(&a)),
+            None => compare_values(left, right, |a, b| a > b, |a, b| a > b),
         },
-        CmpOp::GtE => match (left, right) {
-            (PyValue::Set(a), PyValue::Set(b)) => {
-                let idx = SetIndex::new(a);
-                Ok(b.iter().all(|v| idx.contains(v)))
-            }
-            _ => compare_values(left, right, |a, b| a >= b, |a, b| a >= b),
+        CmpOp::GtE => match set_operands(left, right) {
+            Some((_, a, b)) => Ok(b.is_subset(&a)),
+            None => compare_values(left, right, |a, b| a >= b, |a, b| a >= b),
         },
         CmpOp::In => match right {
-            PyValue::List(items) | PyValue::Tuple(items) | PyValue::Set(items) => {
-                Ok(items.contains(left))
+            PyValue::List(items) => checked_compare(|| items.read().contains(left)),
+            PyValue::Set(set) => {
+                check_hashable(left)?;
+                Ok(set.read().contains(left))
+            }
+            PyValue::FrozenSet(set) => {
+                check_hashable(left)?;
+                Ok(set.contains(left))
             }
+            PyValue::Tuple(items) => checked_compare(|| items.contains(left)),
             PyValue::Str(s) => {
                 if let PyValue::Str(needle) = left {
                     Ok(s.contains(needle.as_str()))
@@ -267,7 +349,31 @@ pub fn apply_cmpop(op: &CmpOp, left: &PyValue, right: &PyValue) -> Result<bool>
                     })
                 }
             }
-            PyValue::Dict(pairs) => Ok(pairs.iter().any(|(k, _)| k == left)),
+            PyValue::Bytes(_) | PyValue::ByteArray(_) => {
+                let data = right.as_bytes().unwrap();
+                match left {
+                    PyValue::Int(_) | PyValue::Bool(_) | PyValue::BigInt(_) => {
+                        Ok(data.contains(&bytes::byte_value(left)?))
+                    }
+                    _ => Ok(bytes::find(&data, &bytes::arg_bytes(left)?).is_some()),
+                }
+            }
+            PyValue::Dict(dict) => {
+                check_hashable(left)?;
+                Ok(dict.read().contains_key(left))
+            }
+            PyValue::DictView(view) => {
+                if view.kind() == DictViewKind::Keys {
+                    check_hashable(left)?;
+                }
+                checked_compare(|| view.contains(left))
+            }
+            PyValue::Range { start, stop, step } => Ok(left.as_int().is_some_and(|n| {
+                let offset = n as i128 - *start as i128;
+                let step = *step as i128;
+                offset % step == 0
+                    && (0..range_len(*start, *stop, step as i64) as i128).contains(&(offset / step))
+            })),
             _ => Err(Error::Type {
                 expected: "container".to_string(),
                 got: right.type_name().to_string(),
@@ -279,6 +385,17 @@ pub fn apply_cmpop(op: &CmpOp, left: &PyValue, right: &PyValue) -> Result<bool>
         }
         CmpOp::Is => match (left, right) {
             (PyValue::None, PyValue::None) => Ok(true),
+            (PyValue::Bool(a), PyValue::Bool(b)) => Ok(a == b),
+            (PyValue::Generator(a), PyValue::Generator(b)) => Ok(a == b),
+            (PyValue::Iterator(a), PyValue::Iterator(b)) => Ok(a == b),
+            (PyValue::Decorator(a), PyValue::Decorator(b)) => Ok(a == b),
+            (PyValue::Class(_), PyValue::Class(_)) | (PyValue::Type(_), PyValue::Type(_)) => {
+                Ok(left == right)
+            }
+            (PyValue::List(a), PyValue::List(b)) => Ok(a.ptr_eq(b)),
+            (PyValue::Set(a), PyValue::Set(b)) => Ok(a.ptr_eq(b)),
+            (PyValue::Dict(a), PyValue::Dict(b)) => Ok(a.ptr_eq(b)),
+            (PyValue::ByteArray(a), PyValue::ByteArray(b)) => Ok(a.ptr_eq(b)),
             _ => Ok(false),
         },
         CmpOp::IsNot => {
@@ -288,12 +405,25 @@ pub fn apply_cmpop(op: &CmpOp, left: &PyValue, right: &PyValue) -> Result<bool>
     }
 }
 
+/// The symbol of an ordering comparison (`<`, `<=`, `>`, `>=`).
+fn ordering_symbol(op: &CmpOp) -> Option<&'static str> {
+    match op {
+        CmpOp::Lt => Some("<"),
+        CmpOp::LtE => Some("<="),
+        CmpOp::Gt => Some(">"),
+        CmpOp::GtE => Some(">="),
+        _ => None,
+    }
+}
+
 /// Compare two values and return their ordering.
 ///
 /// Non-generic to avoid infinite monomorphization with recursive sequence comparison.
 fn compare_elements(left: &PyValue, right: &PyValue) -> Result<std::cmp::Ordering> {
+    if let Some(ord) = int::compare(left, right) {
+        return Ok(ord);
+    }
     match (left, right) {
-        (PyValue::Int(a), PyValue::Int(b)) => Ok(a.cmp(b)),
         (PyValue::Float(a), PyValue::Float(b)) => {
             Ok(a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
         }
@@ -305,15 +435,22 @@ fn compare_elements(left: &PyValue, right: &PyValue) -> Result<std::cmp::Orderin
             let b = *b as f64;
             Ok(a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
         }
+        (PyValue::BigInt(a), PyValue::Float(b)) => Ok(int::to_f64_lossy(a)
+            .partial_cmp(b)
+            .unwrap_or(std::cmp::Ordering::Equal)),
+        (PyValue::Float(a), PyValue::BigInt(b)) => Ok(a
+            .partial_cmp(&int::to_f64_lossy(b))
+            .unwrap_or(std::cmp::Ordering::Equal)),
         (PyValue::Str(a), PyValue::Str(b)) => Ok(a.cmp(b)),
-        (PyValue::Tuple(a), PyValue::Tuple(b)) | (PyValue::List(a), PyValue::List(b)) => {
-            for (av, bv) in a.iter().zip(b.iter()) {
-                match compare_elements(av, bv)? {
-                    std::cmp::Ordering::Equal => {}
-                    ord => return Ok(ord),
-                }
-            }
-            Ok(a.len().cmp(&b.len()))
+        (PyValue::Bytes(_) | PyValue::ByteArray(_), PyValue::Bytes(_) | PyValue::ByteArray(_)) => {
+            Ok(left.as_bytes().unwrap().cmp(&right.as_bytes().unwrap()))
+        }
+        (PyValue::Tuple(a), PyValue::Tuple(b)) => compare_sequences(a, b),
+        (PyValue::List(a), PyValue::List(b)) => {
+            let Some(_guard) = CompareGuard::enter() else {
+                return Err(compare_recursion_error());
+            };
+            compare_sequences(&a.read(), &b.read())
         }
         _ => Err(Error::Type {
             expected: "comparable types".to_string(),
@@ -322,38 +459,79 @@ fn compare_elements(left: &PyValue, right: &PyValue) -> Result<std::cmp::Orderin
     }
 }
 
-/// Apply a numeric binary operation.
-fn numeric_binop<F, G>(left: &PyValue, right: &PyValue, int_op: F, float_op: G) -> Result<PyValue>
+/// `data` as a new value of the same type as `like` (bytes or bytearray).
+fn same_byte_type(like: &PyValue, data: Vec<u8>) -> PyValue {
+    match like {
+        PyValue::ByteArray(_) => PyValue::bytearray(data),
+        _ => PyValue::Bytes(data),
+    }
+}
+
+/// Compare two sequences lexicographically.
+fn compare_sequences(a: &[PyValue], b: &[PyValue]) -> Result<std::cmp::Ordering> {
+    for (av, bv) in a.iter().zip(b.iter()) {
+        match compare_elements(av, bv)? {
+            std::cmp::Ordering::Equal => {}
+            ord => return Ok(ord),
+        }
+    }
+    Ok(a.len().cmp(&b.len()))
+}
+
+/// Apply a numeric binary operation to operands that are not both ints.
+fn numeric_binop<F>(op: &BinOp, left: &PyValue, right: &PyValue, float_op: F) -> Result<PyValue>
 where
-    F: Fn(i64, i64) -> i64,
-    G: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64,
 {
     match (left, right) {
-        (PyValue::Int(a), PyValue::Int(b)) => Ok(PyValue::Int(int_op(*a, *b))),
         (PyValue::Float(a), PyValue::Float(b)) => Ok(PyValue::Float(float_op(*a, *b))),
         (PyValue::Int(a), PyValue::Float(b)) => Ok(PyValue::Float(float_op(*a as f64, *b))),
         (PyValue::Float(a), PyValue::Int(b)) => Ok(PyValue::Float(float_op(*a, *b as f64))),
-        _ => Err(Error::Type {
-            expected: "numbers".to_string(),
-            got: format!("{} and {}", left.type_name(), right.type_name()),
-        }),
+        _ => Err(unsupported_operands(op, left, right)),
     }
 }
 
-/// Apply an integer binary operation.
-fn int_binop<F>(left: &PyValue, right: &PyValue, op: F) -> Result<PyValue>
-where
-    F: Fn(i64, i64) -> i64,
-{
-    let a = left.as_int().ok_or_else(|| Error::Type {
-        expected: "int".to_string(),
-        got: left.type_name().to_string(),
-    })?;
-    let b = right.as_int().ok_or_else(|| Error::Type {
-        expected: "int".to_string(),
-        got: right.type_name().to_string(),
-    })?;
-    Ok(PyValue::Int(op(a, b)))
+/// The `TypeError` CPython raises for operands an operator does not
+/// support, e.g. `unsupported operand type(s) for -: 'str' and 'int'`.
+fn unsupported_operands(op: &BinOp, left: &PyValue, right: &PyValue) -> Error {
+    let (l, r) = (left.type_name(), right.type_name());
+    let is_sequence = |value: &PyValue| {
+        matches!(
+            value,
+            PyValue::Str(_) | PyValue::List(_) | PyValue::Tuple(_)
+        ) || value.as_bytes().is_some()
+    };
+    let message = match op {
+        BinOp::Add if left.as_bytes().is_some() => format!("can't concat {} to {}", r, l),
+        BinOp::Add if is_sequence(left) => {
+            format!("can only concatenate {} (not \"{}\") to {}", l, r, l)
+        }
+        BinOp::Mult if is_sequence(left) || is_sequence(right) => {
+            let other = if is_sequence(left) { r } else { l };
+            format!("can't multiply sequence by non-int of type '{}'", other)
+        }
+        _ => {
+            let symbol = match op {
+                BinOp::Add => "+",
+                BinOp::Sub => "-",
+                BinOp::Mult => "*",
+                BinOp::Div => "/",
+                BinOp::FloorDiv => "//",
+                BinOp::Mod => "%",
+                BinOp::Pow => "** or pow()",
+                BinOp::BitOr => "|",
+                BinOp::BitXor => "^",
+                BinOp::BitAnd => "&",
+                BinOp::LShift => "<<",
+                BinOp::RShift => ">>",
+            };
+            format!(
+                "unsupported operand type(s) for {}: '{}' and '{}'",
+                symbol, l, r
+            )
+        }
+    };
+    Error::exception("TypeError", message)
 }
 
 /// Compare two values with given comparison functions.
@@ -367,34 +545,26 @@ where
     F: Fn(i64, i64) -> bool,
     G: Fn(f64, f64) -> bool,
 {
+    let by_ordering = |ord| match ord {
+        std::cmp::Ordering::Less => int_cmp(0, 1),
+        std::cmp::Ordering::Equal => int_cmp(0, 0),
+        std::cmp::Ordering::Greater => int_cmp(1, 0),
+    };
+    if let Some(ord) = int::compare(left, right) {
+        return Ok(by_ordering(ord));
+    }
     match (left, right) {
-        (PyValue::Int(a), PyValue::Int(b)) => Ok(int_cmp(*a, *b)),
         (PyValue::Float(a), PyValue::Float(b)) => Ok(float_cmp(*a, *b)),
         (PyValue::Int(a), PyValue::Float(b)) => Ok(float_cmp(*a as f64, *b)),
         (PyValue::Float(a), PyValue::Int(b)) => Ok(float_cmp(*a, *b as f64)),
-        (PyValue::Str(a), PyValue::Str(b)) => {
-            let ord = a.cmp(b);
-            Ok(match ord {
-                std::cmp::Ordering::Less => int_cmp(0, 1),
-                std::cmp::Ordering::Equal => int_cmp(0, 0),
-                std::cmp::Ordering::Greater => int_cmp(1, 0),
-            })
-        }
-        (PyValue::Tuple(a), PyValue::Tuple(b)) | (PyValue::List(a), PyValue::List(b)) => {
+        (PyValue::BigInt(a), PyValue::Float(b)) => Ok(float_cmp(int::to_f64_lossy(a), *b)),
+        (PyValue::Float(a), PyValue::BigInt(b)) => Ok(float_cmp(*a, int::to_f64_lossy(b))),
+        (PyValue::Str(a), PyValue::Str(b)) => Ok(by_ordering(a.cmp(b))),
+        (PyValue::Tuple(_), PyValue::Tuple(_))
+        | (PyValue::List(_), PyValue::List(_))
+        | (PyValue::Bytes(_) | PyValue::ByteArray(_), PyValue::Bytes(_) | PyValue::ByteArray(_)) => {
             // Lexicographic comparison
-            for (av, bv) in a.iter().zip(b.iter()) {
-                match compare_elements(av, bv)? {
-                    std::cmp::Ordering::Less => return Ok(int_cmp(0, 1)),
-                    std::cmp::Ordering::Greater => return Ok(int_cmp(1, 0)),
-                    std::cmp::Ordering::Equal => {}
-                }
-            }
-            // All compared elements equal — shorter sequence is "less"
-            Ok(match a.len().cmp(&b.len()) {
-                std::cmp::Ordering::Less => int_cmp(0, 1),
-                std::cmp::Ordering::Equal => int_cmp(0, 0),
-                std::cmp::Ordering::Greater => int_cmp(1, 0),
-            })
+            Ok(by_ordering(compare_elements(left, right)?))
         }
         _ => Err(Error::Type {
             expected: "comparable types".to_string(),
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::compiler::Compiler;
+use crate::decorator::{DecoratorValue, HostDecorator};
 use crate::error::Result;
 use crate::tool::ToolInfo;
 use crate::value::PyValue;
@@ -53,12 +54,39 @@ impl ModuleBuilder {
     pub fn function<F>(&mut self, name: &str, f: F)
     where
         F: Fn(Vec<PyValue>) -> PyValue + Send + Sync + 'static,
+    {
+        let tool_key = format!("__mod_{}__{}", self.module_name, name);
+        self.attrs
+            .push((name.to_string(), PyValue::NativeFunction(tool_key.clone())));
+        self.tools
+            .push((tool_key, Arc::new(move |args| Ok(f(args))) as ToolFn));
+    }
+
+    /// Register a native function that can raise a Python exception by
+    /// returning an error, such as
+    /// `ExceptionValue::new("ValueError", args).into()`.
+    pub fn try_function<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(Vec<PyValue>) -> Result<PyValue> + Send + Sync + 'static,
     {
         let tool_key = format!("__mod_{}__{}", self.module_name, name);
         self.attrs
             .push((name.to_string(), PyValue::NativeFunction(tool_key.clone())));
         self.tools.push((tool_key, Arc::new(f) as ToolFn));
     }
+
+    /// Register a decorator factory as a module attribute, usable as
+    /// `@module.name` or `@module.name(args)`.
+    ///
+    /// See [`Sandbox::register_decorator`].
+    pub fn decorator<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&[PyValue], &[(String, PyValue)]) -> HostDecorator + Send + Sync + 'static,
+    {
+        let decorator = DecoratorValue::factory(name, Arc::new(factory));
+        self.attrs
+            .push((name.to_string(), PyValue::Decorator(decorator)));
+    }
 }
 
 /// A secure Python sandbox for executing untrusted code.
@@ -103,7 +131,8 @@ impl Sandbox {
         }
     }
 
-    /// Create a new sandbox with built-in modules (json, math, typing) pre-registered.
+    /// Create a new sandbox with built-in modules (json, math, typing, dataclasses,
+    /// functools) pre-registered.
     ///
     /// # Example
     ///
@@ -187,7 +216,8 @@ impl Sandbox {
     where
         F: Fn(Vec<PyValue>) -> PyValue + Send + Sync + 'static,
     {
-        self.vm.register_tool(name, Arc::new(f) as ToolFn);
+        self.vm
+            .register_tool(name, Arc::new(move |args| Ok(f(args))) as ToolFn);
     }
 
     /// Register a tool with metadata that can be called from Python code.
@@ -209,7 +239,7 @@ impl Sandbox {
     ///
     /// sandbox.register_tool(info, |args| {
     ///     let city = args.get(0).and_then(|v| v.as_str()).unwrap_or("Unknown");
-    ///     PyValue::Dict(vec![
+    ///     PyValue::dict(vec![
     ///         (PyValue::Str("city".to_string()), PyValue::Str(city.to_string())),
     ///         (PyValue::Str("temp".to_string()), PyValue::Int(22)),
     ///     ])
@@ -227,7 +257,7 @@ impl Sandbox {
         F: Fn(Vec<PyValue>) -> PyValue + Send + Sync + 'static,
     {
         self.vm
-            .register_tool_with_info(info.clone(), Arc::new(f) as ToolFn);
+            .register_tool_with_info(info.clone(), Arc::new(move |args| Ok(f(args))) as ToolFn);
         self.tool_infos.push(info);
     }
 
@@ -253,10 +283,56 @@ impl Sandbox {
     pub fn add<T: crate::tool::Tool + 'static>(&mut self, _: T) {
         let info = T::info().clone();
         self.vm
-            .register_tool_with_info(info.clone(), Arc::new(T::call) as ToolFn);
+            .register_tool_with_info(info.clone(), Arc::new(|args| Ok(T::call(args))) as ToolFn);
         self.tool_infos.push(info);
     }
 
+    /// Register a decorator that Python code can apply to its functions.
+    ///
+    /// The factory receives the arguments of `@name(...)` (none for a bare
+    /// `@name`) and returns the [`HostDecorator`] hooks to run around each
+    /// call of the decorated function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use std::sync::{Arc, Mutex};
+    /// use littrs::{HostDecorator, PyValue, Sandbox};
+    ///
+    /// let store: Arc<Mutex<HashMap<String, PyValue>>> = Arc::default();
+    ///
+    /// let mut sandbox = Sandbox::new();
+    /// sandbox.register_decorator("cached", move |_args, _kwargs| {
+    ///     let (lookup, save) = (store.clone(), store.clone());
+    ///     HostDecorator::new()
+    ///         .before(move |call| lookup.lock().unwrap().get(&call.key()).cloned())
+    ///         .after(move |call, result| {
+    ///             save.lock().unwrap().insert(call.key(), result.clone());
+    ///         })
+    /// });
+    ///
+    /// let result = sandbox.run(r#"
+    /// calls = 0
+    ///
+    /// @cached
+    /// def square(x):
+    ///     global calls
+    ///     calls += 1
+    ///     return x * x
+    ///
+    /// square(4) + square(4) + calls
+    /// "#).unwrap();
+    /// assert_eq!(result, PyValue::Int(33));
+    /// ```
+    pub fn register_decorator<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&[PyValue], &[(String, PyValue)]) -> HostDecorator + Send + Sync + 'static,
+    {
+        let decorator = DecoratorValue::factory(name, Arc::new(factory));
+        self.vm.set_variable(name, PyValue::Decorator(decorator));
+    }
+
     /// Generate Python documentation for all registered tools.
     ///
     /// This is suitable for embedding in an LLM's system prompt for
@@ -316,6 +392,25 @@ impl Sandbox {
         self.vm.set_variable(name, value.into());
     }
 
+    /// Remove a variable from the sandbox's global scope, as `del name`
+    /// would. Returns whether the variable was set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use littrs::{Sandbox, PyValue};
+    ///
+    /// let mut sandbox = Sandbox::new();
+    /// sandbox.set("token", PyValue::Str("secret".to_string()));
+    ///
+    /// assert!(sandbox.unset("token"));
+    /// assert!(sandbox.run("token").is_err());
+    /// assert!(!sandbox.unset("token"));
+    /// ```
+    pub fn unset(&mut self, name: &str) -> bool {
+        self.vm.unset_variable(name)
+    }
+
     /// Run Python code in the sandbox.
     ///
     /// Returns the value of the last expression, or `PyValue::None` if the
@@ -448,7 +543,7 @@ impl Sandbox {
         if std::path::Path::new(&host).is_dir() {
             self.vm.mount_dir(virtual_path.into(), host, writable);
         } else {
-            let content = std::fs::read_to_string(&host).unwrap_or_default();
+            let content = std::fs::read(&host).unwrap_or_default();
             self.vm.mount(virtual_path.into(), host, writable, content);
         }
     }
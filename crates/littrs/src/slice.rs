@@ -1,9 +1,115 @@
 //! Slicing implementation for Python sequences.
 //!
-//! This module handles Python-style slicing for lists and strings.
+//! This module handles Python-style slicing for lists, strings, bytes and
+//! ranges.
 
 use crate::error::{Error, Result};
-use crate::value::PyValue;
+use crate::value::{PyValue, Shared, range_len};
+
+/// The start, stop and step of a slice, each `None` if unspecified.
+pub type SliceBounds = (Option<i64>, Option<i64>, Option<i64>);
+
+/// Check the start, stop and step values of a slice expression.
+pub fn slice_bounds(start: &PyValue, stop: &PyValue, step: &PyValue) -> Result<SliceBounds> {
+    let to_opt = |v: &PyValue| -> Result<Option<i64>> {
+        match v {
+            PyValue::None => Ok(None),
+            PyValue::Int(i) => Ok(Some(*i)),
+            _ => Err(Error::Type {
+                expected: "int".to_string(),
+                got: v.type_name().to_string(),
+            }),
+        }
+    };
+
+    let step = to_opt(step)?;
+    if step == Some(0) {
+        return Err(Error::exception("ValueError", "slice step cannot be zero"));
+    }
+    Ok((to_opt(start)?, to_opt(stop)?, step))
+}
+
+/// The indices selected by `[lower:upper:step]` in a sequence of length
+/// `len`, in slice order.
+pub(crate) fn slice_indices(len: usize, (lower, upper, step): SliceBounds) -> Vec<usize> {
+    let len = len as i64;
+    let step = step.unwrap_or(1);
+    // Negative indices count from the end; out-of-range ones are clamped
+    let clamp = |i: i64, lo: i64, hi: i64| (if i < 0 { i + len } else { i }).clamp(lo, hi);
+    let (start, stop) = if step > 0 {
+        (
+            lower.map_or(0, |i| clamp(i, 0, len)),
+            upper.map_or(len, |i| clamp(i, 0, len)),
+        )
+    } else {
+        (
+            lower.map_or(len - 1, |i| clamp(i, -1, len - 1)),
+            upper.map_or(-1, |i| clamp(i, -1, len - 1)),
+        )
+    };
+
+    let mut indices = Vec::new();
+    let mut i = start;
+    while (step > 0 && i < stop) || (step < 0 && i > stop) {
+        indices.push(i as usize);
+        i += step;
+    }
+    indices
+}
+
+/// Delete `list[lower:upper:step]` in place (of a list or bytearray).
+pub fn delete_slice<T: Clone>(list: &Shared<Vec<T>>, bounds: SliceBounds) {
+    list.write(|items| {
+        let mut remove = vec![false; items.len()];
+        for i in slice_indices(items.len(), bounds) {
+            remove[i] = true;
+        }
+        let mut index = 0;
+        items.retain(|_| {
+            index += 1;
+            !remove[index - 1]
+        });
+    });
+}
+
+/// Replace `list[lower:upper:step]` with `values` in place (of a list or
+/// bytearray).
+///
+/// A simple slice may grow or shrink the list; an extended slice must be
+/// given exactly as many values as it selects.
+pub fn assign_slice<T: Clone>(
+    list: &Shared<Vec<T>>,
+    bounds: SliceBounds,
+    values: Vec<T>,
+) -> Result<()> {
+    list.write(|items| {
+        let (lower, upper, step) = bounds;
+        if step.unwrap_or(1) == 1 {
+            let len = items.len() as i64;
+            let clamp = |i: i64| (if i < 0 { i + len } else { i }).clamp(0, len) as usize;
+            let start = lower.map_or(0, clamp);
+            let stop = upper.map_or(items.len(), clamp).max(start);
+            items.splice(start..stop, values);
+            return Ok(());
+        }
+
+        let indices = slice_indices(items.len(), bounds);
+        if indices.len() != values.len() {
+            return Err(Error::exception(
+                "ValueError",
+                format!(
+                    "attempt to assign sequence of size {} to extended slice of size {}",
+                    values.len(),
+                    indices.len()
+                ),
+            ));
+        }
+        for (i, value) in indices.into_iter().zip(values) {
+            items[i] = value;
+        }
+        Ok(())
+    })
+}
 
 /// Slice a list with Python semantics.
 ///
@@ -14,11 +120,31 @@ pub fn slice_list(
     upper: Option<i64>,
     step: Option<i64>,
 ) -> Result<PyValue> {
+    slice_items(items, lower, upper, step).map(PyValue::list)
+}
+
+/// Slice a tuple with Python semantics.
+pub fn slice_tuple(
+    items: &[PyValue],
+    lower: Option<i64>,
+    upper: Option<i64>,
+    step: Option<i64>,
+) -> Result<PyValue> {
+    slice_items(items, lower, upper, step).map(PyValue::Tuple)
+}
+
+/// The items of `items[lower:upper:step]`.
+fn slice_items(
+    items: &[PyValue],
+    lower: Option<i64>,
+    upper: Option<i64>,
+    step: Option<i64>,
+) -> Result<Vec<PyValue>> {
     let len = items.len() as i64;
     let step = step.unwrap_or(1);
 
     if step == 0 {
-        return Err(Error::Runtime("slice step cannot be zero".to_string()));
+        return Err(Error::exception("ValueError", "slice step cannot be zero"));
     }
 
     if step > 0 {
@@ -34,17 +160,17 @@ pub fn slice_list(
         };
 
         if start >= end {
-            return Ok(PyValue::List(vec![]));
+            return Ok(vec![]);
         }
 
         if step == 1 {
-            Ok(PyValue::List(items[start..end].to_vec()))
+            Ok(items[start..end].to_vec())
         } else {
             let result: Vec<PyValue> = (start..end)
                 .step_by(step as usize)
                 .filter_map(|i| items.get(i).cloned())
                 .collect();
-            Ok(PyValue::List(result))
+            Ok(result)
         }
     } else {
         // Negative step (reverse iteration)
@@ -68,90 +194,77 @@ pub fn slice_list(
             result.push(items[i as usize].clone());
             i += step as isize;
         }
-        Ok(PyValue::List(result))
+        Ok(result)
     }
 }
 
-/// Slice a tuple with Python semantics.
+/// The bytes of `data[lower:upper:step]`.
+pub fn slice_bytes(data: &[u8], bounds: SliceBounds) -> Vec<u8> {
+    slice_indices(data.len(), bounds)
+        .into_iter()
+        .map(|i| data[i])
+        .collect()
+}
+
+/// Slice a string with Python semantics.
 ///
-/// Same logic as `slice_list` but wraps result in `PyValue::Tuple`.
-pub fn slice_tuple(
-    items: &[PyValue],
+/// Indices count Unicode code points, not UTF-8 bytes.
+pub fn slice_string(
+    s: &str,
     lower: Option<i64>,
     upper: Option<i64>,
     step: Option<i64>,
 ) -> Result<PyValue> {
-    // Reuse slice_list logic, then convert List → Tuple
-    let result = slice_list(items, lower, upper, step)?;
-    match result {
-        PyValue::List(items) => Ok(PyValue::Tuple(items)),
-        other => Ok(other),
+    if step == Some(0) {
+        return Err(Error::exception("ValueError", "slice step cannot be zero"));
     }
+    let chars: Vec<char> = s.chars().collect();
+    Ok(PyValue::Str(
+        slice_indices(chars.len(), (lower, upper, step))
+            .into_iter()
+            .map(|i| chars[i])
+            .collect(),
+    ))
 }
 
-/// Slice a string with Python semantics.
+/// Slice a range with Python semantics, producing another range.
 ///
-/// Handles proper Unicode character boundaries.
-pub fn slice_string(
-    s: &str,
+/// The slice bounds are clamped to item indices the way
+/// `slice.indices()` does, so no items are materialized.
+pub fn slice_range(
+    start: i64,
+    stop: i64,
+    step: i64,
     lower: Option<i64>,
     upper: Option<i64>,
-    step: Option<i64>,
+    slice_step: Option<i64>,
 ) -> Result<PyValue> {
-    // Convert string to chars for proper Unicode handling
-    let chars: Vec<char> = s.chars().collect();
-    let len = chars.len() as i64;
-    let step = step.unwrap_or(1);
+    let len = range_len(start, stop, step);
+    let slice_step = slice_step.unwrap_or(1);
 
-    if step == 0 {
-        return Err(Error::Runtime("slice step cannot be zero".to_string()));
+    if slice_step == 0 {
+        return Err(Error::exception("ValueError", "slice step cannot be zero"));
     }
 
-    if step > 0 {
-        let start = match lower {
-            Some(i) if i < 0 => (len + i).max(0) as usize,
-            Some(i) => (i as usize).min(chars.len()),
-            None => 0,
-        };
-        let end = match upper {
-            Some(i) if i < 0 => (len + i).max(0) as usize,
-            Some(i) => (i as usize).min(chars.len()),
-            None => chars.len(),
-        };
-
-        if start >= end {
-            return Ok(PyValue::Str(String::new()));
-        }
-
-        if step == 1 {
-            Ok(PyValue::Str(chars[start..end].iter().collect()))
-        } else {
-            let result: String = (start..end)
-                .step_by(step as usize)
-                .filter_map(|i| chars.get(i))
-                .collect();
-            Ok(PyValue::Str(result))
-        }
+    let (min, max) = if slice_step > 0 {
+        (0, len)
     } else {
-        // Negative step (reverse)
-        let start = match lower {
-            Some(i) if i < 0 => (len + i) as usize,
-            Some(i) => (i as usize).min(chars.len().saturating_sub(1)),
-            None => chars.len().saturating_sub(1),
-        };
-        let end_check = match upper {
-            Some(i) if i < 0 => (len + i) as isize - 1,
-            Some(_) => -1,
-            None => -1,
-        };
-
-        let mut result = String::new();
-        let mut i = start as isize;
+        (-1, len - 1)
+    };
+    let clamp = |index: Option<i64>, default: i64| match index {
+        None => default,
+        Some(i) if i < 0 => (len + i).max(min),
+        Some(i) => i.min(max),
+    };
+    let (lower, upper) = if slice_step > 0 {
+        (clamp(lower, 0), clamp(upper, len))
+    } else {
+        (clamp(lower, len - 1), clamp(upper, -1))
+    };
 
-        while i > end_check && i >= 0 && (i as usize) < chars.len() {
-            result.push(chars[i as usize]);
-            i += step as isize;
-        }
-        Ok(PyValue::Str(result))
-    }
+    Ok(PyValue::Range {
+        start: start + lower * step,
+        stop: start + upper * step,
+        step: step * slice_step,
+    })
 }
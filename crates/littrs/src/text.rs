@@ -0,0 +1,212 @@
+//! Text strings: code-point searching and Unicode character classes.
+//!
+//! Python indexes `str` values by code point, while Rust strings are
+//! indexed by UTF-8 byte. The helpers here convert between the two for
+//! the `str` methods, and classify characters the way `str.isdigit()` and
+//! its friends do.
+
+use crate::error::{Error, Result};
+use crate::value::PyValue;
+
+/// The zero of every run of decimal digits (category `Nd`, Unicode 14.0).
+/// Each run is the ten digits from its zero to its nine.
+const DECIMAL_ZEROS: &[u32] = &[
+    0x30, 0x660, 0x6F0, 0x7C0, 0x966, 0x9E6, 0xA66, 0xAE6, 0xB66, 0xBE6, 0xC66, 0xCE6, 0xD66,
+    0xDE6, 0xE50, 0xED0, 0xF20, 0x1040, 0x1090, 0x17E0, 0x1810, 0x1946, 0x19D0, 0x1A80, 0x1A90,
+    0x1B50, 0x1BB0, 0x1C40, 0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0, 0xFF10,
+    0x104A0, 0x10D30, 0x11066, 0x110F0, 0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0, 0x11650,
+    0x116C0, 0x11730, 0x118E0, 0x11950, 0x11C50, 0x11D50, 0x11DA0, 0x16A60, 0x16AC0, 0x16B50,
+    0x1D7CE, 0x1D7D8, 0x1D7E2, 0x1D7EC, 0x1D7F6, 0x1E140, 0x1E2F0, 0x1E950, 0x1FBF0,
+];
+
+/// The digits that are not decimal digits, such as superscripts and
+/// circled digits, as inclusive ranges.
+const OTHER_DIGITS: &[(u32, u32)] = &[
+    (0xB2, 0xB3),
+    (0xB9, 0xB9),
+    (0x1369, 0x1371),
+    (0x19DA, 0x19DA),
+    (0x2070, 0x2070),
+    (0x2074, 0x2079),
+    (0x2080, 0x2089),
+    (0x2460, 0x2468),
+    (0x2474, 0x247C),
+    (0x2488, 0x2490),
+    (0x24EA, 0x24EA),
+    (0x24F5, 0x24FD),
+    (0x24FF, 0x24FF),
+    (0x2776, 0x277E),
+    (0x2780, 0x2788),
+    (0x278A, 0x2792),
+    (0x10A40, 0x10A43),
+    (0x10E60, 0x10E68),
+    (0x11052, 0x1105A),
+    (0x1F100, 0x1F10A),
+];
+
+/// The format characters (category `Cf`, Unicode 14.0), as inclusive
+/// ranges. They are invisible, so `repr()` escapes them.
+const FORMAT_CHARS: &[(u32, u32)] = &[
+    (0xAD, 0xAD),
+    (0x600, 0x605),
+    (0x61C, 0x61C),
+    (0x6DD, 0x6DD),
+    (0x70F, 0x70F),
+    (0x890, 0x891),
+    (0x8E2, 0x8E2),
+    (0x180E, 0x180E),
+    (0x200B, 0x200F),
+    (0x202A, 0x202E),
+    (0x2060, 0x2064),
+    (0x2066, 0x206F),
+    (0xFEFF, 0xFEFF),
+    (0xFFF9, 0xFFFB),
+    (0x110BD, 0x110BD),
+    (0x110CD, 0x110CD),
+    (0x13430, 0x13438),
+    (0x1BCA0, 0x1BCA3),
+    (0x1D173, 0x1D17A),
+    (0xE0001, 0xE0001),
+    (0xE0020, 0xE007F),
+];
+
+/// Whether `c` is a decimal digit, as `str.isdecimal()` tests.
+pub(crate) fn is_decimal(c: char) -> bool {
+    let code = c as u32;
+    let run = DECIMAL_ZEROS.partition_point(|&zero| zero <= code);
+    run > 0 && code - DECIMAL_ZEROS[run - 1] < 10
+}
+
+/// Whether `c` is a digit, as `str.isdigit()` tests: a decimal digit or
+/// a digit such as `'²'` that cannot be used to write numbers in base 10.
+pub(crate) fn is_digit(c: char) -> bool {
+    let code = c as u32;
+    is_decimal(c)
+        || OTHER_DIGITS
+            .iter()
+            .any(|&(first, last)| (first..=last).contains(&code))
+}
+
+/// Whether `c` is a letter, as `str.isalpha()` tests. Letter-like
+/// numerals such as `'Ⅻ'` are numbers, not letters.
+pub(crate) fn is_alpha(c: char) -> bool {
+    c.is_alphabetic() && !c.is_numeric()
+}
+
+/// Whether `c` is whitespace, as `str.isspace()` and `str.split()` test.
+/// Python also counts the ASCII separator controls `\x1c`-`\x1f`.
+pub(crate) fn is_space(c: char) -> bool {
+    c.is_whitespace() || ('\x1c'..='\x1f').contains(&c)
+}
+
+/// Whether `c` has case: it is upper, lower or title case, like `'ǅ'`.
+pub(crate) fn is_cased(c: char) -> bool {
+    c.is_uppercase()
+        || c.is_lowercase()
+        || (c.to_lowercase().ne(std::iter::once(c)) && c.to_uppercase().ne(std::iter::once(c)))
+}
+
+/// Whether `c` is printable, as `str.isprintable()` tests: not a control,
+/// format or private-use character, nor a separator other than the space.
+/// `repr()` escapes the characters that are not.
+pub(crate) fn is_printable(c: char) -> bool {
+    let code = c as u32;
+    c == ' '
+        || !(c.is_control()
+            || c.is_whitespace()
+            || FORMAT_CHARS
+                .iter()
+                .any(|&(first, last)| (first..=last).contains(&code))
+            || (0xE000..=0xF8FF).contains(&code)
+            || code >= 0xF0000)
+}
+
+/// A character as it appears escaped inside a Python string repr.
+pub(crate) fn escape_char(c: char) -> String {
+    match c as u32 {
+        code @ 0..=0xff => format!("\\x{:02x}", code),
+        code @ 0x100..=0xffff => format!("\\u{:04x}", code),
+        code => format!("\\U{:08x}", code),
+    }
+}
+
+/// The byte offset of the code point at `index` in `s`, or `s.len()` if
+/// `s` is shorter.
+fn byte_offset(s: &str, index: usize) -> usize {
+    s.char_indices()
+        .nth(index)
+        .map_or(s.len(), |(offset, _)| offset)
+}
+
+/// The part of `s` that `str.find(sub, start, end)` and friends search,
+/// with the code-point index it starts at. Bounds are clamped like slice
+/// bounds; `None` means a start past the end of `s`, where nothing (not
+/// even the empty string) is found.
+fn window<'a>(s: &'a str, start: &PyValue, end: &PyValue) -> Result<Option<(&'a str, usize)>> {
+    let len = s.chars().count() as i64;
+    let bound = |value: &PyValue, default: i64| -> Result<i64> {
+        let index = match value {
+            PyValue::None => return Ok(default),
+            PyValue::Int(i) => *i,
+            PyValue::Bool(b) => *b as i64,
+            other => {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!(
+                        "slice indices must be integers or None, not {}",
+                        other.type_name()
+                    ),
+                ));
+            }
+        };
+        Ok(if index < 0 {
+            (index + len).max(0)
+        } else {
+            index
+        })
+    };
+    let start = bound(start, 0)?;
+    let end = bound(end, len)?.min(len);
+    if start > len {
+        return Ok(None);
+    }
+    let start = start as usize;
+    let start_byte = byte_offset(s, start);
+    let end_byte = if end as usize > start {
+        start_byte + byte_offset(&s[start_byte..], end as usize - start)
+    } else {
+        start_byte
+    };
+    Ok(Some((&s[start_byte..end_byte], start)))
+}
+
+/// The optional start and end arguments of a `str` search method.
+fn bounds(args: &[PyValue]) -> (&PyValue, &PyValue) {
+    (
+        args.get(1).unwrap_or(&PyValue::None),
+        args.get(2).unwrap_or(&PyValue::None),
+    )
+}
+
+/// The code-point index of `sub` in `s[start:end]`, as `str.find()` and
+/// `str.rfind()` (when `reverse`) return it. `args` are the method's
+/// arguments: `sub` and the optional `start` and `end`.
+pub(crate) fn find(s: &str, sub: &str, args: &[PyValue], reverse: bool) -> Result<Option<usize>> {
+    let (start, end) = bounds(args);
+    let Some((haystack, offset)) = window(s, start, end)? else {
+        return Ok(None);
+    };
+    let position = if reverse {
+        haystack.rfind(sub)
+    } else {
+        haystack.find(sub)
+    };
+    Ok(position.map(|byte| offset + haystack[..byte].chars().count()))
+}
+
+/// The number of non-overlapping occurrences of `sub` in `s[start:end]`,
+/// as `str.count()` returns it.
+pub(crate) fn count(s: &str, sub: &str, args: &[PyValue]) -> Result<usize> {
+    let (start, end) = bounds(args);
+    Ok(window(s, start, end)?.map_or(0, |(haystack, _)| haystack.matches(sub).count()))
+}
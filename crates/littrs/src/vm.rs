@@ -11,27 +11,62 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::builtins::{BuiltinResult, try_builtin};
-use crate::bytecode::{CodeObject, ExceptionEntry, FunctionDef, Op, UnaryOp};
+use crate::builtins::{BuiltinResult, builtin_dict, builtin_type_object, is_builtin, try_builtin};
+use crate::bytecode::{
+    BinOp, CmpOp, CodeObject, Conversion, ExceptionEntry, FunctionDef, Op, UnaryOp,
+};
+use crate::bytes;
+use crate::class::{ClassValue, InstanceValue, RecordKind, records_to_dicts};
+use crate::decorator::{
+    DecoratedCall, DecoratorCall, DecoratorValue, HostDecorator, callable_doc, callable_name,
+};
 use crate::diagnostic::{Diagnostic, Span};
+use crate::dict::{Dict, check_hashable};
 use crate::error::{Error, Result};
+use crate::exception::ExceptionValue;
+use crate::format::{self, Accessor, FieldArg, Piece};
+use crate::generator::{GeneratorValue, SuspendedFrame};
+use crate::int;
+use crate::iterator::{IterState, IteratorValue};
 use crate::methods;
-use crate::operators::{apply_binop, apply_cmpop};
+use crate::operators::{apply_binop, apply_cmpop, apply_inplace_op};
 use crate::slice;
 use crate::tool::ToolInfo;
-use crate::value::PyValue;
-
-/// An active exception on the exception stack.
-#[derive(Clone, Debug)]
-struct ExceptionState {
-    /// Python exception type name (e.g. "ValueError", "TypeError").
-    exception_type: String,
-    /// The error message.
-    message: String,
-}
-
-/// Type alias for tool callback functions registered by the host.
-pub type ToolFn = Arc<dyn Fn(Vec<PyValue>) -> PyValue + Send + Sync>;
+use crate::value::{Cell, PyValue, ReprGuard, check_sequence_len, range_item, range_len};
+
+/// Type alias for tool callback functions registered by the host. An
+/// error is raised in the script.
+pub type ToolFn = Arc<dyn Fn(Vec<PyValue>) -> Result<PyValue> + Send + Sync>;
+
+/// Positional arguments and keyword `(name, value)` pairs of a call.
+type CallArgs = (Vec<PyValue>, Vec<(String, PyValue)>);
+
+/// Builtins that only inspect their argument, so generators and ranges
+/// are passed to them as is rather than consumed into lists.
+const INSPECTING_BUILTINS: &[&str] = &[
+    "print",
+    "str",
+    "repr",
+    "type",
+    "isinstance",
+    "len",
+    "bool",
+    "hash",
+];
+
+/// Container methods that consume an iterable argument.
+const ITERABLE_ARG_METHODS: &[&str] = &[
+    "extend",
+    "join",
+    "update",
+    "union",
+    "intersection",
+    "difference",
+    "symmetric_difference",
+    "issubset",
+    "issuperset",
+    "isdisjoint",
+];
 
 /// A registered tool with its callback and metadata.
 #[derive(Clone)]
@@ -51,7 +86,7 @@ pub struct MountEntry {
     /// Whether the file is writable from sandbox code.
     pub writable: bool,
     /// File content read at mount time (or accumulated via writes).
-    pub content: String,
+    pub content: Vec<u8>,
 }
 
 /// A mounted directory — files within are resolved lazily on `open()`.
@@ -71,19 +106,28 @@ struct FileState {
     /// The virtual path this file was opened with.
     virtual_path: String,
     /// Current buffer contents.
-    buffer: String,
-    /// Cursor position for reading.
+    buffer: Vec<u8>,
+    /// Cursor position for reading, in bytes.
     cursor: usize,
     /// Whether the file was opened in write mode.
     write_mode: bool,
+    /// Whether the file was opened in binary mode (`"rb"`, `"wb"`), reading
+    /// and writing `bytes` rather than `str`.
+    binary: bool,
     /// Whether the file has been closed.
     closed: bool,
 }
 
-/// State of a single iterator (used by `for` loops and comprehensions).
-struct IterState {
-    items: Vec<PyValue>,
-    index: usize,
+impl FileState {
+    /// `data` read from the file: `bytes` in binary mode, otherwise text
+    /// decoded as UTF-8.
+    fn content(&self, data: &[u8]) -> Result<PyValue> {
+        if self.binary {
+            Ok(PyValue::Bytes(data.to_vec()))
+        } else {
+            bytes::decode_utf8(data).map(PyValue::Str)
+        }
+    }
 }
 
 /// A single activation record on the call stack.
@@ -91,7 +135,7 @@ struct IterState {
 /// Each function call (including the top-level script) gets its own frame.
 /// The frame owns a reference to the [`CodeObject`] it is executing, an
 /// instruction pointer, local variables, and any active iterators.
-struct CallFrame {
+pub(crate) struct CallFrame {
     /// The compiled code being executed.
     code: CodeObject,
     /// Instruction pointer — index of the *next* instruction to execute.
@@ -103,6 +147,56 @@ struct CallFrame {
     stack_base: usize,
     /// Active iterators for `for` loops within this frame.
     iterators: Vec<IterState>,
+    /// Cells for this frame's cell and free variables, keyed by name.
+    cells: HashMap<String, Cell>,
+    /// Whether this frame runs a function body (stores go to `locals`)
+    /// rather than module-level code (stores go to globals).
+    is_function: bool,
+    /// Length of the VM's exception stack when this frame was entered.
+    exc_base: usize,
+    /// Value returned in place of the function's own return value: the new
+    /// instance, for a frame running `__init__`.
+    return_override: Option<PyValue>,
+    /// The host decorator to notify of the return value, for a frame
+    /// running a decorated function.
+    after_return: Option<Box<(HostDecorator, DecoratedCall)>>,
+    /// The generator this frame belongs to, while it is being resumed.
+    generator: Option<GeneratorValue>,
+}
+
+impl CallFrame {
+    /// Create the frame for a call to `func` with the given bound locals.
+    ///
+    /// Parameters that nested functions capture are moved out of `locals`
+    /// into fresh cells, and the function's captured closure cells are
+    /// attached under their free variable names.
+    fn for_function(
+        func: &FunctionDef,
+        mut locals: HashMap<String, PyValue>,
+        stack_base: usize,
+        exc_base: usize,
+    ) -> Self {
+        let mut cells = HashMap::new();
+        for name in &func.cellvars {
+            cells.insert(name.clone(), Cell::new(locals.remove(name)));
+        }
+        for (name, cell) in func.freevars.iter().zip(&func.closure) {
+            cells.insert(name.clone(), cell.clone());
+        }
+        CallFrame {
+            code: func.code.clone(),
+            ip: 0,
+            locals,
+            stack_base,
+            iterators: Vec::new(),
+            cells,
+            is_function: true,
+            exc_base,
+            return_override: None,
+            after_return: None,
+            generator: None,
+        }
+    }
 }
 
 /// The bytecode virtual machine.
@@ -112,7 +206,6 @@ struct CallFrame {
 /// print buffer. A single `Vm` instance is meant to be reused across
 /// multiple `execute()` calls (globals persist between calls, matching
 /// the behaviour of the old `Evaluator`).
-#[derive(Clone)]
 pub struct Vm {
     /// The operand stack shared across all frames.
     stack: Vec<PyValue>,
@@ -131,7 +224,7 @@ pub struct Vm {
     /// Instructions executed so far in the current `execute()` call.
     instruction_count: u64,
     /// Stack of active exceptions (for try/except handling).
-    exception_stack: Vec<ExceptionState>,
+    exception_stack: Vec<ExceptionValue>,
     /// Mounted virtual files (virtual path → mount entry).
     mounts: HashMap<String, MountEntry>,
     /// Mounted directories — files resolved lazily on `open()`.
@@ -140,11 +233,47 @@ pub struct Vm {
     open_files: HashMap<u64, FileState>,
     /// Next file handle id to allocate.
     next_file_handle: u64,
+    /// Number of frames on the frame stacks suspended while a nested one
+    /// runs a generator, a callback, a property getter or `__repr__`, so
+    /// recursion through them counts against the recursion limit.
+    nested_depth: usize,
 }
 
-// We implement Clone manually for the parts that need it, but CallFrame
-// is not Clone (it only exists during execution). The `Clone` on `Vm`
-// covers the persistent state between executions.
+/// A clone covers the persistent state between executions. Globals and
+/// module attributes are deep copied (sharing between them is kept), so a
+/// clone never mutates a list, dict, set or object of the original.
+impl Clone for Vm {
+    fn clone(&self) -> Self {
+        let mut memo = HashMap::new();
+        let mut copy = |value: &PyValue| value.deep_copy_with(&mut memo, &|_| None);
+        let globals = self
+            .globals
+            .iter()
+            .map(|(name, value)| (name.clone(), copy(value)))
+            .collect();
+        let modules = self
+            .modules
+            .iter()
+            .map(|(name, module)| (name.clone(), copy(module)))
+            .collect();
+        Self {
+            stack: self.stack.clone(),
+            globals,
+            tools: self.tools.clone(),
+            modules,
+            print_buffer: self.print_buffer.clone(),
+            instruction_limit: self.instruction_limit,
+            recursion_limit: self.recursion_limit,
+            instruction_count: self.instruction_count,
+            exception_stack: self.exception_stack.clone(),
+            mounts: self.mounts.clone(),
+            dir_mounts: self.dir_mounts.clone(),
+            open_files: self.open_files.clone(),
+            next_file_handle: self.next_file_handle,
+            nested_depth: self.nested_depth,
+        }
+    }
+}
 
 impl Vm {
     /// Create a new, empty VM.
@@ -163,6 +292,7 @@ impl Vm {
             dir_mounts: Vec::new(),
             open_files: HashMap::new(),
             next_file_handle: 0,
+            nested_depth: 0,
         }
     }
 
@@ -191,9 +321,17 @@ impl Vm {
         );
     }
 
+    /// Remove a global variable. Returns whether it was set.
+    pub fn unset_variable(&mut self, name: &str) -> bool {
+        self.globals.remove(name).is_some()
+    }
+
     /// Set a global variable visible to Python code.
+    ///
+    /// The value is copied, so the sandbox never shares a container with
+    /// the host.
     pub fn set_variable(&mut self, name: impl Into<String>, value: PyValue) {
-        self.globals.insert(name.into(), value);
+        self.globals.insert(name.into(), value.deep_copy());
     }
 
     /// Register a module that can be imported from Python code.
@@ -226,7 +364,7 @@ impl Vm {
         virtual_path: String,
         host_path: String,
         writable: bool,
-        content: String,
+        content: Vec<u8>,
     ) {
         self.mounts.insert(
             virtual_path,
@@ -247,12 +385,16 @@ impl Vm {
         });
     }
 
-    /// Get current contents of all writable mounted files.
+    /// Get current contents of all writable mounted files. Binary content
+    /// that is not valid UTF-8 is decoded lossily.
     pub fn get_writable_files(&self) -> HashMap<String, String> {
         self.mounts
             .iter()
             .filter(|(_, entry)| entry.writable)
-            .map(|(path, entry)| (path.clone(), entry.content.clone()))
+            .map(|(path, entry)| {
+                let content = String::from_utf8_lossy(&entry.content).into_owned();
+                (path.clone(), content)
+            })
             .collect()
     }
 
@@ -262,8 +404,10 @@ impl Vm {
     /// or `PyValue::None` if the stack is empty. Global variables set during
     /// execution persist for subsequent calls.
     pub fn execute(&mut self, code: CodeObject) -> Result<PyValue> {
-        // Reset instruction counter for this execution
+        // Reset instruction counter and any exception state left over
+        // from a previous execution that ended in an uncaught error.
         self.instruction_count = 0;
+        self.exception_stack.clear();
 
         // Set up the top-level frame
         let frame = CallFrame {
@@ -272,12 +416,22 @@ impl Vm {
             locals: HashMap::new(),
             stack_base: self.stack.len(),
             iterators: Vec::new(),
+            cells: HashMap::new(),
+            is_function: false,
+            exc_base: 0,
+            return_override: None,
+            after_return: None,
+            generator: None,
         };
 
         let mut frames = vec![frame];
-        let result = self.run(&mut frames)?;
+        let mut result = self.run(&mut frames)?;
 
-        Ok(result)
+        // The host sees a range or iterator as the list of its items
+        if matches!(result, PyValue::Range { .. } | PyValue::Iterator(_)) {
+            result = self.materialize(&mut frames, result)?;
+        }
+        Ok(records_to_dicts(result))
     }
 
     // -----------------------------------------------------------------------
@@ -314,12 +468,7 @@ impl Vm {
             frames.last_mut().unwrap().ip += 1;
 
             // Check instruction limit (uncatchable)
-            self.instruction_count += 1;
-            if let Some(limit) = self.instruction_limit
-                && self.instruction_count > limit
-            {
-                return Err(Error::InstructionLimitExceeded(limit));
-            }
+            self.tick()?;
 
             // Dispatch the instruction, catching errors for exception handling
             let result = self.dispatch_op(op, span, ip, frames);
@@ -359,6 +508,10 @@ impl Vm {
             Op::Pop => {
                 self.stack.pop();
             }
+            Op::DupTwo => {
+                let len = self.stack.len();
+                self.stack.extend_from_within(len - 2..);
+            }
             Op::Dup => {
                 let val = self.stack.last().cloned().unwrap_or(PyValue::None);
                 self.stack.push(val);
@@ -380,28 +533,133 @@ impl Vm {
                     self.stack.push(val.clone());
                 } else if let Some(val) = self.globals.get(name) {
                     self.stack.push(val.clone());
+                } else if let Some(class) = ClassValue::builtin_exception(name) {
+                    self.stack.push(PyValue::Class(Box::new(class)));
+                } else if let Some(ty) = builtin_type_object(name) {
+                    self.stack.push(ty);
                 } else {
                     let name = name.clone();
                     return Err(Error::NameError(name));
                 }
             }
+            Op::LoadFast(i) => {
+                let frame = frames.last().unwrap();
+                let name = &frame.code.names[i as usize];
+                match frame.locals.get(name) {
+                    Some(val) => self.stack.push(val.clone()),
+                    None => {
+                        return Err(Error::exception(
+                            "UnboundLocalError",
+                            format!(
+                                "cannot access local variable '{}' where it is not associated with a value",
+                                name
+                            ),
+                        ));
+                    }
+                }
+            }
             Op::StoreName(i) => {
                 let name = frames.last().unwrap().code.names[i as usize].clone();
                 let val = self.stack.pop().unwrap_or(PyValue::None);
-                if frames.len() > 1 {
+                let frame = frames.last_mut().unwrap();
+                if frame.is_function {
                     // Inside a function: store in locals
-                    frames.last_mut().unwrap().locals.insert(name, val);
+                    frame.locals.insert(name, val);
                 } else {
                     // Top-level: store in globals
                     self.globals.insert(name, val);
                 }
             }
+            Op::LoadGlobal(i) => {
+                let name = &frames.last().unwrap().code.names[i as usize];
+                if let Some(val) = self.globals.get(name) {
+                    self.stack.push(val.clone());
+                } else if let Some(class) = ClassValue::builtin_exception(name) {
+                    self.stack.push(PyValue::Class(Box::new(class)));
+                } else if let Some(ty) = builtin_type_object(name) {
+                    self.stack.push(ty);
+                } else {
+                    return Err(Error::NameError(name.clone()));
+                }
+            }
+            Op::StoreGlobal(i) => {
+                let name = frames.last().unwrap().code.names[i as usize].clone();
+                let val = self.stack.pop().unwrap_or(PyValue::None);
+                self.globals.insert(name, val);
+            }
+            Op::DeleteName(i) => {
+                let frame = frames.last_mut().unwrap();
+                let name = &frame.code.names[i as usize];
+                let removed = if frame.is_function {
+                    frame.locals.remove(name)
+                } else {
+                    self.globals.remove(name)
+                };
+                if removed.is_none() {
+                    return Err(Error::NameError(name.clone()));
+                }
+            }
+            Op::DeleteGlobal(i) => {
+                let name = &frames.last().unwrap().code.names[i as usize];
+                if self.globals.remove(name).is_none() {
+                    return Err(Error::NameError(name.clone()));
+                }
+            }
+            Op::DeleteDeref(i) => {
+                let frame = frames.last().unwrap();
+                let name = &frame.code.names[i as usize];
+                let removed = frame
+                    .cells
+                    .get(name)
+                    .and_then(|cell| cell.with_mut(Option::take));
+                if removed.is_none() {
+                    return Err(Error::NameError(name.clone()));
+                }
+            }
+            Op::LoadDeref(i) => {
+                let frame = frames.last().unwrap();
+                let name = &frame.code.names[i as usize];
+                match frame.cells.get(name).and_then(Cell::get) {
+                    Some(val) => self.stack.push(val),
+                    None => return Err(Error::NameError(name.clone())),
+                }
+            }
+            Op::StoreDeref(i) => {
+                let val = self.stack.pop().unwrap_or(PyValue::None);
+                let frame = frames.last_mut().unwrap();
+                let name = &frame.code.names[i as usize];
+                match frame.cells.get(name) {
+                    Some(cell) => cell.set(val),
+                    None => {
+                        let cell = Cell::new(Some(val));
+                        frame.cells.insert(name.clone(), cell);
+                    }
+                }
+            }
 
             // --- Operators ---
             Op::BinaryOp(binop) => {
                 let right = self.stack.pop().unwrap_or(PyValue::None);
                 let left = self.stack.pop().unwrap_or(PyValue::None);
-                let result = apply_binop(&binop, &left, &right)?;
+                let result = match (&binop, &left) {
+                    // `%s` of an instance uses its `__str__`
+                    (BinOp::Mod, PyValue::Str(template)) if contains_instance(&right) => {
+                        PyValue::Str(format::percent_format(
+                            template,
+                            &right,
+                            &mut |value, conversion| {
+                                self.format_field(frames, value, conversion, "")
+                            },
+                        )?)
+                    }
+                    _ => apply_binop(&binop, &left, &right)?,
+                };
+                self.stack.push(result);
+            }
+            Op::InplaceOp(binop) => {
+                let right = self.stack.pop().unwrap_or(PyValue::None);
+                let left = self.stack.pop().unwrap_or(PyValue::None);
+                let result = apply_inplace_op(&binop, &left, &right)?;
                 self.stack.push(result);
             }
             Op::UnaryOp(unary) => {
@@ -412,7 +670,10 @@ impl Vm {
             Op::CompareOp(cmpop) => {
                 let right = self.stack.pop().unwrap_or(PyValue::None);
                 let left = self.stack.pop().unwrap_or(PyValue::None);
-                let result = apply_cmpop(&cmpop, &left, &right)?;
+                let result = match self.call_eq(frames, &cmpop, &left, &right)? {
+                    Some(result) => result,
+                    None => apply_cmpop(&cmpop, &left, &right)?,
+                };
                 self.stack.push(PyValue::Bool(result));
             }
 
@@ -455,7 +716,7 @@ impl Vm {
             Op::BuildList(n) => {
                 let start = self.stack.len() - n as usize;
                 let items: Vec<PyValue> = self.stack.drain(start..).collect();
-                self.stack.push(PyValue::List(items));
+                self.stack.push(PyValue::list(items));
             }
             Op::BuildTuple(n) => {
                 let start = self.stack.len() - n as usize;
@@ -465,35 +726,48 @@ impl Vm {
             Op::BuildSet(n) => {
                 let start = self.stack.len() - n as usize;
                 let raw: Vec<PyValue> = self.stack.drain(start..).collect();
-                let mut items = Vec::with_capacity(n as usize);
-                for elem in raw {
-                    if !elem.is_hashable() {
-                        return Err(Error::Runtime(format!(
-                            "TypeError: unhashable type: '{}'",
-                            elem.type_name()
-                        )));
-                    }
-                    if !items.contains(&elem) {
-                        items.push(elem);
-                    }
-                }
-                self.stack.push(PyValue::Set(items));
+                self.stack.push(build_set(raw)?);
             }
             Op::BuildDict(n) => {
                 let start = self.stack.len() - (n as usize * 2);
                 let raw: Vec<PyValue> = self.stack.drain(start..).collect();
-                let mut pairs = Vec::with_capacity(n as usize);
+                let mut dict = Dict::with_capacity(n as usize);
                 for chunk in raw.chunks(2) {
-                    let key = chunk[0].clone();
-                    if !key.is_hashable() {
-                        return Err(Error::Runtime(format!(
-                            "TypeError: unhashable type: '{}'",
-                            key.type_name()
-                        )));
+                    check_hashable(&chunk[0])?;
+                    dict.insert(chunk[0].clone(), chunk[1].clone());
+                }
+                self.stack.push(dict.into());
+            }
+            Op::ListExtend => {
+                let iterable = self.stack.pop().unwrap_or(PyValue::None);
+                let items = self.unpack_items(frames, iterable)?;
+                if let Some(PyValue::List(list)) = self.stack.last() {
+                    list.write(|list| list.extend(items));
+                }
+            }
+            Op::DictUpdate => {
+                let mapping = self.stack.pop().unwrap_or(PyValue::None);
+                let PyValue::Dict(source) = &mapping else {
+                    return Err(Error::exception(
+                        "TypeError",
+                        format!("'{}' object is not a mapping", mapping.type_name()),
+                    ));
+                };
+                if let Some(PyValue::Dict(dict)) = self.stack.last() {
+                    for (key, value) in source.read().iter() {
+                        methods::dict_set_item(dict, key.clone(), value.clone());
                     }
-                    pairs.push((key, chunk[1].clone()));
                 }
-                self.stack.push(PyValue::Dict(pairs));
+            }
+            Op::ListToTuple => {
+                if let Some(PyValue::List(list)) = self.stack.pop() {
+                    self.stack.push(PyValue::Tuple(list.read().to_vec()));
+                }
+            }
+            Op::ListToSet => {
+                if let Some(PyValue::List(list)) = self.stack.pop() {
+                    self.stack.push(build_set(list.read().to_vec())?);
+                }
             }
 
             // --- Subscript ---
@@ -503,11 +777,16 @@ impl Vm {
                 let result = self.subscript(&collection, &index)?;
                 self.stack.push(result);
             }
-            Op::StoreSubscript(var_idx) => {
+            Op::StoreSubscript => {
                 let index = self.stack.pop().unwrap_or(PyValue::None);
+                let object = self.stack.pop().unwrap_or(PyValue::None);
                 let value = self.stack.pop().unwrap_or(PyValue::None);
-                let var_name = frames.last().unwrap().code.names[var_idx as usize].clone();
-                self.store_subscript(frames, &var_name, &index, value)?;
+                store_subscript(&object, &index, value)?;
+            }
+            Op::DeleteSubscript => {
+                let index = self.stack.pop().unwrap_or(PyValue::None);
+                let object = self.stack.pop().unwrap_or(PyValue::None);
+                delete_subscript(&object, &index)?;
             }
 
             // --- Slicing ---
@@ -519,26 +798,105 @@ impl Vm {
                 let result = self.apply_slice(&obj, &start, &stop, &step)?;
                 self.stack.push(result);
             }
+            Op::StoreSlice => {
+                let step = self.stack.pop().unwrap_or(PyValue::None);
+                let stop = self.stack.pop().unwrap_or(PyValue::None);
+                let start = self.stack.pop().unwrap_or(PyValue::None);
+                let obj = self.stack.pop().unwrap_or(PyValue::None);
+                let value = self.stack.pop().unwrap_or(PyValue::None);
+                let bounds = slice::slice_bounds(&start, &stop, &step)?;
+                match &obj {
+                    PyValue::List(list) => {
+                        let values = self.unpack_items(frames, value)?;
+                        slice::assign_slice(list, bounds, values)?;
+                    }
+                    PyValue::ByteArray(data) => {
+                        let values = match value.as_bytes() {
+                            Some(values) => values.into_owned(),
+                            None => self
+                                .unpack_items(frames, value)?
+                                .iter()
+                                .map(bytes::byte_value)
+                                .collect::<Result<_>>()?,
+                        };
+                        slice::assign_slice(data, bounds, values)?;
+                    }
+                    _ => {
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!(
+                                "'{}' object does not support item assignment",
+                                obj.type_name()
+                            ),
+                        ));
+                    }
+                }
+            }
+            Op::DeleteSlice => {
+                let step = self.stack.pop().unwrap_or(PyValue::None);
+                let stop = self.stack.pop().unwrap_or(PyValue::None);
+                let start = self.stack.pop().unwrap_or(PyValue::None);
+                let obj = self.stack.pop().unwrap_or(PyValue::None);
+                let bounds = slice::slice_bounds(&start, &stop, &step)?;
+                match &obj {
+                    PyValue::List(list) => slice::delete_slice(list, bounds),
+                    PyValue::ByteArray(data) => slice::delete_slice(data, bounds),
+                    _ => {
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!(
+                                "'{}' object does not support item deletion",
+                                obj.type_name()
+                            ),
+                        ));
+                    }
+                }
+            }
 
             // --- Unpacking ---
             Op::UnpackSequence(n) => {
                 let val = self.stack.pop().unwrap_or(PyValue::None);
-                let items = match val {
-                    PyValue::List(items) | PyValue::Tuple(items) => items,
-                    _ => {
-                        return Err(Error::Type {
-                            expected: "sequence".to_string(),
-                            got: val.type_name().to_string(),
-                        });
-                    }
-                };
-                if items.len() != n as usize {
-                    return Err(Error::Runtime(format!(
-                        "cannot unpack: expected {} values, got {}",
-                        n,
-                        items.len()
-                    )));
+                let items = self.unpack_items(frames, val)?;
+                if items.len() < n as usize {
+                    return Err(Error::exception(
+                        "ValueError",
+                        format!(
+                            "not enough values to unpack (expected {}, got {})",
+                            n,
+                            items.len()
+                        ),
+                    ));
+                }
+                if items.len() > n as usize {
+                    return Err(Error::exception(
+                        "ValueError",
+                        format!("too many values to unpack (expected {})", n),
+                    ));
+                }
+                for item in items.into_iter().rev() {
+                    self.stack.push(item);
+                }
+            }
+            Op::UnpackStarred(before, after) => {
+                let val = self.stack.pop().unwrap_or(PyValue::None);
+                let mut items = self.unpack_items(frames, val)?;
+                let (before, after) = (before as usize, after as usize);
+                if items.len() < before + after {
+                    return Err(Error::exception(
+                        "ValueError",
+                        format!(
+                            "not enough values to unpack (expected at least {}, got {})",
+                            before + after,
+                            items.len()
+                        ),
+                    ));
+                }
+                let tail = items.split_off(items.len() - after);
+                let rest = items.split_off(before);
+                for item in tail.into_iter().rev() {
+                    self.stack.push(item);
                 }
+                self.stack.push(PyValue::list(rest));
                 for item in items.into_iter().rev() {
                     self.stack.push(item);
                 }
@@ -547,38 +905,31 @@ impl Vm {
             // --- Iteration ---
             Op::GetIter => {
                 let val = self.stack.pop().unwrap_or(PyValue::None);
-                let items = match val {
-                    PyValue::List(items) | PyValue::Tuple(items) | PyValue::Set(items) => items,
-                    PyValue::Dict(pairs) => pairs.into_iter().map(|(k, _)| k).collect(),
-                    PyValue::Str(s) => s.chars().map(|c| PyValue::Str(c.to_string())).collect(),
-                    other => {
-                        return Err(Error::Type {
-                            expected: "iterable".to_string(),
-                            got: other.type_name().to_string(),
-                        });
-                    }
-                };
-                frames
-                    .last_mut()
-                    .unwrap()
-                    .iterators
-                    .push(IterState { items, index: 0 });
+                let iter = IterState::new(val)?;
+                frames.last_mut().unwrap().iterators.push(iter);
             }
             Op::ForIter(target) => {
-                let frame = frames.last_mut().unwrap();
-                let iter = frame.iterators.last_mut().unwrap();
-                if iter.index < iter.items.len() {
-                    let item = iter.items[iter.index].clone();
-                    iter.index += 1;
-                    self.stack.push(item);
-                } else {
-                    frame.iterators.pop();
-                    frame.ip = target as usize;
+                // Advancing may run a `map()` function, which needs the
+                // frame stack, so the iterator is taken out meanwhile.
+                let mut iter = frames.last_mut().unwrap().iterators.pop().unwrap();
+                match self.iter_next(frames, &mut iter)? {
+                    Some(item) => {
+                        frames.last_mut().unwrap().iterators.push(iter);
+                        self.stack.push(item);
+                    }
+                    None => frames.last_mut().unwrap().ip = target as usize,
                 }
             }
             Op::PopIter => {
                 frames.last_mut().unwrap().iterators.pop();
             }
+            Op::YieldFromResult => {
+                let value = match self.stack.pop() {
+                    Some(PyValue::Generator(generator)) => generator.take_return_value(),
+                    _ => PyValue::None,
+                };
+                self.stack.push(value);
+            }
 
             // --- Function calls ---
             Op::CallFunction(name_idx, n_args) => {
@@ -591,17 +942,15 @@ impl Vm {
             }
             Op::CallMethod(method_idx, n_args) => {
                 let method = frames.last().unwrap().code.names[method_idx as usize].clone();
-                self.call_method(frames, &method, n_args as usize)?;
+                self.call_method(frames, &method, n_args as usize, 0)?;
             }
-            Op::CallMutMethod(var_idx, method_idx, n_args) => {
-                let var_name = frames.last().unwrap().code.names[var_idx as usize].clone();
+            Op::CallMethodKw(method_idx, n_pos, n_kw) => {
                 let method = frames.last().unwrap().code.names[method_idx as usize].clone();
-                self.call_mut_method(frames, &var_name, &method, n_args as usize)?;
+                self.call_method(frames, &method, n_pos as usize, n_kw as usize)?;
             }
-            Op::CallMutMethodKw(var_idx, method_idx, n_pos, n_kw) => {
-                let var_name = frames.last().unwrap().code.names[var_idx as usize].clone();
+            Op::CallSuperMethod(method_idx, n_pos, n_kw) => {
                 let method = frames.last().unwrap().code.names[method_idx as usize].clone();
-                self.call_mut_method_kw(frames, &var_name, &method, n_pos as usize, n_kw as usize)?;
+                self.call_super_method(frames, &method, n_pos as usize, n_kw as usize)?;
             }
             Op::CallValue(n_args) => {
                 self.call_value(frames, n_args as usize, 0)?;
@@ -609,11 +958,37 @@ impl Vm {
             Op::CallValueKw(n_pos, n_kw) => {
                 self.call_value(frames, n_pos as usize, n_kw as usize)?;
             }
+            Op::CallFunctionEx(name_idx) => {
+                let name = frames.last().unwrap().code.names[name_idx as usize].clone();
+                let (n_pos, n_kw) = self.spread_call_args()?;
+                self.call_function(frames, &name, n_pos, n_kw, span)?;
+            }
+            Op::CallMethodEx(method_idx) => {
+                let method = frames.last().unwrap().code.names[method_idx as usize].clone();
+                let (n_pos, n_kw) = self.spread_call_args()?;
+                self.call_method(frames, &method, n_pos, n_kw)?;
+            }
+            Op::CallSuperMethodEx(method_idx) => {
+                let method = frames.last().unwrap().code.names[method_idx as usize].clone();
+                let (n_pos, n_kw) = self.spread_call_args()?;
+                self.call_super_method(frames, &method, n_pos, n_kw)?;
+            }
+            Op::CallValueEx => {
+                let (n_pos, n_kw) = self.spread_call_args()?;
+                self.call_value(frames, n_pos, n_kw)?;
+            }
 
             // --- F-strings ---
-            Op::FormatValue => {
-                let val = self.stack.pop().unwrap_or(PyValue::None);
-                self.stack.push(PyValue::Str(val.to_print_string()));
+            Op::FormatValue(conversion, has_spec) => {
+                let spec = if has_spec {
+                    self.stack.pop().unwrap_or(PyValue::None)
+                } else {
+                    PyValue::Str(String::new())
+                };
+                let value = self.stack.pop().unwrap_or(PyValue::None);
+                let spec = spec.as_str().unwrap_or_default();
+                let text = self.format_field(frames, &value, conversion, spec)?;
+                self.stack.push(PyValue::Str(text));
             }
             Op::BuildString(n) => {
                 let start = self.stack.len() - n as usize;
@@ -630,14 +1005,33 @@ impl Vm {
             }
 
             // --- Function definitions ---
-            Op::MakeFunction(i) => {
-                let func_def = frames.last().unwrap().code.functions[i as usize].clone();
+            Op::MakeFunction(i, n_defaults, n_kw_defaults) => {
+                let mut func_def = frames.last().unwrap().code.functions[i as usize].clone();
+                (func_def.defaults, func_def.kw_defaults) =
+                    self.pop_call_args(n_defaults as usize, n_kw_defaults as usize)?;
+                self.stack.push(PyValue::Function(Box::new(func_def)));
+            }
+            Op::MakeClosure(i, n_defaults, n_kw_defaults) => {
+                let frame = frames.last_mut().unwrap();
+                let mut func_def = frame.code.functions[i as usize].clone();
+                (func_def.defaults, func_def.kw_defaults) =
+                    self.pop_call_args(n_defaults as usize, n_kw_defaults as usize)?;
+                func_def.closure = func_def
+                    .freevars
+                    .iter()
+                    .map(|name| frame.cells.entry(name.clone()).or_default().clone())
+                    .collect();
                 self.stack.push(PyValue::Function(Box::new(func_def)));
             }
             Op::ReturnValue => {
                 let retval = self.stack.pop().unwrap_or(PyValue::None);
                 let finished = frames.pop().unwrap();
                 self.stack.truncate(finished.stack_base);
+                let retval = finished.return_override.unwrap_or(retval);
+                if let Some(hook) = finished.after_return {
+                    let (hooks, call) = *hook;
+                    hooks.run_after(&call, &retval);
+                }
                 if frames.is_empty() {
                     // Returning from top-level — push the value back so
                     // the run() loop can return it via end_frame or TOS
@@ -646,6 +1040,26 @@ impl Vm {
                 }
                 self.stack.push(retval);
             }
+            Op::YieldValue => {
+                let value = self.stack.pop().unwrap_or(PyValue::None);
+                // A generator frame is always resumed on its own frame
+                // stack, so popping it hands control back to the consumer.
+                let mut frame = frames.pop().unwrap();
+                let generator = frame
+                    .generator
+                    .take()
+                    .expect("yield outside a generator frame");
+                let stack = self.stack.split_off(frame.stack_base);
+                let exc_base = frame.exc_base.min(self.exception_stack.len());
+                let exceptions = self.exception_stack.split_off(exc_base);
+                generator.suspend(Box::new(SuspendedFrame {
+                    frame,
+                    stack,
+                    exceptions,
+                    started: true,
+                }));
+                self.stack.push(value);
+            }
 
             // --- Imports ---
             Op::ImportModule(name_idx) => {
@@ -653,155 +1067,732 @@ impl Vm {
                 if let Some(module) = self.modules.get(&name) {
                     self.stack.push(module.clone());
                 } else {
-                    return Err(Error::Runtime(format!(
-                        "ModuleNotFoundError: No module named '{}'",
-                        name
-                    )));
+                    return Err(Error::exception(
+                        "ModuleNotFoundError",
+                        format!("No module named '{}'", name),
+                    ));
                 }
             }
             Op::LoadAttr(attr_idx) => {
                 let attr_name = frames.last().unwrap().code.names[attr_idx as usize].clone();
                 let obj = self.stack.pop().unwrap_or(PyValue::None);
-                match &obj {
-                    PyValue::Module { name, attrs } => {
-                        if let Some((_, val)) = attrs.iter().find(|(k, _)| k == &attr_name) {
-                            self.stack.push(val.clone());
-                        } else {
-                            return Err(Error::Runtime(format!(
-                                "AttributeError: module '{}' has no attribute '{}'",
-                                name, attr_name
-                            )));
-                        }
-                    }
-                    _ => {
-                        return Err(Error::Runtime(format!(
-                            "AttributeError: '{}' object has no attribute '{}'",
-                            obj.type_name(),
-                            attr_name
-                        )));
-                    }
-                }
+                let value = self.load_attr(frames, &obj, &attr_name)?;
+                self.stack.push(value);
+            }
+            Op::StoreAttr(attr_idx) => {
+                let attr_name = &frames.last().unwrap().code.names[attr_idx as usize];
+                let obj = self.stack.pop().unwrap_or(PyValue::None);
+                let value = self.stack.pop().unwrap_or(PyValue::None);
+                store_attr(&obj, attr_name, value)?;
             }
 
             // --- Exception handling ---
             Op::Raise => {
-                let message = self.stack.pop().unwrap_or(PyValue::None);
-                let exc_type = self.stack.pop().unwrap_or(PyValue::None);
-                let type_name = match &exc_type {
-                    PyValue::Str(s) => s.clone(),
-                    _ => "Exception".to_string(),
-                };
-                let msg = match &message {
-                    PyValue::Str(s) => s.clone(),
-                    PyValue::None => type_name.clone(),
-                    other => other.to_print_string(),
+                let exc = self.stack.pop().unwrap_or(PyValue::None);
+                return Err(into_exception(exc)?.into());
+            }
+            Op::RaiseFrom => {
+                let cause = self.stack.pop().unwrap_or(PyValue::None);
+                let mut exc = into_exception(self.stack.pop().unwrap_or(PyValue::None))?;
+                exc.cause = match cause {
+                    PyValue::None => None,
+                    cause => Some(Box::new(into_exception(cause)?)),
                 };
-                return Err(Error::Runtime(format!("{}: {}", type_name, msg)));
+                exc.suppress_context = true;
+                return Err(exc.into());
             }
             Op::Reraise => {
                 if let Some(exc) = self.exception_stack.last() {
-                    return Err(Error::Runtime(format!(
-                        "{}: {}",
-                        exc.exception_type, exc.message
-                    )));
+                    return Err(exc.clone().into());
                 }
-                return Err(Error::Runtime(
-                    "No active exception to re-raise".to_string(),
-                ));
+                return Err(ExceptionValue::with_message(
+                    "RuntimeError",
+                    "No active exception to reraise",
+                )
+                .into());
             }
             Op::CheckExcMatch => {
-                let type_name = self.stack.pop().unwrap_or(PyValue::None);
-                let expected = match &type_name {
-                    PyValue::Str(s) => s.as_str(),
-                    _ => "Exception",
-                };
-                let matches = if let Some(exc) = self.exception_stack.last() {
-                    exception_matches(&exc.exception_type, expected)
-                } else {
-                    false
+                let expected = self.stack.pop().unwrap_or(PyValue::None);
+                let matches = match self.exception_stack.last() {
+                    Some(exc) => exception_matches(exc, &expected)?,
+                    None => false,
                 };
                 self.stack.push(PyValue::Bool(matches));
             }
+            Op::LoadException => {
+                let exc =
+                    self.exception_stack.last().cloned().ok_or_else(|| {
+                        Error::Runtime("No active exception to reraise".to_string())
+                    })?;
+                self.stack.push(PyValue::Exception(Box::new(exc)));
+            }
+
+            // --- Classes ---
+            Op::MakeClass(name_idx, n_attrs) => {
+                let start = self.stack.len() - 2 * n_attrs as usize;
+                let mut attrs = Vec::with_capacity(n_attrs as usize);
+                let mut items = self.stack.drain(start..);
+                while let (Some(name), Some(value)) = (items.next(), items.next()) {
+                    if let PyValue::Str(name) = name {
+                        attrs.push((name, value));
+                    }
+                }
+                drop(items);
+                let base = self.stack.pop().unwrap_or(PyValue::None);
+                let base = (!matches!(base, PyValue::None)).then_some(&base);
+                let name = &frames.last().unwrap().code.names[name_idx as usize];
+                let class = ClassValue::new(name, base, attrs)?;
+                self.stack.push(PyValue::Class(Box::new(class)));
+            }
             Op::PopException => {
                 self.exception_stack.pop();
             }
 
             // --- Misc ---
             Op::Nop => {}
+
+            // --- Pattern matching ---
+            Op::MatchSequence => {
+                let subject = self.stack.pop().unwrap_or(PyValue::None);
+                let is_sequence = matches!(
+                    subject.tuple_view().as_ref(),
+                    PyValue::List(_) | PyValue::Tuple(_)
+                );
+                self.stack.push(PyValue::Bool(is_sequence));
+            }
+            Op::GetLen => {
+                let subject = self.stack.pop().unwrap_or(PyValue::None);
+                let len = match subject.tuple_view().as_ref() {
+                    PyValue::List(items) => items.read().len(),
+                    PyValue::Tuple(items) => items.len(),
+                    other => {
+                        return Err(Error::Type {
+                            expected: "sequence".to_string(),
+                            got: other.type_name().to_string(),
+                        });
+                    }
+                };
+                self.stack.push(PyValue::Int(len as i64));
+            }
+            Op::MatchKeys => {
+                let keys = self.stack.pop().unwrap_or(PyValue::None);
+                let subject = self.stack.pop().unwrap_or(PyValue::None);
+                self.stack.push(match_keys(&subject, &keys));
+            }
+            Op::MatchClass(n_pos) => {
+                let kw_names = self.stack.pop().unwrap_or(PyValue::None);
+                let class = self.stack.pop().unwrap_or(PyValue::None);
+                let subject = self.stack.pop().unwrap_or(PyValue::None);
+                let result =
+                    self.match_class(frames, &subject, &class, n_pos as usize, &kw_names)?;
+                self.stack.push(result);
+            }
+            Op::CopyDictWithoutKeys => {
+                let keys = self.stack.pop().unwrap_or(PyValue::None);
+                let subject = self.stack.pop().unwrap_or(PyValue::None);
+                let (PyValue::Dict(dict), PyValue::Tuple(keys)) = (subject, keys) else {
+                    return Err(Error::Runtime(
+                        "CopyDictWithoutKeys requires a dict and a tuple".to_string(),
+                    ));
+                };
+                let mut rest = (*dict.read()).clone();
+                for key in &keys {
+                    rest.remove(key);
+                }
+                self.stack.push(rest.into());
+            }
         }
         Ok(())
     }
 
     // -----------------------------------------------------------------------
-    // Exception handling
+    // Classes and instances
     // -----------------------------------------------------------------------
 
-    /// Try to find and activate an exception handler for the given error.
+    /// Look up `obj.name`.
     ///
-    /// Walks the call stack from the current frame upward, searching each
-    /// frame's exception table for a handler covering the faulting instruction.
-    /// If found, pushes the exception onto the exception stack, optionally
-    /// binds it to a variable, sets the IP to the handler, and returns `true`.
-    /// Returns `false` if no handler is found.
-    fn handle_exception(
+    /// On instances, instance attributes shadow class attributes; methods
+    /// found on the class are bound to the instance and properties are
+    /// evaluated.
+    fn load_attr(
         &mut self,
-        frames: &mut Vec<CallFrame>,
-        err: &Error,
-        fault_ip: usize,
-    ) -> Result<bool> {
-        let exc_type = error_to_exception_type(err);
-        let message = err.to_string();
-        let mut is_first_frame = true;
-
-        while !frames.is_empty() {
-            let ip_to_check = if is_first_frame {
-                is_first_frame = false;
-                fault_ip
-            } else {
-                // For caller frames, the IP points past the call instruction
-                frames.last().unwrap().ip.saturating_sub(1)
-            };
-
-            let handler = find_handler(&frames.last().unwrap().code.exception_table, ip_to_check);
-
-            if let Some(entry) = handler {
-                let handler_target = entry.handler;
-                let var_name_idx = entry.var_name;
-                let n_frames = frames.len();
-
-                // Read stack_base and var name before taking mutable ref
-                let stack_base = frames.last().unwrap().stack_base;
-                let var_info =
-                    var_name_idx.map(|idx| frames.last().unwrap().code.names[idx as usize].clone());
-
-                // Clean up the stack back to this frame's base
-                self.stack.truncate(stack_base);
-
-                // Push exception state
-                self.exception_stack.push(ExceptionState {
-                    exception_type: exc_type.to_string(),
-                    message: message.clone(),
-                });
-
-                // Optionally bind exception message to a variable
-                if let Some(var) = var_info {
-                    if n_frames > 1 {
-                        frames
-                            .last_mut()
-                            .unwrap()
-                            .locals
-                            .insert(var, PyValue::Str(message.clone()));
-                    } else {
-                        self.globals.insert(var, PyValue::Str(message.clone()));
+        frames: &mut [CallFrame],
+        obj: &PyValue,
+        name: &str,
+    ) -> Result<PyValue> {
+        match obj {
+            PyValue::Module {
+                name: module,
+                attrs,
+            } => attrs
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+                .ok_or_else(|| {
+                    Error::exception(
+                        "AttributeError",
+                        format!("module '{}' has no attribute '{}'", module, name),
+                    )
+                }),
+            PyValue::Exception(exc) if exc.get_attr(name).is_some() => {
+                Ok(exc.get_attr(name).unwrap())
+            }
+            PyValue::Instance(instance) => {
+                if let Some(value) = instance.attrs.get(name) {
+                    return Ok(value);
+                }
+                match instance.class.lookup(name) {
+                    Some(PyValue::Function(func)) => Ok(PyValue::BoundMethod {
+                        receiver: Box::new(obj.clone()),
+                        func,
+                    }),
+                    Some(PyValue::Property(getter)) => {
+                        self.invoke_sync(&getter, vec![obj.clone()], frames)
+                    }
+                    Some(PyValue::StaticMethod(func)) => Ok(PyValue::Function(func)),
+                    Some(PyValue::ClassMethod(func)) => Ok(PyValue::BoundMethod {
+                        receiver: Box::new(PyValue::Class(Box::new(instance.class.clone()))),
+                        func,
+                    }),
+                    Some(value) => Ok(value),
+                    None if name == "__class__" => {
+                        Ok(PyValue::Class(Box::new(instance.class.clone())))
                     }
+                    None if name == "_fields" && instance.tuple_items().is_some() => {
+                        let fields = instance.class.fields.iter().cloned();
+                        Ok(PyValue::Tuple(fields.map(PyValue::Str).collect()))
+                    }
+                    None => Err(Error::exception(
+                        "AttributeError",
+                        format!(
+                            "'{}' object has no attribute '{}'",
+                            instance.class.name, name
+                        ),
+                    )),
                 }
-
-                // Jump to handler
-                frames.last_mut().unwrap().ip = handler_target as usize;
-                return Ok(true);
             }
-
-            // No handler in this frame — pop it and try the caller
+            PyValue::Class(class) => match class.lookup(name) {
+                Some(PyValue::StaticMethod(func)) => Ok(PyValue::Function(func)),
+                Some(PyValue::ClassMethod(func)) => Ok(PyValue::BoundMethod {
+                    receiver: Box::new(obj.clone()),
+                    func,
+                }),
+                Some(value) => Ok(value),
+                None if name == "__name__" => Ok(PyValue::Str(class.name.clone())),
+                None if name == "_fields" && class.record == Some(RecordKind::NamedTuple) => {
+                    let fields = class.fields.iter().cloned();
+                    Ok(PyValue::Tuple(fields.map(PyValue::Str).collect()))
+                }
+                None => Err(Error::exception(
+                    "AttributeError",
+                    format!("type object '{}' has no attribute '{}'", class.name, name),
+                )),
+            },
+            PyValue::Function(_)
+            | PyValue::BoundMethod { .. }
+            | PyValue::NativeFunction(_)
+            | PyValue::MethodDescriptor { .. }
+            | PyValue::Decorator(_)
+                if matches!(name, "__name__" | "__doc__") =>
+            {
+                Ok(match name {
+                    "__name__" => PyValue::Str(callable_name(obj)),
+                    _ => callable_doc(obj).map_or(PyValue::None, PyValue::Str),
+                })
+            }
+            PyValue::Type(type_name) => match name {
+                "__name__" => Ok(PyValue::Str(type_name.clone())),
+                _ => methods::method_descriptor(type_name, name).ok_or_else(|| {
+                    Error::exception(
+                        "AttributeError",
+                        format!("type object '{}' has no attribute '{}'", type_name, name),
+                    )
+                }),
+            },
+            _ => Err(Error::exception(
+                "AttributeError",
+                format!("'{}' object has no attribute '{}'", obj.type_name(), name),
+            )),
+        }
+    }
+
+    /// Call a class: create an exception, or create an instance and run
+    /// its `__init__`.
+    fn call_class(
+        &mut self,
+        frames: &mut Vec<CallFrame>,
+        class: ClassValue,
+        mut args: Vec<PyValue>,
+        kw_pairs: Vec<(String, PyValue)>,
+    ) -> Result<()> {
+        if class.is_exception() {
+            if let Some((key, _)) = kw_pairs.first() {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!(
+                        "{}() got an unexpected keyword argument '{}'",
+                        class.name, key
+                    ),
+                ));
+            }
+            let exc = class.new_exception(args);
+            self.stack.push(PyValue::Exception(Box::new(exc)));
+            return Ok(());
+        }
+
+        // Records get a generated `__init__` unless a dataclass defines its own
+        let generated_init = match class.record {
+            Some(RecordKind::NamedTuple) => true,
+            Some(RecordKind::Dataclass(_)) => class.attrs.get("__init__").is_none(),
+            None => false,
+        };
+        if generated_init {
+            let instance = InstanceValue::new(class.clone());
+            instance.init_fields(args, kw_pairs)?;
+            let instance = PyValue::Instance(Box::new(instance));
+            match class.lookup("__post_init__") {
+                Some(PyValue::Function(post_init)) => {
+                    let depth = frames.len();
+                    let args = vec![instance.clone()];
+                    self.invoke_function_def(frames, &post_init, "__post_init__", args, vec![])?;
+                    if frames.len() > depth {
+                        frames.last_mut().unwrap().return_override = Some(instance);
+                    }
+                }
+                _ => self.stack.push(instance),
+            }
+            return Ok(());
+        }
+
+        let instance = PyValue::Instance(Box::new(InstanceValue::new(class.clone())));
+        match class.lookup("__init__") {
+            Some(PyValue::Function(init)) => {
+                args.insert(0, instance.clone());
+                let depth = frames.len();
+                self.invoke_function_def(frames, &init, "__init__", args, kw_pairs)?;
+                if frames.len() > depth {
+                    frames.last_mut().unwrap().return_override = Some(instance);
+                }
+                Ok(())
+            }
+            _ if !args.is_empty() || !kw_pairs.is_empty() => Err(Error::exception(
+                "TypeError",
+                format!("{}() takes no arguments", class.name),
+            )),
+            _ => {
+                self.stack.push(instance);
+                Ok(())
+            }
+        }
+    }
+
+    /// Match a class pattern: `Op::MatchClass`.
+    ///
+    /// Returns a tuple of the positional then keyword attribute values, or
+    /// `None` if the subject is not an instance of `class` or lacks one of
+    /// the attributes.
+    fn match_class(
+        &mut self,
+        frames: &mut [CallFrame],
+        subject: &PyValue,
+        class: &PyValue,
+        n_pos: usize,
+        kw_names: &PyValue,
+    ) -> Result<PyValue> {
+        let mut values = Vec::new();
+        match class {
+            // Built-in types match the whole subject positionally
+            PyValue::Type(type_name) => {
+                if !crate::builtins::is_instance_of(subject, type_name) {
+                    return Ok(PyValue::None);
+                }
+                if n_pos > 1 {
+                    return Err(Error::exception(
+                        "TypeError",
+                        format!(
+                            "{}() accepts 1 positional sub-pattern ({} given)",
+                            type_name, n_pos
+                        ),
+                    ));
+                }
+                if n_pos == 1 {
+                    values.push(subject.clone());
+                }
+            }
+            PyValue::Class(class) => {
+                let is_instance = match subject {
+                    PyValue::Instance(instance) => instance.is_instance(&class.name),
+                    PyValue::Exception(exc) => exc.is_instance(&class.name),
+                    _ => false,
+                };
+                if !is_instance {
+                    return Ok(PyValue::None);
+                }
+                if n_pos > 0 {
+                    // Records match their fields positionally
+                    let match_args: Vec<String> = match class.lookup("__match_args__") {
+                        Some(PyValue::Tuple(names)) => names
+                            .iter()
+                            .filter_map(|n| n.as_str().map(str::to_string))
+                            .collect(),
+                        _ => class.fields.clone(),
+                    };
+                    if n_pos > match_args.len() {
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!(
+                                "{}() accepts {} positional sub-patterns ({} given)",
+                                class.name,
+                                match_args.len(),
+                                n_pos
+                            ),
+                        ));
+                    }
+                    for name in &match_args[..n_pos] {
+                        match self.load_attr(frames, subject, name) {
+                            Ok(value) => values.push(value),
+                            Err(err) if is_attribute_error(&err) => return Ok(PyValue::None),
+                            Err(err) => return Err(err),
+                        }
+                    }
+                }
+            }
+            other => {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!(
+                        "called match pattern must be a class, not '{}'",
+                        other.type_name()
+                    ),
+                ));
+            }
+        }
+
+        if let PyValue::Tuple(kw_names) = kw_names {
+            for name in kw_names.iter().filter_map(PyValue::as_str) {
+                match self.load_attr(frames, subject, name) {
+                    Ok(value) => values.push(value),
+                    Err(err) if is_attribute_error(&err) => return Ok(PyValue::None),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(PyValue::Tuple(values))
+    }
+
+    /// Call `super().method(...)`.
+    ///
+    /// Stack: `[self, class_name, args..., kw pairs]`.
+    fn call_super_method(
+        &mut self,
+        frames: &mut Vec<CallFrame>,
+        method: &str,
+        n_pos: usize,
+        n_kw: usize,
+    ) -> Result<()> {
+        let (args, kw_pairs) = self.pop_call_args(n_pos, n_kw)?;
+        let class_name = self.stack.pop().unwrap_or(PyValue::None);
+        let receiver = self.stack.pop().unwrap_or(PyValue::None);
+
+        let PyValue::Instance(instance) = &receiver else {
+            return Err(Error::exception(
+                "TypeError",
+                "super(): self is not an instance of a class",
+            ));
+        };
+        // Find the defining class in the instance's class chain, then
+        // search from its base.
+        let mut class = Some(&instance.class);
+        while let Some(c) = class
+            && Some(c.name.as_str()) != class_name.as_str()
+        {
+            class = c.base.as_deref();
+        }
+        let found = class
+            .and_then(|c| c.base.as_deref())
+            .and_then(|base| base.lookup(method));
+
+        match found {
+            Some(PyValue::Function(func)) => {
+                let bound = PyValue::BoundMethod {
+                    receiver: Box::new(receiver.clone()),
+                    func,
+                };
+                self.call_callable(frames, bound, args, kw_pairs)
+            }
+            Some(PyValue::Property(getter)) => {
+                let value = self.invoke_sync(&getter, vec![receiver.clone()], frames)?;
+                self.stack.push(value);
+                Ok(())
+            }
+            Some(PyValue::StaticMethod(func)) => {
+                self.call_callable(frames, PyValue::Function(func), args, kw_pairs)
+            }
+            Some(PyValue::ClassMethod(func)) => {
+                let bound = PyValue::BoundMethod {
+                    receiver: Box::new(PyValue::Class(Box::new(instance.class.clone()))),
+                    func,
+                };
+                self.call_callable(frames, bound, args, kw_pairs)
+            }
+            Some(other) => self.call_callable(frames, other, args, kw_pairs),
+            // `object.__init__` does nothing
+            None if method == "__init__" && args.is_empty() && kw_pairs.is_empty() => {
+                self.stack.push(PyValue::None);
+                Ok(())
+            }
+            None => Err(Error::exception(
+                "AttributeError",
+                format!("'super' object has no attribute '{}'", method),
+            )),
+        }
+    }
+
+    /// `str(value)` or `repr(value)`, calling `__str__`/`__repr__` on
+    /// instances (including instances nested in containers).
+    fn display_value(
+        &mut self,
+        frames: &mut [CallFrame],
+        value: &PyValue,
+        repr: bool,
+    ) -> Result<String> {
+        if !contains_instance(value) {
+            return Ok(if repr {
+                value.to_string()
+            } else {
+                value.to_print_string()
+            });
+        }
+        let mut join = |vm: &mut Self, items: &[PyValue]| -> Result<Vec<String>> {
+            items
+                .iter()
+                .map(|item| vm.display_value(frames, item, true))
+                .collect()
+        };
+        match value {
+            PyValue::Instance(instance) => {
+                let method = if repr { "__repr__" } else { "__str__" };
+                let func = instance
+                    .class
+                    .lookup(method)
+                    .or_else(|| instance.class.lookup("__repr__"));
+                match func {
+                    Some(PyValue::Function(func)) => {
+                        match self.invoke_sync(&func, vec![value.clone()], frames)? {
+                            PyValue::Str(s) => Ok(s),
+                            other => Err(Error::exception(
+                                "TypeError",
+                                format!(
+                                    "{}() returned non-string (type {})",
+                                    func.name,
+                                    other.type_name()
+                                ),
+                            )),
+                        }
+                    }
+                    _ => match instance.field_values() {
+                        // The generated `__repr__` of a record
+                        Some(values) => {
+                            let values = join(self, &values)?;
+                            let fields = instance.class.fields.iter().zip(values);
+                            let fields: Vec<String> =
+                                fields.map(|(f, v)| format!("{}={}", f, v)).collect();
+                            Ok(format!("{}({})", instance.class.name, fields.join(", ")))
+                        }
+                        None => Ok(value.to_string()),
+                    },
+                }
+            }
+            PyValue::List(items) => {
+                let Some(_guard) = ReprGuard::enter(items.id()) else {
+                    return Ok("[...]".to_string());
+                };
+                Ok(format!("[{}]", join(self, &items.read())?.join(", ")))
+            }
+            PyValue::Tuple(items) => {
+                let inner = join(self, items)?;
+                Ok(match inner.len() {
+                    1 => format!("({},)", inner[0]),
+                    _ => format!("({})", inner.join(", ")),
+                })
+            }
+            PyValue::Set(set) => {
+                let items: Vec<PyValue> = set.read().iter().cloned().collect();
+                Ok(format!("{{{}}}", join(self, &items)?.join(", ")))
+            }
+            PyValue::FrozenSet(set) => {
+                let items: Vec<PyValue> = set.iter().cloned().collect();
+                Ok(format!("frozenset({{{}}})", join(self, &items)?.join(", ")))
+            }
+            PyValue::Dict(pairs) => {
+                let Some(_guard) = ReprGuard::enter(pairs.id()) else {
+                    return Ok("{...}".to_string());
+                };
+                let pairs = pairs.read();
+                let mut inner = Vec::with_capacity(pairs.len());
+                for (k, v) in pairs.iter() {
+                    let k = self.display_value(frames, k, true)?;
+                    let v = self.display_value(frames, v, true)?;
+                    inner.push(format!("{}: {}", k, v));
+                }
+                Ok(format!("{{{}}}", inner.join(", ")))
+            }
+            PyValue::DictView(view) => Ok(format!(
+                "{}([{}])",
+                view.type_name(),
+                join(self, &view.items())?.join(", ")
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Format `value` for an f-string field, `format()` or `str.format`:
+    /// apply the conversion, then the format spec. Instances are shown with
+    /// `__str__`/`__repr__` and accept only an empty spec.
+    fn format_field(
+        &mut self,
+        frames: &mut [CallFrame],
+        value: &PyValue,
+        conversion: Conversion,
+        spec: &str,
+    ) -> Result<String> {
+        let converted;
+        let value = match conversion {
+            Conversion::None => value,
+            _ => {
+                let text = self.display_value(frames, value, conversion != Conversion::Str)?;
+                converted = PyValue::Str(match conversion {
+                    Conversion::Ascii => format::ascii_escape(&text),
+                    _ => text,
+                });
+                &converted
+            }
+        };
+        match value {
+            _ if spec.is_empty() => self.display_value(frames, value, false),
+            PyValue::Instance(instance) => Err(format::unsupported_spec(&instance.class.name)),
+            _ => format::format_value(value, spec),
+        }
+    }
+
+    /// `template.format(*args, **kwargs)`, with `named` looking up keyword
+    /// fields. Format specs may contain fields of their own.
+    fn format_template(
+        &mut self,
+        frames: &mut [CallFrame],
+        template: &str,
+        args: &[PyValue],
+        named: &dyn Fn(&str) -> Option<PyValue>,
+        numbering: &mut format::Numbering,
+    ) -> Result<String> {
+        let mut out = String::new();
+        for piece in format::parse_template(template)? {
+            let field = match piece {
+                Piece::Literal(text) => {
+                    out.push_str(&text);
+                    continue;
+                }
+                Piece::Field(field) => field,
+            };
+            let mut value = match field.arg {
+                FieldArg::Auto | FieldArg::Index(_) => {
+                    let index = match field.arg {
+                        FieldArg::Index(index) => numbering.manual(index)?,
+                        _ => numbering.next()?,
+                    };
+                    args.get(index).cloned().ok_or_else(|| {
+                        Error::exception(
+                            "IndexError",
+                            format!(
+                                "Replacement index {} out of range for positional args tuple",
+                                index
+                            ),
+                        )
+                    })?
+                }
+                FieldArg::Name(name) => named(name).ok_or_else(|| {
+                    Error::from(ExceptionValue::new(
+                        "KeyError",
+                        vec![PyValue::Str(name.to_string())],
+                    ))
+                })?,
+            };
+            for accessor in &field.accessors {
+                value = match accessor {
+                    Accessor::Attr(name) => self.load_attr(frames, &value, name)?,
+                    Accessor::Item(key) => self.subscript(&value, key)?,
+                };
+            }
+            let spec = if field.spec.contains('{') {
+                self.format_template(frames, field.spec, args, named, numbering)?
+            } else {
+                field.spec.to_string()
+            };
+            out.push_str(&self.format_field(frames, &value, field.conversion, &spec)?);
+        }
+        Ok(out)
+    }
+
+    // -----------------------------------------------------------------------
+    // Exception handling
+    // -----------------------------------------------------------------------
+
+    /// Try to find and activate an exception handler for the given error.
+    ///
+    /// Walks the call stack from the current frame upward, searching each
+    /// frame's exception table for a handler covering the faulting instruction.
+    /// If found, pushes the exception onto the exception stack, optionally
+    /// binds it to a variable, sets the IP to the handler, and returns `true`.
+    /// Returns `false` if no handler is found.
+    fn handle_exception(
+        &mut self,
+        frames: &mut Vec<CallFrame>,
+        err: &Error,
+        fault_ip: usize,
+    ) -> Result<bool> {
+        // An exception raised while another is being handled remembers it
+        // as its `__context__`.
+        let mut exc = ExceptionValue::from_error(err);
+        if exc.context.is_none()
+            && let Some(active) = self.exception_stack.last()
+            && *active != exc
+        {
+            exc.context = Some(Box::new(active.clone()));
+        }
+        let mut is_first_frame = true;
+
+        while !frames.is_empty() {
+            let ip_to_check = if is_first_frame {
+                is_first_frame = false;
+                fault_ip
+            } else {
+                // For caller frames, the IP points past the call instruction
+                frames.last().unwrap().ip.saturating_sub(1)
+            };
+
+            let handler = find_handler(&frames.last().unwrap().code.exception_table, ip_to_check);
+
+            if let Some(entry) = handler {
+                let handler_target = entry.handler;
+                let stack_base = frames.last().unwrap().stack_base;
+
+                // Clean up the stack back to this frame's base, and drop the
+                // loop iterators and handled exceptions of any blocks the
+                // exception unwound out of.
+                self.stack.truncate(stack_base);
+                let frame = frames.last_mut().unwrap();
+                frame.iterators.truncate(entry.iter_depth as usize);
+                self.exception_stack
+                    .truncate(frame.exc_base + entry.exc_depth as usize);
+
+                self.exception_stack.push(exc);
+
+                // Jump to handler
+                frames.last_mut().unwrap().ip = handler_target as usize;
+                return Ok(true);
+            }
+
+            // No handler in this frame — pop it and try the caller
             let finished = frames.pop().unwrap();
             self.stack.truncate(finished.stack_base);
         }
@@ -847,27 +1838,24 @@ impl Vm {
         match op {
             UnaryOp::Not => Ok(PyValue::Bool(!operand.is_truthy())),
             UnaryOp::Neg => match operand {
-                PyValue::Int(i) => Ok(PyValue::Int(-i)),
                 PyValue::Float(f) => Ok(PyValue::Float(-f)),
-                _ => Err(Error::Type {
+                _ => int::neg(operand).ok_or_else(|| Error::Type {
                     expected: "number".to_string(),
                     got: operand.type_name().to_string(),
                 }),
             },
             UnaryOp::Pos => match operand {
-                PyValue::Int(_) | PyValue::Float(_) => Ok(operand.clone()),
+                PyValue::Int(_) | PyValue::BigInt(_) | PyValue::Float(_) => Ok(operand.clone()),
+                PyValue::Bool(b) => Ok(PyValue::Int(*b as i64)),
                 _ => Err(Error::Type {
                     expected: "number".to_string(),
                     got: operand.type_name().to_string(),
                 }),
             },
-            UnaryOp::Invert => match operand {
-                PyValue::Int(i) => Ok(PyValue::Int(!i)),
-                _ => Err(Error::Type {
-                    expected: "int".to_string(),
-                    got: operand.type_name().to_string(),
-                }),
-            },
+            UnaryOp::Invert => int::invert(operand).ok_or_else(|| Error::Type {
+                expected: "int".to_string(),
+                got: operand.type_name().to_string(),
+            }),
         }
     }
 
@@ -877,14 +1865,15 @@ impl Vm {
 
     /// Perform `collection[index]`.
     fn subscript(&self, collection: &PyValue, index: &PyValue) -> Result<PyValue> {
-        match (collection, index) {
+        match (collection.tuple_view().as_ref(), index) {
             (PyValue::List(items), PyValue::Int(idx)) => {
+                let items = items.read();
                 let len = items.len() as i64;
                 let actual = if *idx < 0 { len + idx } else { *idx } as usize;
                 items
                     .get(actual)
                     .cloned()
-                    .ok_or_else(|| Error::Runtime(format!("list index out of range: {}", idx)))
+                    .ok_or_else(|| Error::exception("IndexError", "list index out of range"))
             }
             (PyValue::Tuple(items), PyValue::Int(idx)) => {
                 let len = items.len() as i64;
@@ -892,21 +1881,72 @@ impl Vm {
                 items
                     .get(actual)
                     .cloned()
-                    .ok_or_else(|| Error::Runtime(format!("tuple index out of range: {}", idx)))
+                    .ok_or_else(|| Error::exception("IndexError", "tuple index out of range"))
             }
             (PyValue::Str(s), PyValue::Int(idx)) => {
-                let len = s.len() as i64;
-                let actual = if *idx < 0 { len + idx } else { *idx } as usize;
-                s.chars()
-                    .nth(actual)
-                    .map(|c| PyValue::Str(c.to_string()))
-                    .ok_or_else(|| Error::Runtime(format!("string index out of range: {}", idx)))
+                // Index by code point, counting from the nearer end
+                let c = if *idx < 0 {
+                    usize::try_from(-(idx + 1))
+                        .ok()
+                        .and_then(|i| s.chars().nth_back(i))
+                } else {
+                    s.chars().nth(*idx as usize)
+                };
+                c.map(|c| PyValue::Str(c.to_string())).ok_or_else(|| {
+                    ExceptionValue::new(
+                        "IndexError",
+                        vec![PyValue::Str("string index out of range".to_string())],
+                    )
+                    .into()
+                })
             }
-            (PyValue::Dict(pairs), key) if key.is_hashable() => pairs
-                .iter()
-                .find(|(k, _)| k == key)
-                .map(|(_, v)| v.clone())
-                .ok_or_else(|| Error::Runtime(format!("KeyError: {}", key))),
+            (PyValue::Bytes(_) | PyValue::ByteArray(_), PyValue::Int(idx)) => {
+                let data = collection.as_bytes().unwrap();
+                let len = data.len() as i64;
+                let actual = if *idx < 0 { len + idx } else { *idx };
+                usize::try_from(actual)
+                    .ok()
+                    .and_then(|i| data.get(i))
+                    .map(|&byte| PyValue::Int(byte as i64))
+                    .ok_or_else(|| {
+                        ExceptionValue::new(
+                            "IndexError",
+                            vec![PyValue::Str("index out of range".to_string())],
+                        )
+                        .into()
+                    })
+            }
+            (PyValue::Range { start, stop, step }, PyValue::Int(idx)) => {
+                range_item(*start, *stop, *step, *idx)
+                    .map(PyValue::Int)
+                    .ok_or_else(|| {
+                        ExceptionValue::new(
+                            "IndexError",
+                            vec![PyValue::Str("range object index out of range".to_string())],
+                        )
+                        .into()
+                    })
+            }
+            (PyValue::Dict(dict), key) if key.is_hashable() => dict
+                .read()
+                .get(key)
+                .cloned()
+                .ok_or_else(|| ExceptionValue::new("KeyError", vec![key.clone()]).into()),
+            (
+                PyValue::List(_)
+                | PyValue::Tuple(_)
+                | PyValue::Str(_)
+                | PyValue::Bytes(_)
+                | PyValue::ByteArray(_)
+                | PyValue::Range { .. },
+                PyValue::BigInt(_),
+            ) => Err(ExceptionValue::new(
+                "IndexError",
+                vec![PyValue::Str(
+                    "cannot fit 'int' into an index-sized integer".to_string(),
+                )],
+            )
+            .into()),
             _ => Err(Error::Type {
                 expected: "subscriptable".to_string(),
                 got: collection.type_name().to_string(),
@@ -914,51 +1954,6 @@ impl Vm {
         }
     }
 
-    /// Perform `variable[index] = value`, mutating the variable in place.
-    fn store_subscript(
-        &mut self,
-        frames: &mut [CallFrame],
-        var_name: &str,
-        index: &PyValue,
-        value: PyValue,
-    ) -> Result<()> {
-        let var = self.lookup_var_mut(frames, var_name)?;
-
-        match var {
-            PyValue::List(items) => {
-                let idx = index.as_int().ok_or_else(|| Error::Type {
-                    expected: "int".to_string(),
-                    got: index.type_name().to_string(),
-                })?;
-                let len = items.len() as i64;
-                let actual = if idx < 0 { len + idx } else { idx } as usize;
-                if actual < items.len() {
-                    items[actual] = value;
-                    return Ok(());
-                }
-                Err(Error::Runtime("list index out of range".to_string()))
-            }
-            PyValue::Dict(pairs) => {
-                if !index.is_hashable() {
-                    return Err(Error::Runtime(format!(
-                        "TypeError: unhashable type: '{}'",
-                        index.type_name()
-                    )));
-                }
-                if let Some(existing) = pairs.iter_mut().find(|(k, _)| k == index) {
-                    existing.1 = value;
-                } else {
-                    pairs.push((index.clone(), value));
-                }
-                Ok(())
-            }
-            PyValue::Tuple(_) => Err(Error::Runtime(
-                "TypeError: 'tuple' object does not support item assignment".to_string(),
-            )),
-            _ => Err(Error::Runtime("Cannot assign to subscript".to_string())),
-        }
-    }
-
     // -----------------------------------------------------------------------
     // Slice helper
     // -----------------------------------------------------------------------
@@ -971,31 +1966,23 @@ impl Vm {
         stop: &PyValue,
         step: &PyValue,
     ) -> Result<PyValue> {
-        let to_opt = |v: &PyValue| -> Result<Option<i64>> {
-            match v {
-                PyValue::None => Ok(None),
-                PyValue::Int(i) => Ok(Some(*i)),
-                _ => Err(Error::Type {
-                    expected: "int".to_string(),
-                    got: v.type_name().to_string(),
-                }),
-            }
-        };
-
-        let lower = to_opt(start)?;
-        let upper = to_opt(stop)?;
-        let step_val = to_opt(step)?;
+        let (lower, upper, step_val) = slice::slice_bounds(start, stop, step)?;
 
-        if let Some(s) = step_val
-            && s == 0
-        {
-            return Err(Error::Runtime("slice step cannot be zero".to_string()));
-        }
-
-        match obj {
-            PyValue::List(items) => slice::slice_list(items, lower, upper, step_val),
+        match obj.tuple_view().as_ref() {
+            PyValue::List(items) => slice::slice_list(&items.read(), lower, upper, step_val),
             PyValue::Tuple(items) => slice::slice_tuple(items, lower, upper, step_val),
             PyValue::Str(s) => slice::slice_string(s, lower, upper, step_val),
+            PyValue::Bytes(data) => Ok(PyValue::Bytes(slice::slice_bytes(
+                data,
+                (lower, upper, step_val),
+            ))),
+            PyValue::ByteArray(data) => Ok(PyValue::bytearray(slice::slice_bytes(
+                &data.read(),
+                (lower, upper, step_val),
+            ))),
+            PyValue::Range { start, stop, step } => {
+                slice::slice_range(*start, *stop, *step, lower, upper, step_val)
+            }
             _ => Err(Error::Type {
                 expected: "list, tuple, or str".to_string(),
                 got: obj.type_name().to_string(),
@@ -1020,41 +2007,96 @@ impl Vm {
         n_kw: usize,
         span: Span,
     ) -> Result<()> {
-        // Pop keyword args (name, value pairs) in reverse
-        let mut kw_pairs: Vec<(String, PyValue)> = Vec::with_capacity(n_kw);
-        for _ in 0..n_kw {
-            let value = self.stack.pop().unwrap_or(PyValue::None);
-            let key_val = self.stack.pop().unwrap_or(PyValue::None);
-            let key = match key_val {
-                PyValue::Str(s) => s,
-                _ => {
-                    return Err(Error::Runtime(
-                        "keyword argument name must be a string".to_string(),
-                    ));
-                }
+        let (pos_args, kw_pairs) = self.pop_call_args(n_pos, n_kw)?;
+        self.call_named(frames, name, pos_args, kw_pairs, span)
+    }
+
+    /// Call the function named `name` with already-popped arguments:
+    /// a builtin, a tool, or a callable bound to the name.
+    fn call_named(
+        &mut self,
+        frames: &mut Vec<CallFrame>,
+        name: &str,
+        mut pos_args: Vec<PyValue>,
+        mut kw_pairs: Vec<(String, PyValue)>,
+        span: Span,
+    ) -> Result<()> {
+        let n_kw = kw_pairs.len();
+
+        // Reductions stream over generators and ranges item by item. Other
+        // builtins and tools receive them as lists.
+        if n_kw == 0 && pos_args.first().is_some_and(is_lazy) {
+            let streams = match name {
+                "any" | "all" | "min" | "max" => pos_args.len() == 1,
+                "sum" => pos_args.len() <= 2,
+                _ => false,
             };
-            kw_pairs.push((key, value));
+            if streams {
+                let result = self.builtin_reduce(frames, name, pos_args)?;
+                self.stack.push(result);
+                return Ok(());
+            }
+        }
+        let consumes_iterables = (is_builtin(name) && !INSPECTING_BUILTINS.contains(&name))
+            || name == "sorted"
+            || name == "dict"
+            || self.tools.contains_key(name);
+        if consumes_iterables {
+            pos_args = self.materialize_args(frames, pos_args)?;
+            for (_, value) in &mut kw_pairs {
+                *value = self.materialize(frames, std::mem::replace(value, PyValue::None))?;
+            }
         }
-        kw_pairs.reverse();
-
-        // Pop positional args
-        let start = self.stack.len() - n_pos;
-        let pos_args: Vec<PyValue> = self.stack.drain(start..).collect();
 
-        // 0. Callable-aware builtins (sorted, map, filter, open)
+        // 0. Callable-aware builtins (sorted, open), iterators, and
+        //    conversions that may call `__str__`/`__repr__`
         match name {
+            "str" | "repr"
+                if n_kw == 0 && pos_args.len() == 1 && contains_instance(&pos_args[0]) =>
+            {
+                let text = self.display_value(frames, &pos_args[0], name == "repr")?;
+                self.stack.push(PyValue::Str(text));
+                return Ok(());
+            }
+            "format" if n_kw == 0 && pos_args.first().is_some_and(contains_instance) => {
+                methods::check_args_range("format", &pos_args, 1, 2)?;
+                let spec = match pos_args.get(1) {
+                    Some(spec) => methods::arg_str(spec)?.to_string(),
+                    None => String::new(),
+                };
+                let text = self.format_field(frames, &pos_args[0], Conversion::None, &spec)?;
+                self.stack.push(PyValue::Str(text));
+                return Ok(());
+            }
+            "print" if pos_args.iter().any(contains_instance) => {
+                for arg in &mut pos_args {
+                    let text = self.display_value(frames, arg, false)?;
+                    *arg = PyValue::Str(text);
+                }
+            }
+            "dict" => {
+                let result = builtin_dict(pos_args, kw_pairs)?;
+                self.stack.push(result);
+                return Ok(());
+            }
             "sorted" => {
                 let result = self.builtin_sorted(frames, pos_args, kw_pairs)?;
                 self.stack.push(result);
                 return Ok(());
             }
-            "map" if n_kw == 0 => {
-                let result = self.builtin_map(frames, pos_args)?;
+            "iter" if n_kw == 0 => {
+                methods::check_args("iter", &pos_args, 1)?;
+                let result = builtin_iter(pos_args.pop().unwrap())?;
+                self.stack.push(result);
+                return Ok(());
+            }
+            "next" if n_kw == 0 => {
+                let result = self.builtin_next(frames, pos_args)?;
                 self.stack.push(result);
                 return Ok(());
             }
-            "filter" if n_kw == 0 => {
-                let result = self.builtin_filter(frames, pos_args)?;
+            "zip" | "enumerate" | "map" | "filter" => {
+                let result = builtin_lazy_iterator(name, pos_args, kw_pairs)?;
                 self.stack.push(result);
                 return Ok(());
             }
@@ -1095,14 +2137,27 @@ impl Vm {
             return self.invoke_function_def(frames, &func, name, pos_args, kw_pairs);
         }
 
-        if let Some(PyValue::NativeFunction(key)) = func
-            && let Some(tool) = self.tools.get(&key).cloned()
+        if let Some(PyValue::NativeFunction(key)) = &func
+            && let Some(tool) = self.tools.get(key).cloned()
         {
-            let result = (tool.func)(pos_args);
+            let pos_args = self.materialize_args(frames, pos_args)?;
+            let result =
+                (tool.func)(pos_args.into_iter().map(records_to_dicts).collect())?.deep_copy();
             self.stack.push(result);
             return Ok(());
         }
 
+        if let Some(
+            callable @ (PyValue::Class(_)
+            | PyValue::Type(_)
+            | PyValue::BoundMethod { .. }
+            | PyValue::MethodDescriptor { .. }
+            | PyValue::Decorator(_)),
+        ) = func
+        {
+            return self.call_callable(frames, callable, pos_args, kw_pairs);
+        }
+
         // 4. Nothing matched
         Err(Error::NameError(name.to_string()))
     }
@@ -1118,113 +2173,175 @@ impl Vm {
         pos_args: Vec<PyValue>,
         kw_pairs: Vec<(String, PyValue)>,
     ) -> Result<()> {
-        let n_params = func.params.len();
-        let n_defaults = func.defaults.len();
-        let n_required = n_params - n_defaults;
-        let has_vararg = func.vararg.is_some();
-        let has_kwarg = func.kwarg.is_some();
-
-        // Step 1: Bind positional args to named params
-        let mut bound: Vec<Option<PyValue>> = vec![None; n_params];
-        let mut extra_positional = Vec::new();
-
-        for (i, val) in pos_args.into_iter().enumerate() {
-            if i < n_params {
-                bound[i] = Some(val);
-            } else if has_vararg {
-                extra_positional.push(val);
-            } else {
-                return Err(Error::Runtime(format!(
-                    "{}() takes {} argument(s), {} given",
-                    name,
-                    n_params,
-                    i + 1 + extra_positional.len()
-                )));
-            }
+        let locals = bind_arguments(func, name, pos_args, kw_pairs)?;
+
+        // Check recursion limit before pushing a new frame
+        if let Some(limit) = self.recursion_limit
+            && self.nested_depth + frames.len() >= limit
+        {
+            return Err(Error::RecursionLimitExceeded(limit));
         }
 
-        // Step 2: Map keyword args to named params or collect into **kwargs
-        let mut extra_kwargs: Vec<(String, PyValue)> = Vec::new();
+        let new_frame =
+            CallFrame::for_function(func, locals, self.stack.len(), self.exception_stack.len());
+        if func.is_generator {
+            let generator = GeneratorValue::new(name, new_frame);
+            self.stack.push(PyValue::Generator(generator));
+        } else {
+            frames.push(new_frame);
+        }
+        Ok(())
+    }
 
-        for (kw_name, kw_val) in kw_pairs {
-            if let Some(pos) = func.params.iter().position(|p| p == &kw_name) {
-                if bound[pos].is_some() {
-                    return Err(Error::Runtime(format!(
-                        "{}() got multiple values for argument '{}'",
-                        name, kw_name
-                    )));
-                }
-                bound[pos] = Some(kw_val);
-            } else if has_kwarg {
-                extra_kwargs.push((kw_name, kw_val));
-            } else {
-                return Err(Error::Runtime(format!(
-                    "{}() got an unexpected keyword argument '{}'",
-                    name, kw_name
-                )));
+    /// Call a callable value on the stack.
+    ///
+    /// Pops keyword pairs, positional args, and the callable from the stack.
+    /// Dispatches to `invoke_function_def` for `PyValue::Function`.
+    fn call_value(&mut self, frames: &mut Vec<CallFrame>, n_pos: usize, n_kw: usize) -> Result<()> {
+        let (pos_args, kw_pairs) = self.pop_call_args(n_pos, n_kw)?;
+        let callable = self.stack.pop().unwrap_or(PyValue::None);
+        self.call_callable(frames, callable, pos_args, kw_pairs)
+    }
+
+    /// Call a callable value with already-popped arguments.
+    ///
+    /// User functions, bound methods and `__init__` push a new frame; other
+    /// callables push their result directly.
+    fn call_callable(
+        &mut self,
+        frames: &mut Vec<CallFrame>,
+        callable: PyValue,
+        mut pos_args: Vec<PyValue>,
+        kw_pairs: Vec<(String, PyValue)>,
+    ) -> Result<()> {
+        match callable {
+            PyValue::Function(func) => {
+                let name = func.name.clone();
+                self.invoke_function_def(frames, &func, &name, pos_args, kw_pairs)
+            }
+            PyValue::BoundMethod { receiver, func } => {
+                pos_args.insert(0, *receiver);
+                let name = func.name.clone();
+                self.invoke_function_def(frames, &func, &name, pos_args, kw_pairs)
+            }
+            PyValue::Class(class) => self.call_class(frames, *class, pos_args, kw_pairs),
+            // Built-in types construct through their builtin function
+            PyValue::Type(name) if builtin_type_object(&name).is_some() => {
+                self.call_named(frames, &name, pos_args, kw_pairs, Span::default())
+            }
+            PyValue::Type(name) => Err(Error::exception(
+                "TypeError",
+                format!("cannot create '{}' instances", name),
+            )),
+            PyValue::Decorator(decorator) => {
+                self.call_decorator(frames, &decorator, pos_args, kw_pairs)
+            }
+            // Call the method on the first argument, which must be an
+            // instance of the type
+            PyValue::MethodDescriptor { type_name, method } => {
+                let receiver = match pos_args.first() {
+                    Some(receiver) if crate::builtins::is_instance_of(receiver, &type_name) => {
+                        pos_args.remove(0)
+                    }
+                    Some(other) => {
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!(
+                                "descriptor '{}' for '{}' objects doesn't apply to a '{}' object",
+                                method,
+                                type_name,
+                                other.type_name()
+                            ),
+                        ));
+                    }
+                    None => {
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!(
+                                "unbound method {}.{}() needs an argument",
+                                type_name, method
+                            ),
+                        ));
+                    }
+                };
+                let (n_pos, n_kw) = (pos_args.len(), kw_pairs.len());
+                self.stack.push(receiver);
+                self.stack.extend(pos_args);
+                for (key, value) in kw_pairs {
+                    self.stack.push(PyValue::Str(key));
+                    self.stack.push(value);
+                }
+                self.call_method(frames, &method, n_pos, n_kw)
             }
-        }
-
-        // Step 3: Fill missing params from defaults
-        #[allow(clippy::needless_range_loop)]
-        for i in 0..n_params {
-            if bound[i].is_none() {
-                let default_idx = i as isize - n_required as isize;
-                if default_idx >= 0 && (default_idx as usize) < n_defaults {
-                    bound[i] = Some(func.defaults[default_idx as usize].clone());
+            PyValue::NativeFunction(key) => {
+                if let Some(tool) = self.tools.get(&key).cloned() {
+                    let pos_args = self.materialize_args(frames, pos_args)?;
+                    let result = (tool.func)(pos_args.into_iter().map(records_to_dicts).collect())?
+                        .deep_copy();
+                    self.stack.push(result);
+                    Ok(())
                 } else {
-                    return Err(Error::Runtime(format!(
-                        "{}() missing required argument: '{}'",
-                        name, func.params[i]
-                    )));
+                    Err(Error::exception(
+                        "TypeError",
+                        format!("native function '{}' not found in tools", key),
+                    ))
                 }
             }
+            other => Err(Error::exception(
+                "TypeError",
+                format!("'{}' object is not callable", other.type_name()),
+            )),
         }
+    }
 
-        // Check recursion limit before pushing a new frame
-        if let Some(limit) = self.recursion_limit
-            && frames.len() >= limit
-        {
-            return Err(Error::RecursionLimitExceeded(limit));
-        }
-
-        // Build locals from parameters
-        let mut locals = HashMap::new();
-        for (param, val) in func.params.iter().zip(bound.into_iter()) {
-            locals.insert(param.clone(), val.unwrap());
-        }
+    /// Call a host decorator: apply it to a function, or call a function it
+    /// decorates.
+    ///
+    /// A decorated call runs the `before` hook, which may supply the result,
+    /// and otherwise calls the function. If that pushed a frame the `after`
+    /// hook runs when the frame returns; otherwise the result is already on
+    /// the stack.
+    fn call_decorator(
+        &mut self,
+        frames: &mut Vec<CallFrame>,
+        decorator: &DecoratorValue,
+        pos_args: Vec<PyValue>,
+        kw_pairs: Vec<(String, PyValue)>,
+    ) -> Result<()> {
+        let (hooks, func) = match decorator.call(&pos_args, &kw_pairs)? {
+            DecoratorCall::Value(value) => {
+                self.stack.push(value);
+                return Ok(());
+            }
+            DecoratorCall::Invoke { hooks, func } => (hooks, func),
+        };
 
-        // Bind *args (tuple in Python)
-        if let Some(ref vararg_name) = func.vararg {
-            locals.insert(vararg_name.clone(), PyValue::Tuple(extra_positional));
+        let call = DecoratedCall {
+            function: callable_name(&func),
+            args: pos_args.iter().map(|arg| hooks.convert(arg)).collect(),
+            kwargs: kw_pairs
+                .iter()
+                .map(|(name, value)| (name.clone(), hooks.convert(value)))
+                .collect(),
+        };
+        if let Some(result) = hooks.run_before(&call)? {
+            self.stack.push(result);
+            return Ok(());
         }
 
-        // Bind **kwargs
-        if let Some(ref kwarg_name) = func.kwarg {
-            let kwargs_pairs: Vec<(PyValue, PyValue)> = extra_kwargs
-                .into_iter()
-                .map(|(k, v)| (PyValue::Str(k), v))
-                .collect();
-            locals.insert(kwarg_name.clone(), PyValue::Dict(kwargs_pairs));
+        let depth = frames.len();
+        self.call_callable(frames, func, pos_args, kw_pairs)?;
+        if frames.len() > depth {
+            frames.last_mut().unwrap().after_return = Some(Box::new((hooks, call)));
+        } else if let Some(result) = self.stack.last() {
+            hooks.run_after(&call, result);
         }
-
-        let new_frame = CallFrame {
-            code: func.code.clone(),
-            ip: 0,
-            locals,
-            stack_base: self.stack.len(),
-            iterators: Vec::new(),
-        };
-        frames.push(new_frame);
         Ok(())
     }
 
-    /// Call a callable value on the stack.
-    ///
-    /// Pops keyword pairs, positional args, and the callable from the stack.
-    /// Dispatches to `invoke_function_def` for `PyValue::Function`.
-    fn call_value(&mut self, frames: &mut Vec<CallFrame>, n_pos: usize, n_kw: usize) -> Result<()> {
-        // Pop keyword args (name, value pairs) in reverse
+    /// Pop `n_kw` keyword pairs (name string + value) and then `n_pos`
+    /// positional arguments from the stack.
+    fn pop_call_args(&mut self, n_pos: usize, n_kw: usize) -> Result<CallArgs> {
         let mut kw_pairs: Vec<(String, PyValue)> = Vec::with_capacity(n_kw);
         for _ in 0..n_kw {
             let value = self.stack.pop().unwrap_or(PyValue::None);
@@ -1232,44 +2349,16 @@ impl Vm {
             let key = match key_val {
                 PyValue::Str(s) => s,
                 _ => {
-                    return Err(Error::Runtime(
-                        "keyword argument name must be a string".to_string(),
-                    ));
+                    return Err(Error::exception("TypeError", "keywords must be strings"));
                 }
             };
             kw_pairs.push((key, value));
         }
         kw_pairs.reverse();
 
-        // Pop positional args
         let start = self.stack.len() - n_pos;
         let pos_args: Vec<PyValue> = self.stack.drain(start..).collect();
-
-        // Pop the callable
-        let callable = self.stack.pop().unwrap_or(PyValue::None);
-
-        match callable {
-            PyValue::Function(func) => {
-                let name = func.name.clone();
-                self.invoke_function_def(frames, &func, &name, pos_args, kw_pairs)
-            }
-            PyValue::NativeFunction(key) => {
-                if let Some(tool) = self.tools.get(&key).cloned() {
-                    let result = (tool.func)(pos_args);
-                    self.stack.push(result);
-                    Ok(())
-                } else {
-                    Err(Error::Runtime(format!(
-                        "TypeError: native function '{}' not found in tools",
-                        key
-                    )))
-                }
-            }
-            other => Err(Error::Runtime(format!(
-                "TypeError: '{}' object is not callable",
-                other.type_name()
-            ))),
-        }
+        Ok((pos_args, kw_pairs))
     }
 
     /// Synchronously invoke a function and return its result.
@@ -1283,33 +2372,422 @@ impl Vm {
         args: Vec<PyValue>,
         outer_frames: &mut [CallFrame],
     ) -> Result<PyValue> {
-        let mut locals = HashMap::new();
-        for (param, val) in func.params.iter().zip(args) {
-            locals.insert(param.clone(), val);
+        let locals = bind_arguments(func, &func.name, args, Vec::new())?;
+        let frame =
+            CallFrame::for_function(func, locals, self.stack.len(), self.exception_stack.len());
+        if func.is_generator {
+            return Ok(PyValue::Generator(GeneratorValue::new(&func.name, frame)));
         }
-        let frame = CallFrame {
-            code: func.code.clone(),
-            ip: 0,
-            locals,
-            stack_base: self.stack.len(),
-            iterators: Vec::new(),
+
+        self.check_nested_depth(outer_frames)?;
+        self.run_nested(outer_frames, &mut vec![frame])
+    }
+
+    /// Fail if a frame run on a new frame stack, below `outer_frames` and
+    /// every other suspended stack, would exceed the recursion limit.
+    fn check_nested_depth(&self, outer_frames: &[CallFrame]) -> Result<()> {
+        match self.recursion_limit {
+            Some(limit) if self.nested_depth + outer_frames.len() + 1 >= limit => {
+                Err(Error::RecursionLimitExceeded(limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Run `frames` to completion while `outer_frames` are suspended.
+    fn run_nested(
+        &mut self,
+        outer_frames: &[CallFrame],
+        frames: &mut Vec<CallFrame>,
+    ) -> Result<PyValue> {
+        self.nested_depth += outer_frames.len();
+        let result = self.run(frames);
+        self.nested_depth -= outer_frames.len();
+        result
+    }
+
+    /// `left == right` or `left != right` through a user-defined `__eq__`
+    /// or `__ne__`, or `None` if neither operand's class defines one.
+    ///
+    /// The left operand's method is tried first, then the right's. Without
+    /// `__ne__`, `!=` is the negation of `__eq__`.
+    fn call_eq(
+        &mut self,
+        frames: &mut [CallFrame],
+        op: &CmpOp,
+        left: &PyValue,
+        right: &PyValue,
+    ) -> Result<Option<bool>> {
+        let method = |value: &PyValue, name: &str| match value {
+            PyValue::Instance(instance) => instance.class.special_method(name),
+            _ => None,
+        };
+        let operands = [(left, right), (right, left)];
+        let call = match op {
+            CmpOp::Eq => operands
+                .into_iter()
+                .find_map(|(a, b)| Some((method(a, "__eq__")?, a, b, false))),
+            CmpOp::NotEq => operands
+                .into_iter()
+                .find_map(|(a, b)| Some((method(a, "__ne__")?, a, b, false)))
+                .or_else(|| {
+                    operands
+                        .into_iter()
+                        .find_map(|(a, b)| Some((method(a, "__eq__")?, a, b, true)))
+                }),
+            _ => None,
+        };
+        let Some((func, a, b, negate)) = call else {
+            return Ok(None);
         };
+        let result = self.invoke_sync(&func, vec![a.clone(), b.clone()], frames)?;
+        Ok(Some(result.is_truthy() != negate))
+    }
+
+    /// Call a function, bound method, decorated function or type
+    /// synchronously (for callbacks such as `key=` and `map()`).
+    fn invoke_callable_sync(
+        &mut self,
+        callable: &PyValue,
+        mut args: Vec<PyValue>,
+        outer_frames: &mut [CallFrame],
+    ) -> Result<PyValue> {
+        match callable {
+            PyValue::Function(func) => self.invoke_sync(func, args, outer_frames),
+            PyValue::BoundMethod { receiver, func } => {
+                args.insert(0, (**receiver).clone());
+                self.invoke_sync(func, args, outer_frames)
+            }
+            PyValue::Decorator(_) | PyValue::Type(_) | PyValue::MethodDescriptor { .. } => {
+                self.check_nested_depth(outer_frames)?;
+                let mut frames = Vec::new();
+                self.nested_depth += outer_frames.len();
+                let called = self.call_callable(&mut frames, callable.clone(), args, Vec::new());
+                self.nested_depth -= outer_frames.len();
+                called?;
+                if frames.is_empty() {
+                    Ok(self.stack.pop().unwrap_or(PyValue::None))
+                } else {
+                    self.run_nested(outer_frames, &mut frames)
+                }
+            }
+            other => Err(Error::exception(
+                "TypeError",
+                format!("'{}' object is not callable", other.type_name()),
+            )),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Iteration and generators
+    // -----------------------------------------------------------------------
+
+    /// Count one executed instruction against the instruction limit.
+    fn tick(&mut self) -> Result<()> {
+        self.instruction_count += 1;
+        if let Some(limit) = self.instruction_limit
+            && self.instruction_count > limit
+        {
+            return Err(Error::InstructionLimitExceeded(limit));
+        }
+        Ok(())
+    }
+
+    /// Advance an iterator, returning `None` once it is exhausted.
+    fn iter_next(
+        &mut self,
+        frames: &mut [CallFrame],
+        iter: &mut IterState,
+    ) -> Result<Option<PyValue>> {
+        match iter {
+            IterState::List { list, index } => {
+                let item = list.read().get(*index).cloned();
+                *index += 1;
+                Ok(item)
+            }
+            IterState::Items(items) => Ok(items.next()),
+            state @ (IterState::Dict { .. } | IterState::Set { .. }) => state.next_entry(),
+            IterState::Range { next, stop, step } => {
+                let more = if *step > 0 {
+                    *next < *stop
+                } else {
+                    *next > *stop
+                };
+                if !more {
+                    return Ok(None);
+                }
+                let item = *next;
+                *next = next.checked_add(*step).unwrap_or(*stop);
+                Ok(Some(PyValue::Int(item)))
+            }
+            IterState::Generator(generator) => {
+                let generator = generator.clone();
+                self.resume_generator(&generator)
+            }
+            IterState::Shared(iterator) => {
+                let iterator = iterator.clone();
+                let mut state = iterator.take()?;
+                let result = self.iter_next(frames, &mut state);
+                iterator.restore(state);
+                result
+            }
+            IterState::Enumerate { inner, count } => {
+                let Some(item) = self.iter_next(frames, inner)? else {
+                    return Ok(None);
+                };
+                let index = PyValue::Int(*count);
+                *count += 1;
+                Ok(Some(PyValue::Tuple(vec![index, item])))
+            }
+            IterState::Zip(iters) => Ok(self.next_of_each(frames, iters)?.map(PyValue::Tuple)),
+            IterState::Map { func, iters } => {
+                let Some(args) = self.next_of_each(frames, iters)? else {
+                    return Ok(None);
+                };
+                let func = func.clone();
+                self.invoke_callable_sync(&func, args, frames).map(Some)
+            }
+            IterState::Filter { func, inner } => {
+                while let Some(item) = self.iter_next(frames, inner)? {
+                    let keep = match func {
+                        PyValue::None => item.is_truthy(),
+                        _ => {
+                            let func = func.clone();
+                            self.invoke_callable_sync(&func, vec![item.clone()], frames)?
+                                .is_truthy()
+                        }
+                    };
+                    if keep {
+                        return Ok(Some(item));
+                    }
+                    self.tick()?;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Advance each of `iters` once (for `zip()` and `map()`), returning
+    /// `None` as soon as one is exhausted.
+    fn next_of_each(
+        &mut self,
+        frames: &mut [CallFrame],
+        iters: &mut [IterState],
+    ) -> Result<Option<Vec<PyValue>>> {
+        if iters.is_empty() {
+            return Ok(None);
+        }
+        let mut items = Vec::with_capacity(iters.len());
+        for iter in iters {
+            match self.iter_next(frames, iter)? {
+                Some(item) => items.push(item),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(items))
+    }
 
-        // Check recursion limit (count outer frames + 1 for the new frame)
+    /// Run a generator up to its next `yield`.
+    ///
+    /// Returns the yielded value, or `None` once the generator has returned.
+    /// The generator's frame runs on a fresh frame stack, like
+    /// `invoke_sync`, and is saved back into the generator by `YieldValue`.
+    fn resume_generator(&mut self, generator: &GeneratorValue) -> Result<Option<PyValue>> {
         if let Some(limit) = self.recursion_limit
-            && (outer_frames.len() + 1) >= limit
+            && self.nested_depth >= limit
         {
             return Err(Error::RecursionLimitExceeded(limit));
         }
+        let Some(suspended) = generator.take()? else {
+            return Ok(None);
+        };
+        let SuspendedFrame {
+            mut frame,
+            stack,
+            exceptions,
+            started,
+        } = *suspended;
+        frame.stack_base = self.stack.len();
+        frame.exc_base = self.exception_stack.len();
+        frame.generator = Some(generator.clone());
+        self.stack.extend(stack);
+        self.exception_stack.extend(exceptions);
+        if started {
+            // The value of the `yield` expression the frame resumes from
+            self.stack.push(PyValue::None);
+        }
 
-        let mut frames = vec![frame];
-        self.run(&mut frames)
+        self.nested_depth += 1;
+        let result = self.run(&mut vec![frame]);
+        self.nested_depth -= 1;
+
+        match result {
+            Ok(value) if generator.is_suspended() => Ok(Some(value)),
+            Ok(value) => {
+                generator.finish_returning(value);
+                Ok(None)
+            }
+            Err(err) => {
+                generator.finish();
+                Err(err)
+            }
+        }
+    }
+
+    /// Consume a generator or range into a list, for builtins, methods and
+    /// tools that need all of its items. Each item counts as an executed
+    /// instruction, and the list may not exceed the sandbox's size limit.
+    /// Other values are returned unchanged.
+    fn materialize(&mut self, frames: &mut [CallFrame], value: PyValue) -> Result<PyValue> {
+        if !is_lazy(&value) {
+            return Ok(value);
+        }
+        // Fail as consuming a range would, without consuming it
+        if let PyValue::Range { start, stop, step } = value {
+            let len = range_len(start, stop, step) as u64;
+            if let Some(limit) = self.instruction_limit
+                && self.instruction_count.saturating_add(len) > limit
+            {
+                return Err(Error::InstructionLimitExceeded(limit));
+            }
+            check_sequence_len(usize::try_from(len).ok())?;
+        }
+        let mut iter = IterState::new(value)?;
+        let mut items = Vec::new();
+        while let Some(item) = self.iter_next(frames, &mut iter)? {
+            self.tick()?;
+            items.push(item);
+            check_sequence_len(Some(items.len()))?;
+        }
+        Ok(PyValue::list(items))
+    }
+
+    /// Replace the argument list and keyword dict of a `Call*Ex`
+    /// instruction with the stack layout of the `Call*Kw` instructions.
+    /// Returns the number of positional and keyword arguments.
+    fn spread_call_args(&mut self) -> Result<(usize, usize)> {
+        let kwargs = self.stack.pop().unwrap_or(PyValue::None);
+        let args = self.stack.pop().unwrap_or(PyValue::None);
+        let (PyValue::List(args), PyValue::Dict(kwargs)) = (args, kwargs) else {
+            return Err(Error::Runtime("malformed call arguments".to_string()));
+        };
+        let args = args.read();
+        let kwargs = kwargs.read();
+        self.stack.extend(args.iter().cloned());
+        for (key, value) in kwargs.iter() {
+            if !matches!(key, PyValue::Str(_)) {
+                return Err(Error::exception("TypeError", "keywords must be strings"));
+            }
+            self.stack.push(key.clone());
+            self.stack.push(value.clone());
+        }
+        Ok((args.len(), kwargs.len()))
+    }
+
+    /// The items of an iterable being unpacked into several targets or
+    /// assigned to a slice.
+    fn unpack_items(&mut self, frames: &mut [CallFrame], value: PyValue) -> Result<Vec<PyValue>> {
+        match value.into_tuple_view() {
+            PyValue::List(items) => Ok(items.read().to_vec()),
+            PyValue::Tuple(items) => Ok(items),
+            other => {
+                let mut iter = IterState::new(other)?;
+                let mut items = Vec::new();
+                while let Some(item) = self.iter_next(frames, &mut iter)? {
+                    self.tick()?;
+                    items.push(item);
+                }
+                Ok(items)
+            }
+        }
+    }
+
+    /// [`Vm::materialize`] each argument.
+    fn materialize_args(
+        &mut self,
+        frames: &mut [CallFrame],
+        args: Vec<PyValue>,
+    ) -> Result<Vec<PyValue>> {
+        args.into_iter()
+            .map(|arg| self.materialize(frames, arg))
+            .collect()
+    }
+
+    /// Materialize the arguments of container methods that consume an
+    /// iterable (`items.extend(gen)`, `", ".join(gen)`).
+    fn method_args(
+        &mut self,
+        frames: &mut [CallFrame],
+        method: &str,
+        args: Vec<PyValue>,
+    ) -> Result<Vec<PyValue>> {
+        if ITERABLE_ARG_METHODS.contains(&method) {
+            self.materialize_args(frames, args)
+        } else {
+            Ok(args)
+        }
     }
 
     // -----------------------------------------------------------------------
     // Callable-aware builtins
     // -----------------------------------------------------------------------
 
+    /// `sum`, `any`, `all`, `min` and `max` over a generator or range,
+    /// consuming one item at a time rather than building a list first.
+    fn builtin_reduce(
+        &mut self,
+        frames: &mut [CallFrame],
+        name: &str,
+        pos_args: Vec<PyValue>,
+    ) -> Result<PyValue> {
+        let mut args = pos_args.into_iter();
+        let mut iter = IterState::new(args.next().unwrap_or(PyValue::None))?;
+        let mut acc = match name {
+            "sum" => Some(args.next().unwrap_or(PyValue::Int(0))),
+            _ => None,
+        };
+
+        while let Some(item) = self.iter_next(frames, &mut iter)? {
+            self.tick()?;
+            match name {
+                "any" if item.is_truthy() => return Ok(PyValue::Bool(true)),
+                "all" if !item.is_truthy() => return Ok(PyValue::Bool(false)),
+                "sum" => {
+                    if !matches!(
+                        item,
+                        PyValue::Int(_) | PyValue::BigInt(_) | PyValue::Bool(_) | PyValue::Float(_)
+                    ) {
+                        return Err(Error::Type {
+                            expected: "number".to_string(),
+                            got: item.type_name().to_string(),
+                        });
+                    }
+                    let total = acc.take().unwrap_or(PyValue::Int(0));
+                    acc = Some(apply_binop(&BinOp::Add, &total, &item)?);
+                }
+                "min" | "max" => {
+                    let op = if name == "min" { CmpOp::Lt } else { CmpOp::Gt };
+                    let better = match &acc {
+                        Some(best) => apply_cmpop(&op, &item, best)?,
+                        None => true,
+                    };
+                    if better {
+                        acc = Some(item);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match name {
+            "any" => Ok(PyValue::Bool(false)),
+            "all" => Ok(PyValue::Bool(true)),
+            _ => acc.ok_or_else(|| {
+                Error::exception("ValueError", format!("{}() arg is an empty sequence", name))
+            }),
+        }
+    }
+
     /// `sorted(iterable, key=None, reverse=False)`
     fn builtin_sorted(
         &mut self,
@@ -1318,15 +2796,26 @@ impl Vm {
         kw_pairs: Vec<(String, PyValue)>,
     ) -> Result<PyValue> {
         if pos_args.is_empty() || pos_args.len() > 1 {
-            return Err(Error::Runtime(
-                "sorted() requires exactly 1 positional argument".to_string(),
+            return Err(Error::exception(
+                "TypeError",
+                format!("sorted expected 1 argument, got {}", pos_args.len()),
             ));
         }
 
-        let mut items = match &pos_args[0] {
-            PyValue::List(items) | PyValue::Tuple(items) | PyValue::Set(items) => items.clone(),
-            PyValue::Dict(pairs) => pairs.iter().map(|(k, _)| k.clone()).collect(),
+        let mut items = match pos_args[0].tuple_view().as_ref() {
+            PyValue::List(items) => items.read().to_vec(),
+            PyValue::Tuple(items) => items.clone(),
+            PyValue::Set(set) => set.read().iter().cloned().collect(),
+            PyValue::FrozenSet(set) => set.iter().cloned().collect(),
+            PyValue::Dict(dict) => dict.read().keys().cloned().collect(),
+            PyValue::DictView(view) => view.items(),
             PyValue::Str(s) => s.chars().map(|c| PyValue::Str(c.to_string())).collect(),
+            data @ (PyValue::Bytes(_) | PyValue::ByteArray(_)) => data
+                .as_bytes()
+                .unwrap()
+                .iter()
+                .map(|&byte| PyValue::Int(byte as i64))
+                .collect(),
             other => {
                 return Err(Error::Type {
                     expected: "iterable".to_string(),
@@ -1335,29 +2824,33 @@ impl Vm {
             }
         };
 
-        let mut key_func: Option<FunctionDef> = None;
+        let mut key_func: Option<PyValue> = None;
         let mut reverse = false;
 
         for (kw_name, kw_val) in kw_pairs {
             match kw_name.as_str() {
                 "key" => match kw_val {
-                    PyValue::Function(f) => key_func = Some(*f),
+                    PyValue::Function(_)
+                    | PyValue::BoundMethod { .. }
+                    | PyValue::Decorator(_)
+                    | PyValue::Type(_)
+                    | PyValue::MethodDescriptor { .. } => key_func = Some(kw_val),
                     PyValue::None => {}
                     other => {
-                        return Err(Error::Runtime(format!(
-                            "TypeError: '{}' object is not callable",
-                            other.type_name()
-                        )));
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!("'{}' object is not callable", other.type_name()),
+                        ));
                     }
                 },
                 "reverse" => {
                     reverse = kw_val.is_truthy();
                 }
                 other => {
-                    return Err(Error::Runtime(format!(
-                        "sorted() got an unexpected keyword argument '{}'",
-                        other
-                    )));
+                    return Err(Error::exception(
+                        "TypeError",
+                        format!("sorted() got an unexpected keyword argument '{}'", other),
+                    ));
                 }
             }
         }
@@ -1366,7 +2859,7 @@ impl Vm {
             // Compute keys for each item
             let mut keyed: Vec<(PyValue, PyValue)> = Vec::with_capacity(items.len());
             for item in items {
-                let key = self.invoke_sync(func, vec![item.clone()], frames)?;
+                let key = self.invoke_callable_sync(func, vec![item.clone()], frames)?;
                 keyed.push((key, item));
             }
             keyed.sort_by(|(a, _), (b, _)| compare_for_sort(a, b));
@@ -1379,99 +2872,45 @@ impl Vm {
             items.reverse();
         }
 
-        Ok(PyValue::List(items))
-    }
-
-    /// `map(func, iterable)` — apply func to each item, return list.
-    fn builtin_map(&mut self, frames: &mut [CallFrame], pos_args: Vec<PyValue>) -> Result<PyValue> {
-        if pos_args.len() != 2 {
-            return Err(Error::Runtime(
-                "map() requires exactly 2 arguments".to_string(),
-            ));
-        }
-        let func = match &pos_args[0] {
-            PyValue::Function(f) => (**f).clone(),
-            other => {
-                return Err(Error::Runtime(format!(
-                    "TypeError: '{}' object is not callable",
-                    other.type_name()
-                )));
-            }
-        };
-
-        let items = match &pos_args[1] {
-            PyValue::List(items) | PyValue::Tuple(items) | PyValue::Set(items) => items.clone(),
-            PyValue::Dict(pairs) => pairs.iter().map(|(k, _)| k.clone()).collect(),
-            PyValue::Str(s) => s.chars().map(|c| PyValue::Str(c.to_string())).collect(),
-            other => {
-                return Err(Error::Type {
-                    expected: "iterable".to_string(),
-                    got: other.type_name().to_string(),
-                });
-            }
-        };
-
-        let mut results = Vec::with_capacity(items.len());
-        for item in items {
-            let result = self.invoke_sync(&func, vec![item], frames)?;
-            results.push(result);
-        }
-
-        Ok(PyValue::List(results))
+        Ok(PyValue::list(items))
     }
 
-    /// `filter(func_or_none, iterable)` — keep items where func returns truthy.
-    fn builtin_filter(
-        &mut self,
-        frames: &mut [CallFrame],
-        pos_args: Vec<PyValue>,
-    ) -> Result<PyValue> {
-        if pos_args.len() != 2 {
-            return Err(Error::Runtime(
-                "filter() requires exactly 2 arguments".to_string(),
-            ));
-        }
-
-        let items = match &pos_args[1] {
-            PyValue::List(items) | PyValue::Tuple(items) | PyValue::Set(items) => items.clone(),
-            PyValue::Dict(pairs) => pairs.iter().map(|(k, _)| k.clone()).collect(),
-            PyValue::Str(s) => s.chars().map(|c| PyValue::Str(c.to_string())).collect(),
-            other => {
-                return Err(Error::Type {
-                    expected: "iterable".to_string(),
-                    got: other.type_name().to_string(),
-                });
-            }
-        };
-
-        let mut results = Vec::new();
-
-        match &pos_args[0] {
-            PyValue::Function(func) => {
-                let func = (**func).clone();
-                for item in items {
-                    let result = self.invoke_sync(&func, vec![item.clone()], frames)?;
-                    if result.is_truthy() {
-                        results.push(item);
-                    }
+    /// `next(iterator[, default])`: advance a generator or iterator object.
+    /// An exhausted iterator returns `default`, or raises `StopIteration`.
+    fn builtin_next(&mut self, frames: &mut [CallFrame], args: Vec<PyValue>) -> Result<PyValue> {
+        methods::check_args_range("next", &args, 1, 2)?;
+        let mut args = args.into_iter();
+        let mut returned = PyValue::None;
+        let item = match args.next().unwrap() {
+            PyValue::Generator(generator) => {
+                let item = self.resume_generator(&generator)?;
+                if item.is_none() {
+                    returned = generator.take_return_value();
                 }
+                item
             }
-            PyValue::None => {
-                for item in items {
-                    if item.is_truthy() {
-                        results.push(item);
-                    }
-                }
+            PyValue::Iterator(iterator) => {
+                self.iter_next(frames, &mut IterState::Shared(iterator))?
             }
             other => {
-                return Err(Error::Runtime(format!(
-                    "TypeError: '{}' object is not callable",
-                    other.type_name()
-                )));
+                return Err(Error::exception(
+                    "TypeError",
+                    format!("'{}' object is not an iterator", other.type_name()),
+                ));
             }
+        };
+        match (item, args.next()) {
+            (Some(item), _) => Ok(item),
+            (None, Some(default)) => Ok(default),
+            // `StopIteration.value` carries what a generator returned
+            (None, None) => Err(Error::Exception(Box::new(ExceptionValue::new(
+                "StopIteration",
+                match returned {
+                    PyValue::None => vec![],
+                    value => vec![value],
+                },
+            )))),
         }
-
-        Ok(PyValue::List(results))
     }
 
     // -----------------------------------------------------------------------
@@ -1483,8 +2922,9 @@ impl Vm {
         let path = match args.first() {
             Some(PyValue::Str(s)) => s.clone(),
             _ => {
-                return Err(Error::Runtime(
-                    "TypeError: open() argument must be a string".to_string(),
+                return Err(Error::exception(
+                    "TypeError",
+                    "open() argument must be a string",
                 ));
             }
         };
@@ -1493,8 +2933,9 @@ impl Vm {
             Some(PyValue::Str(s)) => s.clone(),
             None => "r".to_string(),
             _ => {
-                return Err(Error::Runtime(
-                    "TypeError: open() mode must be a string".to_string(),
+                return Err(Error::exception(
+                    "TypeError",
+                    "open() mode must be a string",
                 ));
             }
         };
@@ -1511,7 +2952,7 @@ impl Vm {
                             .to_string()
                     };
                     let host_path = format!("{}/{}", dm.host_dir, relative);
-                    let content = std::fs::read_to_string(&host_path).ok()?;
+                    let content = std::fs::read(&host_path).ok()?;
                     Some(MountEntry {
                         host_path,
                         writable: dm.writable,
@@ -1548,7 +2989,7 @@ impl Vm {
                                 Some(MountEntry {
                                     host_path,
                                     writable: true,
-                                    content: String::new(),
+                                    content: Vec::new(),
                                 })
                             })
                         } else {
@@ -1561,10 +3002,10 @@ impl Vm {
                                 entry
                             }
                             None => {
-                                return Err(Error::Runtime(format!(
-                                    "FileNotFoundError: [Errno 2] No such file or directory: '{}'",
-                                    path
-                                )));
+                                return Err(Error::exception(
+                                    "FileNotFoundError",
+                                    format!("[Errno 2] No such file or directory: '{}'", path),
+                                ));
                             }
                         }
                     }
@@ -1575,17 +3016,17 @@ impl Vm {
         let write_mode = mode.contains('w') || mode.contains('a');
 
         if write_mode && !entry.writable {
-            return Err(Error::Runtime(format!(
-                "PermissionError: [Errno 13] Permission denied: '{}'",
-                path
-            )));
+            return Err(Error::exception(
+                "PermissionError",
+                format!("[Errno 13] Permission denied: '{}'", path),
+            ));
         }
 
         let handle = self.next_file_handle;
         self.next_file_handle += 1;
 
         let buffer = if write_mode {
-            String::new()
+            Vec::new()
         } else {
             entry.content.clone()
         };
@@ -1597,6 +3038,7 @@ impl Vm {
                 buffer,
                 cursor: 0,
                 write_mode,
+                binary: mode.contains('b'),
                 closed: false,
             },
         );
@@ -1615,84 +3057,79 @@ impl Vm {
         let file = match self.open_files.get(&handle) {
             Some(f) => f,
             None => {
-                return Err(Error::Runtime(
-                    "ValueError: I/O operation on closed file".to_string(),
+                return Err(Error::exception(
+                    "ValueError",
+                    "I/O operation on closed file",
                 ));
             }
         };
 
         if file.closed && method != "__exit__" {
-            return Err(Error::Runtime(
-                "ValueError: I/O operation on closed file".to_string(),
+            return Err(Error::exception(
+                "ValueError",
+                "I/O operation on closed file",
             ));
         }
 
         match method {
             "read" => {
                 if file.write_mode {
-                    return Err(Error::Runtime(
-                        "UnsupportedOperation: not readable".to_string(),
-                    ));
+                    return Err(Error::exception("UnsupportedOperation", "not readable"));
                 }
-                let content = file.buffer[file.cursor..].to_string();
+                let content = file.content(&file.buffer[file.cursor..])?;
                 let len = file.buffer.len();
                 self.open_files.get_mut(&handle).unwrap().cursor = len;
-                Ok(PyValue::Str(content))
+                Ok(content)
             }
             "readline" => {
                 if file.write_mode {
-                    return Err(Error::Runtime(
-                        "UnsupportedOperation: not readable".to_string(),
-                    ));
+                    return Err(Error::exception("UnsupportedOperation", "not readable"));
                 }
                 let remaining = &file.buffer[file.cursor..];
-                let line = if let Some(pos) = remaining.find('\n') {
-                    remaining[..=pos].to_string()
-                } else {
-                    remaining.to_string()
+                let line = match remaining.iter().position(|&byte| byte == b'\n') {
+                    Some(pos) => &remaining[..=pos],
+                    None => remaining,
                 };
+                let content = file.content(line)?;
                 let advance = line.len();
                 self.open_files.get_mut(&handle).unwrap().cursor += advance;
-                Ok(PyValue::Str(line))
+                Ok(content)
             }
             "readlines" => {
                 if file.write_mode {
-                    return Err(Error::Runtime(
-                        "UnsupportedOperation: not readable".to_string(),
-                    ));
+                    return Err(Error::exception("UnsupportedOperation", "not readable"));
                 }
-                let remaining = file.buffer[file.cursor..].to_string();
+                let remaining = &file.buffer[file.cursor..];
+                let lines = remaining
+                    .split_inclusive(|&byte| byte == b'\n')
+                    .map(|line| file.content(line))
+                    .collect::<Result<Vec<PyValue>>>()?;
                 let len = file.buffer.len();
                 self.open_files.get_mut(&handle).unwrap().cursor = len;
-                let lines: Vec<PyValue> = if remaining.is_empty() {
-                    Vec::new()
-                } else {
-                    remaining
-                        .split_inclusive('\n')
-                        .map(|line| PyValue::Str(line.to_string()))
-                        .collect()
-                };
-                Ok(PyValue::List(lines))
+                Ok(PyValue::list(lines))
             }
             "write" => {
                 if !file.write_mode {
-                    return Err(Error::Runtime(
-                        "UnsupportedOperation: not writable".to_string(),
-                    ));
+                    return Err(Error::exception("UnsupportedOperation", "not writable"));
                 }
-                let text = match args.first() {
-                    Some(PyValue::Str(s)) => s.clone(),
-                    _ => {
-                        return Err(Error::Runtime(
-                            "TypeError: write() argument must be a string".to_string(),
+                let data = match (file.binary, args.first()) {
+                    (false, Some(PyValue::Str(s))) => s.as_bytes().to_vec(),
+                    (false, _) => {
+                        return Err(Error::exception(
+                            "TypeError",
+                            "write() argument must be a string",
                         ));
                     }
+                    (true, arg) => bytes::arg_bytes(arg.unwrap_or(&PyValue::None))?,
                 };
-                let count = text.len() as i64;
+                let count = match args.first() {
+                    Some(PyValue::Str(s)) => s.chars().count(),
+                    _ => data.len(),
+                } as i64;
                 let vpath = file.virtual_path.clone();
 
                 let file_mut = self.open_files.get_mut(&handle).unwrap();
-                file_mut.buffer.push_str(&text);
+                file_mut.buffer.extend_from_slice(&data);
 
                 // Write-through: update mount content
                 let new_content = file_mut.buffer.clone();
@@ -1744,10 +3181,10 @@ impl Vm {
                 }
                 Ok(PyValue::None)
             }
-            _ => Err(Error::Runtime(format!(
-                "AttributeError: '_io.TextIOWrapper' object has no attribute '{}'",
-                method
-            ))),
+            _ => Err(Error::exception(
+                "AttributeError",
+                format!("'_io.TextIOWrapper' object has no attribute '{}'", method),
+            )),
         }
     }
 
@@ -1760,7 +3197,8 @@ impl Vm {
         kw_pairs: &[(String, PyValue)],
         span: Span,
     ) -> Result<PyValue> {
-        let mut final_args = pos_args;
+        // The host sees records as dicts
+        let mut final_args: Vec<PyValue> = pos_args.into_iter().map(records_to_dicts).collect();
 
         if !kw_pairs.is_empty() && !tool.arg_names.is_empty() {
             // Extend to accommodate all parameters
@@ -1772,7 +3210,7 @@ impl Vm {
             for (kw_name, kw_val) in kw_pairs {
                 if let Some(pos) = tool.arg_names.iter().position(|n| n == kw_name) {
                     if pos < final_args.len() {
-                        final_args[pos] = kw_val.clone();
+                        final_args[pos] = records_to_dicts(kw_val.clone());
                     }
                 } else {
                     let signature = tool.arg_names.join(", ");
@@ -1795,7 +3233,7 @@ impl Vm {
         } else if !kw_pairs.is_empty() {
             // Tool has no arg_names — append keyword values in order (fallback)
             for (_, kw_val) in kw_pairs {
-                final_args.push(kw_val.clone());
+                final_args.push(records_to_dicts(kw_val.clone()));
             }
         }
 
@@ -1813,14 +3251,14 @@ impl Vm {
             }
         }
 
-        Ok((tool.func)(final_args))
+        Ok((tool.func)(final_args)?.deep_copy())
     }
 
     // -----------------------------------------------------------------------
     // Method call dispatch
     // -----------------------------------------------------------------------
 
-    /// Call a non-mutating method on the object at TOS.
+    /// Call a method on the object at TOS.
     ///
     /// Stack layout: `[args..., object]`. Pops the object and args, calls
     /// the appropriate method handler, pushes the result.
@@ -1828,15 +3266,42 @@ impl Vm {
         &mut self,
         frames: &mut Vec<CallFrame>,
         method: &str,
-        n_args: usize,
+        n_pos: usize,
+        n_kw: usize,
     ) -> Result<()> {
         // The object is on the stack below the args
-        // Stack: [... object, arg0, arg1, ...]
+        // Stack: [... object, arg0, arg1, ..., kw pairs]
         // We need to pop args first, then the object
-        let args_start = self.stack.len() - n_args;
-        let args: Vec<PyValue> = self.stack.drain(args_start..).collect();
+        let (args, kw_pairs) = self.pop_call_args(n_pos, n_kw)?;
         let object = self.stack.pop().unwrap_or(PyValue::None);
 
+        // Named tuple helpers and tuple methods
+        if let PyValue::Instance(instance) = &object
+            && instance.class.lookup(method).is_none()
+            && let Some(items) = instance.tuple_items()
+        {
+            let result = match method {
+                "_asdict" => instance.to_dict().unwrap_or(PyValue::None),
+                "_replace" => PyValue::Instance(Box::new(instance.replace(kw_pairs)?)),
+                _ => methods::call_tuple_method(&items, method, args)?,
+            };
+            self.stack.push(result);
+            return Ok(());
+        }
+
+        // Methods of user-defined classes and instances
+        if matches!(object, PyValue::Instance(_) | PyValue::Class(_)) {
+            let callee = self.load_attr(frames, &object, method)?;
+            return self.call_callable(frames, callee, args, kw_pairs);
+        }
+
+        // `str.lower(s)` calls the method on its first argument
+        if let PyValue::Type(type_name) = &object
+            && let Some(descriptor) = methods::method_descriptor(type_name, method)
+        {
+            return self.call_callable(frames, descriptor, args, kw_pairs);
+        }
+
         // File handle methods — dispatch before type-based dispatch
         if let PyValue::File(handle) = &object {
             let result = self.call_file_method(*handle, method, args)?;
@@ -1853,142 +3318,119 @@ impl Vm {
             match attr_val {
                 Some(PyValue::NativeFunction(key)) => {
                     if let Some(tool) = self.tools.get(&key).cloned() {
-                        let result = (tool.func)(args);
+                        let args = self.materialize_args(frames, args)?;
+                        let result = (tool.func)(args.into_iter().map(records_to_dicts).collect())?
+                            .deep_copy();
                         self.stack.push(result);
                         return Ok(());
                     }
-                    return Err(Error::Runtime(format!(
-                        "AttributeError: module '{}' function '{}' not found in tools",
-                        name, method
-                    )));
+                    return Err(Error::exception(
+                        "AttributeError",
+                        format!("module '{}' function '{}' not found in tools", name, method),
+                    ));
                 }
                 Some(PyValue::Function(func)) => {
                     let func_name = func.name.clone();
-                    return self.invoke_function_def(frames, &func, &func_name, args, Vec::new());
+                    return self.invoke_function_def(frames, &func, &func_name, args, kw_pairs);
+                }
+                Some(callable @ (PyValue::Class(_) | PyValue::Decorator(_))) => {
+                    return self.call_callable(frames, callable, args, kw_pairs);
                 }
                 Some(_) => {
-                    return Err(Error::Runtime(format!(
-                        "TypeError: '{}' attribute '{}' is not callable",
-                        name, method
-                    )));
+                    return Err(Error::exception(
+                        "TypeError",
+                        format!("'{}' attribute '{}' is not callable", name, method),
+                    ));
                 }
                 None => {
-                    return Err(Error::Runtime(format!(
-                        "AttributeError: module '{}' has no attribute '{}'",
-                        name, method
-                    )));
+                    return Err(Error::exception(
+                        "AttributeError",
+                        format!("module '{}' has no attribute '{}'", name, method),
+                    ));
                 }
             }
         }
 
-        let result = match &object {
-            PyValue::Str(s) => methods::call_str_method(s, method, args),
-            PyValue::List(items) => methods::call_list_method(items, method, args),
-            PyValue::Tuple(items) => methods::call_tuple_method(items, method, args),
-            PyValue::Dict(pairs) => methods::call_dict_method(pairs, method, args),
-            PyValue::Set(items) => methods::call_set_method(items, method, args),
-            _ => Err(Error::Unsupported(format!(
-                "Method '{}' not supported on type '{}'",
-                method,
-                object.type_name()
-            ))),
-        }?;
-
-        self.stack.push(result);
-        Ok(())
-    }
-
-    /// Call a mutating method on a named variable.
-    ///
-    /// Stack layout: `[args...]`. Looks up the variable by name, gets a
-    /// mutable reference, and delegates to `mutate_list` or `mutate_dict`.
-    fn call_mut_method(
-        &mut self,
-        frames: &mut [CallFrame],
-        var_name: &str,
-        method: &str,
-        n_args: usize,
-    ) -> Result<()> {
-        let start = self.stack.len() - n_args;
-        let args: Vec<PyValue> = self.stack.drain(start..).collect();
-
-        let var = self.lookup_var_mut(frames, var_name)?;
-
-        let result = match var {
-            PyValue::List(items) => methods::mutate_list(items, method, args),
-            PyValue::Dict(pairs) => methods::mutate_dict(pairs, method, args),
-            PyValue::Set(items) => methods::mutate_set(items, method, args),
-            PyValue::Tuple(_) => {
-                return Err(Error::Runtime(format!(
-                    "TypeError: 'tuple' object has no attribute '{}'",
-                    method
-                )));
-            }
-            _ => Err(Error::Unsupported(format!(
-                "Mutating method '{}' not supported on type '{}'",
-                method,
-                var.type_name()
-            ))),
-        }?;
-
-        self.stack.push(result);
-        Ok(())
-    }
-
-    /// Call a mutating method on a named variable with keyword arguments.
-    fn call_mut_method_kw(
-        &mut self,
-        frames: &mut [CallFrame],
-        var_name: &str,
-        method: &str,
-        n_pos: usize,
-        n_kw: usize,
-    ) -> Result<()> {
-        // Pop keyword args (name, value pairs) in reverse
-        let mut kw_pairs: Vec<(String, PyValue)> = Vec::with_capacity(n_kw);
-        for _ in 0..n_kw {
-            let value = self.stack.pop().unwrap_or(PyValue::None);
-            let key_val = self.stack.pop().unwrap_or(PyValue::None);
-            let key = match key_val {
-                PyValue::Str(s) => s,
-                _ => {
-                    return Err(Error::Runtime(
-                        "keyword argument name must be a string".to_string(),
+        // `str.format` may look up attributes and call `__str__`
+        if let PyValue::Str(template) = &object
+            && (method == "format" || method == "format_map")
+        {
+            let result = if method == "format" {
+                let named = |name: &str| {
+                    kw_pairs
+                        .iter()
+                        .find(|(k, _)| k == name)
+                        .map(|(_, v)| v.clone())
+                };
+                self.format_template(frames, template, &args, &named, &mut Default::default())?
+            } else {
+                methods::check_args("format_map", &args, 1)?;
+                let PyValue::Dict(mapping) = &args[0] else {
+                    return Err(Error::exception(
+                        "TypeError",
+                        format!("'{}' object is not a mapping", args[0].type_name()),
                     ));
-                }
+                };
+                let named = |name: &str| mapping.read().get(&PyValue::from(name)).cloned();
+                self.format_template(frames, template, &[], &named, &mut Default::default())?
             };
-            kw_pairs.push((key, value));
+            self.stack.push(PyValue::Str(result));
+            return Ok(());
         }
-        kw_pairs.reverse();
-
-        // Pop positional args
-        let start = self.stack.len() - n_pos;
-        let pos_args: Vec<PyValue> = self.stack.drain(start..).collect();
 
-        // Check if this is list.sort with kwargs
-        if method == "sort" {
-            // Check the variable is a list
-            let is_list = {
-                let var = self.lookup_var_mut(frames, var_name)?;
-                matches!(var, PyValue::List(_))
-            };
-            if is_list {
-                let result = self.sort_list_in_place(frames, var_name, pos_args, kw_pairs)?;
-                self.stack.push(result);
-                return Ok(());
+        // `list.sort` may call a key function, so it is run by the VM
+        if let PyValue::List(list) = &object
+            && method == "sort"
+        {
+            if !args.is_empty() {
+                return Err(Error::exception(
+                    "TypeError",
+                    "sort() takes no positional arguments",
+                ));
             }
+            let mut items = list.write(std::mem::take);
+            let result = self.sort_items(frames, &mut items, kw_pairs);
+            // Write the items back, even if a key function failed
+            list.write(|list_items| *list_items = items);
+            result?;
+            self.stack.push(PyValue::None);
+            return Ok(());
         }
 
-        // Fall through to regular mutating method (ignore kwargs)
-        let var = self.lookup_var_mut(frames, var_name)?;
-        let result = match var {
-            PyValue::List(items) => methods::mutate_list(items, method, pos_args),
-            PyValue::Dict(pairs) => methods::mutate_dict(pairs, method, pos_args),
-            PyValue::Set(items) => methods::mutate_set(items, method, pos_args),
+        let args = self.method_args(frames, method, args)?;
+        let args = if matches!(method, "encode" | "decode") {
+            methods::codec_args(method, args, kw_pairs)?
+        } else {
+            args
+        };
+        let result = match &object {
+            PyValue::Str(s) => methods::call_str_method(s, method, args),
+            PyValue::Bytes(data) => methods::call_bytes_method(data, false, method, args),
+            PyValue::ByteArray(data) if methods::BYTEARRAY_MUTATING_METHODS.contains(&method) => {
+                methods::mutate_bytearray(data, method, args)
+            }
+            PyValue::ByteArray(data) => {
+                methods::call_bytes_method(&data.read(), true, method, args)
+            }
+            PyValue::Type(type_name) => methods::call_type_method(type_name, method, args),
+            PyValue::List(list) if methods::LIST_MUTATING_METHODS.contains(&method) => {
+                methods::mutate_list(list, method, args)
+            }
+            PyValue::List(items) => methods::call_list_method(&items.read(), method, args),
+            PyValue::Tuple(items) => methods::call_tuple_method(items, method, args),
+            PyValue::Dict(dict) if methods::DICT_MUTATING_METHODS.contains(&method) => {
+                methods::mutate_dict(dict, method, args)
+            }
+            PyValue::Dict(dict) => methods::call_dict_method(dict, method, args),
+            PyValue::Set(set) if methods::SET_MUTATING_METHODS.contains(&method) => {
+                methods::mutate_set(set, method, args)
+            }
+            PyValue::Set(set) => methods::call_set_method(&set.read(), false, method, args),
+            PyValue::FrozenSet(set) => methods::call_set_method(set, true, method, args),
             _ => Err(Error::Unsupported(format!(
-                "Mutating method '{}' not supported on type '{}'",
+                "Method '{}' not supported on type '{}'",
                 method,
-                var.type_name()
+                object.type_name()
             ))),
         }?;
 
@@ -1996,59 +3438,56 @@ impl Vm {
         Ok(())
     }
 
-    /// Sort a list in place with optional `key` and `reverse` kwargs.
-    fn sort_list_in_place(
+    /// Sort `items` with optional `key` and `reverse` kwargs.
+    ///
+    /// `items` is left unchanged if a key function fails.
+    fn sort_items(
         &mut self,
         frames: &mut [CallFrame],
-        var_name: &str,
-        _pos_args: Vec<PyValue>,
+        items: &mut Vec<PyValue>,
         kw_pairs: Vec<(String, PyValue)>,
-    ) -> Result<PyValue> {
-        let mut key_func: Option<FunctionDef> = None;
+    ) -> Result<()> {
+        let mut key_func: Option<PyValue> = None;
         let mut reverse = false;
 
         for (kw_name, kw_val) in kw_pairs {
             match kw_name.as_str() {
                 "key" => match kw_val {
-                    PyValue::Function(f) => key_func = Some(*f),
+                    PyValue::Function(_)
+                    | PyValue::BoundMethod { .. }
+                    | PyValue::Decorator(_)
+                    | PyValue::Type(_)
+                    | PyValue::MethodDescriptor { .. } => key_func = Some(kw_val),
                     PyValue::None => {}
                     other => {
-                        return Err(Error::Runtime(format!(
-                            "TypeError: '{}' object is not callable",
-                            other.type_name()
-                        )));
+                        return Err(Error::exception(
+                            "TypeError",
+                            format!("'{}' object is not callable", other.type_name()),
+                        ));
                     }
                 },
                 "reverse" => {
                     reverse = kw_val.is_truthy();
                 }
                 other => {
-                    return Err(Error::Runtime(format!(
-                        "sort() got an unexpected keyword argument '{}'",
-                        other
-                    )));
+                    return Err(Error::exception(
+                        "TypeError",
+                        format!("sort() got an unexpected keyword argument '{}'", other),
+                    ));
                 }
             }
         }
 
-        // Extract items from the variable to avoid borrow conflicts with invoke_sync
-        let mut items = {
-            let var = self.lookup_var_mut(frames, var_name)?;
-            match var {
-                PyValue::List(items) => std::mem::take(items),
-                _ => unreachable!(),
-            }
-        };
-
         if let Some(ref func) = key_func {
             // Compute keys for each item
-            let mut keyed: Vec<(PyValue, PyValue)> = Vec::with_capacity(items.len());
-            for item in items {
-                let key = self.invoke_sync(func, vec![item.clone()], frames)?;
-                keyed.push((key, item));
+            let mut keys = Vec::with_capacity(items.len());
+            for item in items.iter() {
+                keys.push(self.invoke_callable_sync(func, vec![item.clone()], frames)?);
             }
+            let mut keyed: Vec<(PyValue, PyValue)> =
+                keys.into_iter().zip(std::mem::take(items)).collect();
             keyed.sort_by(|(a, _), (b, _)| compare_for_sort(a, b));
-            items = keyed.into_iter().map(|(_, item)| item).collect();
+            *items = keyed.into_iter().map(|(_, item)| item).collect();
         } else {
             items.sort_by(compare_for_sort);
         }
@@ -2056,37 +3495,7 @@ impl Vm {
         if reverse {
             items.reverse();
         }
-
-        // Write sorted items back
-        let var = self.lookup_var_mut(frames, var_name)?;
-        if let PyValue::List(list_items) = var {
-            *list_items = items;
-        }
-
-        Ok(PyValue::None)
-    }
-
-    // -----------------------------------------------------------------------
-    // Variable lookup helpers
-    // -----------------------------------------------------------------------
-
-    /// Get a mutable reference to a variable, checking locals then globals.
-    fn lookup_var_mut<'a>(
-        &'a mut self,
-        frames: &'a mut [CallFrame],
-        name: &str,
-    ) -> Result<&'a mut PyValue> {
-        // Check locals in the current frame first
-        if let Some(frame) = frames.last_mut()
-            && frame.locals.contains_key(name)
-        {
-            return Ok(frame.locals.get_mut(name).unwrap());
-        }
-        // Then globals
-        if self.globals.contains_key(name) {
-            return Ok(self.globals.get_mut(name).unwrap());
-        }
-        Err(Error::NameError(name.to_string()))
+        Ok(())
     }
 
     // -----------------------------------------------------------------------
@@ -2109,14 +3518,20 @@ impl Vm {
         let is_compatible = match expected_type {
             "any" => true,
             "str" => matches!(value, PyValue::Str(_)),
-            "int" => matches!(value, PyValue::Int(_)),
-            "float" => matches!(value, PyValue::Float(_) | PyValue::Int(_)),
+            "int" => matches!(value, PyValue::Int(_) | PyValue::BigInt(_)),
+            "float" => matches!(
+                value,
+                PyValue::Float(_) | PyValue::Int(_) | PyValue::BigInt(_)
+            ),
             "bool" => matches!(value, PyValue::Bool(_)),
             "list" => matches!(value, PyValue::List(_)),
             "tuple" => matches!(value, PyValue::Tuple(_)),
             "dict" => matches!(value, PyValue::Dict(_)),
             "set" => matches!(value, PyValue::Set(_)),
-            "number" => matches!(value, PyValue::Int(_) | PyValue::Float(_)),
+            "number" => matches!(
+                value,
+                PyValue::Int(_) | PyValue::BigInt(_) | PyValue::Float(_)
+            ),
             _ => true,
         };
 
@@ -2174,55 +3589,128 @@ impl Default for Vm {
     }
 }
 
-/// Helper: produce an empty source string for diagnostic messages.
-/// (The real source is in the CodeObject, but we don't thread it here.)
-fn frames_source(_name: &str, _span: Span) -> String {
-    String::new()
+/// Whether `value` produces its items lazily (a generator, range or
+/// iterator object).
+fn is_lazy(value: &PyValue) -> bool {
+    matches!(
+        value,
+        PyValue::Generator(_) | PyValue::Range { .. } | PyValue::Iterator(_)
+    )
 }
 
-/// Map an internal `Error` to a Python exception type name.
-fn error_to_exception_type(err: &Error) -> &'static str {
-    match err {
-        Error::Type { .. } => "TypeError",
-        Error::DivisionByZero => "ZeroDivisionError",
-        Error::NameError(_) => "NameError",
-        Error::Runtime(msg) => {
-            if msg.starts_with("ValueError") {
-                "ValueError"
-            } else if msg.starts_with("ZeroDivisionError") {
-                "ZeroDivisionError"
-            } else if msg.starts_with("TypeError") {
-                "TypeError"
-            } else if msg.starts_with("NameError") {
-                "NameError"
-            } else if msg.starts_with("ModuleNotFoundError") {
-                "ModuleNotFoundError"
-            } else if msg.starts_with("AttributeError") {
-                "AttributeError"
-            } else if msg.contains("index out of range") {
-                "IndexError"
-            } else if msg.contains("KeyError") {
-                "KeyError"
-            } else if msg.starts_with("FileNotFoundError") {
-                "FileNotFoundError"
-            } else if msg.starts_with("PermissionError") {
-                "PermissionError"
-            } else if msg.starts_with("UnsupportedOperation") {
-                "UnsupportedOperation"
-            } else if msg.starts_with("AssertionError") {
-                "AssertionError"
+/// `iter(iterable)`: generators and iterator objects are their own
+/// iterators.
+fn builtin_iter(value: PyValue) -> Result<PyValue> {
+    match value {
+        PyValue::Generator(_) | PyValue::Iterator(_) => Ok(value),
+        other => Ok(PyValue::Iterator(IteratorValue::over(other)?)),
+    }
+}
+
+/// `zip()`, `enumerate()`, `map()` and `filter()`, which return iterator
+/// objects that consume their arguments one item at a time.
+fn builtin_lazy_iterator(
+    name: &str,
+    args: Vec<PyValue>,
+    mut kw_pairs: Vec<(String, PyValue)>,
+) -> Result<PyValue> {
+    let kind = match name {
+        "zip" => "zip",
+        "enumerate" => "enumerate",
+        "map" => "map",
+        _ => "filter",
+    };
+    let mut args = args.into_iter();
+    let state = match kind {
+        "zip" => {
+            check_kwargs(name, &kw_pairs, &[])?;
+            IterState::Zip(args.map(IterState::new).collect::<Result<_>>()?)
+        }
+        "enumerate" => {
+            check_kwargs(name, &kw_pairs, &["start"])?;
+            let mut args: Vec<PyValue> = args.collect();
+            args.extend(kw_pairs.pop().map(|(_, start)| start));
+            methods::check_args_range(name, &args, 1, 2)?;
+            let start = match args.len() {
+                2 => args.pop().unwrap(),
+                _ => PyValue::Int(0),
+            };
+            let iterable = args.pop().unwrap();
+            let PyValue::Int(count) = start else {
+                return Err(Error::Type {
+                    expected: "int".to_string(),
+                    got: start.type_name().to_string(),
+                });
+            };
+            IterState::Enumerate {
+                inner: Box::new(IterState::new(iterable)?),
+                count,
+            }
+        }
+        _ => {
+            check_kwargs(name, &kw_pairs, &[])?;
+            let func = args.next().unwrap_or(PyValue::None);
+            let iters: Vec<IterState> = args.map(IterState::new).collect::<Result<_>>()?;
+            let callable = match func {
+                PyValue::Function(_)
+                | PyValue::BoundMethod { .. }
+                | PyValue::Decorator(_)
+                | PyValue::Type(_)
+                | PyValue::MethodDescriptor { .. } => true,
+                PyValue::None => kind == "filter",
+                _ => false,
+            };
+            if !callable {
+                return Err(Error::exception(
+                    "TypeError",
+                    format!("'{}' object is not callable", func.type_name()),
+                ));
+            }
+            if name == "map" {
+                if iters.is_empty() {
+                    return Err(Error::exception(
+                        "TypeError",
+                        "map() must have at least two arguments.",
+                    ));
+                }
+                IterState::Map { func, iters }
             } else {
-                "RuntimeError"
+                let [inner] = <[IterState; 1]>::try_from(iters).map_err(|iters| {
+                    Error::exception(
+                        "TypeError",
+                        format!("filter expected 2 arguments, got {}", iters.len() + 1),
+                    )
+                })?;
+                IterState::Filter {
+                    func,
+                    inner: Box::new(inner),
+                }
             }
         }
-        Error::InstructionLimitExceeded(_) => "InstructionLimitExceeded",
-        Error::RecursionLimitExceeded(_) => "RecursionLimitExceeded",
-        Error::Parse(_) => "SyntaxError",
-        Error::Unsupported(_) => "RuntimeError",
-        Error::Diagnostic(_) => "RuntimeError",
+    };
+    Ok(PyValue::Iterator(IteratorValue::new(kind, state)))
+}
+
+/// Reject keyword arguments a builtin does not accept.
+fn check_kwargs(name: &str, kw_pairs: &[(String, PyValue)], allowed: &[&str]) -> Result<()> {
+    match kw_pairs
+        .iter()
+        .find(|(key, _)| !allowed.contains(&key.as_str()))
+    {
+        Some((key, _)) => Err(Error::exception(
+            "TypeError",
+            format!("{}() got an unexpected keyword argument '{}'", name, key),
+        )),
+        None => Ok(()),
     }
 }
 
+/// Helper: produce an empty source string for diagnostic messages.
+/// (The real source is in the CodeObject, but we don't thread it here.)
+fn frames_source(_name: &str, _span: Span) -> String {
+    String::new()
+}
+
 /// Check whether an error is uncatchable (resource limits).
 fn is_uncatchable(err: &Error) -> bool {
     matches!(
@@ -2231,42 +3719,419 @@ fn is_uncatchable(err: &Error) -> bool {
     )
 }
 
-/// Check if an exception type matches a handler type.
-///
-/// `Exception` and `BaseException` match everything. Otherwise the types
-/// must match exactly.
-fn exception_matches(actual: &str, expected: &str) -> bool {
-    matches!(expected, "Exception" | "BaseException") || actual == expected
+/// Check if an exception matches the class (or tuple of classes) named in an
+/// `except` clause.
+fn exception_matches(exc: &ExceptionValue, expected: &PyValue) -> Result<bool> {
+    match expected {
+        PyValue::Class(class) if class.is_exception() => Ok(exc.is_instance(&class.name)),
+        PyValue::Tuple(classes) => {
+            for class in classes {
+                if exception_matches(exc, class)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => Err(ExceptionValue::with_message(
+            "TypeError",
+            "catching classes that do not inherit from BaseException is not allowed",
+        )
+        .into()),
+    }
+}
+
+/// Unwrap the operand of a `raise` statement into an exception value,
+/// instantiating exception classes (`raise ValueError`).
+fn into_exception(value: PyValue) -> Result<ExceptionValue> {
+    match value {
+        PyValue::Exception(exc) => Ok(*exc),
+        PyValue::Class(class) if class.is_exception() => Ok(class.new_exception(vec![])),
+        _ => Err(ExceptionValue::with_message(
+            "TypeError",
+            "exceptions must derive from BaseException",
+        )
+        .into()),
+    }
+}
+
+/// Set an attribute on an instance or class.
+fn store_attr(obj: &PyValue, name: &str, value: PyValue) -> Result<()> {
+    match obj {
+        PyValue::Instance(instance) => {
+            if instance.tuple_items().is_some() {
+                return Err(Error::exception(
+                    "AttributeError",
+                    format!(
+                        "can't set attribute '{}' of '{}' object",
+                        name, instance.class.name
+                    ),
+                ));
+            }
+            if instance
+                .class
+                .dataclass_options()
+                .is_some_and(|options| options.frozen)
+            {
+                return Err(Error::exception(
+                    "FrozenInstanceError",
+                    format!("cannot assign to field '{}'", name),
+                ));
+            }
+            if let Some(PyValue::Property(_)) = instance.class.lookup(name) {
+                return Err(Error::exception(
+                    "AttributeError",
+                    format!(
+                        "property '{}' of '{}' object has no setter",
+                        name, instance.class.name
+                    ),
+                ));
+            }
+            instance.attrs.set(name, value);
+            Ok(())
+        }
+        PyValue::Class(class) if !class.is_exception() => {
+            class.attrs.set(name, value);
+            Ok(())
+        }
+        other => Err(Error::exception(
+            "AttributeError",
+            format!("'{}' object has no attribute '{}'", other.type_name(), name),
+        )),
+    }
+}
+
+/// Whether `err` is an `AttributeError` (a missing attribute makes a class
+/// pattern fail rather than raise).
+fn is_attribute_error(err: &Error) -> bool {
+    ExceptionValue::from_error(err).is_instance("AttributeError")
+}
+
+/// Match a mapping pattern's keys: `Op::MatchKeys`.
+fn match_keys(subject: &PyValue, keys: &PyValue) -> PyValue {
+    let (PyValue::Dict(dict), PyValue::Tuple(keys)) = (subject, keys) else {
+        return PyValue::None;
+    };
+    let mut values = Vec::with_capacity(keys.len());
+    let dict = dict.read();
+    for key in keys {
+        match dict.get(key) {
+            Some(value) => values.push(value.clone()),
+            None => return PyValue::None,
+        }
+    }
+    PyValue::Tuple(values)
+}
+
+/// Perform `object[index] = value`, mutating the object in place.
+fn store_subscript(object: &PyValue, index: &PyValue, value: PyValue) -> Result<()> {
+    match object {
+        PyValue::List(list) => {
+            let idx = index.as_int().ok_or_else(|| Error::Type {
+                expected: "int".to_string(),
+                got: index.type_name().to_string(),
+            })?;
+            list.write(|items| {
+                let len = items.len() as i64;
+                let actual = if idx < 0 { len + idx } else { idx };
+                if (0..len).contains(&actual) {
+                    items[actual as usize] = value;
+                    return Ok(());
+                }
+                Err(Error::exception(
+                    "IndexError",
+                    "list assignment index out of range",
+                ))
+            })
+        }
+        PyValue::Dict(dict) => {
+            check_hashable(index)?;
+            methods::dict_set_item(dict, index.clone(), value);
+            Ok(())
+        }
+        PyValue::ByteArray(data) => {
+            let idx = index.as_int().ok_or_else(|| Error::Type {
+                expected: "int".to_string(),
+                got: index.type_name().to_string(),
+            })?;
+            let byte = bytes::byte_value(&value)?;
+            data.write(|data| {
+                let len = data.len() as i64;
+                let actual = if idx < 0 { len + idx } else { idx };
+                if (0..len).contains(&actual) {
+                    data[actual as usize] = byte;
+                    return Ok(());
+                }
+                Err(ExceptionValue::new(
+                    "IndexError",
+                    vec![PyValue::Str("bytearray index out of range".to_string())],
+                )
+                .into())
+            })
+        }
+        _ => Err(Error::exception(
+            "TypeError",
+            format!(
+                "'{}' object does not support item assignment",
+                object.type_name()
+            ),
+        )),
+    }
+}
+
+/// Bind call arguments to the parameters of `func`, returning the new
+/// frame's locals. Errors follow CPython's `TypeError` messages.
+fn bind_arguments(
+    func: &FunctionDef,
+    name: &str,
+    pos_args: Vec<PyValue>,
+    kw_pairs: Vec<(String, PyValue)>,
+) -> Result<HashMap<String, PyValue>> {
+    let type_error =
+        |message: String| Error::exception("TypeError", format!("{}() {}", name, message));
+    let n_params = func.params.len();
+    let n_given = pos_args.len();
+
+    // Step 1: Bind positional args to named params
+    let mut bound: Vec<Option<PyValue>> = vec![None; n_params];
+    let mut extra_positional = Vec::new();
+    for (i, val) in pos_args.into_iter().enumerate() {
+        match bound.get_mut(i) {
+            Some(slot) => *slot = Some(val),
+            None => extra_positional.push(val),
+        }
+    }
+    if !extra_positional.is_empty() && func.vararg.is_none() {
+        let n_required = n_params - func.defaults.len();
+        let takes = if n_required == n_params {
+            format!("{} positional argument{}", n_params, plural(n_params))
+        } else {
+            format!("from {} to {} positional arguments", n_required, n_params)
+        };
+        let given = if n_given == 1 { "was" } else { "were" };
+        return Err(type_error(format!(
+            "takes {} but {} {} given",
+            takes, n_given, given
+        )));
+    }
+
+    // Step 2: Map keyword args to named params or collect into **kwargs
+    let mut kwonly: Vec<Option<PyValue>> = vec![None; func.kwonly.len()];
+    let mut extra_kwargs: Vec<(PyValue, PyValue)> = Vec::new();
+    let mut posonly_passed = Vec::new();
+    for (kw_name, kw_val) in kw_pairs {
+        let slot = match func.params.iter().position(|p| p == &kw_name) {
+            Some(pos) if pos >= func.posonly => Some(&mut bound[pos]),
+            _ => func
+                .kwonly
+                .iter()
+                .position(|p| p == &kw_name)
+                .map(|pos| &mut kwonly[pos]),
+        };
+        if let Some(slot) = slot {
+            if slot.is_some() {
+                return Err(type_error(format!(
+                    "got multiple values for argument '{}'",
+                    kw_name
+                )));
+            }
+            *slot = Some(kw_val);
+        } else if func.kwarg.is_some() {
+            extra_kwargs.push((PyValue::Str(kw_name), kw_val));
+        } else if func.params[..func.posonly].contains(&kw_name) {
+            posonly_passed.push(kw_name);
+        } else {
+            return Err(type_error(format!(
+                "got an unexpected keyword argument '{}'",
+                kw_name
+            )));
+        }
+    }
+    if !posonly_passed.is_empty() {
+        return Err(type_error(format!(
+            "got some positional-only arguments passed as keyword arguments: '{}'",
+            posonly_passed.join(", ")
+        )));
+    }
+
+    // Step 3: Fill missing params from defaults
+    let first_default = n_params - func.defaults.len();
+    let mut missing = Vec::new();
+    for (i, slot) in bound.iter_mut().enumerate() {
+        if slot.is_none() {
+            match i.checked_sub(first_default) {
+                Some(default_idx) => *slot = Some(func.defaults[default_idx].clone()),
+                None => missing.push(func.params[i].as_str()),
+            }
+        }
+    }
+    if !missing.is_empty() {
+        return Err(type_error(format!(
+            "missing {} required positional argument{}: {}",
+            missing.len(),
+            plural(missing.len()),
+            name_list(&missing)
+        )));
+    }
+    let mut missing = Vec::new();
+    for (param, slot) in func.kwonly.iter().zip(&mut kwonly) {
+        if slot.is_none() {
+            match func.kw_defaults.iter().find(|(name, _)| name == param) {
+                Some((_, default)) => *slot = Some(default.clone()),
+                None => missing.push(param.as_str()),
+            }
+        }
+    }
+    if !missing.is_empty() {
+        return Err(type_error(format!(
+            "missing {} required keyword-only argument{}: {}",
+            missing.len(),
+            plural(missing.len()),
+            name_list(&missing)
+        )));
+    }
+
+    let mut locals = HashMap::new();
+    let params = func.params.iter().chain(&func.kwonly);
+    for (param, val) in params.zip(bound.into_iter().chain(kwonly)) {
+        locals.insert(param.clone(), val.unwrap());
+    }
+    // *args is a tuple in Python
+    if let Some(ref vararg_name) = func.vararg {
+        locals.insert(vararg_name.clone(), PyValue::Tuple(extra_positional));
+    }
+    if let Some(ref kwarg_name) = func.kwarg {
+        locals.insert(kwarg_name.clone(), PyValue::dict(extra_kwargs));
+    }
+    Ok(locals)
+}
+
+/// `"s"` unless `n` is 1.
+fn plural(n: usize) -> &'static str {
+    if n == 1 { "" } else { "s" }
+}
+
+/// Quote and join parameter names the way CPython's argument errors do:
+/// `'a'`, `'a' and 'b'`, `'a', 'b', and 'c'`.
+fn name_list(names: &[&str]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+    match quoted.as_slice() {
+        [] => String::new(),
+        [one] => one.clone(),
+        [first, second] => format!("{} and {}", first, second),
+        [init @ .., last] => format!("{}, and {}", init.join(", "), last),
+    }
+}
+
+/// Build a set from `items`, dropping duplicates.
+fn build_set(items: Vec<PyValue>) -> Result<PyValue> {
+    items.iter().try_for_each(check_hashable)?;
+    Ok(PyValue::set(items))
+}
+
+/// Perform `del object[index]`.
+fn delete_subscript(object: &PyValue, index: &PyValue) -> Result<()> {
+    match object {
+        PyValue::List(list) => {
+            let idx = index.as_int().ok_or_else(|| Error::Type {
+                expected: "int".to_string(),
+                got: index.type_name().to_string(),
+            })?;
+            list.write(|items| {
+                let len = items.len() as i64;
+                let actual = if idx < 0 { len + idx } else { idx };
+                if (0..len).contains(&actual) {
+                    items.remove(actual as usize);
+                    return Ok(());
+                }
+                Err(ExceptionValue::new(
+                    "IndexError",
+                    vec![PyValue::Str(
+                        "list assignment index out of range".to_string(),
+                    )],
+                )
+                .into())
+            })
+        }
+        PyValue::Dict(dict) => {
+            check_hashable(index)?;
+            match dict.write(|pairs| pairs.remove(index)) {
+                Some(_) => Ok(()),
+                None => Err(ExceptionValue::new("KeyError", vec![index.clone()]).into()),
+            }
+        }
+        PyValue::ByteArray(data) => {
+            let idx = index.as_int().ok_or_else(|| Error::Type {
+                expected: "int".to_string(),
+                got: index.type_name().to_string(),
+            })?;
+            data.write(|data| {
+                let len = data.len() as i64;
+                let actual = if idx < 0 { len + idx } else { idx };
+                if (0..len).contains(&actual) {
+                    data.remove(actual as usize);
+                    return Ok(());
+                }
+                Err(ExceptionValue::new(
+                    "IndexError",
+                    vec![PyValue::Str("bytearray index out of range".to_string())],
+                )
+                .into())
+            })
+        }
+        _ => Err(Error::exception(
+            "TypeError",
+            format!(
+                "'{}' object does not support item deletion",
+                object.type_name()
+            ),
+        )),
+    }
+}
+
+/// Whether `value` is or contains an instance of a user-defined class, and
+/// so may need `__str__`/`__repr__` to be called to display it.
+fn contains_instance(value: &PyValue) -> bool {
+    match value {
+        PyValue::Instance(_) => true,
+        PyValue::Tuple(items) => items.iter().any(contains_instance),
+        // A container that contains itself is checked once
+        PyValue::List(items) => ReprGuard::enter(items.id())
+            .is_some_and(|_guard| items.read().iter().any(contains_instance)),
+        PyValue::Set(set) => set.read().iter().any(contains_instance),
+        PyValue::FrozenSet(set) => set.iter().any(contains_instance),
+        PyValue::Dict(pairs) => ReprGuard::enter(pairs.id()).is_some_and(|_guard| {
+            pairs
+                .read()
+                .iter()
+                .any(|(k, v)| contains_instance(k) || contains_instance(v))
+        }),
+        PyValue::DictView(view) => view.items().iter().any(contains_instance),
+        _ => false,
+    }
 }
 
-/// Compare two PyValues for sorting (used by `sorted()`).
+/// Compare two PyValues for sorting (used by `sorted()`). Values that do
+/// not order compare equal.
 fn compare_for_sort(a: &PyValue, b: &PyValue) -> std::cmp::Ordering {
-    match (a, b) {
-        (PyValue::Int(x), PyValue::Int(y)) => x.cmp(y),
-        (PyValue::Float(x), PyValue::Float(y)) => {
-            x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal)
-        }
-        (PyValue::Int(x), PyValue::Float(y)) => (*x as f64)
-            .partial_cmp(y)
-            .unwrap_or(std::cmp::Ordering::Equal),
-        (PyValue::Float(x), PyValue::Int(y)) => x
-            .partial_cmp(&(*y as f64))
-            .unwrap_or(std::cmp::Ordering::Equal),
-        (PyValue::Str(x), PyValue::Str(y)) => x.cmp(y),
-        _ => std::cmp::Ordering::Equal,
+    let less = |x, y| apply_cmpop(&CmpOp::Lt, x, y).unwrap_or(false);
+    if less(a, b) {
+        std::cmp::Ordering::Less
+    } else if less(b, a) {
+        std::cmp::Ordering::Greater
+    } else {
+        std::cmp::Ordering::Equal
     }
 }
 
 /// Search the exception table for a handler covering the given instruction index.
 ///
-/// Returns the last (innermost) matching entry, since nested try blocks
-/// produce entries that appear later in the table.
+/// Protected ranges are properly nested, so the innermost handler is the
+/// matching entry with the smallest range.
 fn find_handler(table: &[ExceptionEntry], ip: usize) -> Option<ExceptionEntry> {
     let ip = ip as u32;
-    // Search in reverse so inner handlers take precedence
     table
         .iter()
-        .rev()
-        .find(|e| ip >= e.start && ip < e.end)
+        .filter(|e| ip >= e.start && ip < e.end)
+        .min_by_key(|e| e.end - e.start)
         .cloned()
 }
@@ -1,4 +1,4 @@
-use littrs::{Limits, PyValue, Sandbox};
+use littrs::{BigInt, FromPyValue, Limits, PyValue, Sandbox};
 use std::f64::consts::PI;
 
 #[test]
@@ -43,7 +43,7 @@ fn test_lists() {
 
     assert_eq!(
         sandbox.run("[1, 2, 3]").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 
     sandbox.run("nums = [10, 20, 30]").unwrap();
@@ -150,15 +150,15 @@ fn test_builtin_range() {
 
     assert_eq!(
         sandbox.run("range(3)").unwrap(),
-        PyValue::List(vec![PyValue::Int(0), PyValue::Int(1), PyValue::Int(2)])
+        PyValue::list(vec![PyValue::Int(0), PyValue::Int(1), PyValue::Int(2)])
     );
     assert_eq!(
         sandbox.run("range(1, 4)").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
     assert_eq!(
         sandbox.run("range(0, 10, 2)").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(0),
             PyValue::Int(2),
             PyValue::Int(4),
@@ -168,6 +168,19 @@ fn test_builtin_range() {
     );
 }
 
+#[test]
+fn test_huge_range_is_not_materialized() {
+    let mut sandbox = Sandbox::with_builtins();
+    for code in ["range(10**18)", "list(range(10**18))"] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains("MemoryError"), "{}: {}", code, err);
+    }
+    assert_eq!(
+        sandbox.run("len(range(10**18))").unwrap(),
+        PyValue::Int(10i64.pow(18))
+    );
+}
+
 #[test]
 fn test_builtin_sum_min_max() {
     let mut sandbox = Sandbox::new();
@@ -211,7 +224,7 @@ fn test_tool_returning_dict() {
 
     sandbox.register_fn("get_user", |args| {
         let id = args[0].as_int().unwrap_or(0);
-        PyValue::Dict(vec![
+        PyValue::dict(vec![
             (PyValue::Str("id".to_string()), PyValue::Int(id)),
             (
                 PyValue::Str("name".to_string()),
@@ -360,7 +373,7 @@ fn test_tool_with_keyword_arguments() {
         .returns("str");
 
     sandbox.register_tool(info, |args| {
-        let name = args.get(0).and_then(|v| v.as_str()).unwrap_or("World");
+        let name = args.first().and_then(|v| v.as_str()).unwrap_or("World");
         let greeting = args.get(1).and_then(|v| v.as_str()).unwrap_or("Hello");
         PyValue::Str(format!("{}, {}!", greeting, name))
     });
@@ -399,7 +412,7 @@ fn test_list_comprehension_basic() {
     // Basic list comprehension
     assert_eq!(
         sandbox.run("[x for x in range(5)]").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(0),
             PyValue::Int(1),
             PyValue::Int(2),
@@ -416,7 +429,7 @@ fn test_list_comprehension_with_expression() {
     // List comprehension with expression
     assert_eq!(
         sandbox.run("[x * 2 for x in range(4)]").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(0),
             PyValue::Int(2),
             PyValue::Int(4),
@@ -427,7 +440,7 @@ fn test_list_comprehension_with_expression() {
     // Squares
     assert_eq!(
         sandbox.run("[x ** 2 for x in range(1, 5)]").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(1),
             PyValue::Int(4),
             PyValue::Int(9),
@@ -443,7 +456,7 @@ fn test_list_comprehension_with_filter() {
     // List comprehension with if filter
     assert_eq!(
         sandbox.run("[x for x in range(10) if x % 2 == 0]").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(0),
             PyValue::Int(2),
             PyValue::Int(4),
@@ -455,7 +468,7 @@ fn test_list_comprehension_with_filter() {
     // Filter with expression
     assert_eq!(
         sandbox.run("[x * 2 for x in range(5) if x > 1]").unwrap(),
-        PyValue::List(vec![PyValue::Int(4), PyValue::Int(6), PyValue::Int(8),])
+        PyValue::list(vec![PyValue::Int(4), PyValue::Int(6), PyValue::Int(8),])
     );
 }
 
@@ -467,7 +480,7 @@ fn test_list_comprehension_over_list() {
 
     assert_eq!(
         sandbox.run("[n + 10 for n in nums]").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(11),
             PyValue::Int(12),
             PyValue::Int(13),
@@ -484,7 +497,7 @@ fn test_list_comprehension_over_string() {
     // Iterate over string characters
     assert_eq!(
         sandbox.run("[c for c in 'abc']").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("a".to_string()),
             PyValue::Str("b".to_string()),
             PyValue::Str("c".to_string()),
@@ -501,7 +514,7 @@ fn test_list_comprehension_nested() {
         sandbox
             .run("[x * y for x in range(1, 3) for y in range(1, 3)]")
             .unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(1), // 1*1
             PyValue::Int(2), // 1*2
             PyValue::Int(2), // 2*1
@@ -519,7 +532,7 @@ fn test_list_comprehension_multiple_filters() {
         sandbox
             .run("[x for x in range(20) if x % 2 == 0 if x % 3 == 0]")
             .unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(0),
             PyValue::Int(6),
             PyValue::Int(12),
@@ -774,7 +787,7 @@ result
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(1),
             PyValue::Int(2),
             PyValue::Int(4),
@@ -805,7 +818,7 @@ result
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(0),
             PyValue::Int(1),
             PyValue::Int(10),
@@ -965,7 +978,7 @@ def f(a, b=2, c=3):
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![PyValue::Int(6), PyValue::Int(24), PyValue::Int(51),])
+        PyValue::list(vec![PyValue::Int(6), PyValue::Int(24), PyValue::Int(51),])
     );
 }
 
@@ -1018,7 +1031,7 @@ def f(x, y=None):
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![PyValue::Int(5), PyValue::Int(8)])
+        PyValue::list(vec![PyValue::Int(5), PyValue::Int(8)])
     );
 }
 
@@ -1125,17 +1138,12 @@ fn test_try_except_with_as() {
 try:
     x = 1 / 0
 except ZeroDivisionError as e:
-    msg = e
+    msg = str(e)
 msg
 "#,
         )
         .unwrap();
-    // The error message should contain "Division by zero"
-    if let PyValue::Str(s) = result {
-        assert!(s.contains("ivision by zero") || s.contains("zero"));
-    } else {
-        panic!("Expected string, got {:?}", result);
-    }
+    assert_eq!(result, PyValue::Str("division by zero".to_string()));
 }
 
 #[test]
@@ -1225,16 +1233,12 @@ fn test_raise_with_message_as() {
 try:
     raise ValueError("test message")
 except ValueError as e:
-    msg = e
+    msg = str(e)
 msg
 "#,
         )
         .unwrap();
-    if let PyValue::Str(s) = result {
-        assert!(s.contains("test message"));
-    } else {
-        panic!("Expected string, got {:?}", result);
-    }
+    assert_eq!(result, PyValue::Str("test message".to_string()));
 }
 
 #[test]
@@ -1335,6 +1339,346 @@ except:
     assert!(err.to_string().contains("Instruction limit"));
 }
 
+// ============================================================================
+// Try/Finally
+// ============================================================================
+
+#[test]
+fn test_try_finally_normal_exit() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+log = []
+try:
+    log.append("body")
+finally:
+    log.append("finally")
+log
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Str("body".to_string()),
+            PyValue::Str("finally".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_try_finally_uncaught_exception() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+try:
+    raise ValueError("boom")
+finally:
+    print("cleanup")
+"#;
+    let err = sandbox.run(code).unwrap_err();
+    assert!(err.to_string().contains("ValueError"));
+    assert!(err.to_string().contains("boom"));
+
+    let code = r#"
+log = []
+def work():
+    try:
+        raise KeyError("missing")
+    finally:
+        log.append("cleanup")
+
+try:
+    work()
+except KeyError:
+    log.append("caught")
+log
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Str("cleanup".to_string()),
+            PyValue::Str("caught".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_try_except_finally() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+log = []
+for value in [1, 0]:
+    try:
+        log.append(10 // value)
+    except ZeroDivisionError:
+        log.append("div")
+    else:
+        log.append("else")
+    finally:
+        log.append("fin")
+log
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Int(10),
+            PyValue::Str("else".to_string()),
+            PyValue::Str("fin".to_string()),
+            PyValue::Str("div".to_string()),
+            PyValue::Str("fin".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_try_finally_exception_in_handler() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+log = []
+try:
+    try:
+        raise ValueError("first")
+    except ValueError:
+        raise TypeError("second")
+    finally:
+        log.append("fin")
+except TypeError:
+    log.append("outer")
+log
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Str("fin".to_string()),
+            PyValue::Str("outer".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_try_finally_return() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+log = []
+def f():
+    try:
+        return "result"
+    finally:
+        log.append("fin")
+
+[f(), log]
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Str("result".to_string()),
+            PyValue::list(vec![PyValue::Str("fin".to_string())]),
+        ])
+    );
+}
+
+#[test]
+fn test_try_finally_return_overrides() {
+    let mut sandbox = Sandbox::new();
+
+    // A return in finally replaces the pending return value...
+    let code = r#"
+def f():
+    try:
+        return 1
+    finally:
+        return 2
+f()
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(2));
+
+    // ...and swallows a pending exception
+    let code = r#"
+def g():
+    try:
+        raise ValueError("lost")
+    finally:
+        return "swallowed"
+g()
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::Str("swallowed".to_string())
+    );
+}
+
+#[test]
+fn test_try_finally_break_continue() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+log = []
+for i in range(5):
+    try:
+        if i == 1:
+            continue
+        if i == 3:
+            break
+        log.append(i)
+    finally:
+        log.append("f" + str(i))
+log
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Int(0),
+            PyValue::Str("f0".to_string()),
+            PyValue::Str("f1".to_string()),
+            PyValue::Int(2),
+            PyValue::Str("f2".to_string()),
+            PyValue::Str("f3".to_string()),
+        ])
+    );
+
+    let code = r#"
+n = 0
+seen = []
+while True:
+    try:
+        n += 1
+        if n == 3:
+            break
+    finally:
+        seen.append(n)
+seen
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+    );
+}
+
+#[test]
+fn test_nested_try_finally_order() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+log = []
+def f():
+    try:
+        try:
+            return "r"
+        finally:
+            log.append("inner")
+    finally:
+        log.append("outer")
+
+[f(), log]
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Str("r".to_string()),
+            PyValue::list(vec![
+                PyValue::Str("inner".to_string()),
+                PyValue::Str("outer".to_string()),
+            ]),
+        ])
+    );
+}
+
+#[test]
+fn test_exception_in_finally_during_return() {
+    let mut sandbox = Sandbox::new();
+
+    // The finally body runs once even when it raises on the return path
+    let code = r#"
+log = []
+def f():
+    try:
+        return 1
+    finally:
+        log.append("fin")
+        raise KeyError("from finally")
+
+try:
+    f()
+except KeyError:
+    log.append("caught")
+log
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Str("fin".to_string()),
+            PyValue::Str("caught".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_return_from_except_handler() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+log = []
+def f():
+    try:
+        raise ValueError("x")
+    except ValueError:
+        return "handled"
+    finally:
+        log.append("fin")
+
+[f(), log]
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Str("handled".to_string()),
+            PyValue::list(vec![PyValue::Str("fin".to_string())]),
+        ])
+    );
+}
+
+#[test]
+fn test_nested_try_inner_handler_wins() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+try:
+    try:
+        1 / 0
+    except ZeroDivisionError:
+        where = "inner"
+except:
+    where = "outer"
+where
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::Str("inner".to_string())
+    );
+}
+
+#[test]
+fn test_exception_inside_loops_resets_iterators() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+seen = []
+for a in [1, 2, 3]:
+    try:
+        for b in ["x", "y"]:
+            raise ValueError("stop")
+    except ValueError:
+        seen.append(a)
+seen
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+    );
+}
+
 // ============================================================================
 // *args and **kwargs
 // ============================================================================
@@ -1373,7 +1717,7 @@ f(1, 2, 3)
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(1),
             PyValue::Tuple(vec![PyValue::Int(2), PyValue::Int(3)])
         ])
@@ -1411,7 +1755,7 @@ f(x=1, y=2)
         .unwrap();
     assert_eq!(
         result,
-        PyValue::Dict(vec![
+        PyValue::dict(vec![
             (PyValue::Str("x".to_string()), PyValue::Int(1)),
             (PyValue::Str("y".to_string()), PyValue::Int(2)),
         ])
@@ -1433,9 +1777,9 @@ f(1, x=2, y=3)
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(1),
-            PyValue::Dict(vec![
+            PyValue::dict(vec![
                 (PyValue::Str("x".to_string()), PyValue::Int(2)),
                 (PyValue::Str("y".to_string()), PyValue::Int(3)),
             ])
@@ -1456,7 +1800,7 @@ f(1)
 "#,
         )
         .unwrap();
-    assert_eq!(result, PyValue::Dict(vec![]));
+    assert_eq!(result, PyValue::dict(vec![]));
 }
 
 #[test]
@@ -1474,11 +1818,11 @@ f(1, 10, 20, 30, x=99)
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(1),
             PyValue::Int(10),
             PyValue::Tuple(vec![PyValue::Int(20), PyValue::Int(30)]),
-            PyValue::Dict(vec![(PyValue::Str("x".to_string()), PyValue::Int(99))]),
+            PyValue::dict(vec![(PyValue::Str("x".to_string()), PyValue::Int(99))]),
         ])
     );
 }
@@ -1534,6 +1878,4590 @@ f(1, 2, c=3)
     assert!(err.to_string().contains("unexpected keyword"));
 }
 
+#[test]
+fn test_keyword_only_and_positional_only_params() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+def f(a, /, b, *, limit=10, flag):
+    return [a, b, limit, flag]
+print(f(1, 2, flag=True))
+print(f(1, b=2, flag=0, limit=3))
+def join(*parts, sep=", "):
+    return sep.join(list(parts))
+print(join("a", "b"), join("a", "b", sep="|"))
+def collect(a, /, **kw):
+    return a, kw
+print(collect(1, a=2))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "[1, 2, 10, True]",
+            "[1, 2, 3, 0]",
+            "a, b a|b",
+            "(1, {'a': 2})",
+        ]
+    );
+}
+
+#[test]
+fn test_argument_binding_errors() {
+    let mut sandbox = Sandbox::new();
+    sandbox
+        .run(
+            r#"
+def f(a, /, b, *, limit=10, flag):
+    pass
+def g(a, b=1):
+    pass
+"#,
+        )
+        .unwrap();
+    let cases = [
+        (
+            "f(a=1, b=2, flag=1)",
+            "f() got some positional-only arguments passed as keyword arguments: 'a'",
+        ),
+        (
+            "f(1, 2)",
+            "f() missing 1 required keyword-only argument: 'flag'",
+        ),
+        (
+            "f()",
+            "f() missing 2 required positional arguments: 'a' and 'b'",
+        ),
+        (
+            "f(1, 2, flag=1, b=3)",
+            "f() got multiple values for argument 'b'",
+        ),
+        (
+            "f(1, 2, flag=1, zz=3)",
+            "f() got an unexpected keyword argument 'zz'",
+        ),
+        (
+            "g(1, 2, 3)",
+            "g() takes from 1 to 2 positional arguments but 3 were given",
+        ),
+    ];
+    for (code, message) in cases {
+        let source = format!(
+            "try:\n    {}\nexcept TypeError as e:\n    result = str(e)\nresult",
+            code
+        );
+        assert_eq!(
+            sandbox.run(&source).unwrap(),
+            PyValue::Str(message.to_string()),
+            "{}",
+            code
+        );
+    }
+}
+
+#[test]
+fn test_operator_type_error_messages() {
+    let mut sandbox = Sandbox::new();
+    let cases = [
+        ("'a' + 1", "can only concatenate str (not \"int\") to str"),
+        (
+            "[1] + (2,)",
+            "can only concatenate list (not \"tuple\") to list",
+        ),
+        (
+            "'a' * 'b'",
+            "can't multiply sequence by non-int of type 'str'",
+        ),
+        (
+            "'a' - 1",
+            "unsupported operand type(s) for -: 'str' and 'int'",
+        ),
+        (
+            "1 / None",
+            "unsupported operand type(s) for /: 'int' and 'NoneType'",
+        ),
+        (
+            "2 ** 'a'",
+            "unsupported operand type(s) for ** or pow(): 'int' and 'str'",
+        ),
+        (
+            "1 | 'a'",
+            "unsupported operand type(s) for |: 'int' and 'str'",
+        ),
+        ("len(5)", "object of type 'int' has no len()"),
+    ];
+    for (code, message) in cases {
+        let source = format!(
+            "try:\n    {}\nexcept TypeError as e:\n    result = str(e)\nresult",
+            code
+        );
+        assert_eq!(
+            sandbox.run(&source).unwrap(),
+            PyValue::Str(message.to_string()),
+            "{}",
+            code
+        );
+    }
+}
+
+#[test]
+fn test_defaults_evaluated_at_definition() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+items = [1]
+def record(x=len(items), seen=[]):
+    seen.append(x)
+    return seen
+items.append(2)
+print(record(), record())
+n = 5
+def make():
+    m = 3
+    return lambda q=m * n, *, r=n: (q, r)
+print(make()())
+print(sorted([3, 1, 2], key=lambda v, sign=-1: v * sign))
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["[1, 1] [1, 1]", "(15, 5)", "[3, 2, 1]"]);
+}
+
+#[test]
+fn test_call_site_unpacking() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+def f(a, b, c=0, **kw):
+    return [a, b, c, kw]
+args = [1, 2]
+opts = {"c": 3, "d": 4}
+print(f(*args))
+print(f(*args, **opts))
+print(f(0, *[5], c=9, **{"z": 1}))
+g = f
+print(g(*(1, 2), **opts))
+print(max(*[3, 9, 2]), "{}-{}".format(*["a", "b"]))
+items = []
+items.extend(*[[8, 9]])
+print(items)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "[1, 2, 0, {}]",
+            "[1, 2, 3, {'d': 4}]",
+            "[0, 5, 9, {'z': 1}]",
+            "[1, 2, 3, {'d': 4}]",
+            "9 a-b",
+            "[8, 9]",
+        ]
+    );
+}
+
+#[test]
+fn test_call_site_unpacking_super_and_closures() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+class Base:
+    def __init__(self, *args, **kwargs):
+        self.args = args
+        self.kwargs = kwargs
+class Child(Base):
+    def __init__(self, *args, **kwargs):
+        super().__init__(*args, **kwargs)
+def wrap(func):
+    def wrapper(*args, **kwargs):
+        return func(*args, **kwargs)
+    return wrapper
+child = Child(1, 2, k=3)
+[child.args, child.kwargs, wrap(lambda x, y=0: x + y)(1, y=2)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Tuple(vec![PyValue::Int(1), PyValue::Int(2)]),
+            PyValue::dict(vec![(PyValue::Str("k".to_string()), PyValue::Int(3))]),
+            PyValue::Int(3),
+        ])
+    );
+}
+
+#[test]
+fn test_call_site_unpacking_tool() {
+    use littrs::ToolInfo;
+
+    let mut sandbox = Sandbox::new();
+    let info = ToolInfo::new("greet", "Greet someone")
+        .arg("name", "str", "The name")
+        .arg_opt("greeting", "str", "The greeting")
+        .returns("str");
+    sandbox.register_tool(info, |args| {
+        let name = args.first().and_then(|v| v.as_str()).unwrap_or("World");
+        let greeting = args.get(1).and_then(|v| v.as_str()).unwrap_or("Hello");
+        PyValue::Str(format!("{}, {}!", greeting, name))
+    });
+
+    assert_eq!(
+        sandbox
+            .run("opts = {'greeting': 'Hey'}\ngreet(*['Ann'], **opts)")
+            .unwrap(),
+        PyValue::Str("Hey, Ann!".to_string())
+    );
+    assert_eq!(
+        sandbox.run("greet(**{'name': 'Bo'})").unwrap(),
+        PyValue::Str("Hello, Bo!".to_string())
+    );
+}
+
+#[test]
+fn test_call_site_unpacking_errors() {
+    let mut sandbox = Sandbox::new();
+    let err = sandbox
+        .run("def f(**kw):\n    return kw\nf(**{1: 2})")
+        .unwrap_err();
+    assert!(err.to_string().contains("keywords must be strings"));
+
+    let err = sandbox.run("{**[1]}").unwrap_err();
+    assert!(err.to_string().contains("'list' object is not a mapping"));
+}
+
+#[test]
+fn test_display_unpacking() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+a = [1, 2]
+b = (3,)
+print([*a, *b, 4], (*a, 0), {*a, *a, 5})
+print([*range(3), *"ab", *(x * 2 for x in a)])
+base = {"x": 1, "y": 2}
+print({**base, "y": 3, **{"z": 4}})
+print({"a": 0, **base})
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "[1, 2, 3, 4] (1, 2, 0) {1, 2, 5}",
+            "[0, 1, 2, 'a', 'b', 2, 4]",
+            "{'x': 1, 'y': 3, 'z': 4}",
+            "{'a': 0, 'x': 1, 'y': 2}",
+        ]
+    );
+}
+
+// ============================================================================
+// Exception objects
+// ============================================================================
+
+#[test]
+fn test_exception_hierarchy_lookup_error() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+caught = []
+try:
+    {}["missing"]
+except LookupError as e:
+    caught.append(type(e).__name__)
+try:
+    [1, 2][5]
+except LookupError as e:
+    caught.append(type(e).__name__)
+caught
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("KeyError".to_string()),
+            PyValue::Str("IndexError".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_exception_hierarchy_arithmetic_and_os_errors() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+caught = []
+try:
+    1 // 0
+except ArithmeticError:
+    caught.append("arithmetic")
+try:
+    open("/missing.txt")
+except OSError as e:
+    caught.append(type(e).__name__)
+caught
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("arithmetic".to_string()),
+            PyValue::Str("FileNotFoundError".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_exception_subclass_handler_does_not_catch_sibling() {
+    let mut sandbox = Sandbox::new();
+
+    let err = sandbox
+        .run(
+            r#"
+try:
+    [][0]
+except KeyError:
+    pass
+"#,
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("IndexError"));
+}
+
+#[test]
+fn test_builtin_errors_raise_cpython_exception_types() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .capture(
+            r#"
+try:
+    [].pop()
+except IndexError as e:
+    print("IndexError", e)
+try:
+    set().pop()
+except KeyError as e:
+    print("KeyError", e)
+try:
+    [1, 2][::0]
+except ValueError as e:
+    print("ValueError", e)
+try:
+    max([])
+except ValueError as e:
+    print("ValueError", e)
+try:
+    [1].index(5)
+except ValueError as e:
+    print("ValueError", e)
+try:
+    [1].remove(5)
+except ValueError as e:
+    print("ValueError", e)
+try:
+    range(1, 2, 0)
+except ValueError as e:
+    print("ValueError", e)
+try:
+    [1][3] = 0
+except IndexError as e:
+    print("IndexError", e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "IndexError pop from empty list",
+            "KeyError 'pop from an empty set'",
+            "ValueError slice step cannot be zero",
+            "ValueError max() arg is an empty sequence",
+            "ValueError 5 is not in list",
+            "ValueError list.remove(x): x not in list",
+            "ValueError range() arg 3 must not be zero",
+            "IndexError list assignment index out of range",
+        ]
+    );
+}
+
+#[test]
+fn test_exception_args_str_and_repr() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+try:
+    raise ValueError("bad input", 42)
+except ValueError as e:
+    info = (e.args, str(e))
+try:
+    raise ValueError("bad input")
+except ValueError as e:
+    info = info + (str(e), repr(e))
+info
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::Tuple(vec![
+                PyValue::Str("bad input".to_string()),
+                PyValue::Int(42),
+            ]),
+            PyValue::Str("('bad input', 42)".to_string()),
+            PyValue::Str("bad input".to_string()),
+            PyValue::Str("ValueError('bad input')".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_key_error_str_is_key_repr() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+try:
+    {"a": 1}["b"]
+except KeyError as e:
+    msg = str(e)
+    key = e.args[0]
+(msg, key)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::Str("'b'".to_string()),
+            PyValue::Str("b".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_raise_bare_exception_class() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+try:
+    raise ValueError
+except ValueError as e:
+    result = (e.args, repr(e))
+result
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::Tuple(vec![]),
+            PyValue::Str("ValueError()".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_raise_exception_value() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+err = KeyError("k")
+try:
+    raise err
+except LookupError as e:
+    caught = e
+caught.args
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Tuple(vec![PyValue::Str("k".to_string())]));
+}
+
+#[test]
+fn test_raise_non_exception_is_type_error() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+try:
+    raise 42
+except TypeError as e:
+    msg = str(e)
+msg
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Str("exceptions must derive from BaseException".to_string())
+    );
+}
+
+#[test]
+fn test_except_tuple_of_types() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+caught = []
+for value in ["x", None]:
+    try:
+        if value is None:
+            raise TypeError("none")
+        int(value)
+    except (TypeError, ValueError) as e:
+        caught.append(type(e).__name__)
+caught
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("ValueError".to_string()),
+            PyValue::Str("TypeError".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_raise_from_sets_cause() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+try:
+    try:
+        int("abc")
+    except ValueError as e:
+        raise RuntimeError("could not parse") from e
+except RuntimeError as e:
+    result = (str(e), type(e.__cause__).__name__, e.__suppress_context__)
+result
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::Str("could not parse".to_string()),
+            PyValue::Str("ValueError".to_string()),
+            PyValue::Bool(true),
+        ])
+    );
+}
+
+#[test]
+fn test_raise_from_none() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+try:
+    try:
+        {}["x"]
+    except KeyError:
+        raise ValueError("missing") from None
+except ValueError as e:
+    result = (e.__cause__, e.__suppress_context__, type(e.__context__).__name__)
+result
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::None,
+            PyValue::Bool(true),
+            PyValue::Str("KeyError".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_implicit_exception_context() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+try:
+    try:
+        1 / 0
+    except ZeroDivisionError:
+        raise ValueError("while handling")
+except ValueError as e:
+    result = (type(e.__context__).__name__, e.__cause__)
+result
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::Str("ZeroDivisionError".to_string()),
+            PyValue::None,
+        ])
+    );
+}
+
+#[test]
+fn test_reraise_keeps_exception_object() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+try:
+    try:
+        raise KeyError("inner")
+    except KeyError:
+        raise
+except LookupError as e:
+    result = (e.args, e.__context__)
+result
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::Tuple(vec![PyValue::Str("inner".to_string())]),
+            PyValue::None,
+        ])
+    );
+}
+
+#[test]
+fn test_uncaught_exception_reaches_host() {
+    let mut sandbox = Sandbox::new();
+
+    let err = sandbox.run(r#"raise KeyError("gone")"#).unwrap_err();
+    match err {
+        littrs::Error::Exception(exc) => {
+            assert_eq!(exc.type_name(), "KeyError");
+            assert!(exc.is_instance("LookupError"));
+            assert_eq!(exc.to_string(), "KeyError: 'gone'");
+        }
+        other => panic!("Expected exception, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_custom_exception_class() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class RetryableError(Exception):
+    """Raised when a request can be retried."""
+
+try:
+    raise RetryableError("rate limited")
+except RetryableError as e:
+    result = (type(e).__name__, str(e), repr(e))
+result
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::Str("RetryableError".to_string()),
+            PyValue::Str("rate limited".to_string()),
+            PyValue::Str("RetryableError('rate limited')".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_custom_exception_inheritance_chain() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class AppError(Exception):
+    pass
+
+class NotFound(AppError):
+    pass
+
+class BadLookup(KeyError):
+    pass
+
+caught = []
+for exc in [NotFound("page"), AppError("app"), BadLookup("k"), ValueError("v")]:
+    try:
+        raise exc
+    except NotFound:
+        caught.append("not found")
+    except AppError:
+        caught.append("app")
+    except LookupError:
+        caught.append("lookup")
+    except Exception:
+        caught.append("other")
+caught
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("not found".to_string()),
+            PyValue::Str("app".to_string()),
+            PyValue::Str("lookup".to_string()),
+            PyValue::Str("other".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_custom_exception_defined_in_function() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def check(value):
+    class InvalidValue(ValueError):
+        pass
+    try:
+        if value < 0:
+            raise InvalidValue
+        return "ok"
+    except ValueError as e:
+        return type(e).__name__
+
+[check(1), check(-1)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("ok".to_string()),
+            PyValue::Str("InvalidValue".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_custom_exception_uncaught_shows_name() {
+    let mut sandbox = Sandbox::new();
+
+    let err = sandbox
+        .run(
+            r#"
+class QuotaExceeded(Exception):
+    pass
+
+raise QuotaExceeded("daily limit")
+"#,
+        )
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Runtime error: QuotaExceeded: daily limit");
+    match err {
+        littrs::Error::Exception(exc) => assert!(exc.is_instance("Exception")),
+        other => panic!("Expected exception, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_class_with_non_exception_base_rejected() {
+    let mut sandbox = Sandbox::new();
+
+    let err = sandbox
+        .run(
+            r#"
+Base = {}
+
+class Config(Base):
+    pass
+"#,
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("Config"));
+}
+
+#[test]
+fn test_exception_class_with_body_unsupported() {
+    let mut sandbox = Sandbox::new();
+
+    let err = sandbox
+        .run(
+            r#"
+class ApiError(Exception):
+    code = 500
+"#,
+        )
+        .unwrap_err();
+    assert!(matches!(err, littrs::Error::Unsupported(_)));
+}
+
+// ============================================================================
+// Classes
+// ============================================================================
+
+#[test]
+fn test_class_init_and_attributes() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Point:
+    def __init__(self, x, y=0):
+        self.x = x
+        self.y = y
+
+p = Point(3, y=4)
+[p.x, p.y, Point(1).y]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![PyValue::Int(3), PyValue::Int(4), PyValue::Int(0)])
+    );
+}
+
+#[test]
+fn test_class_methods_share_instance() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Counter:
+    def __init__(self):
+        self.count = 0
+
+    def increment(self, by=1):
+        self.count += by
+        return self.count
+
+c = Counter()
+alias = c
+c.increment()
+alias.increment(by=5)
+c.count
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Int(6));
+}
+
+#[test]
+fn test_class_methods_call_each_other() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Math:
+    def factorial(self, n):
+        return 1 if n <= 1 else n * self.factorial(n - 1)
+
+Math().factorial(5)
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Int(120));
+}
+
+#[test]
+fn test_class_variables() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Config:
+    retries = 3
+    name = "default"
+
+c = Config()
+c.name = "custom"
+[Config.retries, c.retries, Config.name, c.name]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Int(3),
+            PyValue::Int(3),
+            PyValue::Str("default".to_string()),
+            PyValue::Str("custom".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_class_inheritance_and_super() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Animal:
+    def __init__(self, name):
+        self.name = name
+
+    def describe(self):
+        return self.name + " makes a sound"
+
+class Dog(Animal):
+    def __init__(self, name, breed):
+        super().__init__(name)
+        self.breed = breed
+
+    def describe(self):
+        return super().describe() + " (" + self.breed + ")"
+
+d = Dog("Rex", "collie")
+[d.describe(), isinstance(d, Animal), isinstance(d, Dog), isinstance(Animal("x"), Dog)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("Rex makes a sound (collie)".to_string()),
+            PyValue::Bool(true),
+            PyValue::Bool(true),
+            PyValue::Bool(false),
+        ])
+    );
+}
+
+#[test]
+fn test_class_property() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Rect:
+    def __init__(self, w, h):
+        self.w = w
+        self.h = h
+
+    @property
+    def area(self):
+        return self.w * self.h
+
+r = Rect(2, 3)
+r.w = 5
+r.area
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Int(15));
+
+    let err = sandbox.run("r.area = 1").unwrap_err();
+    assert!(err.to_string().contains("has no setter"));
+}
+
+#[test]
+fn test_class_eq_and_ne() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .capture(
+            r#"
+class A:
+    def __init__(self, v):
+        self.v = v
+    def __eq__(self, other):
+        return isinstance(other, A) and self.v == other.v
+
+class B(A):
+    def __ne__(self, other):
+        return False
+
+a = A(1)
+print(A(1) == A(1), A(1) != A(2), A(1) != A(1), A(1) == 1, 1 == A(1), a == a, B(1) != B(2))
+try:
+    {A(1): 1}
+except TypeError as e:
+    print(e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "True True False False False True False",
+            "unhashable type: 'A'"
+        ]
+    );
+}
+
+#[test]
+fn test_class_unsupported_special_methods() {
+    let mut sandbox = Sandbox::new();
+    for (code, message) in [
+        (
+            "class C:\n    def __len__(self):\n        return 0",
+            "Special method '__len__' in class 'C'",
+        ),
+        (
+            "class C:\n    def __hash__(self):\n        return 0",
+            "Special method '__hash__' in class 'C'",
+        ),
+        (
+            "class A:\n    def __eq__(self, o):\n        return True\n[A()] == [A()]",
+            "custom __eq__ inside containers",
+        ),
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains(message), "{}: {}", code, err);
+    }
+}
+
+#[test]
+fn test_class_staticmethod_and_classmethod() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+class Shape:
+    count = 0
+
+    def __init__(self, n):
+        self.n = n
+
+    @staticmethod
+    def double(x):
+        return x * 2
+
+    @classmethod
+    def make(cls, n):
+        cls.count += 1
+        return cls(n)
+
+class Square(Shape):
+    pass
+
+s = Shape.make(5)
+q = Square.make(6)
+print(Shape.double(s.n), q.double(q.n), type(s).__name__, type(q).__name__, s.double(2))
+print(Shape.count, Square.count)
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["10 12 Shape Square 4", "1 2"]);
+}
+
+#[test]
+fn test_class_repr_and_str() {
+    let mut sandbox = Sandbox::new();
+
+    let output = sandbox
+        .capture(
+            r#"
+class Money:
+    def __init__(self, cents):
+        self.cents = cents
+
+    def __repr__(self):
+        return f"Money({self.cents})"
+
+    def __str__(self):
+        return f"${self.cents // 100}.{self.cents % 100}"
+
+class Plain:
+    pass
+
+m = Money(250)
+print(m)
+print(repr(m), str(m), f"{m}", [m])
+print(Plain())
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        output.output,
+        vec![
+            "$2.50",
+            "Money(250) $2.50 $2.50 [Money(250)]",
+            "<Plain object>"
+        ]
+    );
+}
+
+#[test]
+fn test_bound_method_as_value() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Greeter:
+    def __init__(self, greeting):
+        self.greeting = greeting
+
+    def greet(self, name):
+        return self.greeting + ", " + name
+
+hello = Greeter("Hello").greet
+list(map(hello, ["a", "b"]))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("Hello, a".to_string()),
+            PyValue::Str("Hello, b".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_class_container_attribute_mutation() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Cart:
+    def __init__(self):
+        self.items = []
+
+    def add(self, item):
+        self.items.append(item)
+        return self
+
+cart = Cart()
+cart.add("b").add("a")
+cart.items.append("c")
+cart.items.sort(reverse=True)
+cart.items
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("c".to_string()),
+            PyValue::Str("b".to_string()),
+            PyValue::Str("a".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_class_errors() {
+    let mut sandbox = Sandbox::new();
+
+    sandbox
+        .run(
+            r#"
+class Empty:
+    pass
+
+e = Empty()
+"#,
+        )
+        .unwrap();
+
+    let err = sandbox.run("e.missing").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("'Empty' object has no attribute 'missing'")
+    );
+
+    let err = sandbox.run("Empty(1)").unwrap_err();
+    assert!(err.to_string().contains("Empty() takes no arguments"));
+}
+
+#[test]
+fn test_class_multiple_inheritance_unsupported() {
+    let mut sandbox = Sandbox::new();
+
+    let err = sandbox
+        .run(
+            r#"
+class A:
+    pass
+
+class B:
+    pass
+
+class C(A, B):
+    pass
+"#,
+        )
+        .unwrap_err();
+    assert!(matches!(err, littrs::Error::Unsupported(_)));
+}
+
+// ============================================================================
+// Dataclasses and named tuples
+// ============================================================================
+
+#[test]
+fn test_dataclass_init_eq_repr() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    let output = sandbox
+        .capture(
+            r#"
+from dataclasses import dataclass
+
+@dataclass
+class Point:
+    x: int
+    y: int = 0
+
+    def total(self):
+        return self.x + self.y
+
+p = Point(1, y=2)
+print(p, [Point(3)])
+print(p == Point(1, 2), p == Point(2, 1), p.total())
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        output.output,
+        vec!["Point(x=1, y=2) [Point(x=3, y=0)]", "True False 3"]
+    );
+}
+
+#[test]
+fn test_dataclass_init_errors() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    sandbox
+        .run(
+            r#"
+from dataclasses import dataclass
+
+@dataclass
+class User:
+    name: str
+    age: int
+"#,
+        )
+        .unwrap();
+
+    let err = sandbox.run("User('a')").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("User() missing 1 required argument: 'age'")
+    );
+
+    let err = sandbox.run("User('a', 1, email='x')").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("unexpected keyword argument 'email'")
+    );
+
+    for code in [
+        "@dataclass\nclass P:\n    x: int = 0\n    y: int",
+        "@dataclass\nclass Admin(User):\n    level: int = 1\n\n@dataclass\nclass Root(Admin):\n    key: str",
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(
+            err.contains("TypeError: non-default argument")
+                && err.contains("follows default argument"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn test_dataclass_inheritance_and_post_init() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    let result = sandbox
+        .run(
+            r#"
+from dataclasses import dataclass
+
+@dataclass
+class Base:
+    id: str
+
+@dataclass
+class Order(Base):
+    items: list
+
+    def __post_init__(self):
+        self.count = len(self.items)
+
+o = Order("o1", ["a", "b"])
+[o.id, o.count, isinstance(o, Base)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("o1".to_string()),
+            PyValue::Int(2),
+            PyValue::Bool(true),
+        ])
+    );
+}
+
+#[test]
+fn test_dataclass_returned_as_dict() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    let result = sandbox
+        .run(
+            r#"
+from dataclasses import dataclass, asdict
+
+@dataclass
+class Item:
+    name: str
+    qty: int = 1
+
+[Item("a"), asdict(Item("b", 2))]
+"#,
+        )
+        .unwrap();
+    let item = |name: &str, qty: i64| {
+        PyValue::dict(vec![
+            (
+                PyValue::Str("name".to_string()),
+                PyValue::Str(name.to_string()),
+            ),
+            (PyValue::Str("qty".to_string()), PyValue::Int(qty)),
+        ])
+    };
+    assert_eq!(result, PyValue::list(vec![item("a", 1), item("b", 2)]));
+}
+
+#[test]
+fn test_dataclass_passed_to_tool_as_dict() {
+    let mut sandbox = Sandbox::with_builtins();
+    sandbox.register_fn("get_name", |args| match &args[0] {
+        PyValue::Dict(pairs) => pairs.read().values().next().unwrap().clone(),
+        other => PyValue::Str(other.type_name().to_string()),
+    });
+
+    let result = sandbox
+        .run(
+            r#"
+from dataclasses import dataclass
+
+@dataclass
+class User:
+    name: str
+
+get_name(User("ada"))
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Str("ada".to_string()));
+}
+
+#[test]
+fn test_dataclass_frozen_and_order() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    let result = sandbox
+        .capture(
+            r#"
+from dataclasses import dataclass, FrozenInstanceError
+
+@dataclass(frozen=True)
+class Point:
+    x: int
+    y: int = 0
+
+@dataclass
+class Box:
+    size: int
+
+@dataclass(order=True)
+class Version:
+    major: int
+    minor: int = 0
+
+p = Point(1, 2)
+try:
+    p.x = 5
+except FrozenInstanceError as e:
+    print(type(e).__name__, e, isinstance(e, AttributeError))
+print({p: "a"}[Point(1, 2)], len({p, Point(1, 2)}), hash(p) == hash(Point(1, 2)))
+try:
+    hash(Box(1))
+except TypeError as e:
+    print(e)
+print(Version(1, 2) < Version(1, 3), Version(2) >= Version(1, 9), sorted([Version(2), Version(1, 5), Version(1)]))
+for a, b in [(Version(1), 1), (Box(1), Box(2))]:
+    try:
+        a < b
+    except TypeError as e:
+        print(e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "FrozenInstanceError cannot assign to field 'x' True",
+            "a 1 True",
+            "unhashable type: 'Box'",
+            "True True [Version(major=1, minor=0), Version(major=1, minor=5), Version(major=2, minor=0)]",
+            "'<' not supported between instances of 'Version' and 'int'",
+            "'<' not supported between instances of 'Box' and 'Box'",
+        ]
+    );
+}
+
+#[test]
+fn test_dataclass_options() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    let result = sandbox
+        .capture(
+            r#"
+from dataclasses import dataclass
+
+@dataclass(eq=False)
+class Node:
+    id: int
+
+n = Node(1)
+print(n == Node(1), n == n, len({n, Node(1)}))
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["False True 2"]);
+
+    for (code, message) in [
+        (
+            "@dataclass(slots=True)\nclass A:\n    x: int",
+            "TypeError: dataclass() option 'slots=True' is not supported",
+        ),
+        (
+            "@dataclass(order=True, eq=False)\nclass A:\n    x: int",
+            "ValueError: eq must be true if order is true",
+        ),
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains(message), "{}: {}", code, err);
+    }
+}
+
+#[test]
+fn test_named_tuple_behaves_as_tuple() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    let output = sandbox
+        .capture(
+            r#"
+from typing import NamedTuple
+
+class Pair(NamedTuple):
+    key: str
+    value: int = 0
+
+p = Pair("a", 1)
+k, v = p
+print(p, p.key, p[1], p[-2], len(p), list(p))
+print(k, v, p == ("a", 1), "a" in p, isinstance(p, "tuple"))
+print(p._asdict(), p._replace(value=5), p._fields, Pair("b"))
+print(p + (2,), (0,) + p, p * 2, Pair._fields)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        output.output,
+        vec![
+            "Pair(key='a', value=1) a 1 a 2 ['a', 1]",
+            "a 1 True True True",
+            "{'key': 'a', 'value': 1} Pair(key='a', value=5) ('key', 'value') Pair(key='b', value=0)",
+            "('a', 1, 2) (0, 'a', 1) ('a', 1, 'a', 1) ('key', 'value')",
+        ]
+    );
+}
+
+#[test]
+fn test_named_tuple_is_immutable_and_hashable() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    sandbox
+        .run(
+            r#"
+from typing import NamedTuple
+
+class Pos(NamedTuple):
+    x: int
+    y: int
+
+p = Pos(1, 2)
+seen = {p: "here"}
+"#,
+        )
+        .unwrap();
+
+    assert_eq!(
+        sandbox.run("seen[(1, 2)]").unwrap(),
+        PyValue::Str("here".to_string())
+    );
+    let err = sandbox.run("p.x = 5").unwrap_err();
+    assert!(err.to_string().contains("can't set attribute"));
+    assert_eq!(
+        sandbox.run("p").unwrap(),
+        PyValue::dict(vec![
+            (PyValue::Str("x".to_string()), PyValue::Int(1)),
+            (PyValue::Str("y".to_string()), PyValue::Int(2)),
+        ])
+    );
+}
+
+// ============================================================================
+// Structural pattern matching
+// ============================================================================
+
+#[test]
+fn test_match_literal_and_wildcard() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def describe(code):
+    match code:
+        case 200:
+            return "ok"
+        case 404 | 410:
+            return "gone"
+        case "retry":
+            return "again"
+        case None:
+            return "none"
+        case _:
+            return "other"
+
+[describe(200), describe(410), describe("retry"), describe(None), describe(500)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("ok".to_string()),
+            PyValue::Str("gone".to_string()),
+            PyValue::Str("again".to_string()),
+            PyValue::Str("none".to_string()),
+            PyValue::Str("other".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_match_mapping_destructures_tool_response() {
+    let mut sandbox = Sandbox::new();
+    sandbox.register_fn("fetch", |args| {
+        if args[0].as_str() == Some("good") {
+            PyValue::dict(vec![
+                (
+                    PyValue::Str("status".to_string()),
+                    PyValue::Str("ok".to_string()),
+                ),
+                (PyValue::Str("data".to_string()), PyValue::Int(42)),
+            ])
+        } else {
+            PyValue::dict(vec![
+                (
+                    PyValue::Str("status".to_string()),
+                    PyValue::Str("error".to_string()),
+                ),
+                (
+                    PyValue::Str("message".to_string()),
+                    PyValue::Str("boom".to_string()),
+                ),
+                (PyValue::Str("retry".to_string()), PyValue::Bool(false)),
+            ])
+        }
+    });
+
+    let result = sandbox
+        .run(
+            r#"
+def handle(name):
+    match fetch(name):
+        case {"status": "ok", "data": d}:
+            return d
+        case {"status": "error", "message": msg, **rest}:
+            return [msg, rest]
+
+[handle("good"), handle("bad")]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Int(42),
+            PyValue::list(vec![
+                PyValue::Str("boom".to_string()),
+                PyValue::dict(vec![(
+                    PyValue::Str("retry".to_string()),
+                    PyValue::Bool(false)
+                )]),
+            ]),
+        ])
+    );
+}
+
+#[test]
+fn test_match_sequence_patterns() {
+    let mut sandbox = Sandbox::new();
+
+    let output = sandbox
+        .capture(
+            r#"
+def shape(value):
+    match value:
+        case []:
+            return "empty"
+        case [x]:
+            return f"one {x}"
+        case (x, y):
+            return f"two {x} {y}"
+        case [first, *rest, last]:
+            return f"many {first} {rest} {last}"
+        case _:
+            return "not a sequence"
+
+print(shape([]), shape([1]), shape((1, 2)), shape([1, 2, 3, 4]), shape("ab"))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        output.output,
+        vec!["empty one 1 two 1 2 many 1 [2, 3] 4 not a sequence"]
+    );
+}
+
+#[test]
+fn test_match_guard_and_as() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def size(n):
+    match n:
+        case int() as small if small < 10:
+            return "small"
+        case int(big) if big < 1000:
+            return "medium"
+        case int():
+            return "large"
+        case str():
+            return "text"
+
+[size(3), size(50), size(5000), size("x")]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("small".to_string()),
+            PyValue::Str("medium".to_string()),
+            PyValue::Str("large".to_string()),
+            PyValue::Str("text".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_match_class_patterns() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    let result = sandbox
+        .run(
+            r#"
+from dataclasses import dataclass
+
+@dataclass
+class Point:
+    x: int
+    y: int
+
+class Circle:
+    def __init__(self, r):
+        self.r = r
+
+def where(shape):
+    match shape:
+        case Point(x=0, y=0):
+            return "origin"
+        case Point(0, y):
+            return f"on y axis at {y}"
+        case Point(x, y):
+            return f"at {x},{y}"
+        case Circle(r=r):
+            return f"circle {r}"
+        case ValueError(args=[msg]):
+            return "error " + msg
+
+[where(Point(0, 0)), where(Point(0, 5)), where(Point(1, 2)), where(Circle(3)), where(ValueError("bad"))]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("origin".to_string()),
+            PyValue::Str("on y axis at 5".to_string()),
+            PyValue::Str("at 1,2".to_string()),
+            PyValue::Str("circle 3".to_string()),
+            PyValue::Str("error bad".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_match_no_case_matches() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+result = "unchanged"
+match 5:
+    case 1:
+        result = "one"
+result
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Str("unchanged".to_string()));
+}
+
+#[test]
+fn test_match_capture_in_closure() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def make(point):
+    match point:
+        case [x, y]:
+            return lambda: x + y
+
+make([2, 3])()
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Int(5));
+}
+
+// ============================================================================
+// Generators
+// ============================================================================
+
+#[test]
+fn test_generator_function() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def countdown(n):
+    while n > 0:
+        yield n
+        n -= 1
+
+gen = countdown(3)
+[x * 10 for x in gen] + list(gen)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![PyValue::Int(30), PyValue::Int(20), PyValue::Int(10)])
+    );
+}
+
+#[test]
+fn test_generator_runs_lazily() {
+    let mut sandbox = Sandbox::new();
+
+    let output = sandbox
+        .capture(
+            r#"
+def numbers():
+    for i in range(3):
+        print("produce", i)
+        yield i
+
+for n in numbers():
+    print("consume", n)
+    if n == 1:
+        break
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        output.output,
+        vec!["produce 0", "consume 0", "produce 1", "consume 1"]
+    );
+}
+
+#[test]
+fn test_infinite_generator() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def fib():
+    a, b = 0, 1
+    while True:
+        yield a
+        a, b = b, a + b
+
+result = []
+for value in fib():
+    if value > 20:
+        break
+    result.append(value)
+result
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(
+            [0, 1, 1, 2, 3, 5, 8, 13]
+                .into_iter()
+                .map(PyValue::Int)
+                .collect()
+        )
+    );
+}
+
+#[test]
+fn test_yield_from() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def flatten(items):
+    for item in items:
+        if isinstance(item, "list"):
+            yield from flatten(item)
+        else:
+            yield item
+
+def numbers():
+    yield from flatten([1, [2, [3, 4]]])
+    yield from range(5, 7)
+
+list(numbers())
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::list((1..=6).map(PyValue::Int).collect()));
+}
+
+#[test]
+fn test_generator_return_value() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .capture(
+            r#"
+def fetch_pages():
+    yield "a"
+    yield "b"
+    return 2
+
+def pipeline():
+    count = yield from fetch_pages()
+    rest = yield from ["c"]
+    yield (count, rest)
+    return "done"
+
+print(list(pipeline()))
+g = pipeline()
+try:
+    while True:
+        next(g)
+except StopIteration as e:
+    print(e.value, e.args)
+try:
+    next(g)
+except StopIteration as e:
+    print(e.value, e.args)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec!["['a', 'b', 'c', (2, None)]", "done ('done',)", "None ()"]
+    );
+}
+
+#[test]
+fn test_generator_expression_is_lazy() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+factor = 3
+squares = (x * factor for x in range(10**12) if x % 2 == 0)
+first = []
+for s in squares:
+    if len(first) == 3:
+        break
+    first.append(s)
+first, any(x > 5 for x in range(10**12)), ", ".join(str(c) for c in "abc")
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::list(vec![PyValue::Int(0), PyValue::Int(6), PyValue::Int(12)]),
+            PyValue::Bool(true),
+            PyValue::Str("a, b, c".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_generator_expression_closure() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def scaled(values, k):
+    return (v * k for v in values)
+
+sum(scaled([1, 2, 3], 10)), max(x % 7 for x in range(20)), min(range(4, 9))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![PyValue::Int(60), PyValue::Int(6), PyValue::Int(4)])
+    );
+}
+
+#[test]
+fn test_generator_exceptions() {
+    let mut sandbox = Sandbox::new();
+
+    let output = sandbox
+        .capture(
+            r#"
+def risky():
+    try:
+        yield 1
+        raise ValueError("bad item")
+    finally:
+        print("cleanup")
+
+try:
+    for x in risky():
+        print("got", x)
+except ValueError as e:
+    print("caught", e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(output.output, vec!["got 1", "cleanup", "caught bad item"]);
+}
+
+#[test]
+fn test_generator_over_paginated_tool() {
+    let mut sandbox = Sandbox::new();
+    sandbox.register_fn("fetch_page", |args| {
+        let page = args[0].as_int().unwrap_or(0);
+        if page < 3 {
+            PyValue::list(vec![PyValue::Int(page * 2), PyValue::Int(page * 2 + 1)])
+        } else {
+            PyValue::list(vec![])
+        }
+    });
+
+    let result = sandbox
+        .run(
+            r#"
+def records():
+    page = 0
+    while True:
+        items = fetch_page(page)
+        if not items:
+            return
+        yield from items
+        page += 1
+
+list(r for r in records() if r % 2 == 1)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(3), PyValue::Int(5)])
+    );
+}
+
+#[test]
+fn test_lazy_range() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+r = range(0, 10**12, 3)
+[len(r), r[2], r[-1], 9 in r, 10 in r, str(range(5)), str(r[1:3]), range(0) == range(2, 2)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Int(333_333_333_334),
+            PyValue::Int(6),
+            PyValue::Int(999_999_999_999),
+            PyValue::Bool(true),
+            PyValue::Bool(false),
+            PyValue::Str("range(0, 5)".to_string()),
+            PyValue::Str("range(3, 9, 3)".to_string()),
+            PyValue::Bool(true),
+        ])
+    );
+}
+
+#[test]
+fn test_generator_stopped_by_instruction_limit() {
+    let mut sandbox = Sandbox::new();
+    sandbox.limit(Limits {
+        max_instructions: Some(10_000),
+        ..Default::default()
+    });
+
+    let err = sandbox.run("sum(x * x for x in range(10**9))").unwrap_err();
+    assert!(err.to_string().contains("Instruction limit"));
+
+    let err = sandbox.run("list(range(10**9))").unwrap_err();
+    assert!(err.to_string().contains("Instruction limit"));
+}
+
+#[test]
+fn test_recursive_generator_limit() {
+    let mut sandbox = Sandbox::new();
+    sandbox.limit(Limits {
+        max_recursion_depth: Some(20),
+        ..Default::default()
+    });
+
+    let err = sandbox
+        .run(
+            r#"
+def walk(n):
+    yield n
+    yield from walk(n + 1)
+
+list(walk(0))
+"#,
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("Recursion limit"));
+}
+
+#[test]
+fn test_recursion_through_callbacks_limit() {
+    let mut sandbox = Sandbox::with_builtins();
+    sandbox.limit(Limits {
+        max_recursion_depth: Some(20),
+        ..Default::default()
+    });
+
+    for code in [
+        r#"
+class A:
+    @property
+    def p(self):
+        return A().p
+
+A().p
+"#,
+        r#"
+class B:
+    def __repr__(self):
+        return repr(B())
+
+repr(B())
+"#,
+        r#"
+def key(x):
+    return sorted([x], key=key)[0]
+
+sorted([1], key=key)
+"#,
+        r#"
+def f(n):
+    return list(map(f, [n]))
+
+f(0)
+"#,
+    ] {
+        let err = sandbox.run(code).unwrap_err();
+        assert!(
+            err.to_string().contains("Recursion limit"),
+            "{}: {}",
+            code,
+            err
+        );
+    }
+
+    // Callbacks that return leave the depth as it was
+    let result = sandbox
+        .run("[sorted([3, 1], key=lambda x: -x) for _ in range(50)][0]")
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![PyValue::Int(3), PyValue::Int(1)])
+    );
+}
+
+#[test]
+fn test_yield_outside_function() {
+    let mut sandbox = Sandbox::new();
+    let err = sandbox.run("yield 1").unwrap_err();
+    assert!(err.to_string().contains("'yield' outside function"));
+}
+
+// ============================================================================
+// Iterator tests
+// ============================================================================
+
+#[test]
+fn test_iter_and_next() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+it = iter([1, 2, 3])
+first = next(it)
+second = next(it, None)
+[first, second, list(it), next(it, None)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Int(1),
+            PyValue::Int(2),
+            PyValue::list(vec![PyValue::Int(3)]),
+            PyValue::None,
+        ])
+    );
+}
+
+#[test]
+fn test_next_raises_stop_iteration() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def squares():
+    yield 1
+    yield 4
+
+gen = squares()
+seen = []
+while True:
+    try:
+        seen.append(next(gen))
+    except StopIteration:
+        break
+seen
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(4)])
+    );
+
+    let err = sandbox.run("next([1, 2])").unwrap_err();
+    assert!(err.to_string().contains("'list' object is not an iterator"));
+}
+
+#[test]
+fn test_iterator_shared_by_loops() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+lines = iter(["name,qty", "apple,3", "pear,5"])
+header = next(lines).split(",")
+rows = []
+for line in lines:
+    rows.append(line.split(",")[1])
+[header, rows]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::list(vec![
+                PyValue::Str("name".into()),
+                PyValue::Str("qty".into())
+            ]),
+            PyValue::list(vec![PyValue::Str("3".into()), PyValue::Str("5".into())]),
+        ])
+    );
+}
+
+#[test]
+fn test_zip_and_enumerate_are_lazy() {
+    let mut sandbox = Sandbox::new();
+
+    let out = sandbox
+        .capture(
+            r#"
+def naturals():
+    n = 0
+    while True:
+        n += 1
+        yield n
+
+pairs = zip("abc", naturals())
+print(next(pairs))
+for i, (letter, n) in enumerate(pairs, start=1):
+    print(i, letter, n)
+print(pairs)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        out.output,
+        vec!["('a', 1)", "1 b 2", "2 c 3", "<zip object>"]
+    );
+}
+
+#[test]
+fn test_map_and_filter_are_lazy() {
+    let mut sandbox = Sandbox::new();
+
+    let out = sandbox
+        .capture(
+            r#"
+def naturals():
+    n = 0
+    while True:
+        n += 1
+        print("produce", n)
+        yield n
+
+evens = filter(lambda x: x % 2 == 0, naturals())
+doubled = map(lambda x: x * 10, evens)
+print(next(doubled))
+print(list(map(lambda a, b: a + b, [1, 2, 3], [10, 20])))
+"#,
+        )
+        .unwrap();
+    assert_eq!(out.output, vec!["produce 1", "produce 2", "20", "[11, 22]"]);
+}
+
+// ============================================================================
+// Aliasing tests
+// ============================================================================
+
+#[test]
+fn test_assignment_shares_list() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+a = [1, 2]
+b = a
+b.append(3)
+a[0] = 10
+[a, b, a is b, a == [10, 2, 3], a is [10, 2, 3]]
+"#,
+        )
+        .unwrap();
+    let list = PyValue::list(vec![PyValue::Int(10), PyValue::Int(2), PyValue::Int(3)]);
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            list.clone(),
+            list,
+            PyValue::Bool(true),
+            PyValue::Bool(true),
+            PyValue::Bool(false),
+        ])
+    );
+}
+
+#[test]
+fn test_function_mutates_argument() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+def add_item(items, counts, seen, item):
+    items.append(item)
+    counts[item] = counts.get(item, 0) + 1
+    seen.add(item)
+
+items = []
+counts = {}
+seen = set()
+add_item(items, counts, seen, "a")
+add_item(items, counts, seen, "a")
+[items, counts["a"], len(seen)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::list(vec![
+                PyValue::Str("a".to_string()),
+                PyValue::Str("a".to_string()),
+            ]),
+            PyValue::Int(2),
+            PyValue::Int(1),
+        ])
+    );
+}
+
+#[test]
+fn test_nested_containers_are_shared() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+grid = [[]] * 3
+grid[0].append(1)
+groups = {}
+for word in ["apple", "avocado", "banana"]:
+    groups.setdefault(word[0], []).append(word)
+copy = grid.copy()
+copy.append([2])
+[grid, len(groups["a"]), len(copy), copy[0] is grid[0]]
+"#,
+        )
+        .unwrap();
+    let row = PyValue::list(vec![PyValue::Int(1)]);
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::list(vec![row.clone(), row.clone(), row]),
+            PyValue::Int(2),
+            PyValue::Int(4),
+            PyValue::Bool(true),
+        ])
+    );
+}
+
+#[test]
+fn test_attribute_container_is_shared() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+class Basket:
+    def __init__(self):
+        self.items = []
+
+basket = Basket()
+items = basket.items
+items.append("apple")
+basket.items.append("pear")
+items
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("apple".to_string()),
+            PyValue::Str("pear".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_augmented_assignment_mutates_in_place() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+a = [1]
+b = a
+b += [2]
+c = a
+c = c + [3]
+s = {1}
+t = s
+t |= {2}
+n = 1
+m = n
+m += 1
+[a, len(c), len(s), n]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::list(vec![PyValue::Int(1), PyValue::Int(2)]),
+            PyValue::Int(3),
+            PyValue::Int(2),
+            PyValue::Int(1),
+        ])
+    );
+}
+
+#[test]
+fn test_loop_sees_appended_items() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+edges = {"a": ["b", "c"], "b": ["d"], "c": [], "d": []}
+queue = ["a"]
+for node in queue:
+    for child in edges[node]:
+        queue.append(child)
+queue
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(
+            ["a", "b", "c", "d"]
+                .into_iter()
+                .map(|s| PyValue::Str(s.to_string()))
+                .collect()
+        )
+    );
+}
+
+#[test]
+fn test_extend_with_itself() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox.run("a = [1, 2]\na.extend(a)\na").unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Int(1),
+            PyValue::Int(2),
+            PyValue::Int(1),
+            PyValue::Int(2),
+        ])
+    );
+}
+
+#[test]
+fn test_print_self_referencing_list() {
+    let mut sandbox = Sandbox::new();
+
+    let out = sandbox
+        .capture("a = [1]\na.append(a)\nd = {}\nd['self'] = d\nprint(a, d)")
+        .unwrap();
+    assert_eq!(out.output, vec!["[1, [...]] {'self': {...}}"]);
+}
+
+#[test]
+fn test_compare_self_referencing_lists() {
+    let mut sandbox = Sandbox::new();
+
+    let out = sandbox
+        .capture(
+            r#"
+a = [1]
+a.append(a)
+b = [1]
+b.append(b)
+print(a == a, a in [a])
+for check in (lambda: a == b, lambda: a != b, lambda: a < b, lambda: a in [b]):
+    try:
+        check()
+    except RecursionError as e:
+        print(e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        out.output,
+        vec![
+            "True True",
+            "maximum recursion depth exceeded in comparison",
+            "maximum recursion depth exceeded in comparison",
+            "maximum recursion depth exceeded in comparison",
+            "maximum recursion depth exceeded in comparison",
+        ]
+    );
+}
+
+#[test]
+fn test_json_dumps_self_referencing_containers() {
+    let mut sandbox = Sandbox::with_builtins();
+
+    let out = sandbox
+        .capture(
+            r#"
+import json
+a = [1]
+a.append(a)
+d = {}
+d["self"] = d
+for value in (a, [d]):
+    try:
+        json.dumps(value)
+    except ValueError as e:
+        print(e)
+shared = [1]
+print(json.dumps([shared, shared]))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        out.output,
+        vec![
+            "Circular reference detected",
+            "Circular reference detected",
+            "[[1],[1]]",
+        ]
+    );
+}
+
+#[test]
+fn test_host_values_are_copied() {
+    let mut sandbox = Sandbox::new();
+    let items = PyValue::list(vec![PyValue::Int(1)]);
+    sandbox.set("items", items.clone());
+
+    sandbox.run("items.append(2)").unwrap();
+    let result = sandbox.run("items").unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2)])
+    );
+    assert_eq!(items, PyValue::list(vec![PyValue::Int(1)]));
+}
+
+#[test]
+fn test_cloned_sandbox_is_isolated() {
+    let mut template = Sandbox::new();
+    template.module("cfg", |m| {
+        m.constant("tags", PyValue::list(vec![]));
+    });
+    template
+        .run(
+            r#"
+class Box:
+    def __init__(self):
+        self.items = []
+
+items = [1]
+alias = items
+box = Box()
+"#,
+        )
+        .unwrap();
+
+    let mut clone = template.clone();
+    let result = clone
+        .run(
+            r#"
+import cfg
+items.append(2)
+box.items.append(3)
+cfg.tags.append(4)
+(alias, box.items, cfg.tags)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::list(vec![PyValue::Int(1), PyValue::Int(2)]),
+            PyValue::list(vec![PyValue::Int(3)]),
+            PyValue::list(vec![PyValue::Int(4)]),
+        ])
+    );
+
+    let result = template
+        .run("import cfg\n(items, box.items, cfg.tags)")
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Tuple(vec![
+            PyValue::list(vec![PyValue::Int(1)]),
+            PyValue::list(vec![]),
+            PyValue::list(vec![]),
+        ])
+    );
+}
+
+// ============================================================================
+// Nested assignment tests
+// ============================================================================
+
+#[test]
+fn test_nested_subscript_assignment() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+data = {"user": {"tags": ["a", "b"]}}
+data["user"]["tags"][0] = "x"
+data["user"]["name"] = "ada"
+data
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::dict(vec![(
+            PyValue::Str("user".to_string()),
+            PyValue::dict(vec![
+                (
+                    PyValue::Str("tags".to_string()),
+                    PyValue::list(vec![
+                        PyValue::Str("x".to_string()),
+                        PyValue::Str("b".to_string()),
+                    ]),
+                ),
+                (
+                    PyValue::Str("name".to_string()),
+                    PyValue::Str("ada".to_string()),
+                ),
+            ]),
+        )])
+    );
+}
+
+#[test]
+fn test_nested_augmented_assignment() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+rows = [{"score": 1}, {"score": 5}]
+for i in range(len(rows)):
+    rows[i]["score"] += 10
+
+class Stats:
+    def __init__(self):
+        self.counts = {"hits": [0]}
+
+stats = Stats()
+stats.counts["hits"][0] += 1
+[rows[0]["score"], rows[1]["score"], stats.counts["hits"][0]]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![PyValue::Int(11), PyValue::Int(15), PyValue::Int(1)])
+    );
+}
+
+#[test]
+fn test_augmented_assignment_evaluates_target_once() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+calls = []
+def index(i):
+    calls.append(i)
+    return i
+
+class Counter:
+    def __init__(self):
+        self.n = 0
+
+counter = Counter()
+def get_counter():
+    calls.append("counter")
+    return counter
+
+totals = [0, 0]
+totals[index(1)] += 5
+get_counter().n += 1
+[totals, counter.n, calls]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::list(vec![PyValue::Int(0), PyValue::Int(5)]),
+            PyValue::Int(1),
+            PyValue::list(vec![PyValue::Int(1), PyValue::Str("counter".to_string()),]),
+        ])
+    );
+}
+
+#[test]
+fn test_mutating_method_on_nested_receiver() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+groups = {"a": [], "b": []}
+for word in ["apple", "banana", "avocado"]:
+    groups[word[0]].append(word)
+groups["a"].sort(reverse=True)
+groups
+"#,
+        )
+        .unwrap();
+    let strs =
+        |items: &[&str]| PyValue::list(items.iter().map(|s| PyValue::Str(s.to_string())).collect());
+    assert_eq!(
+        result,
+        PyValue::dict(vec![
+            (PyValue::Str("a".to_string()), strs(&["avocado", "apple"])),
+            (PyValue::Str("b".to_string()), strs(&["banana"])),
+        ])
+    );
+}
+
+#[test]
+fn test_item_assignment_errors() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .run(
+            r#"
+errors = []
+for target in [(1, 2), "ab"]:
+    try:
+        target[0] = 1
+    except TypeError as e:
+        errors.append(str(e))
+errors
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("'tuple' object does not support item assignment".to_string()),
+            PyValue::Str("'str' object does not support item assignment".to_string()),
+        ])
+    );
+}
+
+// ============================================================================
+// Decorator tests
+// ============================================================================
+
+#[test]
+fn test_function_decorators() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+def retry(times):
+    def decorate(fn):
+        def wrapper(*args, **kwargs):
+            for _ in range(times):
+                try:
+                    return fn(*args, **kwargs)
+                except ValueError as e:
+                    last = e
+            raise last
+        return wrapper
+    return decorate
+
+attempts = []
+
+@retry(3)
+def flaky(x, scale=1):
+    attempts.append(x)
+    if len(attempts) < 3:
+        raise ValueError("boom")
+    return x * scale
+
+def bold(fn):
+    return lambda *args: "<b>" + fn(*args) + "</b>"
+
+def italic(fn):
+    return lambda *args: "<i>" + fn(*args) + "</i>"
+
+@bold
+@italic
+def greet(name):
+    return "hi " + name
+
+[flaky(5, scale=2), len(attempts), greet("bob")]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Int(10),
+            PyValue::Int(3),
+            PyValue::Str("<b><i>hi bob</i></b>".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_method_decorators() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+def tagged(fn):
+    def wrapper(self, *args):
+        return ("tagged", fn(self, *args))
+    return wrapper
+
+class Box:
+    def __init__(self, value):
+        self.value = value
+
+    @tagged
+    def get(self):
+        return self.value
+
+    @property
+    def double(self):
+        return self.value * 2
+
+b = Box(4)
+[b.get(), b.double]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Tuple(vec![PyValue::Str("tagged".to_string()), PyValue::Int(4)]),
+            PyValue::Int(8),
+        ])
+    );
+}
+
+#[test]
+fn test_functools_lru_cache() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .run(
+            r#"
+import functools
+from functools import lru_cache, wraps
+
+calls = 0
+
+@functools.lru_cache(maxsize=None)
+def fib(n):
+    global calls
+    calls += 1
+    return n if n < 2 else fib(n - 1) + fib(n - 2)
+
+@lru_cache
+def square(x):
+    return x * x
+
+def logged(fn):
+    @wraps(fn)
+    def wrapper(*args):
+        return fn(*args)
+    return wrapper
+
+@logged
+def add(a, b):
+    return a + b
+
+[fib(80), calls, list(map(square, [1, 2, 3])), add(1, 2)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Int(23416728348467685),
+            PyValue::Int(81),
+            PyValue::list(vec![PyValue::Int(1), PyValue::Int(4), PyValue::Int(9)]),
+            PyValue::Int(3),
+        ])
+    );
+}
+
+#[test]
+fn test_function_name_and_doc() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .capture(
+            r#"
+import functools
+
+def log(fn):
+    @functools.wraps(fn)
+    def wrapper(*args, **kwargs):
+        print(f"calling {fn.__name__}")
+        return fn(*args, **kwargs)
+    return wrapper
+
+@log
+def add(a, b):
+    """Add two numbers."""
+    return a + b
+
+def plain(x):
+    return x
+
+class C:
+    def m(self):
+        "A method."
+
+@functools.lru_cache
+def cached(n):
+    return n
+
+print(add(1, 2), add.__name__, add.__doc__)
+print(plain.__name__, plain.__doc__, (lambda: 0).__name__)
+print(C().m.__name__, C().m.__doc__, C.m.__name__, cached.__name__)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "calling add",
+            "3 add Add two numbers.",
+            "plain None <lambda>",
+            "m A method. m cached",
+        ]
+    );
+}
+
+#[test]
+fn test_functools_lru_cache_keys_on_argument_values() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .capture(
+            r#"
+from functools import lru_cache
+
+class P:
+    def __init__(self, v):
+        self.v = v
+    def __repr__(self):
+        return "P"
+
+@lru_cache
+def g(p):
+    return p.v
+
+@lru_cache
+def h(x):
+    return x
+
+print(g(P(1)), g(P(2)))
+try:
+    h([1])
+except TypeError as e:
+    print(e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["1 2", "unhashable type: 'list'"]);
+}
+
+#[test]
+fn test_host_decorator_memoizes_through_host_storage() {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    let store: Arc<Mutex<HashMap<String, PyValue>>> = Arc::default();
+    let ttls: Arc<Mutex<Vec<PyValue>>> = Arc::default();
+
+    let mut sandbox = Sandbox::new();
+    let (factory_store, factory_ttls) = (store.clone(), ttls.clone());
+    sandbox.register_decorator("cached", move |_args, kwargs| {
+        factory_ttls.lock().unwrap().extend(
+            kwargs
+                .iter()
+                .filter(|(name, _)| name == "ttl")
+                .map(|(_, value)| value.clone()),
+        );
+        let (lookup, save) = (factory_store.clone(), factory_store.clone());
+        littrs::HostDecorator::new()
+            .before(move |call| lookup.lock().unwrap().get(&call.key()).cloned())
+            .after(move |call, result| {
+                save.lock().unwrap().insert(call.key(), result.clone());
+            })
+    });
+
+    let result = sandbox
+        .run(
+            r#"
+calls = []
+
+@cached
+def lookup(user_id):
+    calls.append(user_id)
+    return {"id": user_id}
+
+@cached(ttl=60)
+def search(query, limit=10):
+    calls.append(query)
+    return [query] * limit
+
+lookup(1)
+lookup(1)
+search("rust", limit=2)
+search("rust", limit=2)
+[calls, lookup(1)]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::list(vec![PyValue::Int(1), PyValue::Str("rust".to_string())]),
+            PyValue::dict(vec![(PyValue::Str("id".to_string()), PyValue::Int(1))]),
+        ])
+    );
+
+    {
+        let store = store.lock().unwrap();
+        assert!(store.contains_key("lookup(1)"));
+        assert!(store.contains_key("search('rust', limit=2)"));
+    }
+    assert_eq!(*ttls.lock().unwrap(), vec![PyValue::Int(60)]);
+
+    // A later run still sees the host's cache
+    assert_eq!(
+        sandbox.run("calls.clear()\nlookup(1)\ncalls").unwrap(),
+        PyValue::list(vec![])
+    );
+}
+
+#[test]
+fn test_host_decorator_skips_after_hook_on_error() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let completed = Arc::new(AtomicUsize::new(0));
+    let mut sandbox = Sandbox::new();
+    let counter = completed.clone();
+    sandbox.register_decorator("audited", move |_, _| {
+        let counter = counter.clone();
+        littrs::HostDecorator::new().after(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+    });
+
+    let result = sandbox
+        .run(
+            r#"
+@audited
+def check(x):
+    if x < 0:
+        raise ValueError("negative")
+    return x
+
+try:
+    check(-1)
+except ValueError as e:
+    caught = str(e)
+[check(2), caught]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![PyValue::Int(2), PyValue::Str("negative".to_string())])
+    );
+    assert_eq!(completed.load(Ordering::SeqCst), 1);
+}
+
+// ============================================================================
+// Format spec tests
+// ============================================================================
+
+#[test]
+fn test_fstring_format_specs() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+price = 1234.5
+n = 1234567
+w = 8
+print(f"[{price:>10.2f}]", f"{n:,}", f"{n:_}", f"{price:,.2f}", f"[{3.14159:08.3f}]")
+print(f"[{'ab':*^7}]", f"[{'ab':<4}]", f"{255:#x}", f"{5:#06b}", f"{-5:+d}", f"{7:03d}")
+print(f"{0.256:.1%}", f"{12345.678:e}", f"{0.0001:g}", f"{1e20:g}", f"[{2:{w}}]", f"[{price:{w}.{1}f}]")
+print(format(float("nan"), "08.2f"), f"{float('-inf'):08}", "%08.2f" % float("inf"), f"[{float('nan'):<6}]")
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "[   1234.50] 1,234,567 1_234_567 1,234.50 [0003.142]",
+            "[**ab***] [ab  ] 0xff 0b0101 -5 007",
+            "25.6% 1.234568e+04 0.0001 1e+20 [       2] [  1234.5]",
+            "00000nan -0000inf 00000inf [nan   ]",
+        ]
+    );
+}
+
+#[test]
+fn test_fstring_conversions_and_debug() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+class Point:
+    def __init__(self, x):
+        self.x = x
+    def __repr__(self):
+        return f"Point({self.x!r})"
+    def __str__(self):
+        return "point"
+p = Point("a")
+x = 3
+print(f"{p}", f"{p!r}", f"{p!s:>6}", f"{'é'!a}", f"{x=}", f"{x = }", f"{x=:03}")
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec!["point Point('a')  point '\\xe9' x=3 x = 3 x=003"]
+    );
+}
+
+#[test]
+fn test_format_builtin_and_str_format_fields() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+class User:
+    def __init__(self, name):
+        self.name = name
+print(format(1234.5, ",.2f"), format(42), format("x", ">3"))
+print("{name}: {u.name:>5} {0[1]} {1!r}".format([1, 2], "b", name="row", u=User("ada")))
+print("{:<6}|{:>8.2f}|".format("total", 9.5), "{{}} {:.3}".format(1 / 3))
+print("{a[k]} {b}".format_map({"a": {"k": 5}, "b": 6}))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "1,234.50 42   x",
+            "row:   ada 2 'b'",
+            "total |    9.50| {} 0.333",
+            "5 6",
+        ]
+    );
+}
+
+#[test]
+fn test_percent_formatting() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+print("%.2f %s %5d %-4s| %x %r %%" % (3.14159, "s", 42, "ab", 255, "q"))
+print("%(a)s-%(b)05.1f" % {"a": 1, "b": 2.5}, "%d items" % 3, "%*d" % (4, 7))
+s = "total: %s"
+s %= [1, 2]
+print(s)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "3.14 s    42 ab  | ff 'q' %",
+            "1-002.5 3 items    7",
+            "total: [1, 2]",
+        ]
+    );
+}
+
+#[test]
+fn test_format_errors() {
+    let mut sandbox = Sandbox::new();
+    let cases = [
+        ("'{} {}'.format(1)", "IndexError"),
+        ("'{x}'.format()", "KeyError"),
+        ("'%d %d' % (1,)", "not enough arguments"),
+        ("'%d' % (1, 2)", "not all arguments converted"),
+        ("f\"{'a':d}\"", "Unknown format code 'd'"),
+        ("format(1, '.2q')", "Unknown format code 'q'"),
+        ("format(1, ',_')", "Cannot specify both ',' and '_'."),
+        ("'{'.format()", "Single '{' encountered in format string"),
+        ("'{0:'.format(1)", "unmatched '{' in format spec"),
+        ("f\"{1:>2000000000}\"", "exceeds the sandbox limit"),
+        ("'{:>{}}'.format(1, 10 ** 9)", "exceeds the sandbox limit"),
+        ("'%*d' % (10 ** 9, 1)", "exceeds the sandbox limit"),
+        (
+            "format(1.0, '.99999999999999999999f')",
+            "exceeds the sandbox limit",
+        ),
+    ];
+    for (code, expected) in cases {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains(expected), "{}: {}", code, err);
+    }
+}
+
+// ============================================================================
+// Type object tests
+// ============================================================================
+
+#[test]
+fn test_isinstance_with_type_objects() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+class Point:
+    pass
+values = [1, 2.5, "s", [1], {"a": 1}, (1,), {1}, None, Point()]
+print([isinstance(v, (int, float)) for v in values])
+print(isinstance("s", str), isinstance([], list), isinstance({}, dict), isinstance(Point(), (int, Point)))
+print(isinstance(int, type), isinstance(Point, type), isinstance(3, "int"))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "[True, True, False, False, False, False, False, False, False]",
+            "True True True True",
+            "True True True",
+        ]
+    );
+}
+
+#[test]
+fn test_methods_on_type_objects() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .capture(
+            r#"
+names = ["bob", "Alice", "carol"]
+print(sorted(names, key=str.lower), list(map(str.upper, names)))
+xs = [1]
+list.append(xs, 2)
+print(str.join("-", ["a", "b"]), xs, dict.get({"a": 1}, "a"), str.lower, str.lower.__name__)
+for code in [lambda: str.lower(1), lambda: str.lower(), lambda: int.foo]:
+    try:
+        code()
+    except Exception as e:
+        print(type(e).__name__, e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "['Alice', 'bob', 'carol'] ['BOB', 'ALICE', 'CAROL']",
+            "a-b [1, 2] 1 <method 'lower' of 'str' objects> lower",
+            "TypeError descriptor 'lower' for 'str' objects doesn't apply to a 'int' object",
+            "TypeError unbound method str.lower() needs an argument",
+            "AttributeError type object 'int' has no attribute 'foo'",
+        ]
+    );
+}
+
+#[test]
+fn test_isinstance_numeric_types() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+print(isinstance(1, float), isinstance(2**70, float), isinstance(1.5, int))
+print(isinstance(True, int), isinstance(False, (str, int)), isinstance(1, bool))
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["False False False", "True True False"]);
+}
+
+#[test]
+fn test_type_returns_type_objects() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+class Point:
+    pass
+x = {"a": 1}
+print(type(x) is dict, type(x) == dict, type(x) is list, type(x).__name__, type(x))
+print(type(3) in (int, float), type(type(3)), type(Point()) is Point, {int: "i"}[type(1)])
+try:
+    raise KeyError("k")
+except LookupError as e:
+    print(type(e), type(e) is KeyError, type(e).__name__)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "True True False dict <class 'dict'>",
+            "True <class 'type'> True i",
+            "<class 'KeyError'> True KeyError",
+        ]
+    );
+    assert_eq!(
+        sandbox.run("type([])").unwrap(),
+        PyValue::Type("list".to_string())
+    );
+}
+
+#[test]
+fn test_types_are_callable_values() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+print(sorted(["10", "9", "100"], key=int), list(map(str, [1, 2])), list(map(float, "12")))
+convert = int
+print(convert("42") + 1, type(1.5)("2"), list("ab"), dict(zip("ab", [1, 2])), dict([("k", 1)], v=2))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "['9', '10', '100'] ['1', '2'] [1.0, 2.0]",
+            "43 2.0 ['a', 'b'] {'a': 1, 'b': 2} {'k': 1, 'v': 2}",
+        ]
+    );
+
+    let err = sandbox.run("type(None)()").unwrap_err().to_string();
+    assert!(
+        err.contains("cannot create 'NoneType' instances"),
+        "{}",
+        err
+    );
+    let err = sandbox.run("isinstance(1, 2)").unwrap_err().to_string();
+    assert!(err.contains("isinstance() arg 2 must be a type"), "{}", err);
+}
+
+// ============================================================================
+// Arbitrary-precision int tests
+// ============================================================================
+
+#[test]
+fn test_int_overflow_promotes_to_big_int() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+big = 9223372036854775807 + 1
+print(big, 2 ** 64, -(-9223372036854775807 - 1), abs(-9223372036854775807 - 1))
+print(big * big, 2 ** 100 // 3, -(2 ** 100) % 7, divmod(-(2 ** 70), 3))
+print(1 << 100, (1 << 100) >> 98, ~(2 ** 64), (2 ** 64) | 1, 2 ** 64 - 2 ** 64 + 5)
+print(10 ** 400 / 10 ** 399, pow(3, 200, 10 ** 9 + 7), 0x1_0000_0000_0000_0000)
+print(type(big).__name__, isinstance(big, int), big > 1.5, sorted([2 ** 65, 1, -(2 ** 70)]))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "9223372036854775808 18446744073709551616 9223372036854775808 9223372036854775808",
+            "85070591730234615865843651857942052864 422550200076076467165567735125 5 (-393530540239137101142, 2)",
+            "1267650600228229401496703205376 4 -18446744073709551617 18446744073709551617 5",
+            "10.0 136318165 18446744073709551616",
+            "int True True [-1180591620717411303424, 1, 36893488147419103232]",
+        ]
+    );
+    // Results that fit are stored as plain ints again
+    assert_eq!(sandbox.run("2 ** 64 // 2 ** 60").unwrap(), PyValue::Int(16));
+}
+
+#[test]
+fn test_int_floor_division_and_modulo_round_down() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture("print(-7 // 2, -7 % 2, 7 % -2, divmod(-7, 2), 2 ** -1, True + True)")
+        .unwrap();
+    assert_eq!(result.output, vec!["-4 1 -1 (-4, 1) 0.5 2"]);
+}
+
+#[test]
+fn test_big_int_conversions() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .capture(
+            r#"
+import json, math
+n = int("123456789012345678901234567890")
+print(n + 1, int("-0x_ff", 16), int("0b101", 0), int(" 1_000 "), int(1e20))
+print(str(2 ** 64), repr(-(2 ** 64)), float(2 ** 64), hash(2 ** 64) == hash(2 ** 64))
+print(bin(2 ** 65), hex(-(2 ** 64)), oct(2 ** 64))
+print(f"{2 ** 70:,}", f"{2 ** 70:x}", "%d" % 2 ** 70, format(2 ** 64, "e"))
+print(math.factorial(25), json.dumps({"a": 2 ** 63}), json.loads("18446744073709551615"))
+print({2 ** 64: "a"}[2 ** 64], 2 ** 64 in {2 ** 64})
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "123456789012345678901234567891 -255 5 1000 100000000000000000000",
+            "18446744073709551616 -18446744073709551616 1.8446744073709552e+19 True",
+            "0b100000000000000000000000000000000000000000000000000000000000000000 -0x10000000000000000 0o2000000000000000000000",
+            "1,180,591,620,717,411,303,424 400000000000000000 1180591620717411303424 1.844674e+19",
+            "15511210043330985984000000 {\"a\":9223372036854775808} 18446744073709551615",
+            "a True",
+        ]
+    );
+}
+
+#[test]
+fn test_mixed_numeric_equality_and_hashing() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .capture(
+            r#"
+from functools import lru_cache
+print(2.0 == 2, True == 1, 2 ** 64 == 2.0 ** 64, 2 ** 53 + 1 == float(2 ** 53 + 1), 0.5 == 0)
+print({2 ** 64: 1}.get(2.0 ** 64), {1: "a"}[1.0], {True: "t"}.get(1), {1, 1.0, True}, 1.0 in [1])
+calls = []
+
+@lru_cache
+def h(x):
+    calls.append(x)
+    return x
+
+h(1)
+h(1.0)
+print(calls)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec!["True True True False False", "1 a t {1} True", "[1]",]
+    );
+}
+
+#[test]
+fn test_big_int_errors() {
+    let mut sandbox = Sandbox::new();
+    for (code, message) in [
+        ("2 ** (1 << 40)", "OverflowError"),
+        ("1 << -1", "ValueError: negative shift count"),
+        ("float(10 ** 400)", "int too large to convert to float"),
+        ("int('12a')", "invalid literal for int() with base 10"),
+        (
+            "[1, 2][2 ** 70]",
+            "cannot fit 'int' into an index-sized integer",
+        ),
+        ("range(2 ** 70)", "OverflowError"),
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains(message), "{}: {}", code, err);
+    }
+}
+
+#[test]
+fn test_big_int_from_py_value() {
+    let mut sandbox = Sandbox::new();
+    sandbox.register_fn("double", |args| match i64::from_py_value(&args[0]) {
+        Ok(n) => PyValue::Int(n * 2),
+        Err(err) => PyValue::Str(err.to_string()),
+    });
+    let result = sandbox.run("double(2 ** 64)").unwrap();
+    assert_eq!(
+        result,
+        PyValue::Str("expected int in i64 range, got int out of range".to_string())
+    );
+    let big = sandbox.run("2 ** 64").unwrap();
+    assert_eq!(
+        BigInt::from_py_value(&big).unwrap(),
+        BigInt::from(u64::MAX) + 1
+    );
+}
+
+// ============================================================================
+// Bytes and bytearray tests
+// ============================================================================
+
+#[test]
+fn test_bytes_literals_indexing_and_slicing() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+b = b"hello\x00\xff"
+print(b, len(b), b[0], b[-1], b[1:3], b[::-2], list(b[:2]))
+print(b"it's", b'say "hi"', b"\t\\", type(b) is bytes, isinstance(b, bytes))
+print(b"ab" + b"cd", b"ab" * 2, b"ab" < b"b", b"ab" == bytearray(b"ab"), {b"k": 1}[b"k"])
+print(104 in b, b"ll" in b, bytes([65, 66]), bytes(2), bytes(range(3)), sorted([b"b", b"a"]))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "b'hello\\x00\\xff' 7 104 255 b'el' b'\\xffolh' [104, 101]",
+            "b\"it's\" b'say \"hi\"' b'\\t\\\\' True True",
+            "b'abcd' b'abab' True True 1",
+            "True True b'AB' b'\\x00\\x00' b'\\x00\\x01\\x02' [b'a', b'b']",
+        ]
+    );
+}
+
+#[test]
+fn test_bytes_encode_decode() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+data = "café €".encode()
+print(data, len(data), data.decode(), data.decode("utf-8") == "café €")
+print("café".encode("latin-1"), "café".encode("ascii", "replace"), "café".encode("ascii", errors="ignore"))
+print(b"caf\xe9".decode("latin-1"), b"a\xffb".decode("utf-8", "replace"), str(b"hi", "ascii"), bytes("hi", "utf-8"))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "b'caf\\xc3\\xa9 \\xe2\\x82\\xac' 9 café € True",
+            "b'caf\\xe9' b'caf?' b'caf'",
+            "café a\u{fffd}b hi b'hi'",
+        ]
+    );
+}
+
+#[test]
+fn test_bytes_methods() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+b = b"key=value; other=thing"
+print(b.find(b"="), b.rfind(b"="), b.find(b"x"), b.index(118), b.count(b"e"))
+print(b.split(b"; "), b"  a  b ".split(), b.startswith(b"key"), b.endswith(b"ing"))
+print(b.hex(), bytes.fromhex("de ad BE EF"), bytearray.fromhex("00ff"))
+print(b" x ".strip(), b"AbC".lower(), b"-".join([b"a", b"b"]), b.replace(b"=", b": "))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "3 16 -1 4 3",
+            "[b'key=value', b'other=thing'] [b'a', b'b'] True True",
+            "6b65793d76616c75653b206f746865723d7468696e67 b'\\xde\\xad\\xbe\\xef' bytearray(b'\\x00\\xff')",
+            "b'x' b'abc' b'a-b' b'key: value; other: thing'",
+        ]
+    );
+}
+
+#[test]
+fn test_bytearray_mutation() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+buf = bytearray(b"abc")
+alias = buf
+buf.append(100)
+buf[0] = 65
+buf += b"!"
+buf.extend([1, 2])
+del buf[-1]
+buf[1:3] = b"ZZZ"
+print(alias, len(buf), buf.pop(), bytes(buf), buf.upper(), buf is alias)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec!["bytearray(b'AZZZd!') 7 1 b'AZZZd!' bytearray(b'AZZZD!') True"]
+    );
+}
+
+#[test]
+fn test_sequence_repetition_by_bool() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+x = [1, 2]
+x *= True
+print(b"ab" * True, True * "xy", [1] * False, (1, 2) * True, x, b"ab" * True == b"ab")
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["b'ab' xy [] (1, 2) [1, 2] True"]);
+}
+
+#[test]
+fn test_bytes_errors() {
+    let mut sandbox = Sandbox::new();
+    for (code, message) in [
+        (
+            "b'\\xc3('.decode()",
+            "UnicodeDecodeError: 'utf-8' codec can't decode byte 0xc3 in position 0: invalid continuation byte",
+        ),
+        (
+            "'€'.encode('latin-1')",
+            "UnicodeEncodeError: 'latin-1' codec can't encode character '\\u20ac' in position 0",
+        ),
+        (
+            "'x'.encode('rot13')",
+            "LookupError: unknown encoding: rot13",
+        ),
+        ("bytes([256])", "ValueError: byte must be in range(0, 256)"),
+        ("bytes('x')", "string argument without an encoding"),
+        ("bytes.fromhex('0g')", "non-hexadecimal number found"),
+        ("b'abc'[3]", "index out of range"),
+        ("b'abc'[0] = 1", "does not support item assignment"),
+        ("b'abc' + 'd'", "TypeError: can't concat str to bytes"),
+        (
+            "'a' in b'abc'",
+            "a bytes-like object is required, not 'str'",
+        ),
+        ("{bytearray(): 1}", "unhashable type: 'bytearray'"),
+        ("b'ab' * 2**62", "MemoryError"),
+        ("b'ab' * 2**70", "OverflowError"),
+        ("b'a' * 10**10", "MemoryError"),
+        ("bytes(10**12)", "MemoryError"),
+        ("bytearray(10**12)", "MemoryError"),
+        ("'a' * 10**10", "MemoryError"),
+        ("[0] * 10**10", "MemoryError"),
+        ("x = [0]\nx *= 10**10", "MemoryError"),
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains(message), "{}: {}", code, err);
+    }
+}
+
+#[test]
+fn test_bytes_from_host() {
+    let mut sandbox = Sandbox::new();
+    sandbox.register_fn("checksum", |args| {
+        match Vec::<u8>::from_py_value(&args[0]) {
+            Ok(data) => PyValue::Int(data.iter().map(|&b| b as i64).sum()),
+            Err(err) => PyValue::Str(err.to_string()),
+        }
+    });
+    sandbox.register_fn("fetch", |_| PyValue::Bytes(vec![0xca, 0xfe]));
+    let result = sandbox
+        .capture("print(fetch().hex(), checksum(b'\\x01\\x02'), checksum(bytearray(b'a')), checksum('a'))")
+        .unwrap();
+    assert_eq!(result.output, vec!["cafe 3 97 expected list, got str"]);
+    assert_eq!(
+        sandbox.run("b'\\x00' + fetch()").unwrap(),
+        PyValue::Bytes(vec![0x00, 0xca, 0xfe])
+    );
+    assert_eq!(
+        sandbox.run("bytearray(b'x')").unwrap(),
+        PyValue::bytearray(b"x".to_vec())
+    );
+}
+
+// ============================================================================
+// Frozenset and hashed dict/set tests
+// ============================================================================
+
+#[test]
+fn test_frozenset_basics() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+fs = frozenset([3, 1, 2, 1])
+print(fs, len(fs), 2 in fs, frozenset(), type(fs) is frozenset, isinstance(fs, frozenset))
+print(fs | {4}, fs & {1, 5}, fs - {1}, {1, 2} | fs, fs == {1, 2, 3}, fs <= {1, 2, 3, 4})
+seen = {frozenset([1, 2]): "a", frozenset([2, 1]): "b"}
+print(seen, {fs, frozenset([1, 2, 3])}, hash(frozenset([1, 2])) == hash(frozenset([2, 1])))
+print(fs.union([7]), fs.issubset([1, 2, 3]), sorted(fs), frozenset("aba") == frozenset("ab"))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "frozenset({3, 1, 2}) 3 True frozenset() True True",
+            "frozenset({3, 1, 2, 4}) frozenset({1}) frozenset({3, 2}) {1, 2, 3} True True",
+            "{frozenset({1, 2}): 'b'} {frozenset({3, 1, 2})} True",
+            "frozenset({3, 1, 2, 7}) True [1, 2, 3] True",
+        ]
+    );
+}
+
+#[test]
+fn test_frozenset_is_immutable() {
+    let mut sandbox = Sandbox::new();
+    let err = sandbox.run("frozenset([1]).add(2)").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("'frozenset' object has no attribute 'add'"),
+        "{}",
+        err
+    );
+    let err = sandbox.run("frozenset([[1]])").unwrap_err();
+    assert!(
+        err.to_string().contains("unhashable type: 'list'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_dict_and_set_keep_insertion_order() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+d = {"a": 1, "b": 2, "c": 3}
+del d["a"]
+d["a"] = 4
+d["b"] = 5
+print(d, {"x": 1, "y": 2} == {"y": 2, "x": 1})
+print(d.popitem(), d)
+s = {3, 1, 2}
+s.discard(1)
+s.add(1)
+print(s, {1, 2} == {2, 1})
+print(s.pop(), s)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "{'b': 5, 'c': 3, 'a': 4} True",
+            "('a', 4) {'b': 5, 'c': 3}",
+            "{3, 2, 1} True",
+            "3 {2, 1}",
+        ]
+    );
+    let err = sandbox.run("{}.popitem()").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("KeyError: 'popitem(): dictionary is empty'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_large_dict_and_set() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+index = {"key%d" % i: i for i in range(50000)}
+for i in range(0, 50000, 2):
+    del index["key%d" % i]
+ids = set(range(50000))
+print(len(index), index["key49999"], "key49998" in index, list(index)[:3], sum(ids), 49999 in ids)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec!["25000 49999 False ['key1', 'key3', 'key5'] 1249975000 True"]
+    );
+}
+
+#[test]
+fn test_unhashable_keys_and_items() {
+    let mut sandbox = Sandbox::new();
+    for code in [
+        "{[1]: 2}",
+        "{[1]}",
+        "d = {}\nd[[1]] = 2",
+        "{}.get([1])",
+        "[1] in {1: 2}",
+        "[1] in {1}",
+        "s = set()\ns.add({})",
+        "set([[1]])",
+        "hash({1})",
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(
+            err.contains("TypeError: unhashable type"),
+            "{}: {}",
+            code,
+            err
+        );
+    }
+}
+
+// ============================================================================
+// Unicode string tests
+// ============================================================================
+
+#[test]
+fn test_str_indexes_by_code_point() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+s = "café€😀x"
+print(len(s), len("日本語"), s[3], s[-2], s[5], s[1:5], s[::-1], s[-3:], s[4:1:-1], s[::2])
+print(s.find("€"), s.index("x"), s.rfind("é"), s.rindex("€"), s.find("é", 2), s.find("x", -2), s.find("c", 1), s.count("é"), s.count("", 2, 4))
+print(list(s), [c for c in "ü日"])
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "7 3 é 😀 😀 afé€ x😀€éfac €😀x €éf cf€x",
+            "4 6 3 4 3 6 -1 1 3",
+            "['c', 'a', 'f', 'é', '€', '😀', 'x'] ['ü', '日']",
+        ]
+    );
+
+    let err = sandbox.run("'é'[1]").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("IndexError: string index out of range"),
+        "{}",
+        err
+    );
+    let err = sandbox.run("'café'.index('x')").unwrap_err();
+    assert!(
+        err.to_string().contains("ValueError: substring not found"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_str_padding_counts_code_points() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+s = "café€😀x"
+print(s.center(11, "*"), "ab".center(5, "*"), s.ljust(9, "-"), s.rjust(9), "-é".zfill(4), "é".center(-1))
+print(f"{'日本':>4}|", "%-4s|" % "ü", "{:^5}".format("é"))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "**café€😀x** **ab* café€😀x--   café€😀x -00é é",
+            "  日本| ü   |   é  ",
+        ]
+    );
+}
+
+#[test]
+fn test_str_character_classes() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+print("café".isalpha(), "日本語".isalpha(), "Ⅻ".isalpha(), "٣٤".isdigit(), "٣٤".isdecimal(), "²".isdigit(), "²".isdecimal(), "½".isnumeric(), "Ⅻ".isalnum())
+print("ÉCOLE 1".isupper(), "straße".islower(), "ǅa".islower(), "ǅungla".istitle(), "Über Alles".istitle(), "　\t\x1c".isspace(), "a\x1fb c".split())
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "True True False True True True False True True",
+            "True True False True True True ['a', 'b', 'c']",
+        ]
+    );
+}
+
+#[test]
+fn test_ord_and_chr() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+print(ord("é"), ord("😀"), ord(b"a"), chr(233), chr(0x1F600), chr(0x10FFFF) == "\U0010ffff", chr(ord("€")))
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["233 128512 97 é 😀 True €"]);
+
+    for (code, message) in [
+        (
+            "ord('ab')",
+            "TypeError: ord() expected a character, but string of length 2 found",
+        ),
+        (
+            "ord('')",
+            "TypeError: ord() expected a character, but string of length 0 found",
+        ),
+        (
+            "ord(1)",
+            "TypeError: ord() expected string of length 1, but int found",
+        ),
+        ("chr(-1)", "ValueError: chr() arg not in range(0x110000)"),
+        (
+            "chr(0x110000)",
+            "ValueError: chr() arg not in range(0x110000)",
+        ),
+        ("chr(0xD800)", "is a surrogate"),
+        (
+            "chr('a')",
+            "'str' object cannot be interpreted as an integer",
+        ),
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains(message), "{}: {}", code, err);
+    }
+}
+
+// ============================================================================
+// Repr tests
+// ============================================================================
+
+#[test]
+fn test_float_repr_matches_cpython() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+print(1e16, 1e15, 1e-5, 0.0001, 0.1, 1/3, 2.5, 100.0, -0.0, 1e22, 3.14e-10, 2.0**70)
+print(float("inf"), -float("inf"), float("nan"), repr(1e-7), str(1e100), [1e16, 0.1 + 0.2])
+print(f"{1e16}", "%s" % 1e-5, "{}".format(1.5e300), str(123456789012345678.0))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "1e+16 1000000000000000.0 1e-05 0.0001 0.1 0.3333333333333333 2.5 100.0 -0.0 1e+22 3.14e-10 1.1805916207174113e+21",
+            "inf -inf nan 1e-07 1e+100 [1e+16, 0.30000000000000004]",
+            "1e+16 1e-05 1.5e+300 1.2345678901234568e+17",
+        ]
+    );
+}
+
+#[test]
+fn test_str_repr_quotes_and_escapes() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+print(repr("it's"), repr('say "hi"'), repr("both ' and \""), repr("a\nb\tc\\d\r"))
+print(repr("\x00\x1f\x7f"), repr("é😀"), repr("\u200b\xa0"), repr("\ue000"), repr("\U0010fffd"))
+print(["a'b", "x\ny"], ("é",), {"k\n": 'v"'}, "%r" % "x'y", "{!r}".format("q"))
+print("abc".isprintable(), "".isprintable(), "a\n".isprintable(), "\u200b".isprintable())
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            r#""it's" 'say "hi"' 'both \' and "' 'a\nb\tc\\d\r'"#,
+            r"'\x00\x1f\x7f' 'é😀' '\u200b\xa0' '\ue000' '\U0010fffd'",
+            r#"["a'b", 'x\ny'] ('é',) {'k\n': 'v"'} "x'y" 'q'"#,
+            "True True False False",
+        ]
+    );
+}
+
+#[test]
+fn test_container_repr_matches_cpython() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+print(set(), frozenset(), {1, 2}, [set(), [()]], (1,), ((),), {(): {}})
+print({1: {"x": [1.5, 1e-9]}, 2: (None, True)}, str(["x"]), repr([["'"]]))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "set() frozenset() {1, 2} [set(), [()]] (1,) ((),) {(): {}}",
+            r#"{1: {'x': [1.5, 1e-09]}, 2: (None, True)} ['x'] [["'"]]"#,
+        ]
+    );
+}
+
+#[test]
+fn test_dict_views() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+d = {"a": 1, "b": 2}
+k, v, it = d.keys(), d.values(), d.items()
+d["c"] = 3
+print(k, v, it)
+print(len(k), len(it), bool({}.keys()), type(k).__name__, type(v).__name__, type(it).__name__)
+print("a" in k, "z" in k, 3 in v, ("a", 1) in it, ("a", 2) in it)
+print(k == {"a", "b", "c"}, k == ["a", "b", "c"], it == {("a", 1), ("b", 2), ("c", 3)})
+print(k & {"a", "z"}, k - {"a"}, sorted(k | {"z"}), dict(it), list(v), [key for key, _ in it])
+del d["a"]
+print(k, {}.items())
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "dict_keys(['a', 'b', 'c']) dict_values([1, 2, 3]) dict_items([('a', 1), ('b', 2), ('c', 3)])",
+            "3 3 False dict_keys dict_values dict_items",
+            "True False True True False",
+            "True False True",
+            "{'a'} {'b', 'c'} ['a', 'b', 'c', 'z'] {'a': 1, 'b': 2, 'c': 3} [1, 2, 3] ['a', 'b', 'c']",
+            "dict_keys(['b', 'c']) dict_items([])",
+        ]
+    );
+}
+
+#[test]
+fn test_dict_and_set_changed_during_iteration() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+d = {1: 1, 2: 2, 3: 3}
+for k, v in d.items():
+    if k == 1:
+        d[3] = 30
+    print(k, v)
+for code in ["d", "ks", "s"]:
+    d, s = {1: "a", 2: "b"}, {1, 2}
+    try:
+        if code == "d":
+            for k, v in d.items():
+                d[k + 10] = v
+        elif code == "ks":
+            for k in d:
+                del d[k]
+                d[k + 10] = 0
+        else:
+            for x in s:
+                s.add(x + 10)
+    except RuntimeError as e:
+        print(e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "1 1",
+            "2 2",
+            "3 30",
+            "dictionary changed size during iteration",
+            "dictionary keys changed during iteration",
+            "Set changed size during iteration",
+        ]
+    );
+}
+
+// ============================================================================
+// Loop else and assignment expression tests
+// ============================================================================
+
+#[test]
+fn test_for_else() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+def find(items, target):
+    for item in items:
+        if item == target:
+            print("found", item)
+            break
+    else:
+        print("not found")
+find([1, 2, 3], 2)
+find([1, 2, 3], 5)
+for i in range(2):
+    for j in range(3):
+        if j == 1:
+            break
+    else:
+        print("inner else")
+    print("outer", i)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec!["found 2", "not found", "outer 0", "outer 1"]
+    );
+}
+
+#[test]
+fn test_while_else() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+n = 0
+while n < 3:
+    n += 1
+else:
+    print("done", n)
+while True:
+    break
+else:
+    print("never")
+def retry(attempts):
+    while attempts:
+        attempts -= 1
+        if attempts == 1:
+            return "succeeded"
+    else:
+        return "gave up"
+print(retry(3), retry(1))
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["done 3", "succeeded gave up"]);
+}
+
+#[test]
+fn test_walrus() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+items = [1, 2, 3]
+if (n := len(items)) > 2:
+    print("n", n)
+queue = [1, 2, 3, 4]
+while (chunk := queue.pop()) != 2:
+    print("chunk", chunk)
+print(queue)
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["n 3", "chunk 4", "chunk 3", "[1]"]);
+}
+
+#[test]
+fn test_walrus_in_comprehensions() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+data = [1, 2, 3, 4]
+doubled = [y for x in data if (y := x * 2) > 4]
+print(doubled, y)
+print(any((last := v) > 2 for v in data), last)
+def totals():
+    total = sum((seen := v) for v in [7, 8])
+    return total, seen
+print(totals())
+"#,
+        )
+        .unwrap();
+    assert_eq!(result.output, vec!["[6, 8] 8", "True 3", "(15, 8)"]);
+}
+
+#[test]
+fn test_walrus_cannot_rebind_comprehension_variable() {
+    let mut sandbox = Sandbox::new();
+    for (code, name) in [
+        ("[i := 0 for i in range(3)]", "i"),
+        ("{(a := 1) for a, b in []}", "a"),
+        ("list(x := 1 for x in [])", "x"),
+        ("def f():\n    return {k: (k := 1) for k in []}", "k"),
+        ("[[i := 0 for j in range(2)] for i in range(2)]", "i"),
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        let message = format!(
+            "assignment expression cannot rebind comprehension iteration variable '{}'",
+            name
+        );
+        assert!(err.contains(&message), "{}: {}", code, err);
+    }
+    let result = sandbox
+        .capture("[y := i for i in range(3)]\nprint(y, [(lambda: (i := 5))() for i in range(2)])")
+        .unwrap();
+    assert_eq!(result.output, vec!["2 [5, 5]"]);
+}
+
+// ============================================================================
+// Starred unpacking and slice assignment tests
+// ============================================================================
+
+#[test]
+fn test_starred_unpacking() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+first, *rest = [1, 2, 3, 4]
+print(first, rest)
+*init, last = (1, 2, 3)
+print(init, last)
+head, *middle, tail = "hello"
+print(head, middle, tail)
+only, *empty = [1]
+print(only, empty)
+[a, *b] = range(3)
+print(a, b)
+for key, *values in [[1, 2, 3], [4]]:
+    print(key, values)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "1 [2, 3, 4]",
+            "[1, 2] 3",
+            "h ['e', 'l', 'l'] o",
+            "1 []",
+            "0 [1, 2]",
+            "1 [2, 3]",
+            "4 []",
+        ]
+    );
+}
+
+#[test]
+fn test_starred_unpacking_too_few_values() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+try:
+    a, *b, c = [1]
+except ValueError as e:
+    print(e)
+try:
+    a, b, c = (1, 2)
+except ValueError as e:
+    print(e)
+try:
+    a, b = [1, 2, 3]
+except ValueError as e:
+    print(e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "not enough values to unpack (expected at least 2, got 1)",
+            "not enough values to unpack (expected 3, got 2)",
+            "too many values to unpack (expected 2)",
+        ]
+    );
+
+    let err = sandbox.run("*a, *b = [1, 2]").unwrap_err();
+    assert!(err.to_string().contains("multiple starred expressions"));
+}
+
+#[test]
+fn test_slice_assignment() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .capture(
+            r#"
+xs = [0, 1, 2, 3, 4, 5]
+alias = xs
+xs[1:3] = [9]
+print(alias)
+xs[:0] = (7, 8)
+print(xs)
+xs[len(xs):] = [100]
+print(xs)
+xs[::2] = [0, 0, 0, 0]
+print(xs)
+xs[:] = (i * 10 for i in range(3))
+print(alias)
+data = {"rows": [1, 2, 3]}
+data["rows"][1:] = []
+print(data)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "[0, 9, 3, 4, 5]",
+            "[7, 8, 0, 9, 3, 4, 5]",
+            "[7, 8, 0, 9, 3, 4, 5, 100]",
+            "[0, 8, 0, 9, 0, 4, 0, 100]",
+            "[0, 10, 20]",
+            "{'rows': [1]}",
+        ]
+    );
+}
+
+#[test]
+fn test_slice_assignment_errors() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+xs = [1, 2, 3, 4]
+try:
+    xs[::2] = [1]
+except ValueError as e:
+    err = str(e)
+err
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::Str(
+            "attempt to assign sequence of size 1 to extended slice of size 2".to_string()
+        )
+    );
+
+    let err = sandbox.run("t = (1, 2)\nt[0:1] = [3]").unwrap_err();
+    assert!(err.to_string().contains("does not support item assignment"));
+}
+
+// ============================================================================
+// Deletion tests
+// ============================================================================
+
+#[test]
+fn test_del_name() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+x = 1
+del x
+try:
+    x
+    found = True
+except NameError:
+    found = False
+found
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Bool(false));
+
+    let err = sandbox.run("del never_defined").unwrap_err();
+    assert!(err.to_string().contains("never_defined"));
+}
+
+#[test]
+fn test_del_dict_key() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+cache = {"a": 1, "b": 2}
+alias = cache
+del cache["a"]
+try:
+    del cache["missing"]
+except KeyError as e:
+    err = str(e)
+[alias, err]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::dict(vec![(PyValue::Str("b".to_string()), PyValue::Int(2))]),
+            PyValue::Str("'missing'".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_del_list_index() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+items = [1, 2, 3, 4]
+del items[0]
+del items[-1]
+try:
+    del items[10]
+except IndexError as e:
+    err = str(e)
+[items, err]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::list(vec![PyValue::Int(2), PyValue::Int(3)]),
+            PyValue::Str("list assignment index out of range".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_del_slice() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+a = list(range(8))
+del a[1:3]
+b = list(range(8))
+del b[::2]
+c = list(range(10))
+del c[::-3]
+[a, b, c]
+"#,
+        )
+        .unwrap();
+    let ints = |v: &[i64]| PyValue::list(v.iter().map(|&i| PyValue::Int(i)).collect());
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            ints(&[0, 3, 4, 5, 6, 7]),
+            ints(&[1, 3, 5, 7]),
+            ints(&[1, 2, 4, 5, 7, 8]),
+        ])
+    );
+}
+
+#[test]
+fn test_del_in_functions() {
+    let mut sandbox = Sandbox::new();
+    let result = sandbox
+        .run(
+            r#"
+counter = 0
+def reset():
+    global counter
+    del counter
+def local():
+    tmp = 1
+    del tmp
+    try:
+        return tmp
+    except NameError:
+        return "local gone"
+def outer():
+    z = 1
+    def inner():
+        nonlocal z
+        del z
+    inner()
+    try:
+        return z
+    except NameError:
+        return "cell gone"
+reset()
+try:
+    counter
+    state = "kept"
+except NameError:
+    state = "global gone"
+[state, local(), outer()]
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        PyValue::list(vec![
+            PyValue::Str("global gone".to_string()),
+            PyValue::Str("local gone".to_string()),
+            PyValue::Str("cell gone".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_del_unsupported_target() {
+    let mut sandbox = Sandbox::new();
+    let err = sandbox.run("t = (1, 2)\ndel t[0]").unwrap_err();
+    assert!(err.to_string().contains("does not support item deletion"));
+}
+
+#[test]
+fn test_sandbox_unset() {
+    let mut sandbox = Sandbox::new();
+    sandbox.set("token", PyValue::Str("secret".to_string()));
+    assert_eq!(
+        sandbox.run("token").unwrap(),
+        PyValue::Str("secret".to_string())
+    );
+
+    assert!(sandbox.unset("token"));
+    assert!(!sandbox.unset("token"));
+    assert!(sandbox.run("token").is_err());
+}
+
 // ============================================================================
 // Set tests
 // ============================================================================
@@ -1544,7 +6472,7 @@ fn test_set_literal() {
 
     assert_eq!(
         sandbox.run("{1, 2, 3}").unwrap(),
-        PyValue::Set(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::set(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -1555,6 +6483,7 @@ fn test_set_dedup() {
     // Duplicates should be removed
     let result = sandbox.run("{1, 2, 2, 3, 3, 3}").unwrap();
     if let PyValue::Set(items) = &result {
+        let items = items.read();
         assert_eq!(items.len(), 3);
     } else {
         panic!("Expected Set, got {:?}", result);
@@ -1565,7 +6494,7 @@ fn test_set_dedup() {
 fn test_set_empty_builtin() {
     let mut sandbox = Sandbox::new();
 
-    assert_eq!(sandbox.run("set()").unwrap(), PyValue::Set(vec![]));
+    assert_eq!(sandbox.run("set()").unwrap(), PyValue::set(vec![]));
 }
 
 #[test]
@@ -1574,6 +6503,7 @@ fn test_set_from_list() {
 
     let result = sandbox.run("set([1, 2, 2, 3])").unwrap();
     if let PyValue::Set(items) = &result {
+        let items = items.read();
         assert_eq!(items.len(), 3);
         assert!(items.contains(&PyValue::Int(1)));
         assert!(items.contains(&PyValue::Int(2)));
@@ -1609,6 +6539,7 @@ fn test_set_union() {
 
     let result = sandbox.run("{1, 2} | {2, 3}").unwrap();
     if let PyValue::Set(items) = &result {
+        let items = items.read();
         assert_eq!(items.len(), 3);
         assert!(items.contains(&PyValue::Int(1)));
         assert!(items.contains(&PyValue::Int(2)));
@@ -1624,7 +6555,7 @@ fn test_set_intersection() {
 
     assert_eq!(
         sandbox.run("{1, 2, 3} & {2, 3, 4}").unwrap(),
-        PyValue::Set(vec![PyValue::Int(2), PyValue::Int(3)])
+        PyValue::set(vec![PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -1634,7 +6565,7 @@ fn test_set_difference() {
 
     assert_eq!(
         sandbox.run("{1, 2, 3} - {2, 3, 4}").unwrap(),
-        PyValue::Set(vec![PyValue::Int(1)])
+        PyValue::set(vec![PyValue::Int(1)])
     );
 }
 
@@ -1644,6 +6575,7 @@ fn test_set_symmetric_difference() {
 
     let result = sandbox.run("{1, 2, 3} ^ {2, 3, 4}").unwrap();
     if let PyValue::Set(items) = &result {
+        let items = items.read();
         assert_eq!(items.len(), 2);
         assert!(items.contains(&PyValue::Int(1)));
         assert!(items.contains(&PyValue::Int(4)));
@@ -1719,6 +6651,7 @@ fn test_set_method_union_intersection() {
 
     let result = sandbox.run("{1, 2}.union({2, 3})").unwrap();
     if let PyValue::Set(items) = &result {
+        let items = items.read();
         assert_eq!(items.len(), 3);
     } else {
         panic!("Expected Set");
@@ -1726,7 +6659,7 @@ fn test_set_method_union_intersection() {
 
     assert_eq!(
         sandbox.run("{1, 2, 3}.intersection({2, 3, 4})").unwrap(),
-        PyValue::Set(vec![PyValue::Int(2), PyValue::Int(3)])
+        PyValue::set(vec![PyValue::Int(2), PyValue::Int(3)])
     );
 
     assert_eq!(
@@ -1762,7 +6695,7 @@ for x in {3, 1, 2}:
     let result = sandbox.run("sorted(result)").unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -1772,7 +6705,7 @@ fn test_set_type_name() {
 
     assert_eq!(
         sandbox.run("type({1, 2})").unwrap(),
-        PyValue::Str("set".to_string())
+        PyValue::Type("set".to_string())
     );
     assert_eq!(
         sandbox.run("isinstance({1}, 'set')").unwrap(),
@@ -1921,7 +6854,7 @@ fn test_tuple_type_name() {
 
     assert_eq!(
         sandbox.run("type((1, 2))").unwrap(),
-        PyValue::Str("tuple".to_string())
+        PyValue::Type("tuple".to_string())
     );
 }
 
@@ -1958,7 +6891,7 @@ for x in (10, 20, 30):
         .unwrap();
     assert_eq!(
         sandbox.run("result").unwrap(),
-        PyValue::List(vec![PyValue::Int(10), PyValue::Int(20), PyValue::Int(30)])
+        PyValue::list(vec![PyValue::Int(10), PyValue::Int(20), PyValue::Int(30)])
     );
 }
 
@@ -2076,7 +7009,7 @@ fn test_enumerate_returns_tuples() {
 
     assert_eq!(
         sandbox.run("list(enumerate(['a', 'b']))").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Tuple(vec![PyValue::Int(0), PyValue::Str("a".to_string())]),
             PyValue::Tuple(vec![PyValue::Int(1), PyValue::Str("b".to_string())]),
         ])
@@ -2089,7 +7022,7 @@ fn test_zip_returns_tuples() {
 
     assert_eq!(
         sandbox.run("list(zip([1, 2], ['a', 'b']))").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Tuple(vec![PyValue::Int(1), PyValue::Str("a".to_string())]),
             PyValue::Tuple(vec![PyValue::Int(2), PyValue::Str("b".to_string())]),
         ])
@@ -2105,7 +7038,7 @@ fn test_dict_items_returns_tuples() {
     let result = sandbox.run("list(d.items())").unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![PyValue::Tuple(vec![
+        PyValue::list(vec![PyValue::Tuple(vec![
             PyValue::Str("a".to_string()),
             PyValue::Int(1),
         ])])
@@ -2144,7 +7077,7 @@ fn test_tuple_edge_cases() {
     // 4. sorted() on tuple
     assert_eq!(
         sandbox.run("sorted((3, 1, 2))").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 
     // 5. tuple() with no args → empty tuple
@@ -2162,7 +7095,7 @@ fn test_tuple_edge_cases() {
     // 7. Tuple in list comprehension: [x for x in (1,2,3)]
     assert_eq!(
         sandbox.run("[x for x in (1,2,3)]").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 
     // 8. min()/max() on tuple
@@ -2181,7 +7114,7 @@ fn test_tuple_edge_cases() {
     // 11. reversed() on tuple → list
     assert_eq!(
         sandbox.run("list(reversed((1, 2, 3)))").unwrap(),
-        PyValue::List(vec![PyValue::Int(3), PyValue::Int(2), PyValue::Int(1)])
+        PyValue::list(vec![PyValue::Int(3), PyValue::Int(2), PyValue::Int(1)])
     );
 
     // 12. Print format for tuples
@@ -2207,7 +7140,7 @@ fn test_set_edge_cases() {
     // Sets are unordered, so we check via sorted list
     assert_eq!(
         sandbox.run("sorted(set('abca'))").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("a".to_string()),
             PyValue::Str("b".to_string()),
             PyValue::Str("c".to_string()),
@@ -2217,19 +7150,19 @@ fn test_set_edge_cases() {
     // 2. set() from tuple
     assert_eq!(
         sandbox.run("sorted(set((3, 1, 2, 1)))").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 
     // 3. sorted() on set
     assert_eq!(
         sandbox.run("sorted({3, 1, 2})").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 
     // 4. list() on set (order not guaranteed, so sort after)
     assert_eq!(
         sandbox.run("sorted(list({3, 1, 2}))").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 
     // 5. sum()/min()/max() on set
@@ -2253,7 +7186,7 @@ fn test_set_edge_cases() {
     sandbox.run("b = a.copy()").unwrap();
     assert_eq!(
         sandbox.run("sorted(b)").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 
     // 9. Set of mixed types: {1, 'a', True}
@@ -2265,7 +7198,7 @@ fn test_set_edge_cases() {
     // 10. Set containing tuples: {(1,2), (3,4)}
     assert_eq!(
         sandbox.run("sorted({(1,2), (3,4)})").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Tuple(vec![PyValue::Int(1), PyValue::Int(2)]),
             PyValue::Tuple(vec![PyValue::Int(3), PyValue::Int(4)]),
         ])
@@ -2275,7 +7208,7 @@ fn test_set_edge_cases() {
     sandbox.run("s = {100}").unwrap();
     assert_eq!(
         sandbox.run("list(enumerate(s))").unwrap(),
-        PyValue::List(vec![PyValue::Tuple(vec![
+        PyValue::list(vec![PyValue::Tuple(vec![
             PyValue::Int(0),
             PyValue::Int(100)
         ]),])
@@ -2284,13 +7217,13 @@ fn test_set_edge_cases() {
     // 12. Nested set operations: ({1,2} | {3}) & {1, 3}
     assert_eq!(
         sandbox.run("sorted(({1,2} | {3}) & {1, 3})").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(3)])
     );
 
     // 13. set([1, 2, 2, 3]) dedup
     assert_eq!(
         sandbox.run("sorted(set([1, 2, 2, 3]))").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -2316,13 +7249,13 @@ fn test_dict_key_edge_cases() {
     // 2. dict.keys() returns non-string keys
     assert_eq!(
         sandbox.run("sorted(d.keys())").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2)])
     );
 
     // 3. dict.items() with int keys → list of tuples
     assert_eq!(
         sandbox.run("sorted(d.items())").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Tuple(vec![PyValue::Int(1), PyValue::Str("one".to_string())]),
             PyValue::Tuple(vec![PyValue::Int(2), PyValue::Str("two".to_string())]),
         ])
@@ -2361,7 +7294,7 @@ fn test_dict_key_edge_cases() {
         .unwrap();
     assert_eq!(
         sandbox.run("sorted(keys)").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2)])
     );
 }
 
@@ -2439,13 +7372,13 @@ fn test_function_type_name() {
 
     assert_eq!(
         sandbox.run("type(lambda x: x)").unwrap(),
-        PyValue::Str("function".to_string())
+        PyValue::Type("function".to_string())
     );
 
     sandbox.run("def foo():\n    pass").unwrap();
     assert_eq!(
         sandbox.run("type(foo)").unwrap(),
-        PyValue::Str("function".to_string())
+        PyValue::Type("function".to_string())
     );
 }
 
@@ -2465,12 +7398,12 @@ fn test_sorted_with_reverse() {
 
     assert_eq!(
         sandbox.run("sorted([3, 1, 2], reverse=True)").unwrap(),
-        PyValue::List(vec![PyValue::Int(3), PyValue::Int(2), PyValue::Int(1)])
+        PyValue::list(vec![PyValue::Int(3), PyValue::Int(2), PyValue::Int(1)])
     );
 
     assert_eq!(
         sandbox.run("sorted([3, 1, 2], reverse=False)").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -2483,7 +7416,7 @@ fn test_sorted_with_key() {
         sandbox
             .run("sorted(['banana', 'apple', 'cherry'], key=lambda x: len(x))")
             .unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("apple".to_string()),
             PyValue::Str("banana".to_string()),
             PyValue::Str("cherry".to_string()),
@@ -2495,7 +7428,7 @@ fn test_sorted_with_key() {
         sandbox
             .run("sorted([-3, 1, -2, 4], key=lambda x: abs(x))")
             .unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(1),
             PyValue::Int(-2),
             PyValue::Int(-3),
@@ -2512,7 +7445,7 @@ fn test_sorted_with_key_and_reverse() {
         sandbox
             .run("sorted(['banana', 'apple', 'cherry'], key=lambda x: len(x), reverse=True)")
             .unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("cherry".to_string()),
             PyValue::Str("banana".to_string()),
             PyValue::Str("apple".to_string()),
@@ -2528,7 +7461,7 @@ fn test_map_basic() {
         sandbox
             .run("list(map(lambda x: x * 2, [1, 2, 3]))")
             .unwrap(),
-        PyValue::List(vec![PyValue::Int(2), PyValue::Int(4), PyValue::Int(6)])
+        PyValue::list(vec![PyValue::Int(2), PyValue::Int(4), PyValue::Int(6)])
     );
 }
 
@@ -2539,7 +7472,7 @@ fn test_map_with_named_function() {
     sandbox.run("def square(x):\n    return x * x").unwrap();
     assert_eq!(
         sandbox.run("list(map(square, [1, 2, 3]))").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(4), PyValue::Int(9)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(4), PyValue::Int(9)])
     );
 }
 
@@ -2551,7 +7484,7 @@ fn test_filter_with_lambda() {
         sandbox
             .run("list(filter(lambda x: x > 2, [1, 2, 3, 4]))")
             .unwrap(),
-        PyValue::List(vec![PyValue::Int(3), PyValue::Int(4)])
+        PyValue::list(vec![PyValue::Int(3), PyValue::Int(4)])
     );
 }
 
@@ -2563,7 +7496,7 @@ fn test_filter_with_none() {
         sandbox
             .run("list(filter(None, [0, 1, '', 'hello', False, True]))")
             .unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(1),
             PyValue::Str("hello".to_string()),
             PyValue::Bool(true),
@@ -2575,10 +7508,10 @@ fn test_filter_with_none() {
 fn test_map_returns_list() {
     let mut sandbox = Sandbox::new();
 
-    // map already returns a list (not a lazy iterator like Python 3)
+    // A map object returned to the host is converted to the list of its items
     assert_eq!(
         sandbox.run("map(lambda x: x + 1, [10, 20])").unwrap(),
-        PyValue::List(vec![PyValue::Int(11), PyValue::Int(21)])
+        PyValue::list(vec![PyValue::Int(11), PyValue::Int(21)])
     );
 }
 
@@ -2590,7 +7523,7 @@ fn test_filter_returns_list() {
         sandbox
             .run("filter(lambda x: x > 0, [-1, 0, 1, 2])")
             .unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2)])
     );
 }
 
@@ -2604,7 +7537,7 @@ fn test_lambda_in_list_comprehension_equivalent() {
         .unwrap();
     assert_eq!(
         sandbox.run("result").unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Int(0),
             PyValue::Int(1),
             PyValue::Int(4),
@@ -2651,6 +7584,459 @@ fn test_callable_value_via_call_value_kw() {
     assert_eq!(sandbox.run("f(5, y=20)").unwrap(), PyValue::Int(25));
 }
 
+// ============================================================================
+// Closures
+// ============================================================================
+
+#[test]
+fn test_closure_make_adder() {
+    let mut sandbox = Sandbox::new();
+
+    sandbox
+        .run(
+            r#"
+def make_adder(n):
+    def add(x):
+        return x + n
+    return add
+
+add5 = make_adder(5)
+add10 = make_adder(10)
+"#,
+        )
+        .unwrap();
+    assert_eq!(sandbox.run("add5(1)").unwrap(), PyValue::Int(6));
+    assert_eq!(sandbox.run("add10(1)").unwrap(), PyValue::Int(11));
+}
+
+#[test]
+fn test_closure_lambda_capture() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def multiplier(k):
+    return lambda x: x * k
+
+triple = multiplier(3)
+[triple(i) for i in range(4)]
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Int(0),
+            PyValue::Int(3),
+            PyValue::Int(6),
+            PyValue::Int(9),
+        ])
+    );
+}
+
+#[test]
+fn test_closure_sees_later_assignment() {
+    let mut sandbox = Sandbox::new();
+
+    // The closure reads the variable when called, not when defined
+    let code = r#"
+def outer():
+    x = 1
+    def get():
+        return x
+    x = 2
+    return get()
+
+outer()
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(2));
+}
+
+#[test]
+fn test_closure_mutates_captured_list() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def make_counter():
+    counts = [0]
+    def inc():
+        counts[0] += 1
+        counts.append(counts[0])
+        return counts[0]
+    return inc
+
+c = make_counter()
+c()
+c()
+c()
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(3));
+}
+
+#[test]
+fn test_closure_independent_instances() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def make_acc():
+    items = []
+    def add(x):
+        items.append(x)
+        return len(items)
+    return add
+
+a = make_acc()
+b = make_acc()
+a(1)
+a(2)
+b(3)
+[a(0), b(0)]
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![PyValue::Int(3), PyValue::Int(2)])
+    );
+}
+
+#[test]
+fn test_closure_multiple_levels() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def a(x):
+    def b(y):
+        def c(z):
+            return x + y + z
+        return c
+    return b
+
+a(1)(2)(3)
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(6));
+}
+
+#[test]
+fn test_closure_recursive_inner_function() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def fact(n):
+    def go(k):
+        if k <= 1:
+            return 1
+        return k * go(k - 1)
+    return go(n)
+
+fact(5)
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(120));
+}
+
+#[test]
+fn test_closure_as_sort_key() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def sort_by_field(rows, field):
+    return sorted(rows, key=lambda r: r[field])
+
+rows = [{"n": 3}, {"n": 1}, {"n": 2}]
+[r["n"] for r in sort_by_field(rows, "n")]
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+    );
+}
+
+#[test]
+fn test_closure_unbound_variable() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def outer():
+    def get():
+        return x
+    v = get()
+    x = 1
+    return v
+
+outer()
+"#;
+    assert!(sandbox.run(code).is_err());
+}
+
+#[test]
+fn test_module_level_function_reads_globals() {
+    let mut sandbox = Sandbox::new();
+
+    // Module-level names are globals, not closure cells
+    sandbox
+        .run("base = 1\ndef get():\n    return base\n")
+        .unwrap();
+    sandbox.run("base = 100").unwrap();
+    assert_eq!(sandbox.run("get()").unwrap(), PyValue::Int(100));
+}
+
+// ============================================================================
+// global and nonlocal
+// ============================================================================
+
+#[test]
+fn test_global_counter() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+count = 0
+def bump():
+    global count
+    count += 1
+
+bump()
+bump()
+count
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(2));
+    // The global persists across runs
+    sandbox.run("bump()").unwrap();
+    assert_eq!(sandbox.run("count").unwrap(), PyValue::Int(3));
+}
+
+#[test]
+fn test_global_creates_new_variable() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def setup():
+    global config
+    config = {"debug": True}
+
+setup()
+config["debug"]
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Bool(true));
+}
+
+#[test]
+fn test_assignment_without_global_is_local() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+x = 1
+def f():
+    x = 2
+    return x
+
+[f(), x]
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![PyValue::Int(2), PyValue::Int(1)])
+    );
+}
+
+#[test]
+fn test_local_read_before_assignment_is_unbound_local_error() {
+    let mut sandbox = Sandbox::new();
+
+    let result = sandbox
+        .capture(
+            r#"
+count = 0
+def inc():
+    count += 1
+    return count
+
+def show():
+    print(x)
+    x = 1
+
+for fn in [inc, show]:
+    try:
+        fn()
+    except NameError as e:
+        print(type(e).__name__, e)
+print(count)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "UnboundLocalError cannot access local variable 'count' where it is not associated with a value",
+            "UnboundLocalError cannot access local variable 'x' where it is not associated with a value",
+            "0",
+        ]
+    );
+
+    let err = sandbox.run("def f():\n    y = y + 1\nf()").unwrap_err();
+    assert!(err.to_string().contains("UnboundLocalError"));
+}
+
+#[test]
+fn test_global_mutating_method() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+log = []
+def record(msg):
+    global log
+    log.append(msg)
+
+record("a")
+record("b")
+log
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![
+            PyValue::Str("a".to_string()),
+            PyValue::Str("b".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_global_in_nested_function() {
+    let mut sandbox = Sandbox::new();
+
+    // A nested function sees the global, not the enclosing function's scope
+    let code = r#"
+total = 0
+def outer():
+    def add(n):
+        global total
+        total = total + n
+    add(5)
+    add(7)
+
+outer()
+total
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(12));
+}
+
+#[test]
+fn test_nonlocal_counter() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def make_counter():
+    count = 0
+    def inc():
+        nonlocal count
+        count += 1
+        return count
+    return inc
+
+c = make_counter()
+c()
+c()
+c()
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(3));
+}
+
+#[test]
+fn test_nonlocal_visible_to_enclosing_function() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def outer():
+    result = "before"
+    def set_it():
+        nonlocal result
+        result = "after"
+    set_it()
+    return result
+
+outer()
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::Str("after".to_string())
+    );
+}
+
+#[test]
+fn test_nonlocal_through_multiple_levels() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def a():
+    x = 1
+    def b():
+        def c():
+            nonlocal x
+            x = x * 10
+        c()
+    b()
+    b()
+    return x
+
+a()
+"#;
+    assert_eq!(sandbox.run(code).unwrap(), PyValue::Int(100));
+}
+
+#[test]
+fn test_nonlocal_without_binding_is_error() {
+    let mut sandbox = Sandbox::new();
+
+    let err = sandbox
+        .run("def f():\n    nonlocal y\n    y = 1\n")
+        .unwrap_err();
+    assert!(err.to_string().contains("no binding for nonlocal 'y'"));
+
+    let err = sandbox.run("nonlocal z").unwrap_err();
+    assert!(err.to_string().contains("module level"));
+}
+
+#[test]
+fn test_declaration_after_use_is_error() {
+    let mut sandbox = Sandbox::new();
+    for (code, message) in [
+        (
+            "def f():\n    x = 2\n    global x",
+            "name 'x' is assigned to before global declaration",
+        ),
+        (
+            "x = 2\nglobal x",
+            "name 'x' is assigned to before global declaration",
+        ),
+        (
+            "def f():\n    print(x)\n    global x",
+            "name 'x' is used prior to global declaration",
+        ),
+        (
+            "def f(x):\n    global x",
+            "name 'x' is parameter and global",
+        ),
+        (
+            "def f():\n    y = 1\n    def g():\n        y = 2\n        nonlocal y",
+            "name 'y' is assigned to before nonlocal declaration",
+        ),
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains(message), "{}: {}", code, err);
+    }
+}
+
+#[test]
+fn test_locals_in_sort_key_do_not_leak_to_globals() {
+    let mut sandbox = Sandbox::new();
+
+    let code = r#"
+def key(x):
+    tmp = -x
+    return tmp
+
+sorted([1, 3, 2], key=key)
+"#;
+    assert_eq!(
+        sandbox.run(code).unwrap(),
+        PyValue::list(vec![PyValue::Int(3), PyValue::Int(2), PyValue::Int(1)])
+    );
+    assert!(sandbox.run("tmp").is_err());
+}
+
 // ============================================================================
 // Import system tests
 // ============================================================================
@@ -2737,7 +8123,7 @@ fn test_from_json_import() {
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -2801,7 +8187,7 @@ fn test_custom_module_registration() {
 fn test_custom_module_from_import() {
     let mut sandbox = Sandbox::new();
     sandbox.module("tools", |m| {
-        m.constant("PI", PyValue::Float(3.14));
+        m.constant("PI", PyValue::Float(PI));
         m.function("add", |args| {
             let a = args.first().and_then(|v| v.as_int()).unwrap_or(0);
             let b = args.get(1).and_then(|v| v.as_int()).unwrap_or(0);
@@ -2811,7 +8197,7 @@ fn test_custom_module_from_import() {
 
     assert_eq!(
         sandbox.run("from tools import PI; PI").unwrap(),
-        PyValue::Float(3.14)
+        PyValue::Float(PI)
     );
     assert_eq!(
         sandbox.run("from tools import add; add(3, 4)").unwrap(),
@@ -2870,6 +8256,15 @@ fn test_math_factorial() {
         sandbox.run("import math; math.factorial(5)").unwrap(),
         PyValue::Int(120)
     );
+    for (code, message) in [
+        ("math.factorial(10 ** 6)", "OverflowError"),
+        ("math.factorial(2 ** 70)", "OverflowError"),
+        ("math.factorial(-1)", "ValueError"),
+        ("math.factorial(1.5)", "TypeError"),
+    ] {
+        let err = sandbox.run(code).unwrap_err().to_string();
+        assert!(err.contains(message), "{}: {}", code, err);
+    }
 }
 
 #[test]
@@ -2886,12 +8281,37 @@ json.loads(json.dumps(data))
         .unwrap();
     // Verify the roundtrip preserves structure
     if let PyValue::Dict(pairs) = &result {
+        let pairs = pairs.read();
         assert_eq!(pairs.len(), 3);
     } else {
         panic!("Expected dict, got {:?}", result);
     }
 }
 
+#[test]
+fn test_json_big_int_roundtrip() {
+    let mut sandbox = Sandbox::with_builtins();
+    let result = sandbox
+        .capture(
+            r#"
+import json
+data = {"big": 2**70, "neg": -2**70, "id": 123456789012345678901234567890}
+text = json.dumps(data)
+print(text)
+back = json.loads(text)
+print(back == data, type(back["big"]).__name__, json.loads("1e400"), json.loads("2.5"))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            r#"{"big":1180591620717411303424,"id":123456789012345678901234567890,"neg":-1180591620717411303424}"#,
+            "True int inf 2.5",
+        ]
+    );
+}
+
 #[test]
 fn test_import_in_function() {
     let mut sandbox = Sandbox::with_builtins();
@@ -3131,7 +8551,7 @@ f.close()
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("line1\n".to_string()),
             PyValue::Str("line2\n".to_string()),
         ])
@@ -3159,7 +8579,7 @@ lines
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("a\n".to_string()),
             PyValue::Str("b\n".to_string()),
             PyValue::Str("c".to_string()),
@@ -3276,6 +8696,80 @@ f.close()
     assert_eq!(host_content, "hello world");
 }
 
+#[test]
+fn test_binary_file_read_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("image.bin");
+    let output = dir.path().join("copy.bin");
+    std::fs::write(&input, [0x89, b'P', b'N', b'G', 0x00, 0xff, b'\n', 0x01]).unwrap();
+    std::fs::write(&output, "").unwrap();
+
+    let mut sandbox = Sandbox::new();
+    sandbox.mount("image.bin", input.to_str().unwrap(), false);
+    sandbox.mount("copy.bin", output.to_str().unwrap(), true);
+
+    let result = sandbox
+        .capture(
+            r#"
+with open("image.bin", "rb") as f:
+    header = f.readline()
+    rest = f.read()
+print(header, rest, header[:4] == b"\x89PNG")
+with open("copy.bin", "wb") as f:
+    print(f.write(header + rest), f.write(bytearray([2])))
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec!["b'\\x89PNG\\x00\\xff\\n' b'\\x01' True", "8 1"]
+    );
+    assert_eq!(
+        std::fs::read(&output).unwrap(),
+        vec![0x89, b'P', b'N', b'G', 0x00, 0xff, b'\n', 0x01, 0x02]
+    );
+}
+
+#[test]
+fn test_binary_file_mode_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("data.bin");
+    let output = dir.path().join("out.bin");
+    std::fs::write(&input, [0xff, 0xfe]).unwrap();
+    std::fs::write(&output, "").unwrap();
+
+    let mut sandbox = Sandbox::new();
+    sandbox.mount("data.bin", input.to_str().unwrap(), false);
+    sandbox.mount("out.bin", output.to_str().unwrap(), true);
+
+    let result = sandbox
+        .capture(
+            r#"
+try:
+    open("data.bin").read()
+except UnicodeDecodeError as e:
+    print(e)
+try:
+    open("out.bin", "wb").write("text")
+except TypeError as e:
+    print(e)
+try:
+    open("out.bin", "w").write(b"data")
+except TypeError as e:
+    print(e)
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        result.output,
+        vec![
+            "'utf-8' codec can't decode byte 0xff in position 0: invalid start byte",
+            "a bytes-like object is required, not 'str'",
+            "write() argument must be a string",
+        ]
+    );
+}
+
 // -----------------------------------------------------------------------
 // Dict comprehensions
 // -----------------------------------------------------------------------
@@ -3286,7 +8780,7 @@ fn test_dict_comprehension_basic() {
     let result = sandbox.run("{k: v for k, v in [(1, 2), (3, 4)]}").unwrap();
     assert_eq!(
         result,
-        PyValue::Dict(vec![
+        PyValue::dict(vec![
             (PyValue::Int(1), PyValue::Int(2)),
             (PyValue::Int(3), PyValue::Int(4)),
         ])
@@ -3306,7 +8800,7 @@ items = [(1, -1), (2, 5), (3, -2), (4, 10)]
         .unwrap();
     assert_eq!(
         result,
-        PyValue::Dict(vec![
+        PyValue::dict(vec![
             (PyValue::Int(2), PyValue::Int(5)),
             (PyValue::Int(4), PyValue::Int(10)),
         ])
@@ -3326,7 +8820,7 @@ items = [("a", 1), ("b", 2), ("c", 3)]
         .unwrap();
     assert_eq!(
         result,
-        PyValue::Dict(vec![
+        PyValue::dict(vec![
             (PyValue::Str("a".to_string()), PyValue::Int(2)),
             (PyValue::Str("b".to_string()), PyValue::Int(4)),
             (PyValue::Str("c".to_string()), PyValue::Int(6)),
@@ -3340,7 +8834,7 @@ fn test_dict_comprehension_from_range() {
     let result = sandbox.run("{x: x * x for x in range(4)}").unwrap();
     assert_eq!(
         result,
-        PyValue::Dict(vec![
+        PyValue::dict(vec![
             (PyValue::Int(0), PyValue::Int(0)),
             (PyValue::Int(1), PyValue::Int(1)),
             (PyValue::Int(2), PyValue::Int(4)),
@@ -3360,7 +8854,7 @@ fn test_set_comprehension_basic() {
     // Set deduplicates
     assert_eq!(
         result,
-        PyValue::Set(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::set(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -3377,7 +8871,7 @@ items = [-2, -1, 0, 1, 2, 3]
         .unwrap();
     assert_eq!(
         result,
-        PyValue::Set(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::set(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -3387,7 +8881,7 @@ fn test_set_comprehension_with_expression() {
     let result = sandbox.run("{x * 2 for x in [1, 2, 3]}").unwrap();
     assert_eq!(
         result,
-        PyValue::Set(vec![PyValue::Int(2), PyValue::Int(4), PyValue::Int(6)])
+        PyValue::set(vec![PyValue::Int(2), PyValue::Int(4), PyValue::Int(6)])
     );
 }
 
@@ -3710,7 +9204,7 @@ fn test_str_splitlines() {
 
     assert_eq!(
         sandbox.run(r#"'a\nb\nc'.splitlines()"#).unwrap(),
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("a".to_string()),
             PyValue::Str("b".to_string()),
             PyValue::Str("c".to_string()),
@@ -3799,7 +9293,7 @@ fn test_list_sort_basic() {
     sandbox.run("nums.sort()").unwrap();
     assert_eq!(
         sandbox.run("nums").unwrap(),
-        PyValue::List(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
+        PyValue::list(vec![PyValue::Int(1), PyValue::Int(2), PyValue::Int(3)])
     );
 }
 
@@ -3811,7 +9305,7 @@ fn test_list_sort_reverse() {
     sandbox.run("nums.sort(reverse=True)").unwrap();
     assert_eq!(
         sandbox.run("nums").unwrap(),
-        PyValue::List(vec![PyValue::Int(3), PyValue::Int(2), PyValue::Int(1)])
+        PyValue::list(vec![PyValue::Int(3), PyValue::Int(2), PyValue::Int(1)])
     );
 }
 
@@ -3830,7 +9324,7 @@ words
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("pie".to_string()),
             PyValue::Str("Apple".to_string()),
             PyValue::Str("banana".to_string()),
@@ -3854,7 +9348,7 @@ words
         .unwrap();
     assert_eq!(
         result,
-        PyValue::List(vec![
+        PyValue::list(vec![
             PyValue::Str("cherry".to_string()),
             PyValue::Str("banana".to_string()),
             PyValue::Str("Apple".to_string()),
@@ -4029,6 +9523,33 @@ with open("out.txt", "w") as f:
     assert_eq!(host_content, "before error");
 }
 
+#[test]
+fn test_with_statement_return_calls_exit() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("out.txt"), "").unwrap();
+
+    let mut sandbox = Sandbox::new();
+    sandbox.mount(".", dir.path().to_str().unwrap(), true);
+
+    // Returning from inside the with block still runs __exit__.
+    let result = sandbox
+        .run(
+            r#"
+def save(text):
+    with open("out.txt", "w") as f:
+        f.write(text)
+        return "saved"
+
+save("early return")
+"#,
+        )
+        .unwrap();
+    assert_eq!(result, PyValue::Str("saved".to_string()));
+
+    let host_content = std::fs::read_to_string(dir.path().join("out.txt")).unwrap();
+    assert_eq!(host_content, "early return");
+}
+
 #[test]
 fn test_with_statement_no_as_clause() {
     let dir = tempfile::tempdir().unwrap();
@@ -27,7 +27,7 @@ fn greet(name: String, prefix: Option<String>) -> String {
 /// Return a dict with weather info.
 #[tool]
 fn get_weather(city: String) -> PyValue {
-    PyValue::Dict(vec![
+    PyValue::dict(vec![
         (PyValue::Str("city".to_string()), PyValue::Str(city)),
         (PyValue::Str("temp".to_string()), PyValue::Int(22)),
         (
@@ -92,16 +92,9 @@ fn test_tool_call_with_optional_arg_omitted() {
 fn test_tool_call_returns_pyvalue() {
     let result = get_weather::call(vec![PyValue::Str("Paris".to_string())]);
     if let PyValue::Dict(pairs) = result {
-        let city = pairs
-            .iter()
-            .find(|(k, _)| k == &PyValue::Str("city".to_string()));
-        assert_eq!(
-            city,
-            Some(&(
-                PyValue::Str("city".to_string()),
-                PyValue::Str("Paris".to_string())
-            ))
-        );
+        let pairs = pairs.read();
+        let city = pairs.get(&PyValue::Str("city".to_string()));
+        assert_eq!(city, Some(&PyValue::Str("Paris".to_string())));
     } else {
         panic!("Expected Dict, got {:?}", result);
     }
@@ -114,11 +107,10 @@ fn test_tool_call_with_wrong_type() {
 
     // Should return an error dict
     if let PyValue::Dict(pairs) = result {
-        let error = pairs
-            .iter()
-            .find(|(k, _)| k == &PyValue::Str("error".to_string()));
+        let pairs = pairs.read();
+        let error = pairs.get(&PyValue::Str("error".to_string()));
         assert!(error.is_some(), "Expected error in result");
-        if let Some((_, PyValue::Str(msg))) = error {
+        if let Some(PyValue::Str(msg)) = error {
             assert!(
                 msg.contains("argument 'a'"),
                 "Error should mention argument name: {}",
@@ -142,11 +134,10 @@ fn test_tool_call_with_missing_required_arg() {
 
     // Should return an error dict
     if let PyValue::Dict(pairs) = result {
-        let error = pairs
-            .iter()
-            .find(|(k, _)| k == &PyValue::Str("error".to_string()));
+        let pairs = pairs.read();
+        let error = pairs.get(&PyValue::Str("error".to_string()));
         assert!(error.is_some(), "Expected error in result");
-        if let Some((_, PyValue::Str(msg))) = error {
+        if let Some(PyValue::Str(msg)) = error {
             assert!(
                 msg.contains("missing"),
                 "Error should mention missing: {}",
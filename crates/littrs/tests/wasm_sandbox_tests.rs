@@ -47,7 +47,7 @@ fn test_register_tool() {
 
     sandbox
         .register_fn("double", |args| {
-            let n = args.get(0).and_then(|v| v.as_int()).unwrap_or(0);
+            let n = args.first().and_then(|v| v.as_int()).unwrap_or(0);
             PyValue::Int(n * 2)
         })
         .unwrap();
@@ -61,8 +61,8 @@ fn test_tool_with_dict_result() {
 
     sandbox
         .register_fn("get_user", |args| {
-            let id = args.get(0).and_then(|v| v.as_int()).unwrap_or(0);
-            PyValue::Dict(vec![
+            let id = args.first().and_then(|v| v.as_int()).unwrap_or(0);
+            PyValue::dict(vec![
                 (PyValue::Str("id".to_string()), PyValue::Int(id)),
                 (
                     PyValue::Str("name".to_string()),